@@ -1,20 +1,34 @@
 //! REST API server for nvim-web
 //!
 //! Provides HTTP endpoints for session management and automation.
+//!
+//! With OIDC configured every endpoint but `/health` needs a login, the
+//! `/sessions/:id` endpoints only serve the owner of the session, the
+//! `/snapshot/:id` endpoints only the owner of the snapshot, and `/audit`
+//! only serves admins.
 
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{delete, get, post},
-    Json, Router,
+    body::Bytes,
+    extract::{ConnectInfo, Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Extension, Json, Router,
 };
 use serde::Deserialize;
 use tokio::sync::RwLock;
 
-use crate::session::{AsyncSessionManager, SessionInfo};
+use crate::audit::{self, AuditAction, AuditEvent, AuditQuery};
+use crate::collaboration::Role;
+use crate::launch::LaunchRequest;
+use crate::oidc::{AuthUser, Grant, OidcClient, UserRole};
+use crate::session::{AsyncSessionManager, SessionAccess, SessionInfo};
+use crate::sharing::{owns, Snapshot};
 use crate::vfs::SshFsBackend;
 
 // Shared state
@@ -22,6 +36,38 @@ use crate::vfs::SshFsBackend;
 pub struct AppState {
     pub session_manager: Arc<RwLock<AsyncSessionManager>>,
     pub ws_port: u16,
+    /// OIDC client, `None` when login is not enforced
+    pub auth: Option<Arc<OidcClient>>,
+}
+
+/// The logged-in user of a request, if login is enforced
+type Caller = Option<Extension<AuthUser>>;
+
+fn subject(caller: &Caller) -> Option<&str> {
+    caller.as_ref().map(|Extension(user)| user.sub.as_str())
+}
+
+/// Role and limits of the caller, `None` when login is not enforced
+fn grant(state: &AppState, caller: &Caller) -> Option<Grant> {
+    let (auth, Extension(user)) = (state.auth.as_ref()?, caller.as_ref()?);
+    Some(auth.grant(user))
+}
+
+/// Client address of a request, for the audit log
+fn client_ip(
+    state: &AppState,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: &HeaderMap,
+) -> Option<IpAddr> {
+    let peer = peer.map(|ConnectInfo(addr)| addr.ip());
+    audit::client_ip(state.auth.as_deref(), peer, headers)
+}
+
+fn forbidden(e: &anyhow::Error) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::FORBIDDEN,
+        Json(serde_json::json!({ "error": e.to_string() })),
+    )
 }
 
 // SSH connection request
@@ -34,16 +80,30 @@ pub struct SshConnectRequest {
 }
 
 // Routes
-pub fn api_router() -> Router<AppState> {
-    Router::new()
-        .route("/health", get(health_check))
-        .route("/sessions", get(list_sessions).post(create_session))
-        .route("/sessions/count", get(session_count))
+pub fn api_router(state: AppState) -> Router<AppState> {
+    let owned = Router::new()
         .route("/sessions/:id", delete(delete_session))
         .route("/sessions/:id/share", post(create_share_link))
         .route("/sessions/:id/shares", get(list_share_links))
         .route("/sessions/:id/snapshot", post(create_snapshot))
         .route("/sessions/:id/snapshots", get(list_snapshots))
+        .route("/sessions/:id/participants", get(list_participants))
+        .route(
+            "/sessions/:id/participants/:peer/role",
+            put(set_participant_role),
+        )
+        .route(
+            "/sessions/:id/control",
+            post(grant_control).delete(revoke_control),
+        )
+        .route("/sessions/:id/input-log", get(input_log))
+        .route("/sessions/:id/comments", get(list_comments))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_owner));
+
+    Router::new()
+        .route("/sessions", get(list_sessions).post(create_session))
+        .route("/sessions/count", get(session_count))
+        .merge(owned)
         .route("/open", post(open_project))
         .route("/claim/:token", get(claim_token))
         .route("/token/:token", get(get_token_info))
@@ -55,9 +115,74 @@ pub fn api_router() -> Router<AppState> {
             "/snapshot/:id",
             get(get_snapshot).delete(delete_snapshot_handler),
         )
+        .route("/snapshot/:id/restore", post(restore_snapshot))
+        .route("/snapshot/:id/export", get(export_snapshot))
+        .route("/snapshots/import", post(import_snapshot))
         .route("/ssh/test", post(test_ssh_connection))
         .route("/ssh/connect", post(connect_ssh))
         .route("/ssh/disconnect", post(disconnect_ssh))
+        .route("/audit", get(query_audit))
+        .route_layer(middleware::from_fn_with_state(state, require_login))
+        .route("/health", get(health_check))
+}
+
+/// Reject requests without a login when OIDC is configured
+async fn require_login(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    match state.auth {
+        Some(auth) => crate::oidc::auth_middleware(State(auth), request, next).await,
+        None => Ok(next.run(request).await),
+    }
+}
+
+/// Owners recorded for a session that is not running, `None` if unknown
+///
+/// Snapshots are left out: an imported one keeps the ID of the session it
+/// was taken from, which may be someone else's.
+fn stored_owners(mgr: &AsyncSessionManager, id: &str) -> Option<Vec<Option<String>>> {
+    let mut owners = crate::sharing::link_owners(id)?;
+    owners.extend(crate::comments::stored_owners(id).ok()?);
+    if let Some(registry) = &mgr.registry {
+        owners.extend(registry.get(id).ok()?.map(|record| record.owner));
+    }
+    Some(owners)
+}
+
+/// Only let the owner manage a session
+///
+/// Other users get the same 404 as for a session that does not exist. A
+/// session that is not running belongs to the owner recorded for it in the
+/// session registry, on its share links and on its comments; one with no
+/// records belongs to nobody.
+async fn require_owner(
+    State(state): State<AppState>,
+    Path(params): Path<HashMap<String, String>>,
+    caller: Caller,
+    request: Request,
+    next: Next,
+) -> Response {
+    let id = params.get("id").map(String::as_str).unwrap_or_default();
+    let user = subject(&caller);
+    let owner = {
+        let mgr = state.session_manager.read().await;
+        match mgr.access(id, user) {
+            Some(access) => access == SessionAccess::Owner,
+            None => stored_owners(&mgr, id).is_some_and(|owners| {
+                !owners.is_empty() && owners.iter().all(|owner| owns(owner.as_deref(), user))
+            }),
+        }
+    };
+    if !owner {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": "session not found" })),
+        )
+            .into_response();
+    }
+    next.run(request).await
 }
 
 // Handlers
@@ -66,20 +191,44 @@ async fn health_check() -> Json<serde_json::Value> {
     Json(serde_json::json!({ "status": "ok", "version": "0.1.0" }))
 }
 
-async fn list_sessions(State(state): State<AppState>) -> Json<serde_json::Value> {
-    let sessions: Vec<SessionInfo> = state.session_manager.read().await.list_sessions();
+async fn list_sessions(State(state): State<AppState>, caller: Caller) -> Json<serde_json::Value> {
+    let sessions: Vec<SessionInfo> = state
+        .session_manager
+        .read()
+        .await
+        .list_sessions_for(subject(&caller));
     // Use serde_json::to_value to serialize the list
     Json(serde_json::json!({ "sessions": sessions }))
 }
 
-async fn session_count(State(state): State<AppState>) -> Json<serde_json::Value> {
+async fn session_count(State(state): State<AppState>, caller: Caller) -> Json<serde_json::Value> {
     let mgr = state.session_manager.read().await;
-    Json(serde_json::json!({ "count": mgr.session_count() }))
+    let count = match caller {
+        Some(_) => mgr.list_sessions_for(subject(&caller)).len(),
+        None => mgr.session_count(),
+    };
+    Json(serde_json::json!({ "count": count }))
 }
 
-async fn create_session(State(state): State<AppState>) -> impl IntoResponse {
+/// Start a session, optionally with a launch profile or for a project
+async fn create_session(
+    State(state): State<AppState>,
+    caller: Caller,
+    payload: Option<Json<LaunchRequest>>,
+) -> impl IntoResponse {
+    let launch = payload.map(|Json(launch)| launch).unwrap_or_default();
+    let owner = grant(&state, &caller);
     let mut mgr = state.session_manager.write().await;
-    match mgr.create_session(None).await {
+    if let Err(e) = mgr.check_create(owner.as_ref()) {
+        return forbidden(&e);
+    }
+    if let Err(e) = mgr.profiles.resolve(&launch) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": format!("{e:#}") })),
+        );
+    }
+    match mgr.create_session(None, owner.as_ref(), &launch).await {
         Ok(id) => (
             StatusCode::OK,
             Json(serde_json::json!({ "id": id, "created": true })),
@@ -199,7 +348,38 @@ async fn get_token_info(Path(token): Path<String>) -> impl IntoResponse {
     }
 }
 
-async fn test_ssh_connection(Json(payload): Json<SshConnectRequest>) -> impl IntoResponse {
+/// Audit event for an SSH request, naming the target but never the password
+fn ssh_event(
+    action: AuditAction,
+    state: &AppState,
+    caller: &Caller,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: &HeaderMap,
+    payload: &SshConnectRequest,
+) -> AuditEvent {
+    AuditEvent::new(action)
+        .user(subject(caller))
+        .ip(client_ip(state, peer, headers))
+        .detail("host", payload.host.as_str())
+        .detail("port", payload.port.unwrap_or(22))
+        .detail("ssh_user", payload.user.as_str())
+}
+
+async fn test_ssh_connection(
+    State(state): State<AppState>,
+    caller: Caller,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(payload): Json<SshConnectRequest>,
+) -> impl IntoResponse {
+    let event = ssh_event(
+        AuditAction::SshTest,
+        &state,
+        &caller,
+        peer,
+        &headers,
+        &payload,
+    );
     let uri = format!(
         "vfs://ssh/{}@{}:{}/",
         payload.user,
@@ -208,18 +388,41 @@ async fn test_ssh_connection(Json(payload): Json<SshConnectRequest>) -> impl Int
     );
 
     match SshFsBackend::test_connection(&uri, payload.password.as_deref()) {
-        Ok(()) => (StatusCode::OK, Json(serde_json::json!({ "success": true }))),
-        Err(e) => (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({ "error": e.to_string() })),
-        ),
+        Ok(()) => {
+            event.record();
+            (StatusCode::OK, Json(serde_json::json!({ "success": true })))
+        }
+        Err(e) => {
+            event.failed().detail("error", e.to_string()).record();
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({ "error": e.to_string() })),
+            )
+        }
     }
 }
 
 async fn connect_ssh(
     State(state): State<AppState>,
+    caller: Caller,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
     Json(payload): Json<SshConnectRequest>,
 ) -> impl IntoResponse {
+    let event = ssh_event(
+        AuditAction::SshConnect,
+        &state,
+        &caller,
+        peer,
+        &headers,
+        &payload,
+    );
+    if let Some(owner) = grant(&state, &caller) {
+        if let Err(e) = owner.limits.check_vfs_scheme("ssh") {
+            event.failed().detail("error", e.to_string()).record();
+            return forbidden(&e);
+        }
+    }
     let uri = format!(
         "vfs://ssh/{}@{}:{}/",
         payload.user,
@@ -235,6 +438,7 @@ async fn connect_ssh(
                 .write()
                 .await
                 .set_active_ssh(Some(uri.clone()));
+            event.record();
 
             (
                 StatusCode::OK,
@@ -244,26 +448,70 @@ async fn connect_ssh(
                 })),
             )
         }
-        Err(e) => (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({ "error": e.to_string() })),
-        ),
+        Err(e) => {
+            event.failed().detail("error", e.to_string()).record();
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({ "error": e.to_string() })),
+            )
+        }
     }
 }
 
-async fn disconnect_ssh(State(state): State<AppState>) -> impl IntoResponse {
-    state.session_manager.write().await.set_active_ssh(None);
+async fn disconnect_ssh(
+    State(state): State<AppState>,
+    caller: Caller,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let previous = state.session_manager.write().await.active_ssh.take();
+    let mut event = AuditEvent::new(AuditAction::SshDisconnect)
+        .user(subject(&caller))
+        .ip(client_ip(&state, peer, &headers));
+    if let Some(uri) = previous {
+        event = event.detail("uri", uri);
+    }
+    event.record();
 
     (StatusCode::OK, Json(serde_json::json!({ "success": true })))
 }
 
+/// Query the audit log; only admins may read it when login is enforced
+async fn query_audit(
+    State(state): State<AppState>,
+    caller: Caller,
+    Query(query): Query<AuditQuery>,
+) -> impl IntoResponse {
+    if state.auth.is_some() && grant(&state, &caller).is_none_or(|g| g.role != UserRole::Admin) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(serde_json::json!({ "error": "the audit log is only open to admins" })),
+        );
+    }
+    // Reads the whole log file, so keep it off the async threads
+    let events = tokio::task::spawn_blocking(move || audit::query(&query))
+        .await
+        .unwrap_or_else(|e| Err(e.into()));
+    match events {
+        Ok(events) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "events": events })),
+        ),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": format!("{e:#}") })),
+        ),
+    }
+}
+
 // Share link handlers
 
 async fn create_share_link(
     Path(session_id): Path<String>,
+    caller: Caller,
     Json(options): Json<crate::sharing::ShareOptions>,
 ) -> impl IntoResponse {
-    let link = crate::sharing::create_share_link(&session_id, options);
+    let link = crate::sharing::create_share_link(&session_id, options, subject(&caller));
     (
         StatusCode::OK,
         Json(serde_json::json!({
@@ -281,12 +529,25 @@ async fn list_share_links(Path(session_id): Path<String>) -> impl IntoResponse {
     Json(serde_json::json!({ "links": links }))
 }
 
-async fn use_share_link(Path(token): Path<String>) -> impl IntoResponse {
-    match crate::sharing::use_share_link(&token) {
-        Some((session_id, read_only)) => (
-            StatusCode::OK,
-            Json(serde_json::json!({ "session_id": session_id, "read_only": read_only })),
-        ),
+/// Redeem a share link, letting the caller into the session as a guest
+async fn use_share_link(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    caller: Caller,
+) -> impl IntoResponse {
+    match crate::sharing::use_share_link(&token, subject(&caller)) {
+        Some((session_id, read_only)) => {
+            if let Some(user) = subject(&caller) {
+                let mut mgr = state.session_manager.write().await;
+                if let Some(session) = mgr.get_session_mut(&session_id) {
+                    session.acl.add_guest(user, read_only);
+                }
+            }
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({ "session_id": session_id, "read_only": read_only })),
+            )
+        }
         None => (
             StatusCode::NOT_FOUND,
             Json(serde_json::json!({ "error": "share link invalid or expired" })),
@@ -294,14 +555,148 @@ async fn use_share_link(Path(token): Path<String>) -> impl IntoResponse {
     }
 }
 
-async fn revoke_share_link(Path(token): Path<String>) -> impl IntoResponse {
-    if crate::sharing::revoke_share_link(&token) {
+async fn revoke_share_link(Path(token): Path<String>, caller: Caller) -> impl IntoResponse {
+    if crate::sharing::revoke_share_link(&token, subject(&caller)) {
         Json(serde_json::json!({ "revoked": true }))
     } else {
         Json(serde_json::json!({ "error": "share link not found" }))
     }
 }
 
+// Participant handlers
+
+fn no_participants() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(serde_json::json!({ "error": "session has no participants" })),
+    )
+}
+
+async fn list_participants(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+) -> impl IntoResponse {
+    let registry = state.session_manager.read().await.collaboration();
+    let reg = registry.read().await;
+    let Some(viewers) = reg.get(&session_id) else {
+        return no_participants();
+    };
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "participants": viewers.list_viewers(),
+            "driver": viewers.driver(),
+            "requests": viewers.control_requests(),
+        })),
+    )
+}
+
+#[derive(Deserialize)]
+struct RoleRequest {
+    role: Role,
+}
+
+async fn set_participant_role(
+    State(state): State<AppState>,
+    Path((session_id, peer)): Path<(String, String)>,
+    Json(payload): Json<RoleRequest>,
+) -> impl IntoResponse {
+    let registry = state.session_manager.read().await.collaboration();
+    let mut reg = registry.write().await;
+    let Some(viewers) = reg.get_mut(&session_id) else {
+        return no_participants();
+    };
+    match viewers.set_role(&peer, payload.role) {
+        Ok(()) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "id": peer, "role": payload.role })),
+        ),
+        Err(e) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": e.to_string() })),
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+struct ControlRequest {
+    peer: String,
+}
+
+async fn grant_control(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    Json(payload): Json<ControlRequest>,
+) -> impl IntoResponse {
+    let registry = state.session_manager.read().await.collaboration();
+    let mut reg = registry.write().await;
+    let Some(viewers) = reg.get_mut(&session_id) else {
+        return no_participants();
+    };
+    match viewers.grant_control(&payload.peer) {
+        Ok(()) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "driver": payload.peer })),
+        ),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": e.to_string() })),
+        ),
+    }
+}
+
+async fn revoke_control(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+) -> impl IntoResponse {
+    let registry = state.session_manager.read().await.collaboration();
+    let mut reg = registry.write().await;
+    let Some(viewers) = reg.get_mut(&session_id) else {
+        return no_participants();
+    };
+    viewers.revoke_control();
+    (StatusCode::OK, Json(serde_json::json!({ "driver": null })))
+}
+
+/// Recent keystrokes with the participant that sent them
+async fn input_log(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+) -> impl IntoResponse {
+    let registry = state.session_manager.read().await.collaboration();
+    let reg = registry.read().await;
+    let Some(viewers) = reg.get(&session_id) else {
+        return no_participants();
+    };
+    let entries: Vec<_> = viewers.input_log().collect();
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({ "entries": entries })),
+    )
+}
+
+/// Review comments of a session, with line numbers refreshed if it is live
+async fn list_comments(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+) -> impl IntoResponse {
+    let mgr = state.session_manager.read().await;
+    let comments = match mgr.get_session(&session_id) {
+        Some(session) => crate::comments::list(session, &mgr.collaboration()).await,
+        None => crate::comments::stored(&session_id),
+    };
+    match comments {
+        Ok(comments) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "comments": comments })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": e.to_string() })),
+        ),
+    }
+}
+
 // Snapshot handlers
 
 #[derive(Deserialize)]
@@ -313,28 +708,116 @@ struct SnapshotRequest {
     description: Option<String>,
 }
 
+/// Snapshot a session; live sessions on this host are captured in full
+///
+/// Fields given in the request override the captured ones.
 async fn create_snapshot(
+    State(state): State<AppState>,
     Path(session_id): Path<String>,
+    caller: Caller,
     Json(payload): Json<SnapshotRequest>,
 ) -> impl IntoResponse {
-    let snap = crate::sharing::create_snapshot(
-        &session_id,
-        std::path::PathBuf::from(payload.cwd.unwrap_or_default()),
-        payload.open_files.unwrap_or_default(),
-        payload.current_file,
-        payload.cursor,
-        payload.description,
-    );
-    Json(serde_json::json!({ "id": snap.id, "session_id": snap.session_id }))
+    let captured = {
+        let mgr = state.session_manager.read().await;
+        match mgr.get_session(&session_id) {
+            Some(session) => {
+                Some(crate::snapshot::capture(session, payload.description.clone()).await)
+            }
+            None => None,
+        }
+    };
+    let mut snap = match captured {
+        Some(Ok(snap)) => snap,
+        Some(Err(e)) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "error": format!("{e:#}") })),
+            );
+        }
+        None => Snapshot::new(&session_id, std::path::PathBuf::new(), payload.description),
+    };
+    snap.owner = subject(&caller).map(ToString::to_string);
+
+    if let Some(cwd) = payload.cwd {
+        snap.cwd = cwd.into();
+    }
+    if let Some(open_files) = payload.open_files {
+        snap.open_files = open_files;
+    }
+    if payload.current_file.is_some() {
+        snap.current_file = payload.current_file;
+    }
+    if payload.cursor.is_some() {
+        snap.cursor = payload.cursor;
+    }
+    crate::sharing::save_snapshot(&snap);
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({ "id": snap.id, "session_id": snap.session_id })),
+    )
+}
+
+/// A stored snapshot, if the caller owns it
+fn owned_snapshot(id: &str, caller: &Caller) -> Option<Snapshot> {
+    crate::sharing::get_snapshot(id).filter(|snap| owns(snap.owner.as_deref(), subject(caller)))
+}
+
+async fn export_snapshot(Path(id): Path<String>, caller: Caller) -> impl IntoResponse {
+    let archive = owned_snapshot(&id, &caller)
+        .ok_or_else(|| anyhow::anyhow!("not found"))
+        .and_then(|snap| crate::snapshot::export_archive(&snap));
+    match archive {
+        Ok(data) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "application/json".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{id}.nvim-web-snapshot.json\""),
+                ),
+            ],
+            data,
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": e.to_string() })),
+        )
+            .into_response(),
+    }
 }
 
-async fn list_snapshots(Path(session_id): Path<String>) -> impl IntoResponse {
-    let snapshots = crate::sharing::list_snapshots(&session_id);
+async fn import_snapshot(caller: Caller, body: Bytes) -> impl IntoResponse {
+    let imported = crate::snapshot::import_archive(&body).map(|snap| Snapshot {
+        owner: subject(&caller).map(ToString::to_string),
+        ..snap
+    });
+    match imported {
+        Ok(snap) if crate::sharing::save_snapshot(&snap) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "id": snap.id, "session_id": snap.session_id })),
+        ),
+        Ok(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": "failed to store snapshot" })),
+        ),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": format!("{e:#}") })),
+        ),
+    }
+}
+
+async fn list_snapshots(Path(session_id): Path<String>, caller: Caller) -> impl IntoResponse {
+    let snapshots: Vec<Snapshot> = crate::sharing::list_snapshots(&session_id)
+        .into_iter()
+        .filter(|snap| owns(snap.owner.as_deref(), subject(&caller)))
+        .collect();
     Json(serde_json::json!({ "snapshots": snapshots }))
 }
 
-async fn get_snapshot(Path(id): Path<String>) -> impl IntoResponse {
-    match crate::sharing::get_snapshot(&id) {
+async fn get_snapshot(Path(id): Path<String>, caller: Caller) -> impl IntoResponse {
+    match owned_snapshot(&id, &caller) {
         Some(snap) => (StatusCode::OK, Json(serde_json::to_value(snap).unwrap())),
         None => (
             StatusCode::NOT_FOUND,
@@ -343,14 +826,68 @@ async fn get_snapshot(Path(id): Path<String>) -> impl IntoResponse {
     }
 }
 
-async fn delete_snapshot_handler(Path(id): Path<String>) -> impl IntoResponse {
-    if crate::sharing::delete_snapshot(&id) {
+async fn delete_snapshot_handler(Path(id): Path<String>, caller: Caller) -> impl IntoResponse {
+    if owned_snapshot(&id, &caller).is_some() && crate::sharing::delete_snapshot(&id) {
         Json(serde_json::json!({ "deleted": true }))
     } else {
         Json(serde_json::json!({ "error": "not found" }))
     }
 }
 
+#[derive(Deserialize)]
+struct RestoreRequest {
+    /// Also create a share link for the restored session
+    share: Option<crate::sharing::ShareOptions>,
+}
+
+async fn restore_snapshot(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    caller: Caller,
+    payload: Option<Json<RestoreRequest>>,
+) -> impl IntoResponse {
+    let Some(snapshot) = owned_snapshot(&id, &caller) else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": "not found" })),
+        );
+    };
+
+    let owner = grant(&state, &caller);
+    let mut mgr = state.session_manager.write().await;
+    if let Err(e) = mgr.check_create(owner.as_ref()) {
+        return forbidden(&e);
+    }
+    let restored = mgr.restore_snapshot(&snapshot, owner.as_ref()).await;
+    let session_id = match restored {
+        Ok(session_id) => session_id,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "error": format!("{e:#}") })),
+            );
+        }
+    };
+
+    drop(mgr);
+
+    let mut body = serde_json::json!({
+        "session_id": session_id,
+        "snapshot_id": snapshot.id,
+        "url": format!("/?session={session_id}")
+    });
+    if let Some(options) = payload.and_then(|Json(request)| request.share) {
+        let link = crate::sharing::create_share_link(&session_id, options, subject(&caller));
+        body["share"] = serde_json::json!({
+            "token": link.token,
+            "read_only": link.read_only,
+            "max_uses": link.max_uses,
+            "url": format!("/?share={}", link.token)
+        });
+    }
+    (StatusCode::OK, Json(body))
+}
+
 // Deprecated entry point kept for signature compatibility if needed, but unused
 pub fn serve_api(
     _addr: &str,
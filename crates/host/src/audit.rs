@@ -0,0 +1,469 @@
+//! Audit log of security-relevant events
+//!
+//! Logins, share links, session connections, `tool_exec` runs and SSH
+//! connections are recorded as JSON lines in an append-only file, by default
+//! ~/.config/nvim-web/audit.jsonl, and optionally sent to a syslog daemon.
+//! The file is never rewritten; admins read it back through `GET /api/audit`.
+//!
+//! Events are recorded through the process-wide log set up by [`init`];
+//! before that (and in tests) [`AuditEvent::record`] does nothing. Recording
+//! only queues the event: a writer thread does the file and syslog writes,
+//! so async handlers never block on them. [`flush`] waits for the queue to
+//! drain, e.g. on shutdown.
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs, UdpSocket};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+use anyhow::{Context, Result};
+use axum::http::HeaderMap;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::config::AuditConfig;
+use crate::oidc::OidcClient;
+
+/// Events returned by a query unless it asks for fewer
+pub const DEFAULT_QUERY_LIMIT: usize = 100;
+/// Most events a single query returns
+pub const MAX_QUERY_LIMIT: usize = 1000;
+
+/// syslog facility `authpriv`
+const SYSLOG_FACILITY: u8 = 10;
+
+static LOG: OnceLock<Global> = OnceLock::new();
+
+/// The process-wide log and the queue of its writer thread
+struct Global {
+    log: Arc<AuditLog>,
+    queue: Sender<Job>,
+}
+
+/// Work for the writer thread
+enum Job {
+    Record(AuditEvent),
+    /// Reply once everything queued before is written
+    Flush(Sender<()>),
+}
+
+impl Global {
+    fn send(&self, job: Job) -> bool {
+        self.queue.send(job).is_ok()
+    }
+}
+
+/// What happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// OIDC login completed, or failed at the callback
+    Login,
+    Logout,
+    /// WebSocket or WebTransport connection refused for lack of a login
+    ConnectionRejected,
+    /// Connection attached to a session as owner, editor or viewer
+    SessionAttached,
+    ShareLinkCreated,
+    ShareLinkClaimed,
+    ShareLinkRevoked,
+    /// Browser ran a command through the `tool_exec` RPC
+    ToolExec,
+    SshTest,
+    SshConnect,
+    SshDisconnect,
+}
+
+/// One audit log entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    /// RFC 3339 time in UTC, with milliseconds
+    pub time: String,
+    pub action: AuditAction,
+    /// Whether the attempt succeeded
+    pub success: bool,
+    /// Subject (`sub`) of the user, `None` without login
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Client address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<IpAddr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Action-specific details
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub details: Map<String, Value>,
+}
+
+impl AuditEvent {
+    /// A successful `action` happening now
+    pub fn new(action: AuditAction) -> Self {
+        Self {
+            time: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            action,
+            success: true,
+            user: None,
+            ip: None,
+            session_id: None,
+            details: Map::new(),
+        }
+    }
+
+    pub fn user(mut self, user: Option<&str>) -> Self {
+        self.user = user.map(String::from);
+        self
+    }
+
+    pub fn ip(mut self, ip: Option<IpAddr>) -> Self {
+        self.ip = ip;
+        self
+    }
+
+    pub fn session(mut self, session_id: &str) -> Self {
+        self.session_id = Some(session_id.to_string());
+        self
+    }
+
+    /// Mark the attempt as failed
+    pub fn failed(mut self) -> Self {
+        self.success = false;
+        self
+    }
+
+    /// Succeeded or failed depending on `success`
+    pub fn success(mut self, success: bool) -> Self {
+        self.success = success;
+        self
+    }
+
+    pub fn detail(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.details.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Queue the event for the process-wide audit log
+    pub fn record(self) {
+        if let Some(global) = LOG.get() {
+            global.send(Job::Record(self));
+        }
+    }
+
+    /// The event as an RFC 5424 syslog message
+    fn to_syslog(&self, json: &str) -> String {
+        let severity = if self.success { 6 } else { 4 };
+        let action = serde_json::to_value(self.action)
+            .ok()
+            .and_then(|v| v.as_str().map(String::from))
+            .unwrap_or_default();
+        format!(
+            "<{}>1 {} - nvim-web {} {action} - {json}",
+            SYSLOG_FACILITY * 8 + severity,
+            self.time,
+            std::process::id(),
+        )
+    }
+}
+
+/// Filter for reading the audit log back
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditQuery {
+    pub action: Option<AuditAction>,
+    pub user: Option<String>,
+    pub session_id: Option<String>,
+    /// Only events at or after this RFC 3339 time
+    pub since: Option<String>,
+    /// Newest events returned, [`DEFAULT_QUERY_LIMIT`] by default
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, event: &AuditEvent, since: Option<DateTime<Utc>>) -> bool {
+        self.action.is_none_or(|action| action == event.action)
+            && (self.user.is_none() || self.user == event.user)
+            && (self.session_id.is_none() || self.session_id == event.session_id)
+            && since.is_none_or(|since| {
+                DateTime::parse_from_rfc3339(&event.time).is_ok_and(|time| time >= since)
+            })
+    }
+}
+
+/// Where syslog messages go
+enum Syslog {
+    Udp(UdpSocket),
+    Unix(UnixDatagram),
+}
+
+impl Syslog {
+    /// Connect to `udp://host:port` or `unix:///path/to/socket`
+    fn connect(target: &str) -> Result<Self> {
+        if let Some(addr) = target.strip_prefix("udp://") {
+            let daemon = addr
+                .to_socket_addrs()?
+                .next()
+                .with_context(|| format!("Cannot resolve syslog address {addr}"))?;
+            let local: IpAddr = if daemon.is_ipv4() {
+                Ipv4Addr::UNSPECIFIED.into()
+            } else {
+                Ipv6Addr::UNSPECIFIED.into()
+            };
+            let socket = UdpSocket::bind((local, 0))?;
+            socket.connect(daemon)?;
+            Ok(Self::Udp(socket))
+        } else if let Some(path) = target.strip_prefix("unix://") {
+            let socket = UnixDatagram::unbound()?;
+            socket
+                .connect(path)
+                .with_context(|| format!("Cannot reach syslog at {path}"))?;
+            Ok(Self::Unix(socket))
+        } else {
+            anyhow::bail!("syslog must be udp://host:port or unix:///path, got {target}")
+        }
+    }
+
+    fn send(&self, message: &str) -> std::io::Result<usize> {
+        match self {
+            Self::Udp(socket) => socket.send(message.as_bytes()),
+            Self::Unix(socket) => socket.send(message.as_bytes()),
+        }
+    }
+}
+
+/// Append-only audit log with an optional syslog copy
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+    syslog: Option<Syslog>,
+}
+
+impl AuditLog {
+    /// Open the log file at `path` for appending, creating it if needed
+    pub fn open(path: &Path, syslog: Option<&str>) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .mode(0o600)
+            .open(path)
+            .with_context(|| format!("Cannot open audit log {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+            syslog: syslog.map(Syslog::connect).transpose()?,
+        })
+    }
+
+    /// Append `event`; failures are logged, never returned to the caller
+    pub fn record(&self, event: &AuditEvent) {
+        let json = match serde_json::to_string(event) {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to encode audit event");
+                return;
+            }
+        };
+        {
+            let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Err(e) = writeln!(file, "{json}").and_then(|()| file.flush()) {
+                tracing::error!(error = %e, path = %self.path.display(), "Failed to write audit log");
+            }
+        }
+        if let Some(syslog) = &self.syslog {
+            if let Err(e) = syslog.send(&event.to_syslog(&json)) {
+                tracing::warn!(error = %e, "Failed to send audit event to syslog");
+            }
+        }
+    }
+
+    /// The newest events matching `query`, oldest first
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEvent>> {
+        let since = query
+            .since
+            .as_deref()
+            .map(DateTime::parse_from_rfc3339)
+            .transpose()
+            .context("since must be an RFC 3339 time")?
+            .map(|since| since.with_timezone(&Utc));
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_QUERY_LIMIT)
+            .min(MAX_QUERY_LIMIT);
+
+        let file = File::open(&self.path)?;
+        let mut events = VecDeque::with_capacity(limit + 1);
+        for event in BufReader::new(file)
+            .lines()
+            .map_while(std::result::Result::ok)
+            .filter_map(|line| serde_json::from_str::<AuditEvent>(&line).ok())
+            .filter(|event| query.matches(event, since))
+        {
+            events.push_back(event);
+            if events.len() > limit {
+                events.pop_front();
+            }
+        }
+        Ok(events.into())
+    }
+}
+
+/// Set up the process-wide audit log from `[audit]`
+///
+/// Does nothing when auditing is disabled or the log is already set up.
+pub fn init(config: &AuditConfig) -> Result<()> {
+    if !config.enabled || LOG.get().is_some() {
+        return Ok(());
+    }
+    let path = match &config.path {
+        Some(path) => PathBuf::from(path),
+        None => dirs::config_dir()
+            .context("Could not determine config directory")?
+            .join("nvim-web")
+            .join("audit.jsonl"),
+    };
+    let log = Arc::new(AuditLog::open(&path, config.syslog.as_deref())?);
+    let (queue, jobs) = mpsc::channel();
+    let writer = Arc::clone(&log);
+    std::thread::Builder::new()
+        .name("audit-writer".to_string())
+        .spawn(move || {
+            for job in jobs {
+                match job {
+                    Job::Record(event) => writer.record(&event),
+                    Job::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        })
+        .context("Failed to start the audit log writer")?;
+    let _ = LOG.set(Global { log, queue });
+    Ok(())
+}
+
+/// Wait until the events recorded so far are written
+pub fn flush() {
+    if let Some(global) = LOG.get() {
+        let (done, written) = mpsc::channel();
+        if global.send(Job::Flush(done)) {
+            let _ = written.recv();
+        }
+    }
+}
+
+/// Query the process-wide audit log
+pub fn query(query: &AuditQuery) -> Result<Vec<AuditEvent>> {
+    LOG.get().context("Audit log is disabled")?.log.query(query)
+}
+
+/// Address of a client, seen through the trusted proxies of `auth`
+pub fn client_ip(
+    auth: Option<&OidcClient>,
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+) -> Option<IpAddr> {
+    match auth {
+        Some(auth) => auth.config().policy.client_ip(peer, headers),
+        None => peer,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let log = AuditLog::open(&path, None).unwrap();
+
+        log.record(&AuditEvent::new(AuditAction::Login).user(Some("alice")));
+        log.record(
+            &AuditEvent::new(AuditAction::ToolExec)
+                .user(Some("bob"))
+                .session("s1")
+                .detail("command", "rg")
+                .failed(),
+        );
+        log.record(&AuditEvent::new(AuditAction::Logout).user(Some("alice")));
+
+        // Reopening appends rather than truncating
+        drop(log);
+        let log = AuditLog::open(&path, None).unwrap();
+        assert_eq!(log.query(&AuditQuery::default()).unwrap().len(), 3);
+
+        let alice = AuditQuery {
+            user: Some("alice".to_string()),
+            ..Default::default()
+        };
+        let events = log.query(&alice).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].action, AuditAction::Login);
+        assert_eq!(events[1].action, AuditAction::Logout);
+
+        let tool = AuditQuery {
+            action: Some(AuditAction::ToolExec),
+            ..Default::default()
+        };
+        let events = log.query(&tool).unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(!events[0].success);
+        assert_eq!(events[0].session_id.as_deref(), Some("s1"));
+        assert_eq!(events[0].details["command"], "rg");
+
+        let newest = AuditQuery {
+            limit: Some(2),
+            ..Default::default()
+        };
+        let events = log.query(&newest).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].action, AuditAction::ToolExec);
+        assert_eq!(events[1].action, AuditAction::Logout);
+        let none = AuditQuery {
+            limit: Some(0),
+            ..Default::default()
+        };
+        assert!(log.query(&none).unwrap().is_empty());
+
+        let future = AuditQuery {
+            since: Some("2999-01-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+        assert!(log.query(&future).unwrap().is_empty());
+        let bad = AuditQuery {
+            since: Some("yesterday".to_string()),
+            ..Default::default()
+        };
+        assert!(log.query(&bad).is_err());
+    }
+
+    #[test]
+    fn test_syslog_copy() {
+        let daemon = UdpSocket::bind("127.0.0.1:0").unwrap();
+        daemon
+            .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+            .unwrap();
+        let target = format!("udp://{}", daemon.local_addr().unwrap());
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::open(&dir.path().join("audit.jsonl"), Some(&target)).unwrap();
+
+        log.record(&AuditEvent::new(AuditAction::SshConnect).failed());
+
+        let mut buf = [0u8; 2048];
+        let len = daemon.recv(&mut buf).unwrap();
+        let message = std::str::from_utf8(&buf[..len]).unwrap();
+        // authpriv.warning
+        assert!(message.starts_with("<84>1 "), "{message}");
+        assert!(message.contains(" nvim-web "));
+        assert!(message.contains(" ssh_connect - {"));
+
+        assert!(Syslog::connect("tcp://127.0.0.1:514").is_err());
+    }
+}
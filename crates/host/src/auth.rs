@@ -10,7 +10,8 @@ use std::path::Path;
 use anyhow::{Context, Result};
 use hmac::{Hmac, Mac};
 use rand::RngCore;
-use sha2::Sha256;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 
@@ -20,11 +21,61 @@ const TOKEN_LENGTH: usize = 32;
 /// Length of challenge nonce in bytes
 const NONCE_LENGTH: usize = 32;
 
+/// Default length of share link and project open tokens in bytes
+pub const DEFAULT_SHARE_TOKEN_BYTES: usize = 32;
+
+/// Shortest share token length accepted from configuration (128 bits)
+pub const MIN_SHARE_TOKEN_BYTES: usize = 16;
+
 type HmacSha256 = Hmac<Sha256>;
 
 /// Generate a cryptographically secure random token
 pub fn generate_secure_token() -> String {
-    let mut bytes = [0u8; TOKEN_LENGTH];
+    random_hex(TOKEN_LENGTH)
+}
+
+/// Generates random tokens for share links and project open links
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShareTokens {
+    bytes: usize,
+}
+
+impl ShareTokens {
+    /// Tokens of `bytes` random bytes, raised to [`MIN_SHARE_TOKEN_BYTES`]
+    pub fn new(bytes: usize) -> Self {
+        Self {
+            bytes: bytes.max(MIN_SHARE_TOKEN_BYTES),
+        }
+    }
+
+    /// Random bytes in each token
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn generate(&self) -> String {
+        random_hex(self.bytes)
+    }
+}
+
+impl Default for ShareTokens {
+    fn default() -> Self {
+        Self::new(DEFAULT_SHARE_TOKEN_BYTES)
+    }
+}
+
+/// SHA-256 of a token, hex-encoded, for storing tokens at rest
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Compare two token hashes in constant time
+pub fn token_hashes_match(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
     rand::rngs::OsRng.fill_bytes(&mut bytes);
     hex::encode(bytes)
 }
@@ -160,6 +211,20 @@ mod tests {
         assert_ne!(t1, t2); // Tokens must be unique
     }
 
+    #[test]
+    fn test_share_token_length_and_hash() {
+        assert_eq!(ShareTokens::new(4).bytes(), MIN_SHARE_TOKEN_BYTES);
+        assert_eq!(ShareTokens::new(48).generate().len(), 96);
+
+        let token = ShareTokens::default().generate();
+        assert_eq!(token.len(), DEFAULT_SHARE_TOKEN_BYTES * 2);
+        let hash = hash_token(&token);
+        assert_eq!(hash.len(), 64);
+        assert!(!hash.contains(&token));
+        assert!(token_hashes_match(&hash, &hash_token(&token)));
+        assert!(!token_hashes_match(&hash, &hash_token("guess")));
+    }
+
     #[test]
     fn test_generate_nonce() {
         let n1 = generate_nonce();
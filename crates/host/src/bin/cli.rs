@@ -4,6 +4,9 @@
 //!
 //! Usage:
 //!   nvim-web open [path]    Open a project in browser
+//!   nvim-web restore <id>   Restore a workspace snapshot in browser
+//!   nvim-web export <id>    Save a snapshot as an archive file
+//!   nvim-web import <file>  Load a snapshot archive into the host
 //!   nvim-web --help         Show help
 
 use std::env;
@@ -30,6 +33,37 @@ fn main() {
             };
             open_project(&path);
         }
+        "restore" => match args.get(2).filter(|id| !id.starts_with('-')) {
+            Some(id) => {
+                let share = args.iter().any(|a| a == "--share");
+                let edit = args.iter().any(|a| a == "--edit");
+                restore_snapshot(id, share, edit);
+            }
+            None => {
+                eprintln!("Missing snapshot id");
+                print_usage();
+            }
+        },
+        "export" => match args.get(2) {
+            Some(id) => {
+                let file = args.get(3).map_or_else(
+                    || PathBuf::from(format!("{id}.nvim-web-snapshot.json")),
+                    PathBuf::from,
+                );
+                export_snapshot(id, &file);
+            }
+            None => {
+                eprintln!("Missing snapshot id");
+                print_usage();
+            }
+        },
+        "import" => match args.get(2) {
+            Some(file) => import_snapshot(std::path::Path::new(file)),
+            None => {
+                eprintln!("Missing archive file");
+                print_usage();
+            }
+        },
         "--help" | "-h" | "help" => print_usage(),
         "--version" | "-v" => println!("nvim-web {VERSION}"),
         _ => {
@@ -45,12 +79,19 @@ fn print_usage() {
     eprintln!();
     eprintln!("  \x1b[1mUSAGE:\x1b[0m");
     eprintln!("    nvim-web open [path]    Open project in browser (default: current dir)");
+    eprintln!("    nvim-web restore <id>   Restore a snapshot into a new session");
+    eprintln!("        --share             Also create a read-only share link");
+    eprintln!("        --edit              Make the share link editable");
+    eprintln!("    nvim-web export <id> [file]");
+    eprintln!("                            Save a snapshot as an archive file");
+    eprintln!("    nvim-web import <file>  Load a snapshot archive, printing its new id");
     eprintln!("    nvim-web --help         Show this help");
     eprintln!("    nvim-web --version      Show version");
     eprintln!();
     eprintln!("  \x1b[1mEXAMPLES:\x1b[0m");
     eprintln!("    nvim-web open           # Open current directory");
     eprintln!("    nvim-web open ~/code    # Open specific path");
+    eprintln!("    nvim-web restore snap_3f2c9a1e8b7d4c6f9e0a1b2c3d4e5f60 --share");
     eprintln!();
 }
 
@@ -129,6 +170,134 @@ fn open_project(path: &std::path::Path) {
     open_browser(url);
 }
 
+fn restore_snapshot(id: &str, share: bool, edit: bool) {
+    eprintln!();
+    eprintln!("  \x1b[1;96mnvim-web\x1b[0m restoring snapshot...");
+    eprintln!("  \x1b[2mSnapshot:\x1b[0m {id}");
+
+    let mut request = serde_json::json!({});
+    if share || edit {
+        request["share"] = serde_json::json!({ "read_only": !edit });
+    }
+
+    let client = reqwest::blocking::Client::new();
+    let api_url = format!("{DEFAULT_HOST}/api/snapshot/{id}/restore");
+    let response = match client.post(&api_url).json(&request).send() {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!();
+            eprintln!("  \x1b[1;31m[error]\x1b[0m Could not connect to nvim-web host");
+            eprintln!("  \x1b[2mDetails: {e}\x1b[0m");
+            return;
+        }
+    };
+
+    let status = response.status();
+    let data: serde_json::Value = response.json().unwrap_or_default();
+    if !status.is_success() {
+        let error = data["error"].as_str().unwrap_or("unknown error");
+        eprintln!();
+        eprintln!("  \x1b[1;31m[error]\x1b[0m API error: {status} - {error}");
+        return;
+    }
+
+    let Some(path) = data["url"].as_str() else {
+        eprintln!("  \x1b[1;31m[error]\x1b[0m Invalid response from API");
+        return;
+    };
+    let url = format!("{DEFAULT_HOST}{path}");
+
+    eprintln!(
+        "  \x1b[2mSession:\x1b[0m {}",
+        data["session_id"].as_str().unwrap_or("")
+    );
+    if let Some(share_path) = data["share"]["url"].as_str() {
+        let mode = if edit { "editable" } else { "read-only" };
+        eprintln!("  \x1b[2mShare ({mode}):\x1b[0m {DEFAULT_HOST}{share_path}");
+    }
+    eprintln!();
+    eprintln!("  \x1b[1;32m[success]\x1b[0m Opening in browser...");
+    eprintln!("  \x1b[4;96m{url}\x1b[0m");
+    eprintln!();
+
+    open_browser(&url);
+}
+
+fn export_snapshot(id: &str, file: &std::path::Path) {
+    let api_url = format!("{DEFAULT_HOST}/api/snapshot/{id}/export");
+    let response = match reqwest::blocking::get(&api_url) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("  \x1b[1;31m[error]\x1b[0m Could not connect to nvim-web host: {e}");
+            return;
+        }
+    };
+    if !response.status().is_success() {
+        eprintln!(
+            "  \x1b[1;31m[error]\x1b[0m API error: {} - {}",
+            response.status(),
+            response.text().unwrap_or_default()
+        );
+        return;
+    }
+
+    let written = response
+        .bytes()
+        .map_err(|e| e.to_string())
+        .and_then(|data| std::fs::write(file, data).map_err(|e| e.to_string()));
+    match written {
+        Ok(()) => eprintln!(
+            "  \x1b[1;32m[success]\x1b[0m Exported {id} to {}",
+            file.display()
+        ),
+        Err(e) => eprintln!(
+            "  \x1b[1;31m[error]\x1b[0m Could not write {}: {e}",
+            file.display()
+        ),
+    }
+}
+
+fn import_snapshot(file: &std::path::Path) {
+    let data = match std::fs::read(file) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!(
+                "  \x1b[1;31m[error]\x1b[0m Could not read {}: {e}",
+                file.display()
+            );
+            return;
+        }
+    };
+
+    let client = reqwest::blocking::Client::new();
+    let api_url = format!("{DEFAULT_HOST}/api/snapshots/import");
+    let response = match client
+        .post(&api_url)
+        .header("Content-Type", "application/json")
+        .body(data)
+        .send()
+    {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("  \x1b[1;31m[error]\x1b[0m Could not connect to nvim-web host: {e}");
+            return;
+        }
+    };
+
+    let status = response.status();
+    let data: serde_json::Value = response.json().unwrap_or_default();
+    match data["id"].as_str() {
+        Some(id) if status.is_success() => {
+            eprintln!("  \x1b[1;32m[success]\x1b[0m Imported snapshot {id}");
+            eprintln!("  Restore it with: \x1b[1mnvim-web restore {id}\x1b[0m");
+        }
+        _ => {
+            let error = data["error"].as_str().unwrap_or("unknown error");
+            eprintln!("  \x1b[1;31m[error]\x1b[0m API error: {status} - {error}");
+        }
+    }
+}
+
 fn open_browser(url: &str) {
     #[cfg(target_os = "macos")]
     {
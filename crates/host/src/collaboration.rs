@@ -3,19 +3,80 @@
 //! Tracks connected viewers per session and relays cursor events between
 //! the session owner and viewers for real-time cursor synchronization.
 //! Integrates with CRDTs for conflict-free collaborative editing.
+//!
+//! Every participant has a [`Role`]. Keystrokes only reach Neovim from the
+//! driver: the participant holding edit control, or any owner while nobody
+//! does. Editors ask for control and an owner grants or revokes it.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
 
-use std::collections::HashMap;
+use anyhow::{anyhow, bail};
 use std::sync::Arc;
 use tokio::sync::{broadcast, RwLock};
 
 use crate::crdt::{BufferCrdt, CrdtManager, CrdtSync, SyncMessage};
 
-/// Cursor position in the editor grid
-#[derive(Debug, Clone, Copy, serde::Serialize)]
-pub struct CursorPosition {
-    pub row: u32,
-    pub col: u32,
-    pub grid: u32,
+pub use nvim_web_protocol::datagram::CursorPosition;
+use nvim_web_protocol::schema::{Comment, Participant};
+
+/// Keystrokes kept per session for [`SessionViewers::input_log`]
+const INPUT_LOG_LEN: usize = 1000;
+
+/// What a participant may do in a session, in increasing order
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Sees the session and shares a cursor
+    Viewer,
+    /// Viewer that may also comment
+    Commenter,
+    /// May edit buffers and take driver control
+    Editor,
+    /// Drives by default and manages other participants
+    Owner,
+}
+
+impl Role {
+    pub fn can_comment(self) -> bool {
+        self >= Self::Commenter
+    }
+
+    pub fn can_edit(self) -> bool {
+        self >= Self::Editor
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Commenter => "commenter",
+            Self::Editor => "editor",
+            Self::Owner => "owner",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "viewer" => Ok(Self::Viewer),
+            "commenter" => Ok(Self::Commenter),
+            "editor" => Ok(Self::Editor),
+            "owner" => Ok(Self::Owner),
+            other => Err(anyhow!("Unknown role: {other}")),
+        }
+    }
 }
 
 /// Information about a connected viewer
@@ -23,11 +84,23 @@ pub struct CursorPosition {
 pub struct ViewerInfo {
     pub id: String,
     pub name: Option<String>,
+    pub role: Role,
     pub color: String,
     pub cursor: Option<CursorPosition>,
     pub connected_at: u64,
 }
 
+impl From<&ViewerInfo> for Participant {
+    fn from(info: &ViewerInfo) -> Self {
+        Self {
+            id: info.id.clone(),
+            name: info.name.clone(),
+            role: info.role.as_str().to_string(),
+            color: info.color.clone(),
+        }
+    }
+}
+
 /// Collaboration event types
 #[derive(Debug, Clone)]
 pub enum CollabEvent {
@@ -35,6 +108,8 @@ pub enum CollabEvent {
     ViewerJoined(ViewerInfo),
     /// A viewer disconnected
     ViewerLeft(String),
+    /// A viewer changed their display name
+    ViewerUpdated(ViewerInfo),
     /// Cursor position update
     CursorMoved {
         viewer_id: String,
@@ -42,6 +117,12 @@ pub enum CollabEvent {
     },
     /// Owner cursor moved (broadcast to all viewers)
     OwnerCursorMoved(CursorPosition),
+    /// A participant's role changed
+    RoleChanged { viewer_id: String, role: Role },
+    /// A participant asked for driver control
+    ControlRequested { viewer_id: String },
+    /// Driver control moved; `None` hands it back to the owners
+    ControlChanged { driver: Option<String> },
     /// Buffer content changed (CRDT update)
     BufferChanged { buffer_id: u64, update: Vec<u8> },
     /// Full buffer sync for new viewer
@@ -53,6 +134,10 @@ pub enum CollabEvent {
         signal_type: SignalType,
         payload: String,
     },
+    /// A review comment was added or changed
+    CommentChanged(Comment),
+    /// A comment and its replies were deleted
+    CommentDeleted(String),
     /// P2P chat message (relayed through server for offline peers)
     ChatMessage {
         from: String,
@@ -70,6 +155,38 @@ pub enum SignalType {
     IceCandidate,
 }
 
+impl SignalType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Offer => "offer",
+            Self::Answer => "answer",
+            Self::IceCandidate => "ice_candidate",
+        }
+    }
+}
+
+impl FromStr for SignalType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "offer" => Ok(Self::Offer),
+            "answer" => Ok(Self::Answer),
+            "ice_candidate" => Ok(Self::IceCandidate),
+            other => Err(anyhow!("Unknown signal type: {other}")),
+        }
+    }
+}
+
+/// Keystrokes sent to Neovim by a participant
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct InputRecord {
+    pub viewer_id: String,
+    pub keys: String,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+}
+
 /// Viewer registry for a single session
 #[derive(Debug)]
 pub struct SessionViewers {
@@ -77,6 +194,12 @@ pub struct SessionViewers {
     event_tx: broadcast::Sender<CollabEvent>,
     /// CRDT manager for buffer documents
     crdt_manager: CrdtManager,
+    /// Participant holding edit control; owners drive while unset
+    driver: Option<String>,
+    /// Participants waiting for edit control, oldest first
+    control_requests: Vec<String>,
+    /// Recent keystrokes, oldest first
+    input_log: VecDeque<InputRecord>,
 }
 
 impl SessionViewers {
@@ -86,15 +209,19 @@ impl SessionViewers {
             viewers: HashMap::new(),
             event_tx,
             crdt_manager: CrdtManager::new(session_id.to_string()),
+            driver: None,
+            control_requests: Vec::new(),
+            input_log: VecDeque::new(),
         }
     }
 
     /// Add a new viewer to the session
-    pub fn add_viewer(&mut self, id: String, name: Option<String>) -> ViewerInfo {
+    pub fn add_viewer(&mut self, id: String, name: Option<String>, role: Role) -> ViewerInfo {
         let color = Self::assign_color(self.viewers.len());
         let info = ViewerInfo {
             id: id.clone(),
             name,
+            role,
             color,
             cursor: None,
             connected_at: std::time::SystemTime::now()
@@ -110,10 +237,30 @@ impl SessionViewers {
     /// Remove a viewer from the session
     pub fn remove_viewer(&mut self, id: &str) {
         if self.viewers.remove(id).is_some() {
+            self.control_requests.retain(|r| r != id);
+            self.release_control(id);
             let _ = self.event_tx.send(CollabEvent::ViewerLeft(id.to_string()));
         }
     }
 
+    /// Change a viewer's display name
+    pub fn set_name(&mut self, id: &str, name: Option<String>) -> anyhow::Result<()> {
+        let viewer = self
+            .viewers
+            .get_mut(id)
+            .ok_or_else(|| anyhow!("Unknown participant: {id}"))?;
+        viewer.name = name;
+        let _ = self
+            .event_tx
+            .send(CollabEvent::ViewerUpdated(viewer.clone()));
+        Ok(())
+    }
+
+    /// Get a viewer by id
+    pub fn viewer(&self, id: &str) -> Option<&ViewerInfo> {
+        self.viewers.get(id)
+    }
+
     /// Update a viewer's cursor position
     pub fn update_cursor(&mut self, viewer_id: &str, position: CursorPosition) {
         if let Some(viewer) = self.viewers.get_mut(viewer_id) {
@@ -145,6 +292,115 @@ impl SessionViewers {
         self.event_tx.subscribe()
     }
 
+    // === Roles and Edit Control ===
+
+    /// Role of a participant (if connected)
+    pub fn role(&self, id: &str) -> Option<Role> {
+        self.viewers.get(id).map(|v| v.role)
+    }
+
+    /// Change a participant's role, dropping control they may no longer hold
+    pub fn set_role(&mut self, id: &str, role: Role) -> anyhow::Result<()> {
+        let viewer = self
+            .viewers
+            .get_mut(id)
+            .ok_or_else(|| anyhow!("Unknown participant: {id}"))?;
+        viewer.role = role;
+        let _ = self.event_tx.send(CollabEvent::RoleChanged {
+            viewer_id: id.to_string(),
+            role,
+        });
+        if !role.can_edit() {
+            self.control_requests.retain(|r| r != id);
+            self.release_control(id);
+        }
+        Ok(())
+    }
+
+    /// Participant holding edit control, `None` if the owners drive
+    pub fn driver(&self) -> Option<&str> {
+        self.driver.as_deref()
+    }
+
+    /// Participants waiting for edit control, oldest first
+    pub fn control_requests(&self) -> &[String] {
+        &self.control_requests
+    }
+
+    /// Whether a participant's keystrokes reach Neovim
+    pub fn can_drive(&self, id: &str) -> bool {
+        match &self.driver {
+            Some(driver) => driver == id,
+            None => self.role(id) == Some(Role::Owner),
+        }
+    }
+
+    /// Ask the owners for edit control
+    pub fn request_control(&mut self, id: &str) -> anyhow::Result<()> {
+        if !self.role(id).is_some_and(Role::can_edit) {
+            bail!("Only editors can take control");
+        }
+        if self.can_drive(id) || self.control_requests.iter().any(|r| r == id) {
+            return Ok(());
+        }
+        self.control_requests.push(id.to_string());
+        let _ = self.event_tx.send(CollabEvent::ControlRequested {
+            viewer_id: id.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Hand edit control to a participant
+    pub fn grant_control(&mut self, id: &str) -> anyhow::Result<()> {
+        if !self.role(id).is_some_and(Role::can_edit) {
+            bail!("Only editors can take control");
+        }
+        self.control_requests.retain(|r| r != id);
+        self.set_driver(Some(id.to_string()));
+        Ok(())
+    }
+
+    /// Hand edit control back to the owners
+    pub fn revoke_control(&mut self) {
+        self.set_driver(None);
+    }
+
+    /// Give up edit control if `id` holds it, returns whether it did
+    pub fn release_control(&mut self, id: &str) -> bool {
+        if self.driver.as_deref() != Some(id) {
+            return false;
+        }
+        self.set_driver(None);
+        true
+    }
+
+    fn set_driver(&mut self, driver: Option<String>) {
+        if self.driver != driver {
+            self.driver.clone_from(&driver);
+            let _ = self.event_tx.send(CollabEvent::ControlChanged { driver });
+        }
+    }
+
+    /// Remember who sent keystrokes to Neovim
+    pub fn record_input(&mut self, id: &str, keys: &str) {
+        if self.input_log.len() == INPUT_LOG_LEN {
+            self.input_log.pop_front();
+        }
+        self.input_log.push_back(InputRecord {
+            viewer_id: id.to_string(),
+            keys: keys.to_string(),
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+        });
+    }
+
+    /// Recent keystrokes, oldest first
+    pub fn input_log(&self) -> impl Iterator<Item = &InputRecord> {
+        self.input_log.iter()
+    }
+
     /// Assign a color based on viewer index
     fn assign_color(index: usize) -> String {
         const COLORS: &[&str] = &[
@@ -167,6 +423,21 @@ impl SessionViewers {
         self.crdt_manager.get_or_create(buffer_id)
     }
 
+    /// Get a buffer's CRDT document (if exists)
+    pub fn buffer(&self, buffer_id: u64) -> Option<&BufferCrdt> {
+        self.crdt_manager.get(buffer_id)
+    }
+
+    /// Merge an update made elsewhere and broadcast it to viewers
+    pub fn apply_buffer_update(&mut self, buffer_id: u64, update: Vec<u8>) -> anyhow::Result<()> {
+        let crdt = self.crdt_manager.get_or_create(buffer_id);
+        crdt.apply_update(&update)?;
+        let _ = self
+            .event_tx
+            .send(CollabEvent::BufferChanged { buffer_id, update });
+        Ok(())
+    }
+
     /// Apply a Neovim buffer change and broadcast to viewers
     pub fn apply_buffer_change(
         &mut self,
@@ -238,6 +509,20 @@ impl SessionViewers {
         });
     }
 
+    // === Comments ===
+
+    /// Tell every participant about a new or changed comment
+    pub fn comment_changed(&self, comment: Comment) {
+        let _ = self.event_tx.send(CollabEvent::CommentChanged(comment));
+    }
+
+    /// Tell every participant a comment thread was deleted
+    pub fn comment_deleted(&self, id: &str) {
+        let _ = self
+            .event_tx
+            .send(CollabEvent::CommentDeleted(id.to_string()));
+    }
+
     /// Get all peer IDs for mesh connection
     pub fn get_peer_ids(&self) -> Vec<String> {
         self.viewers.keys().cloned().collect()
@@ -302,8 +587,8 @@ mod tests {
     #[test]
     fn add_and_list_viewers() {
         let mut session = SessionViewers::new("test");
-        session.add_viewer("v1".to_string(), Some("Alice".to_string()));
-        session.add_viewer("v2".to_string(), None);
+        session.add_viewer("v1".to_string(), Some("Alice".to_string()), Role::Owner);
+        session.add_viewer("v2".to_string(), None, Role::Viewer);
 
         let viewers = session.list_viewers();
         assert_eq!(viewers.len(), 2);
@@ -312,7 +597,7 @@ mod tests {
     #[test]
     fn remove_viewer() {
         let mut session = SessionViewers::new("test");
-        session.add_viewer("v1".to_string(), None);
+        session.add_viewer("v1".to_string(), None, Role::Viewer);
         assert_eq!(session.count(), 1);
 
         session.remove_viewer("v1");
@@ -322,12 +607,75 @@ mod tests {
     #[test]
     fn color_assignment() {
         let mut session = SessionViewers::new("test");
-        let v1 = session.add_viewer("v1".to_string(), None);
-        let v2 = session.add_viewer("v2".to_string(), None);
+        let v1 = session.add_viewer("v1".to_string(), None, Role::Viewer);
+        let v2 = session.add_viewer("v2".to_string(), None, Role::Viewer);
 
         assert_ne!(v1.color, v2.color);
     }
 
+    #[test]
+    fn owners_drive_until_control_is_granted() {
+        let mut session = SessionViewers::new("test");
+        session.add_viewer("owner".to_string(), None, Role::Owner);
+        session.add_viewer("ed".to_string(), None, Role::Editor);
+        session.add_viewer("view".to_string(), None, Role::Viewer);
+        assert!(session.can_drive("owner"));
+        assert!(!session.can_drive("ed"));
+
+        assert!(session.request_control("view").is_err());
+        session.request_control("ed").unwrap();
+        assert_eq!(session.control_requests(), ["ed"]);
+
+        session.grant_control("ed").unwrap();
+        assert!(session.control_requests().is_empty());
+        assert!(session.can_drive("ed"));
+        assert!(!session.can_drive("owner"));
+
+        // Demoting the driver hands control back
+        session.set_role("ed", Role::Commenter).unwrap();
+        assert_eq!(session.driver(), None);
+        assert!(session.can_drive("owner"));
+    }
+
+    #[test]
+    fn driver_leaving_releases_control() {
+        let mut session = SessionViewers::new("test");
+        let mut events = session.subscribe();
+        session.add_viewer("ed".to_string(), None, Role::Editor);
+        session.grant_control("ed").unwrap();
+        session.remove_viewer("ed");
+        assert_eq!(session.driver(), None);
+
+        let mut changes = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let CollabEvent::ControlChanged { driver } = event {
+                changes.push(driver);
+            }
+        }
+        assert_eq!(changes, [Some("ed".to_string()), None]);
+    }
+
+    #[test]
+    fn input_log_is_bounded() {
+        let mut session = SessionViewers::new("test");
+        for i in 0..=INPUT_LOG_LEN {
+            session.record_input("v1", &i.to_string());
+        }
+        let log: Vec<_> = session.input_log().collect();
+        assert_eq!(log.len(), INPUT_LOG_LEN);
+        assert_eq!(log[0].keys, "1");
+        assert_eq!(log[0].viewer_id, "v1");
+    }
+
+    #[test]
+    fn role_round_trips_through_strings() {
+        for role in [Role::Viewer, Role::Commenter, Role::Editor, Role::Owner] {
+            assert_eq!(role.as_str().parse::<Role>().unwrap(), role);
+        }
+        assert!("admin".parse::<Role>().is_err());
+        assert!(Role::Editor.can_comment() && !Role::Commenter.can_edit());
+    }
+
     #[test]
     fn test_buffer_crdt_integration() {
         let mut session = SessionViewers::new("test");
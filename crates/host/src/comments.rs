@@ -0,0 +1,757 @@
+//! Threaded review comments for shared sessions
+//!
+//! Comments are stored per session in SQLite so they outlive the
+//! connections that wrote them. Replies hang off a thread's root, and only
+//! the root may be anchored to a line range of a file.
+//!
+//! While the file is open in Neovim the range is also anchored in the
+//! buffer's shared CRDT document, so it follows edits made by anyone in the
+//! session. The stored line numbers are brought up to date from those
+//! anchors whenever the comments are listed or changed, and unresolved
+//! threads are shown as virtual text at the end of their first line.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, bail, Context, Result};
+use nvim_web_protocol::schema::{Comment, CommentTarget};
+use rmpv::Value;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::collaboration::SharedCollaborationRegistry;
+use crate::session::AsyncSession;
+
+/// Longest comment accepted, in characters
+const MAX_COMMENT_LEN: usize = 4096;
+/// Longest comment text shown in Neovim, in characters
+const VIRT_TEXT_LEN: usize = 80;
+
+/// Encoded CRDT positions of an anchored line range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anchor {
+    pub start: Vec<u8>,
+    pub end: Vec<u8>,
+}
+
+/// SQLite store for comments
+///
+/// Location: ~/.config/nvim-web/comments.db
+pub struct CommentStore {
+    conn: Connection,
+}
+
+impl CommentStore {
+    /// Create or open the default comments database
+    pub fn new() -> Result<Self> {
+        let config_dir = dirs::config_dir().context("Could not determine config directory")?;
+        Self::open(&config_dir.join("nvim-web").join("comments.db"))
+    }
+
+    /// Create or open a comments database at `db_path`
+    pub fn open(db_path: &Path) -> Result<Self> {
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent).context("Failed to create config directory")?;
+        }
+        let conn = Connection::open(db_path).context("Failed to open comments database")?;
+        Self::with_connection(conn)
+    }
+
+    /// In-memory store (nothing survives the process)
+    pub fn in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS comments (
+                id TEXT PRIMARY KEY,
+                session_id TEXT NOT NULL,
+                parent_id TEXT,
+                author_id TEXT NOT NULL,
+                author TEXT NOT NULL,
+                body TEXT NOT NULL,
+                path TEXT,
+                start_line INTEGER NOT NULL DEFAULT 0,
+                end_line INTEGER NOT NULL DEFAULT 0,
+                anchor_start BLOB,
+                anchor_end BLOB,
+                resolved INTEGER NOT NULL DEFAULT 0,
+                created_at INTEGER NOT NULL,
+                owner TEXT
+            );
+            CREATE INDEX IF NOT EXISTS comments_session ON comments (session_id);",
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// Store a comment on a session owned by `owner`
+    pub fn insert(
+        &self,
+        session_id: &str,
+        owner: Option<&str>,
+        comment: &Comment,
+        anchor: Option<&Anchor>,
+    ) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO comments
+                (id, session_id, parent_id, author_id, author, body, path, start_line,
+                 end_line, anchor_start, anchor_end, resolved, created_at, owner)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                comment.id,
+                session_id,
+                comment.parent,
+                comment.author_id,
+                comment.author,
+                comment.body,
+                comment.path,
+                comment.start_line,
+                comment.end_line,
+                anchor.map(|a| &a.start),
+                anchor.map(|a| &a.end),
+                comment.resolved,
+                i64::try_from(comment.created_at).unwrap_or(i64::MAX),
+                owner,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get(&self, session_id: &str, id: &str) -> Result<Option<Comment>> {
+        self.conn
+            .query_row(
+                &format!("SELECT {COLUMNS} FROM comments WHERE session_id = ? AND id = ?"),
+                [session_id, id],
+                |row| comment_from_row(row).map(|(comment, _)| comment),
+            )
+            .optional()
+            .map_err(Into::into)
+    }
+
+    /// All comments of a session with their anchors, oldest first
+    pub fn list(&self, session_id: &str) -> Result<Vec<(Comment, Option<Anchor>)>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {COLUMNS} FROM comments WHERE session_id = ? ORDER BY created_at, rowid"
+        ))?;
+        let comments = stmt
+            .query_map([session_id], comment_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(comments)
+    }
+
+    /// Session owners recorded on the comments of a session
+    pub fn owners(&self, session_id: &str) -> Result<Vec<Option<String>>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT owner FROM comments WHERE session_id = ?")?;
+        let owners = stmt
+            .query_map([session_id], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(owners)
+    }
+
+    pub fn set_body(&self, session_id: &str, id: &str, body: &str) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE comments SET body = ? WHERE session_id = ? AND id = ?",
+            params![body, session_id, id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    pub fn set_resolved(&self, session_id: &str, id: &str, resolved: bool) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE comments SET resolved = ? WHERE session_id = ? AND id = ?",
+            params![resolved, session_id, id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Record where an anchored comment is now, replacing its anchor if given
+    pub fn set_lines(
+        &self,
+        session_id: &str,
+        id: &str,
+        (start_line, end_line): (u32, u32),
+        anchor: Option<&Anchor>,
+    ) -> Result<()> {
+        self.conn.execute(
+            "UPDATE comments SET start_line = ?, end_line = ?,
+                anchor_start = COALESCE(?, anchor_start), anchor_end = COALESCE(?, anchor_end)
+             WHERE session_id = ? AND id = ?",
+            params![
+                start_line,
+                end_line,
+                anchor.map(|a| &a.start),
+                anchor.map(|a| &a.end),
+                session_id,
+                id,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Delete a comment and its replies
+    pub fn delete(&self, session_id: &str, id: &str) -> Result<bool> {
+        let deleted = self.conn.execute(
+            "DELETE FROM comments WHERE session_id = ?1 AND (id = ?2 OR parent_id = ?2)",
+            [session_id, id],
+        )?;
+        Ok(deleted > 0)
+    }
+}
+
+const COLUMNS: &str = "id, parent_id, author_id, author, body, path, start_line, end_line, \
+                       resolved, created_at, anchor_start, anchor_end";
+
+fn comment_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<(Comment, Option<Anchor>)> {
+    let comment = Comment {
+        id: row.get(0)?,
+        parent: row.get(1)?,
+        author_id: row.get(2)?,
+        author: row.get(3)?,
+        body: row.get(4)?,
+        path: row.get(5)?,
+        start_line: row.get(6)?,
+        end_line: row.get(7)?,
+        resolved: row.get(8)?,
+        created_at: u64::try_from(row.get::<_, i64>(9)?).unwrap_or(0),
+    };
+    let anchor = match (row.get(10)?, row.get(11)?) {
+        (Some(start), Some(end)) => Some(Anchor { start, end }),
+        _ => None,
+    };
+    Ok((comment, anchor))
+}
+
+// Process-wide store used by the connection and API handlers
+lazy_static::lazy_static! {
+    static ref STORE: Mutex<CommentStore> = Mutex::new(open_default_store());
+}
+
+fn open_default_store() -> CommentStore {
+    let store = if cfg!(test) {
+        CommentStore::in_memory()
+    } else {
+        CommentStore::new().or_else(|e| {
+            tracing::warn!(error = %e, "Comments will not persist across restarts");
+            CommentStore::in_memory()
+        })
+    };
+    store.expect("Failed to initialize comment store")
+}
+
+fn with_store<T>(f: impl FnOnce(&CommentStore) -> Result<T>) -> Result<T> {
+    let store = STORE.lock().unwrap_or_else(PoisonError::into_inner);
+    f(&store)
+}
+
+/// The cursor line, or the visual selection, of the current file buffer
+///
+/// Returns `[buffer, path, first, last]`, nil if the buffer is not a file.
+const CURSOR_LUA: &str = r"
+local buf = vim.api.nvim_get_current_buf()
+local name = vim.api.nvim_buf_get_name(buf)
+if name == '' or vim.bo[buf].buftype ~= '' then
+  return nil
+end
+local first, last = vim.fn.line('.'), vim.fn.line('.')
+local mode = vim.fn.mode()
+if mode == 'v' or mode == 'V' or mode == '\22' then
+  first = vim.fn.line('v')
+  if first > last then
+    first, last = last, first
+  end
+end
+return { buf, vim.fn.fnamemodify(name, ':.'), first, last }
+";
+
+/// Loaded buffers showing any of the given paths, as `[[path, buffer], ...]`
+const BUFFERS_LUA: &str = r"
+local wanted = {}
+for _, path in ipairs(...) do
+  wanted[path] = true
+end
+local found = {}
+for _, buf in ipairs(vim.api.nvim_list_bufs()) do
+  if vim.api.nvim_buf_is_loaded(buf) then
+    local path = vim.fn.fnamemodify(vim.api.nvim_buf_get_name(buf), ':.')
+    if wanted[path] then
+      found[#found + 1] = { path, buf }
+    end
+  end
+end
+return found
+";
+
+/// Show `[[path, line, text], ...]` as virtual text, also in buffers read later
+const RENDER_LUA: &str = r"
+local ns = vim.api.nvim_create_namespace('nvim_web_comments')
+_G.nvim_web_comments = ...
+
+local function render(buf)
+  if not vim.api.nvim_buf_is_loaded(buf) then
+    return
+  end
+  vim.api.nvim_buf_clear_namespace(buf, ns, 0, -1)
+  local path = vim.fn.fnamemodify(vim.api.nvim_buf_get_name(buf), ':.')
+  local count = vim.api.nvim_buf_line_count(buf)
+  for _, c in ipairs(_G.nvim_web_comments) do
+    if c[1] == path then
+      pcall(vim.api.nvim_buf_set_extmark, buf, ns, math.min(c[2], count) - 1, 0, {
+        virt_text = { { ' ' .. c[3], 'DiagnosticVirtualTextInfo' } },
+        virt_text_pos = 'eol',
+      })
+    end
+  end
+end
+
+for _, buf in ipairs(vim.api.nvim_list_bufs()) do
+  render(buf)
+end
+vim.api.nvim_create_autocmd('BufReadPost', {
+  group = vim.api.nvim_create_augroup('NvimWebComments', { clear = true }),
+  callback = function(ev)
+    render(ev.buf)
+  end,
+})
+";
+
+/// Comments of a session as last stored, for sessions not running here
+pub fn stored(session_id: &str) -> Result<Vec<Comment>> {
+    with_store(|store| store.list(session_id))
+        .map(|comments| comments.into_iter().map(|(comment, _)| comment).collect())
+}
+
+/// Owners a session had when its comments were written
+pub fn stored_owners(session_id: &str) -> Result<Vec<Option<String>>> {
+    with_store(|store| store.owners(session_id))
+}
+
+/// Comments of a session, with anchored line numbers brought up to date
+pub async fn list(
+    session: &AsyncSession,
+    registry: &SharedCollaborationRegistry,
+) -> Result<Vec<Comment>> {
+    let stored = with_store(|store| store.list(&session.id))?;
+    let paths: Vec<&str> = stored
+        .iter()
+        .filter_map(|(comment, _)| comment.path.as_deref())
+        .collect();
+    let buffers = if paths.is_empty() {
+        HashMap::new()
+    } else {
+        loaded_buffers(session, &paths).await?
+    };
+
+    let mut comments = Vec::with_capacity(stored.len());
+    for (mut comment, anchor) in stored {
+        let buffer = comment.path.as_ref().and_then(|path| buffers.get(path));
+        if let Some(&buffer_id) = buffer {
+            let resolved = {
+                let reg = registry.read().await;
+                reg.get(&session.id)
+                    .and_then(|viewers| viewers.buffer(buffer_id))
+                    .zip(anchor.as_ref())
+                    .and_then(|(doc, anchor)| doc.resolve_lines(&anchor.start, &anchor.end))
+            };
+            let (lines, anchor) = match resolved {
+                Some(lines) => (lines, None),
+                // Anchored in a document that is gone; start over where it was last seen
+                None => {
+                    let lines = (comment.start_line, comment.end_line);
+                    (
+                        lines,
+                        anchor_lines(session, registry, buffer_id, lines).await,
+                    )
+                }
+            };
+            if lines != (comment.start_line, comment.end_line) || anchor.is_some() {
+                (comment.start_line, comment.end_line) = lines;
+                with_store(|store| {
+                    store.set_lines(&session.id, &comment.id, lines, anchor.as_ref())
+                })?;
+            }
+        }
+        comments.push(comment);
+    }
+    Ok(comments)
+}
+
+/// Add a comment, or a reply when `parent` is set
+pub async fn add(
+    session: &AsyncSession,
+    registry: &SharedCollaborationRegistry,
+    author_id: &str,
+    body: &str,
+    parent: Option<&str>,
+    target: CommentTarget,
+) -> Result<Comment> {
+    let body = validate(body)?;
+    let author = {
+        let reg = registry.read().await;
+        let viewers = reg
+            .get(&session.id)
+            .ok_or_else(|| anyhow!("Session not found"))?;
+        let viewer = viewers
+            .viewer(author_id)
+            .ok_or_else(|| anyhow!("Unknown participant: {author_id}"))?;
+        viewer
+            .name
+            .clone()
+            .unwrap_or_else(|| author_id.chars().take(8).collect())
+    };
+    let mut comment = Comment {
+        id: uuid::Uuid::new_v4().to_string(),
+        author_id: author_id.to_string(),
+        author,
+        body,
+        created_at: now_millis(),
+        ..Comment::default()
+    };
+
+    let mut anchor = None;
+    if let Some(parent) = parent {
+        let parent = with_store(|store| store.get(&session.id, parent))?
+            .ok_or_else(|| anyhow!("Unknown comment: {parent}"))?;
+        // Threads are flat: replies to a reply belong to its thread
+        comment.parent = Some(parent.parent.unwrap_or(parent.id));
+    } else {
+        let (buffer, path, lines) = match target {
+            CommentTarget::General => (None, None, (0, 0)),
+            CommentTarget::Cursor => {
+                let (buffer, path, lines) = cursor(session).await?;
+                (Some(buffer), Some(path), lines)
+            }
+            CommentTarget::Lines {
+                path,
+                start_line,
+                end_line,
+            } => {
+                if start_line == 0 || end_line < start_line {
+                    bail!("Invalid line range {start_line}-{end_line}");
+                }
+                let buffer = loaded_buffers(session, &[path.as_str()])
+                    .await?
+                    .remove(&path);
+                (buffer, Some(path), (start_line, end_line))
+            }
+        };
+        if let Some(buffer_id) = buffer {
+            anchor = anchor_lines(session, registry, buffer_id, lines).await;
+        }
+        comment.path = path;
+        (comment.start_line, comment.end_line) = lines;
+    }
+
+    with_store(|store| {
+        store.insert(
+            &session.id,
+            session.acl.owner.as_deref(),
+            &comment,
+            anchor.as_ref(),
+        )
+    })?;
+    changed(session, registry, &comment).await;
+    Ok(comment)
+}
+
+/// Change the text of a comment; only its author may
+pub async fn edit(
+    session: &AsyncSession,
+    registry: &SharedCollaborationRegistry,
+    author_id: &str,
+    id: &str,
+    body: &str,
+) -> Result<Comment> {
+    let body = validate(body)?;
+    let comment = existing(&session.id, id)?;
+    if comment.author_id != author_id {
+        bail!("Only the author can edit a comment");
+    }
+    with_store(|store| store.set_body(&session.id, id, &body))?;
+    let comment = Comment { body, ..comment };
+    changed(session, registry, &comment).await;
+    Ok(comment)
+}
+
+/// Mark a thread as resolved or reopen it
+pub async fn resolve(
+    session: &AsyncSession,
+    registry: &SharedCollaborationRegistry,
+    id: &str,
+    resolved: bool,
+) -> Result<Comment> {
+    let comment = existing(&session.id, id)?;
+    with_store(|store| store.set_resolved(&session.id, id, resolved))?;
+    let comment = Comment {
+        resolved,
+        ..comment
+    };
+    changed(session, registry, &comment).await;
+    Ok(comment)
+}
+
+/// Delete a comment and its replies; `may_delete_any` is set for owners
+pub async fn delete(
+    session: &AsyncSession,
+    registry: &SharedCollaborationRegistry,
+    author_id: &str,
+    id: &str,
+    may_delete_any: bool,
+) -> Result<()> {
+    let comment = existing(&session.id, id)?;
+    if !may_delete_any && comment.author_id != author_id {
+        bail!("Only the author or an owner can delete a comment");
+    }
+    with_store(|store| store.delete(&session.id, id))?;
+    if let Some(viewers) = registry.read().await.get(&session.id) {
+        viewers.comment_deleted(id);
+    }
+    render(session, registry).await;
+    Ok(())
+}
+
+fn validate(body: &str) -> Result<String> {
+    let body = body.trim();
+    if body.is_empty() || body.chars().count() > MAX_COMMENT_LEN {
+        bail!("Comments must be 1 to {MAX_COMMENT_LEN} characters");
+    }
+    Ok(body.to_string())
+}
+
+fn existing(session_id: &str, id: &str) -> Result<Comment> {
+    with_store(|store| store.get(session_id, id))?.ok_or_else(|| anyhow!("Unknown comment: {id}"))
+}
+
+/// Tell the participants and refresh the comments shown in Neovim
+async fn changed(
+    session: &AsyncSession,
+    registry: &SharedCollaborationRegistry,
+    comment: &Comment,
+) {
+    if let Some(viewers) = registry.read().await.get(&session.id) {
+        viewers.comment_changed(comment.clone());
+    }
+    render(session, registry).await;
+}
+
+/// Show every unresolved anchored thread in Neovim
+pub async fn render(session: &AsyncSession, registry: &SharedCollaborationRegistry) {
+    let comments = match list(session, registry).await {
+        Ok(comments) => comments,
+        Err(e) => {
+            tracing::warn!(session_id = %session.id, error = %e, "Failed to list comments");
+            return;
+        }
+    };
+    let mut replies: HashMap<&str, usize> = HashMap::new();
+    for parent in comments.iter().filter_map(|c| c.parent.as_deref()) {
+        *replies.entry(parent).or_default() += 1;
+    }
+    let marks = comments
+        .iter()
+        .filter(|c| !c.resolved)
+        .filter_map(|c| Some((c, c.path.as_deref()?)))
+        .map(|(c, path)| {
+            Value::Array(vec![
+                path.into(),
+                c.start_line.into(),
+                virt_text(c, replies.get(c.id.as_str()).copied().unwrap_or(0)).into(),
+            ])
+        })
+        .collect();
+    if let Err(e) = session
+        .rpc_call(
+            "nvim_exec_lua",
+            vec![
+                Value::from(RENDER_LUA),
+                Value::Array(vec![Value::Array(marks)]),
+            ],
+        )
+        .await
+    {
+        tracing::debug!(session_id = %session.id, error = %e, "Failed to show comments in Neovim");
+    }
+}
+
+/// `author: first line of the body (+N replies)`
+fn virt_text(comment: &Comment, replies: usize) -> String {
+    let first_line = comment.body.lines().next().unwrap_or_default();
+    let mut text: String = first_line.chars().take(VIRT_TEXT_LEN).collect();
+    if text.len() < comment.body.len() {
+        text.push('…');
+    }
+    let mut text = format!("{}: {text}", comment.author);
+    match replies {
+        0 => {}
+        1 => text.push_str(" (+1 reply)"),
+        n => text.push_str(&format!(" (+{n} replies)")),
+    }
+    text
+}
+
+/// Anchor a line range in a buffer's shared document
+async fn anchor_lines(
+    session: &AsyncSession,
+    registry: &SharedCollaborationRegistry,
+    buffer_id: u64,
+    (start_line, end_line): (u32, u32),
+) -> Option<Anchor> {
+    if let Err(e) = session.crdt.track(buffer_id).await {
+        tracing::debug!(buffer_id, error = %e, "Cannot anchor comment");
+        return None;
+    }
+    let reg = registry.read().await;
+    let doc = reg.get(&session.id)?.buffer(buffer_id)?;
+    let (start, end) = doc.anchor_lines(start_line, end_line)?;
+    Some(Anchor { start, end })
+}
+
+async fn cursor(session: &AsyncSession) -> Result<(u64, String, (u32, u32))> {
+    let value = session
+        .rpc_call(
+            "nvim_exec_lua",
+            vec![Value::from(CURSOR_LUA), Value::Array(vec![])],
+        )
+        .await?;
+    let items = value
+        .as_array()
+        .ok_or_else(|| anyhow!("The current buffer is not a file"))?;
+    let int = |i: usize| items.get(i).and_then(Value::as_u64);
+    let line = |i: usize| int(i).and_then(|n| u32::try_from(n).ok());
+    match (
+        int(0),
+        items.get(1).and_then(Value::as_str),
+        line(2),
+        line(3),
+    ) {
+        (Some(buffer), Some(path), Some(first), Some(last)) => {
+            Ok((buffer, path.to_string(), (first, last)))
+        }
+        _ => bail!("Unexpected cursor position from Neovim"),
+    }
+}
+
+/// Loaded buffers showing `paths`, by path
+async fn loaded_buffers(session: &AsyncSession, paths: &[&str]) -> Result<HashMap<String, u64>> {
+    let paths = Value::Array(paths.iter().map(|&path| path.into()).collect());
+    let value = session
+        .rpc_call(
+            "nvim_exec_lua",
+            vec![Value::from(BUFFERS_LUA), Value::Array(vec![paths])],
+        )
+        .await?;
+    Ok(value
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|pair| {
+            let pair = pair.as_array()?;
+            Some((pair.first()?.as_str()?.to_string(), pair.get(1)?.as_u64()?))
+        })
+        .collect())
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comment(id: &str, parent: Option<&str>) -> Comment {
+        Comment {
+            id: id.to_string(),
+            parent: parent.map(String::from),
+            author_id: "p1".to_string(),
+            author: "Ada".to_string(),
+            body: format!("comment {id}"),
+            path: parent.is_none().then(|| "src/main.rs".to_string()),
+            start_line: 3,
+            end_line: 4,
+            created_at: 1_700_000_000_000,
+            ..Comment::default()
+        }
+    }
+
+    #[test]
+    fn comments_persist_per_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("comments.db");
+        let anchor = Anchor {
+            start: vec![1, 2],
+            end: vec![3],
+        };
+        {
+            let store = CommentStore::open(&db_path).unwrap();
+            store
+                .insert("s1", Some("alice"), &comment("c1", None), Some(&anchor))
+                .unwrap();
+            store
+                .insert("s1", Some("alice"), &comment("c2", Some("c1")), None)
+                .unwrap();
+            store
+                .insert("s2", Some("alice"), &comment("c3", None), None)
+                .unwrap();
+        }
+
+        let store = CommentStore::open(&db_path).unwrap();
+        let listed = store.list("s1").unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0], (comment("c1", None), Some(anchor.clone())));
+        assert_eq!(listed[1], (comment("c2", Some("c1")), None));
+        assert!(store.get("s2", "c1").unwrap().is_none());
+        assert_eq!(store.owners("s1").unwrap(), [Some("alice".to_string())]);
+        assert!(store.owners("s3").unwrap().is_empty());
+
+        assert!(store.set_body("s1", "c1", "edited").unwrap());
+        assert!(store.set_resolved("s1", "c1", true).unwrap());
+        store.set_lines("s1", "c1", (7, 8), None).unwrap();
+        let (edited, kept) = store.list("s1").unwrap().remove(0);
+        assert_eq!(edited.body, "edited");
+        assert!(edited.resolved);
+        assert_eq!((edited.start_line, edited.end_line), (7, 8));
+        assert_eq!(kept, Some(anchor));
+    }
+
+    #[test]
+    fn deleting_a_thread_deletes_its_replies() {
+        let store = CommentStore::in_memory().unwrap();
+        store
+            .insert("s1", Some("alice"), &comment("c1", None), None)
+            .unwrap();
+        store
+            .insert("s1", Some("alice"), &comment("c2", Some("c1")), None)
+            .unwrap();
+        store
+            .insert("s1", Some("alice"), &comment("c3", None), None)
+            .unwrap();
+
+        assert!(!store.delete("s2", "c1").unwrap());
+        assert!(store.delete("s1", "c1").unwrap());
+        let left: Vec<String> = store
+            .list("s1")
+            .unwrap()
+            .into_iter()
+            .map(|(c, _)| c.id)
+            .collect();
+        assert_eq!(left, ["c3"]);
+    }
+
+    #[test]
+    fn virtual_text_is_one_short_line() {
+        let mut c = comment("c1", None);
+        assert_eq!(virt_text(&c, 0), "Ada: comment c1");
+        c.body = format!("{}\nmore", "x".repeat(100));
+        let text = virt_text(&c, 2);
+        assert!(text.ends_with("… (+2 replies)"));
+        assert_eq!(text.chars().count(), "Ada: ".len() + VIRT_TEXT_LEN + 1 + 13);
+    }
+}
@@ -2,8 +2,13 @@
 //!
 //! Reads config from ~/.config/nvim-web/config.toml
 
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::launch::LaunchProfiles;
+use crate::oidc::AuthConfig;
+use crate::pipe::ToolRegistry;
 
 /// Server configuration
 #[derive(Debug, Clone)]
@@ -35,6 +40,10 @@ impl Default for ServerConfig {
 pub struct SessionConfig {
     pub timeout_secs: u64,
     pub max_sessions: usize,
+    /// Random bytes in share link and project open tokens
+    pub share_token_bytes: usize,
+    /// Days a session can be resumed after a host restart, 0 to not keep them
+    pub resume_days: u64,
 }
 
 impl Default for SessionConfig {
@@ -42,6 +51,8 @@ impl Default for SessionConfig {
         Self {
             timeout_secs: 300,
             max_sessions: 10,
+            share_token_bytes: crate::auth::DEFAULT_SHARE_TOKEN_BYTES,
+            resume_days: 7,
         }
     }
 }
@@ -64,6 +75,66 @@ impl Default for RateLimitConfig {
     }
 }
 
+/// Audit log configuration (see [`crate::audit`])
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    pub enabled: bool,
+    /// JSON lines file, ~/.config/nvim-web/audit.jsonl by default
+    pub path: Option<String>,
+    /// Also send events to syslog: udp://host:port or unix:///dev/log
+    pub syslog: Option<String>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: None,
+            syslog: None,
+        }
+    }
+}
+
+/// Integration features of the runtime plugin (see [`crate::runtime`])
+#[derive(Debug, Clone)]
+pub struct PluginConfig {
+    /// `:VfsStatus`
+    pub vfs_status: bool,
+    /// Change to the git root of the file entered
+    pub auto_cd: bool,
+    /// Report the working directory and git branch to the browser
+    pub cwd_sync: bool,
+    /// Report macro recording to the browser
+    pub recording: bool,
+    /// Browser clipboard for the `+` and `*` registers
+    pub clipboard: bool,
+}
+
+impl Default for PluginConfig {
+    fn default() -> Self {
+        Self {
+            vfs_status: true,
+            auto_cd: true,
+            cwd_sync: true,
+            recording: true,
+            clipboard: true,
+        }
+    }
+}
+
+impl PluginConfig {
+    /// Features by their name in the plugin, and whether they are on
+    pub fn features(&self) -> [(&'static str, bool); 5] {
+        [
+            ("vfs_status", self.vfs_status),
+            ("auto_cd", self.auto_cd),
+            ("cwd_sync", self.cwd_sync),
+            ("recording", self.recording),
+            ("clipboard", self.clipboard),
+        ]
+    }
+}
+
 /// SSH tunnel configuration for port forwarding
 #[derive(Debug, Clone)]
 pub struct SshTunnel {
@@ -89,6 +160,8 @@ pub struct Config {
     pub session: SessionConfig,
     pub rate_limit: RateLimitConfig,
     pub remote: RemoteConfig,
+    pub audit: AuditConfig,
+    pub plugin: PluginConfig,
     pub connections: Vec<Connection>,
 }
 
@@ -131,6 +204,9 @@ impl Config {
         let mut current_connection: Option<Connection> = None;
         let mut in_connections = false;
         let mut in_remote = false;
+        let mut in_audit = false;
+        let mut in_plugin = false;
+        let mut in_strict = false;
 
         for line in content.lines() {
             let line = line.trim();
@@ -151,6 +227,9 @@ impl Config {
                 });
                 in_connections = true;
                 in_remote = false;
+                in_audit = false;
+                in_plugin = false;
+                in_strict = false;
                 continue;
             }
 
@@ -164,6 +243,16 @@ impl Config {
                 in_connections = false;
 
                 in_remote = line == "[remote]";
+                in_audit = line == "[audit]";
+                in_plugin = line == "[plugin]";
+                // Nested tables, read by `load_auth`, `load_tools` and `load_profiles`
+                in_strict = ["auth", "tools", "profiles"]
+                    .iter()
+                    .any(|name| is_table(line, name));
+                continue;
+            }
+
+            if in_strict {
                 continue;
             }
 
@@ -194,6 +283,23 @@ impl Config {
                         "address" => config.remote.address = value.to_string(),
                         _ => {}
                     }
+                } else if in_audit {
+                    match key {
+                        "enabled" => config.audit.enabled = value != "false",
+                        "path" => config.audit.path = Some(value.to_string()),
+                        "syslog" => config.audit.syslog = Some(value.to_string()),
+                        _ => {}
+                    }
+                } else if in_plugin {
+                    let enabled = value != "false";
+                    match key {
+                        "vfs_status" => config.plugin.vfs_status = enabled,
+                        "auto_cd" => config.plugin.auto_cd = enabled,
+                        "cwd_sync" => config.plugin.cwd_sync = enabled,
+                        "recording" => config.plugin.recording = enabled,
+                        "clipboard" => config.plugin.clipboard = enabled,
+                        _ => {}
+                    }
                 } else {
                     match key {
                         "ws_port" => {
@@ -230,6 +336,16 @@ impl Config {
                                 config.session.max_sessions = max;
                             }
                         }
+                        "share_token_bytes" => {
+                            if let Ok(bytes) = value.parse() {
+                                config.session.share_token_bytes = bytes;
+                            }
+                        }
+                        "resume_days" => {
+                            if let Ok(days) = value.parse() {
+                                config.session.resume_days = days;
+                            }
+                        }
                         "max_burst" => {
                             if let Ok(burst) = value.parse() {
                                 config.rate_limit.max_burst = burst;
@@ -256,6 +372,96 @@ impl Config {
         Some(config)
     }
 
+    /// Load the `[auth]` section (see [`AuthConfig`]) from `path`
+    ///
+    /// Unlike the rest of the file, a broken `[auth]` section is an error
+    /// rather than ignored, so a typo cannot silently turn login off. The
+    /// `NVIM_WEB_OIDC_*` environment variables override the file; setting
+    /// the issuer enables authentication.
+    pub fn load_auth(path: &Path) -> Result<AuthConfig> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).context(format!("Failed to read {}", path.display())),
+        };
+        let mut auth = Self::parse_auth(&content)?;
+
+        let env = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        if let Some(issuer) = env("NVIM_WEB_OIDC_ISSUER") {
+            auth.enabled = true;
+            auth.issuer = issuer;
+        }
+        if let Some(client_id) = env("NVIM_WEB_OIDC_CLIENT_ID") {
+            auth.client_id = client_id;
+        }
+        if let Some(secret) = env("NVIM_WEB_OIDC_CLIENT_SECRET") {
+            auth.client_secret = Some(secret);
+        }
+        if let Some(redirect_uri) = env("NVIM_WEB_OIDC_REDIRECT_URI") {
+            auth.redirect_uri = redirect_uri;
+        }
+
+        if auth.enabled {
+            for (name, value) in [
+                ("issuer", &auth.issuer),
+                ("client_id", &auth.client_id),
+                ("redirect_uri", &auth.redirect_uri),
+            ] {
+                if value.is_empty() {
+                    anyhow::bail!("[auth] is enabled but has no {name}");
+                }
+            }
+        }
+        Ok(auth)
+    }
+
+    /// The `[auth]` table of a config file, defaults if there is none
+    fn parse_auth(content: &str) -> Result<AuthConfig> {
+        Self::parse_table(content, "auth")
+    }
+
+    /// Load the `[tools]` section, the tools `tool_exec` may run, from `path`
+    ///
+    /// Like `[auth]`, a broken section is an error rather than ignored. No
+    /// section means no tools.
+    pub fn load_tools(path: &Path) -> Result<ToolRegistry> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Self::parse_table(&content, "tools"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ToolRegistry::default()),
+            Err(e) => Err(e).context(format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// Load the `[profiles]` section, how sessions launch Neovim, from `path`
+    ///
+    /// Like `[auth]`, a broken section is an error rather than ignored.
+    pub fn load_profiles(path: &Path) -> Result<LaunchProfiles> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Self::parse_table(&content, "profiles"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(LaunchProfiles::default()),
+            Err(e) => Err(e).context(format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// The table `name` of a config file, defaults if there is none
+    fn parse_table<T: serde::de::DeserializeOwned + Default>(
+        content: &str,
+        name: &str,
+    ) -> Result<T> {
+        // The rest of the file is read leniently; only insist on valid TOML
+        // when there is a strict section to get right
+        if !content.lines().any(|line| is_table(line.trim(), name)) {
+            return Ok(T::default());
+        }
+        let mut table: toml::Table = toml::from_str(content).context("Invalid config file")?;
+        match table.remove(name) {
+            Some(section) => section
+                .try_into()
+                .with_context(|| format!("Invalid [{name}] section")),
+            None => Ok(T::default()),
+        }
+    }
+
     /// Parse SSH tunnel inline table { host = "...", port = 22, ... }
     fn parse_ssh_tunnel(value: &str) -> Option<SshTunnel> {
         let value = value.trim();
@@ -316,6 +522,62 @@ bind = "127.0.0.1"
 [session]
 timeout = 300
 max_sessions = 10
+# Length of share link tokens in bytes (minimum 16)
+# share_token_bytes = 32
+# Days a session can be resumed with its old ?session= id after the host
+# restarts (0 to not keep sessions)
+# resume_days = 7
+
+# Require OpenID Connect login (see docs/authentication.md)
+# [auth]
+# enabled = true
+# issuer = "https://accounts.google.com"
+# client_id = "xxx.apps.googleusercontent.com"
+# client_secret = "xxx"
+# redirect_uri = "https://your-domain.com/auth/callback"
+#
+# Roles by OIDC group, with limits per role
+# [auth.roles.groups]
+# engineering = "developer"
+# contractors = "viewer-only"
+# [auth.roles.developer]
+# max_sessions = 3
+
+# Tools the browser may run through tool_exec, by name; "{0}" is the
+# browser's first argument. Unregistered commands are refused.
+# [tools.prettier]
+# command = "prettier"
+# args = ["--stdin-filepath", "{0}"]
+# timeout_secs = 10
+# [tools.prettier.limits]
+# memory_mb = 512
+# no_new_privs = true
+
+# Neovim launch profiles, picked with ?profile= on the WebSocket URL,
+# "profile" in POST /api/sessions or [editor] profile in a project's
+# .nvim-web/config.toml; "default" is used when none is picked
+# [profiles.team]
+# binary = "/opt/nvim/bin/nvim"
+# init = "/etc/nvim-web/team/init.lua"
+# appname = "nvim-team"
+# cwd = "~/src"
+# [profiles.clean]
+# clean = true
+
+# Features of the runtime plugin loaded into every session's Neovim;
+# auto_cd changes to the git root of the file entered
+# [plugin]
+# vfs_status = true
+# auto_cd = false
+# cwd_sync = true
+# recording = true
+# clipboard = true
+
+# Audit log of logins, share links, connections, tool_exec and SSH
+# [audit]
+# enabled = true
+# path = "/var/log/nvim-web/audit.jsonl"
+# syslog = "unix:///dev/log"
 
 # Example saved connections
 # [[connections]]
@@ -333,6 +595,14 @@ max_sessions = 10
     }
 }
 
+/// Whether a section header opens the table `name` or one nested in it
+fn is_table(header: &str, name: &str) -> bool {
+    header
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_prefix(name))
+        .is_some_and(|rest| rest.starts_with(']') || rest.starts_with('.'))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -355,4 +625,112 @@ mod tests {
         assert_eq!(tunnel.local_port, 9002);
         assert_eq!(tunnel.remote_port, 9001);
     }
+
+    #[test]
+    fn test_parse_audit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "[audit]\npath = \"/var/log/nvim-web/audit.jsonl\"\nsyslog = \"udp://127.0.0.1:514\"\n\n[remote]\nenabled = true\n",
+        )
+        .unwrap();
+        let config = Config::load_from_path(&path).unwrap();
+        assert!(config.audit.enabled);
+        assert_eq!(
+            config.audit.path.as_deref(),
+            Some("/var/log/nvim-web/audit.jsonl")
+        );
+        assert_eq!(config.audit.syslog.as_deref(), Some("udp://127.0.0.1:514"));
+        assert!(config.remote.enabled);
+
+        std::fs::write(&path, "[audit]\nenabled = false\n").unwrap();
+        assert!(!Config::load_from_path(&path).unwrap().audit.enabled);
+    }
+
+    #[test]
+    fn test_parse_plugin() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[plugin]\nauto_cd = false\nclipboard = true\n").unwrap();
+        let config = Config::load_from_path(&path).unwrap();
+        assert!(!config.plugin.auto_cd);
+        assert!(config.plugin.clipboard);
+        assert!(config.plugin.cwd_sync);
+    }
+
+    #[test]
+    fn test_parse_auth() {
+        let content = r#"
+ws_port = 9001
+
+[auth]
+enabled = true
+issuer = "https://accounts.example.com"
+client_id = "nvim-web"
+redirect_uri = "https://nvim.example.com/auth/callback"
+
+[auth.policy]
+allowed_domains = ["example.com"]
+
+[auth.roles.groups]
+contractors = "viewer-only"
+
+[auth.roles.developer]
+max_sessions = 3
+
+[[connections]]
+name = "local"
+url = "ws://127.0.0.1:9001"
+"#;
+        let auth = Config::parse_auth(content).unwrap();
+        assert!(auth.enabled);
+        assert_eq!(auth.client_id, "nvim-web");
+        assert!(auth.policy.allowed_domains.contains("example.com"));
+        assert_eq!(auth.session.cookie_name, "nvim_web_session");
+        assert_eq!(auth.roles.developer.max_sessions, Some(3));
+        assert!(auth.roles.groups.contains_key("contractors"));
+
+        assert!(!Config::parse_auth("ws_port = 9001").unwrap().enabled);
+        assert!(!Config::parse_auth("bind = 127.0.0.1").unwrap().enabled);
+        assert!(Config::parse_auth("[auth]\nenabled = \"yes\"").is_err());
+        assert!(Config::parse_auth("[auth.roles.developer]\nbackends = [\"docker\"]").is_err());
+    }
+
+    #[test]
+    fn test_parse_tools() {
+        let content = r#"
+bind = "127.0.0.1"
+
+[tools.prettier]
+command = "prettier"
+args = ["--stdin-filepath", "{0}"]
+timeout_secs = 10
+
+[tools.prettier.env]
+bind = "0.0.0.0"
+
+[tools.prettier.limits]
+memory_mb = 512
+"#;
+        let tools: ToolRegistry = Config::parse_table(content, "tools").unwrap();
+        assert_eq!(tools.names(), ["prettier"]);
+        let prettier = tools.get("prettier").unwrap();
+        assert_eq!(prettier.arity(), 1);
+        assert_eq!(prettier.timeout_secs, 10);
+        assert_eq!(prettier.limits.memory_mb, Some(512));
+
+        // Tool keys do not leak into the lenient top-level settings
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, content).unwrap();
+        assert_eq!(
+            Config::load_from_path(&path).unwrap().server.bind,
+            "127.0.0.1"
+        );
+
+        let empty: ToolRegistry = Config::parse_table("[toolsets]\nx = 1", "tools").unwrap();
+        assert!(empty.is_empty());
+        assert!(Config::parse_table::<ToolRegistry>("[tools.x]\nargs = []", "tools").is_err());
+    }
 }
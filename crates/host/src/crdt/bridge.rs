@@ -0,0 +1,718 @@
+//! Two-way sync between Neovim buffers and their CRDT documents
+//!
+//! Buffers are attached with `nvim_buf_attach` when entered. Each line
+//! event is replayed on a replica of the buffer's document and the
+//! resulting Y update is merged into the shared document kept by
+//! [`SessionViewers`], which broadcasts it as `CollabEvent::BufferChanged`.
+//! Updates from browsers are merged into the shared document and written
+//! back with `nvim_buf_set_lines`.
+//!
+//! Echo loops are avoided with `b:changedtick`: a write only happens if the
+//! buffer is still at the tick the bridge last saw, and line events up to
+//! the tick returned by the write are the bridge's own and skipped. If the
+//! user typed in between, the write is retried once their edits have been
+//! merged.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use anyhow::{anyhow, Result};
+use nvim_rs::{Neovim, Value};
+use nvim_web_protocol::schema::HostMessage;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use yrs::updates::encoder::Encode;
+
+use super::{BufferCrdt, SyncMessage};
+use crate::collaboration::{SessionViewers, SharedCollaborationRegistry};
+use crate::session::NvimWriter;
+
+/// Attach normal buffers as they are entered or re-read
+pub const ATTACH_AUTOCMD: &str = r"
+augroup NvimWebCrdt
+  autocmd!
+  autocmd BufEnter,BufReadPost * if &buftype ==# '' | call rpcnotify(0, 'nvim_web_buf_enter', str2nr(expand('<abuf>'))) | endif
+augroup END
+";
+
+/// Set lines only if the buffer is still at the expected tick
+///
+/// Returns the new tick, 0 if the buffer changed meanwhile and -1 if it is
+/// no longer loaded.
+const WRITE_LUA: &str = r"
+local buf, tick, first, last, lines = ...
+if not vim.api.nvim_buf_is_loaded(buf) then
+  return -1
+end
+if vim.api.nvim_buf_get_changedtick(buf) ~= tick then
+  return 0
+end
+vim.api.nvim_buf_set_lines(buf, first, last, false, lines)
+return vim.api.nvim_buf_get_changedtick(buf)
+";
+
+/// Input for a session's bridge task
+#[derive(Debug)]
+pub enum BridgeEvent {
+    /// `nvim_buf_lines_event`; `last` is -1 when the whole buffer is sent
+    Lines {
+        buffer_id: u64,
+        tick: Option<u64>,
+        first: i64,
+        last: i64,
+        lines: Vec<String>,
+    },
+    /// `nvim_buf_changedtick_event`
+    ChangedTick { buffer_id: u64, tick: u64 },
+    /// `nvim_buf_detach_event`
+    Detach { buffer_id: u64 },
+    /// Buffer entered in Neovim, attached if not yet
+    Enter { buffer_id: u64 },
+    /// Sync message from a browser, answered on `reply`
+    Sync {
+        buffer_id: u64,
+        message: SyncMessage,
+        reply: oneshot::Sender<Option<SyncMessage>>,
+    },
+}
+
+impl BridgeEvent {
+    /// Parse a Neovim notification, `None` if it is not for the bridge
+    pub fn from_notification(name: &str, args: &[Value]) -> Option<Self> {
+        let buffer_id = buffer_handle(args.first()?)?;
+        let int = |i: usize| args.get(i).and_then(Value::as_i64);
+        Some(match name {
+            "nvim_buf_lines_event" => Self::Lines {
+                buffer_id,
+                tick: args.get(1).and_then(Value::as_u64),
+                first: int(2)?,
+                last: int(3)?,
+                lines: args
+                    .get(4)?
+                    .as_array()?
+                    .iter()
+                    .map(|line| line.as_str().map(String::from))
+                    .collect::<Option<_>>()?,
+            },
+            "nvim_buf_changedtick_event" => Self::ChangedTick {
+                buffer_id,
+                tick: args.get(1)?.as_u64()?,
+            },
+            "nvim_buf_detach_event" => Self::Detach { buffer_id },
+            "nvim_web_buf_enter" => Self::Enter { buffer_id },
+            _ => return None,
+        })
+    }
+}
+
+/// Buffer handles arrive as msgpack ext values wrapping the number
+fn buffer_handle(value: &Value) -> Option<u64> {
+    match value {
+        Value::Ext(_, data) => rmpv::decode::read_value(&mut data.as_slice())
+            .ok()?
+            .as_u64(),
+        other => other.as_u64(),
+    }
+}
+
+/// Creates the channel feeding a bridge
+pub fn channel() -> (BridgeHandle, mpsc::UnboundedReceiver<BridgeEvent>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (BridgeHandle { tx }, rx)
+}
+
+/// Owner's end of a bridge; the bridge task stops once it is dropped
+pub struct BridgeHandle {
+    tx: mpsc::UnboundedSender<BridgeEvent>,
+}
+
+impl BridgeHandle {
+    /// Sender for the Neovim notification handler that does not keep the bridge alive
+    pub fn notifier(&self) -> BridgeNotifier {
+        BridgeNotifier {
+            tx: self.tx.downgrade(),
+        }
+    }
+
+    /// Handle a browser's sync message, returning the reply for that browser
+    pub async fn sync(&self, buffer_id: u64, message: SyncMessage) -> Result<Option<SyncMessage>> {
+        let (reply, rx) = oneshot::channel();
+        self.tx
+            .send(BridgeEvent::Sync {
+                buffer_id,
+                message,
+                reply,
+            })
+            .map_err(|_| anyhow!("CRDT bridge stopped"))?;
+        rx.await.map_err(|_| anyhow!("CRDT bridge stopped"))
+    }
+
+    /// Attach a buffer and make sure its shared document exists
+    pub async fn track(&self, buffer_id: u64) -> Result<()> {
+        let state_vector = yrs::StateVector::default().encode_v1();
+        self.sync(buffer_id, SyncMessage::SyncStep1 { state_vector })
+            .await
+            .map(drop)
+    }
+}
+
+/// Forwards buffer notifications from Neovim to the bridge
+#[derive(Clone)]
+pub struct BridgeNotifier {
+    tx: mpsc::WeakUnboundedSender<BridgeEvent>,
+}
+
+impl BridgeNotifier {
+    /// Forward `name` if it is a buffer event, returns false otherwise
+    pub fn notify(&self, name: &str, args: &[Value]) -> bool {
+        let Some(event) = BridgeEvent::from_notification(name, args) else {
+            return false;
+        };
+        if let Some(tx) = self.tx.upgrade() {
+            let _ = tx.send(event);
+        }
+        true
+    }
+}
+
+/// Neovim's view of a buffer, mirrored as a CRDT replica
+#[derive(Debug)]
+struct TrackedBuffer {
+    /// Buffer lines as of `tick`
+    lines: Vec<String>,
+    /// Last `b:changedtick` seen or written by the bridge
+    tick: u64,
+    /// Document holding exactly `lines`; local edits are made here so they
+    /// merge with remote edits Neovim has not seen yet
+    replica: BufferCrdt,
+}
+
+impl TrackedBuffer {
+    fn new(replica: BufferCrdt) -> Self {
+        Self {
+            lines: split_lines(&replica.get_content()),
+            tick: 0,
+            replica,
+        }
+    }
+
+    /// Whether a line event is one the bridge has already accounted for
+    fn is_stale(&self, tick: Option<u64>) -> bool {
+        tick.is_some_and(|tick| tick <= self.tick)
+    }
+
+    /// Apply a line event, returning the update if the text changed
+    fn apply_lines(
+        &mut self,
+        tick: Option<u64>,
+        first: i64,
+        last: i64,
+        lines: Vec<String>,
+    ) -> Option<Vec<u8>> {
+        let old = self.lines.join("\n");
+        let len = self.lines.len();
+        let clamp = |n: i64| usize::try_from(n).map_or(len, |n| n.min(len));
+        let first = clamp(first);
+        let last = clamp(last).max(first);
+        self.lines.splice(first..last, lines);
+        if self.lines.is_empty() {
+            // Neovim buffers always have a line
+            self.lines.push(String::new());
+        }
+        self.tick = tick.map_or(self.tick, |tick| tick.max(self.tick));
+
+        let new = self.lines.join("\n");
+        let (start, removed, inserted) = text_diff(&old, &new)?;
+        Some(
+            self.replica
+                .replace(offset(start), offset(removed), inserted),
+        )
+    }
+}
+
+fn offset(n: usize) -> u32 {
+    u32::try_from(n).unwrap_or(u32::MAX)
+}
+
+/// Buffer lines of a document's text
+fn split_lines(text: &str) -> Vec<String> {
+    text.split('\n').map(String::from).collect()
+}
+
+/// Smallest single edit turning `old` into `new`
+///
+/// Returns `(byte offset, removed bytes, inserted text)`, `None` if equal.
+fn text_diff<'a>(old: &str, new: &'a str) -> Option<(usize, usize, &'a str)> {
+    if old == new {
+        return None;
+    }
+    let prefix: usize = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+    let suffix: usize = old[prefix..]
+        .chars()
+        .rev()
+        .zip(new[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+    Some((
+        prefix,
+        old.len() - prefix - suffix,
+        &new[prefix..new.len() - suffix],
+    ))
+}
+
+/// Smallest line range of `old` to replace to get `new`
+///
+/// Returns `(first, last, replacement)` as for `nvim_buf_set_lines`, `None` if equal.
+fn line_diff(old: &[String], new: &[String]) -> Option<(usize, usize, Vec<String>)> {
+    if old == new {
+        return None;
+    }
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    Some((
+        prefix,
+        old.len() - suffix,
+        new[prefix..new.len() - suffix].to_vec(),
+    ))
+}
+
+/// Make sure the shared document for `buffer_id` exists, starting it from `replica`
+fn seed_shared<'a>(
+    viewers: &'a mut SessionViewers,
+    buffer_id: u64,
+    replica: &BufferCrdt,
+) -> &'a mut BufferCrdt {
+    let fresh = viewers.buffer(buffer_id).is_none();
+    let shared = viewers.get_or_create_buffer(buffer_id);
+    if fresh {
+        // A replica's own state always applies
+        let _ = shared.apply_update(&replica.encode_state());
+    }
+    shared
+}
+
+/// Per-session task keeping Neovim buffers and shared documents in sync
+pub struct CrdtBridge {
+    session_id: String,
+    nvim: Neovim<NvimWriter>,
+    registry: SharedCollaborationRegistry,
+    /// Session broadcast to browsers
+    out: broadcast::Sender<Vec<u8>>,
+    events: mpsc::UnboundedReceiver<BridgeEvent>,
+    buffers: HashMap<u64, TrackedBuffer>,
+    /// Buffers `nvim_buf_attach` succeeded for
+    attached: HashSet<u64>,
+    /// Buffers whose shared document may be ahead of Neovim
+    dirty: HashSet<u64>,
+    /// Browser syncs received while waiting for an attach
+    deferred: VecDeque<BridgeEvent>,
+}
+
+impl CrdtBridge {
+    pub fn new(
+        session_id: String,
+        nvim: Neovim<NvimWriter>,
+        registry: SharedCollaborationRegistry,
+        out: broadcast::Sender<Vec<u8>>,
+        events: mpsc::UnboundedReceiver<BridgeEvent>,
+    ) -> Self {
+        Self {
+            session_id,
+            nvim,
+            registry,
+            out,
+            events,
+            buffers: HashMap::new(),
+            attached: HashSet::new(),
+            dirty: HashSet::new(),
+            deferred: VecDeque::new(),
+        }
+    }
+
+    /// Process events until the session's [`BridgeHandle`] is dropped
+    pub async fn run(mut self) {
+        loop {
+            let event = match self.deferred.pop_front() {
+                Some(event) => event,
+                None => match self.events.recv().await {
+                    Some(event) => event,
+                    None => break,
+                },
+            };
+            self.handle(event).await;
+            // Merge everything Neovim already reported before writing to it
+            while let Ok(event) = self.events.try_recv() {
+                self.handle(event).await;
+            }
+            self.flush().await;
+        }
+    }
+
+    async fn handle(&mut self, event: BridgeEvent) {
+        match event {
+            BridgeEvent::Sync {
+                buffer_id,
+                message,
+                reply,
+            } => {
+                let response = self.handle_sync(buffer_id, message).await;
+                let _ = reply.send(response);
+            }
+            event => self.handle_nvim(event).await,
+        }
+    }
+
+    async fn handle_nvim(&mut self, event: BridgeEvent) {
+        match event {
+            BridgeEvent::Lines {
+                buffer_id,
+                tick,
+                first,
+                last,
+                lines,
+            } => {
+                let update = match self.buffers.get_mut(&buffer_id) {
+                    Some(buffer) if buffer.is_stale(tick) => None,
+                    Some(buffer) => buffer.apply_lines(tick, first, last, lines),
+                    // First event after attaching holds the whole buffer
+                    None if first == 0 && last == -1 => {
+                        let replica = self.shared_replica(buffer_id).await;
+                        let mut buffer = TrackedBuffer::new(replica);
+                        let update = buffer.apply_lines(tick, first, last, lines);
+                        self.buffers.insert(buffer_id, buffer);
+                        update
+                    }
+                    None => None,
+                };
+                if let Some(update) = update {
+                    self.publish(buffer_id, update).await;
+                }
+            }
+            BridgeEvent::ChangedTick { buffer_id, tick } => {
+                if let Some(buffer) = self.buffers.get_mut(&buffer_id) {
+                    buffer.tick = buffer.tick.max(tick);
+                }
+            }
+            BridgeEvent::Detach { buffer_id } => {
+                self.buffers.remove(&buffer_id);
+                self.attached.remove(&buffer_id);
+                self.dirty.remove(&buffer_id);
+            }
+            BridgeEvent::Enter { buffer_id } => self.attach(buffer_id).await,
+            BridgeEvent::Sync { .. } => self.deferred.push_back(event),
+        }
+    }
+
+    async fn handle_sync(&mut self, buffer_id: u64, message: SyncMessage) -> Option<SyncMessage> {
+        if !self.buffers.contains_key(&buffer_id) {
+            self.attach(buffer_id).await;
+            // The attach reply follows the event carrying the buffer lines
+            while let Ok(event) = self.events.try_recv() {
+                self.handle_nvim(event).await;
+            }
+        }
+        let Some(buffer) = self.buffers.get(&buffer_id) else {
+            tracing::debug!(buffer_id, "CRDT sync for a buffer that cannot be attached");
+            return None;
+        };
+
+        let update = match &message {
+            SyncMessage::Update { update } | SyncMessage::SyncStep2 { update } => {
+                Some(update.clone())
+            }
+            _ => None,
+        };
+        let response = {
+            let mut reg = self.registry.write().await;
+            let viewers = reg.get_or_create(&self.session_id);
+            seed_shared(viewers, buffer_id, &buffer.replica);
+            viewers.handle_sync_message(buffer_id, message)
+        };
+        match response {
+            Ok(response) => {
+                if let Some(update) = update {
+                    self.send_update(buffer_id, update);
+                    self.dirty.insert(buffer_id);
+                }
+                response
+            }
+            Err(e) => {
+                tracing::warn!(buffer_id, error = %e, "Rejected CRDT sync message");
+                None
+            }
+        }
+    }
+
+    async fn attach(&mut self, buffer_id: u64) {
+        if self.attached.contains(&buffer_id) {
+            return;
+        }
+        let args = vec![
+            Value::from(buffer_id),
+            Value::from(true),
+            Value::Map(vec![]),
+        ];
+        match self.nvim.call("nvim_buf_attach", args).await {
+            Ok(Ok(Value::Boolean(true))) => {
+                self.attached.insert(buffer_id);
+            }
+            result => tracing::debug!(buffer_id, ?result, "nvim_buf_attach failed"),
+        }
+    }
+
+    /// Replica for a newly attached buffer, continuing its shared document if any
+    async fn shared_replica(&self, buffer_id: u64) -> BufferCrdt {
+        let reg = self.registry.read().await;
+        reg.get(&self.session_id)
+            .and_then(|viewers| viewers.buffer(buffer_id))
+            .and_then(|shared| BufferCrdt::from_state(buffer_id, &shared.encode_state()).ok())
+            .unwrap_or_else(|| BufferCrdt::new(buffer_id))
+    }
+
+    /// Merge a local edit into the shared document and broadcast it
+    async fn publish(&mut self, buffer_id: u64, update: Vec<u8>) {
+        let Some(buffer) = self.buffers.get(&buffer_id) else {
+            return;
+        };
+        {
+            let mut reg = self.registry.write().await;
+            // No peers means no one to share with; the replica keeps the history
+            if let Some(viewers) = reg.get_mut(&self.session_id) {
+                seed_shared(viewers, buffer_id, &buffer.replica);
+                if let Err(e) = viewers.apply_buffer_update(buffer_id, update.clone()) {
+                    tracing::warn!(buffer_id, error = %e, "Failed to merge buffer change");
+                }
+                // The shared document may hold remote edits Neovim has not seen
+                self.dirty.insert(buffer_id);
+            }
+        }
+        self.send_update(buffer_id, update);
+    }
+
+    fn send_update(&self, buffer_id: u64, update: Vec<u8>) {
+        let msg = HostMessage::CrdtSync {
+            buffer_id,
+            message: SyncMessage::Update { update },
+        };
+        let _ = self.out.send(msg.encode());
+    }
+
+    /// Write shared documents that are ahead of Neovim into their buffers
+    async fn flush(&mut self) {
+        for buffer_id in std::mem::take(&mut self.dirty) {
+            let Some(buffer) = self.buffers.get(&buffer_id) else {
+                continue;
+            };
+            let (target, catch_up) = {
+                let reg = self.registry.read().await;
+                let Some(shared) = reg
+                    .get(&self.session_id)
+                    .and_then(|viewers| viewers.buffer(buffer_id))
+                else {
+                    continue;
+                };
+                let Ok(catch_up) = shared.encode_diff(&buffer.replica.state_vector()) else {
+                    continue;
+                };
+                (split_lines(&shared.get_content()), catch_up)
+            };
+            let Some((first, last, lines)) = line_diff(&buffer.lines, &target) else {
+                continue;
+            };
+
+            match self.write(buffer_id, buffer.tick, first, last, lines).await {
+                Ok(tick) if tick > 0 => {
+                    let Some(buffer) = self.buffers.get_mut(&buffer_id) else {
+                        continue;
+                    };
+                    buffer.lines = target;
+                    buffer.tick = tick.unsigned_abs();
+                    if let Err(e) = buffer.replica.apply_update(&catch_up) {
+                        tracing::warn!(buffer_id, error = %e, "Failed to update buffer replica");
+                    }
+                }
+                // Edited meanwhile; retried once those edits are merged
+                Ok(0) => {
+                    self.dirty.insert(buffer_id);
+                }
+                Ok(_) => {
+                    self.buffers.remove(&buffer_id);
+                    self.attached.remove(&buffer_id);
+                }
+                Err(e) => {
+                    tracing::warn!(session_id = %self.session_id, buffer_id, error = %e, "Failed to write CRDT changes to Neovim");
+                }
+            }
+        }
+    }
+
+    async fn write(
+        &self,
+        buffer_id: u64,
+        tick: u64,
+        first: usize,
+        last: usize,
+        lines: Vec<String>,
+    ) -> Result<i64> {
+        let args = Value::Array(vec![
+            Value::from(buffer_id),
+            Value::from(tick),
+            Value::from(first),
+            Value::from(last),
+            Value::Array(lines.into_iter().map(Value::from).collect()),
+        ]);
+        let result = self
+            .nvim
+            .call("nvim_exec_lua", vec![Value::from(WRITE_LUA), args])
+            .await
+            .map_err(|e| anyhow!("RPC call failed: {e:?}"))?
+            .map_err(|e| anyhow!("Neovim RPC error: {e:?}"))?;
+        result
+            .as_i64()
+            .ok_or_else(|| anyhow!("Unexpected write result: {result}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(items: &[&str]) -> Vec<String> {
+        items.iter().map(ToString::to_string).collect()
+    }
+
+    fn tracked(content: &[&str], tick: u64) -> TrackedBuffer {
+        let mut buffer = TrackedBuffer::new(BufferCrdt::new(1));
+        buffer.apply_lines(Some(tick), 0, -1, lines(content));
+        buffer
+    }
+
+    #[test]
+    fn test_text_diff() {
+        assert_eq!(text_diff("same", "same"), None);
+        assert_eq!(text_diff("hello", "help"), Some((3, 2, "p")));
+        assert_eq!(text_diff("ab", "aXb"), Some((1, 0, "X")));
+        // Repeated characters are not double counted
+        assert_eq!(text_diff("aaa", "aa"), Some((2, 1, "")));
+        // Offsets stay on character boundaries
+        assert_eq!(text_diff("aü", "aö"), Some((1, 2, "ö")));
+    }
+
+    #[test]
+    fn test_line_diff() {
+        let old = lines(&["a", "b", "c"]);
+        assert_eq!(line_diff(&old, &old), None);
+        assert_eq!(
+            line_diff(&old, &lines(&["a", "x", "y", "c"])),
+            Some((1, 2, lines(&["x", "y"])))
+        );
+        assert_eq!(line_diff(&old, &lines(&["a", "c"])), Some((1, 2, vec![])));
+        assert_eq!(
+            line_diff(&lines(&["a", "a"]), &lines(&["a", "a", "a"])),
+            Some((2, 2, lines(&["a"])))
+        );
+    }
+
+    #[test]
+    fn test_line_events_update_replica() {
+        let mut buffer = tracked(&["one", "two", "three"], 3);
+        assert_eq!(buffer.replica.get_content(), "one\ntwo\nthree");
+
+        // Change line 2, then delete line 1
+        assert!(buffer.apply_lines(Some(4), 1, 2, lines(&["TWO"])).is_some());
+        assert!(buffer.apply_lines(Some(5), 0, 1, vec![]).is_some());
+        assert_eq!(buffer.lines, lines(&["TWO", "three"]));
+        assert_eq!(buffer.replica.get_content(), "TWO\nthree");
+        assert_eq!(buffer.tick, 5);
+
+        // Deleting everything leaves an empty line, like Neovim
+        buffer.apply_lines(Some(6), 0, 2, vec![]);
+        assert_eq!(buffer.lines, lines(&[""]));
+        assert_eq!(buffer.replica.get_content(), "");
+    }
+
+    #[test]
+    fn test_own_writes_are_stale() {
+        let buffer = tracked(&["x"], 7);
+        assert!(buffer.is_stale(Some(6)));
+        assert!(buffer.is_stale(Some(7)));
+        assert!(!buffer.is_stale(Some(8)));
+        assert!(!buffer.is_stale(None));
+    }
+
+    #[test]
+    fn test_local_edit_merges_with_unwritten_remote_edit() {
+        let mut buffer = tracked(&["hello world"], 2);
+        let mut shared = BufferCrdt::new(1);
+        shared.apply_update(&buffer.replica.encode_state()).unwrap();
+
+        // A browser edits the shared document...
+        let mut browser = BufferCrdt::new(1);
+        browser.apply_update(&shared.encode_state()).unwrap();
+        let remote = browser.replace(0, 0, "> ");
+        shared.apply_update(&remote).unwrap();
+
+        // ...while Neovim, not yet written, appends to the same line
+        let local = buffer
+            .apply_lines(Some(3), 0, 1, lines(&["hello world!"]))
+            .unwrap();
+        shared.apply_update(&local).unwrap();
+        assert_eq!(shared.get_content(), "> hello world!");
+
+        // What the bridge writes back to Neovim
+        let target = split_lines(&shared.get_content());
+        assert_eq!(
+            line_diff(&buffer.lines, &target),
+            Some((0, 1, lines(&["> hello world!"])))
+        );
+    }
+
+    #[test]
+    fn test_parse_notifications() {
+        let buf = Value::Ext(0, vec![0x05]);
+        let event = BridgeEvent::from_notification(
+            "nvim_buf_lines_event",
+            &[
+                buf.clone(),
+                Value::from(12),
+                Value::from(0),
+                Value::from(-1),
+                Value::Array(vec![Value::from("a"), Value::from("b")]),
+                Value::from(false),
+            ],
+        );
+        let Some(BridgeEvent::Lines {
+            buffer_id: 5,
+            tick: Some(12),
+            first: 0,
+            last: -1,
+            lines: content,
+        }) = event
+        else {
+            panic!("expected lines event, got {event:?}");
+        };
+        assert_eq!(content, lines(&["a", "b"]));
+
+        assert!(matches!(
+            BridgeEvent::from_notification("nvim_buf_changedtick_event", &[buf.clone(), 13.into()]),
+            Some(BridgeEvent::ChangedTick {
+                buffer_id: 5,
+                tick: 13
+            })
+        ));
+        assert!(matches!(
+            BridgeEvent::from_notification("nvim_web_buf_enter", &[Value::from(3)]),
+            Some(BridgeEvent::Enter { buffer_id: 3 })
+        ));
+        assert!(BridgeEvent::from_notification("redraw", &[buf]).is_none());
+    }
+}
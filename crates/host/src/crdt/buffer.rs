@@ -5,7 +5,7 @@
 
 use yrs::updates::decoder::Decode;
 use yrs::updates::encoder::Encode;
-use yrs::{Doc, GetString, ReadTxn, Text, Transact, Update};
+use yrs::{Assoc, Doc, GetString, IndexedSequence, ReadTxn, StickyIndex, Text, Transact, Update};
 
 /// CRDT document for a single buffer
 #[derive(Debug)]
@@ -32,6 +32,13 @@ impl BufferCrdt {
         }
     }
 
+    /// Recreate a document from a state encoded by [`Self::encode_state`]
+    pub fn from_state(buffer_id: u64, state: &[u8]) -> anyhow::Result<Self> {
+        let mut crdt = Self::new(buffer_id);
+        crdt.apply_update(state)?;
+        Ok(crdt)
+    }
+
     /// Get the buffer ID
     pub fn buffer_id(&self) -> u64 {
         self.buffer_id
@@ -117,6 +124,22 @@ impl BufferCrdt {
         txn.encode_update_v1()
     }
 
+    /// Replace `len` bytes at byte offset `start` with `insert`
+    ///
+    /// Returns the update for syncing. Offsets must lie on character boundaries.
+    pub fn replace(&mut self, start: u32, len: u32, insert: &str) -> Vec<u8> {
+        let text = self.text();
+        let mut txn = self.doc.transact_mut();
+        if len > 0 {
+            text.remove_range(&mut txn, start, len);
+        }
+        if !insert.is_empty() {
+            text.insert(&mut txn, start, insert);
+        }
+        self.version += 1;
+        txn.encode_update_v1()
+    }
+
     /// Apply an update from a remote client
     pub fn apply_update(&mut self, update: &[u8]) -> anyhow::Result<()> {
         let update = Update::decode_v1(update)?;
@@ -145,6 +168,58 @@ impl BufferCrdt {
         txn.encode_state_as_update_v1(&yrs::StateVector::default())
     }
 
+    /// Anchor a 1-based inclusive line range so it follows later edits
+    ///
+    /// Returns the encoded start and end positions for [`Self::resolve_lines`],
+    /// `None` if the range lies past the end of the document.
+    pub fn anchor_lines(&self, start_line: u32, end_line: u32) -> Option<(Vec<u8>, Vec<u8>)> {
+        let start_line = start_line.max(1);
+        let end_line = end_line.max(start_line);
+        let text = self.text();
+        let mut txn = self.doc.transact_mut();
+        let content = text.get_string(&txn);
+        let start = line_start(&content, start_line)?;
+        // The newline ending the range, so edits within its last line keep it
+        let end = content[start..]
+            .match_indices('\n')
+            .nth((end_line - start_line) as usize)
+            .map_or(content.len(), |(idx, _)| start + idx);
+        // Nothing follows the end of the document to stick to
+        let end_assoc = if end == content.len() {
+            Assoc::Before
+        } else {
+            Assoc::After
+        };
+        let start = text.sticky_index(&mut txn, offset(start), Assoc::After)?;
+        let end = text.sticky_index(&mut txn, offset(end), end_assoc)?;
+        Some((start.encode_v1(), end.encode_v1()))
+    }
+
+    /// Current 1-based line range of positions made by [`Self::anchor_lines`]
+    ///
+    /// `None` if the positions belong to another document.
+    pub fn resolve_lines(&self, start: &[u8], end: &[u8]) -> Option<(u32, u32)> {
+        let text = self.text();
+        let txn = self.doc.transact();
+        let content = text.get_string(&txn);
+        let line = |encoded: &[u8]| -> Option<u32> {
+            let index = StickyIndex::decode_v1(encoded)
+                .ok()?
+                .get_offset(&txn)?
+                .index as usize;
+            let index = index.min(content.len());
+            Some(
+                content.as_bytes()[..index]
+                    .iter()
+                    .filter(|&&b| b == b'\n')
+                    .count() as u32
+                    + 1,
+            )
+        };
+        let start = line(start)?;
+        Some((start, line(end)?.max(start)))
+    }
+
     /// Convert line range to character offsets
     fn line_range_to_offsets(content: &str, start_line: u32, end_line: u32) -> (usize, usize) {
         let mut start_offset = 0;
@@ -173,6 +248,22 @@ impl BufferCrdt {
     }
 }
 
+/// Byte offset of the start of 1-based `line`, `None` past the end
+fn line_start(content: &str, line: u32) -> Option<usize> {
+    if line == 1 {
+        return Some(0);
+    }
+    content
+        .match_indices('\n')
+        .nth(line as usize - 2)
+        .map(|(idx, _)| idx + 1)
+        .filter(|&idx| idx < content.len())
+}
+
+fn offset(n: usize) -> u32 {
+    u32::try_from(n).unwrap_or(u32::MAX)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -221,6 +312,37 @@ mod tests {
         assert_eq!(crdt2.get_content(), "hello world");
     }
 
+    #[test]
+    fn test_anchor_follows_edits() {
+        let mut crdt = BufferCrdt::new(1);
+        crdt.set_content("a\nb\nc\nd\n");
+        let (start, end) = crdt.anchor_lines(2, 3).unwrap();
+        assert_eq!(crdt.resolve_lines(&start, &end), Some((2, 3)));
+
+        // Lines inserted above move the range down
+        crdt.replace(0, 0, "x\ny\n");
+        assert_eq!(crdt.resolve_lines(&start, &end), Some((4, 5)));
+
+        // Editing inside the range keeps it
+        crdt.replace(9, 0, " changed");
+        assert_eq!(crdt.get_lines()[4], "c changed");
+        assert_eq!(crdt.resolve_lines(&start, &end), Some((4, 5)));
+
+        // A remote replica resolves the same positions
+        let replica = BufferCrdt::from_state(1, &crdt.encode_state()).unwrap();
+        assert_eq!(replica.resolve_lines(&start, &end), Some((4, 5)));
+        assert_eq!(BufferCrdt::new(2).resolve_lines(&start, &end), None);
+    }
+
+    #[test]
+    fn test_anchor_past_end() {
+        let mut crdt = BufferCrdt::new(1);
+        crdt.set_content("a\nb");
+        assert!(crdt.anchor_lines(3, 3).is_none());
+        let (start, end) = crdt.anchor_lines(2, 9).unwrap();
+        assert_eq!(crdt.resolve_lines(&start, &end), Some((2, 2)));
+    }
+
     #[test]
     fn test_concurrent_edits() {
         let mut crdt1 = BufferCrdt::new(1);
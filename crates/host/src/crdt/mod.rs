@@ -4,6 +4,7 @@
 //! Each buffer gets its own Y.Doc with text content that syncs
 //! bidirectionally between Neovim and connected clients.
 
+pub mod bridge;
 mod buffer;
 mod sync;
 
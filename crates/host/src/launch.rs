@@ -0,0 +1,301 @@
+//! Neovim launch profiles
+//!
+//! Profiles in the `[profiles]` section of config.toml say how a session's
+//! local Neovim is started: binary, extra arguments, environment, working
+//! directory, `--clean` or a custom init file, and `NVIM_APPNAME`. A session
+//! uses the profile named at creation (`?profile=` on the WebSocket URL or
+//! `profile` in `POST /api/sessions`), else the one its project's
+//! `.nvim-web/config.toml` names, else the profile called `default`, else
+//! plain `nvim --embed` in `$HOME`.
+//!
+//! Sessions on a remote Neovim (`--remote`) are not launched by the host and
+//! ignore profiles.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::project::ProjectConfig;
+
+/// Profile used when a session names none
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// How to start Neovim for a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LaunchProfile {
+    /// Neovim binary, looked up in `PATH` unless it is a path
+    #[serde(default = "default_binary")]
+    pub binary: String,
+    /// Arguments after `--embed` and the init options
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment variables to set
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Working directory, `$HOME` by default; `~/` is expanded
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Start with `--clean`: no user config or plugins
+    #[serde(default)]
+    pub clean: bool,
+    /// Init file passed with `-u`; `~/` is expanded
+    #[serde(default)]
+    pub init: Option<String>,
+    /// `NVIM_APPNAME`, to keep the profile's config, data and state apart
+    #[serde(default)]
+    pub appname: Option<String>,
+}
+
+fn default_binary() -> String {
+    "nvim".to_string()
+}
+
+impl Default for LaunchProfile {
+    fn default() -> Self {
+        Self {
+            binary: default_binary(),
+            args: Vec::new(),
+            env: HashMap::new(),
+            cwd: None,
+            clean: false,
+            init: None,
+            appname: None,
+        }
+    }
+}
+
+impl LaunchProfile {
+    /// Working directory Neovim starts in
+    pub fn working_dir(&self) -> Option<PathBuf> {
+        match &self.cwd {
+            Some(cwd) => Some(expand_home(cwd)),
+            None => dirs::home_dir(),
+        }
+    }
+
+    /// The `nvim --embed` command for this profile, without stdio set up
+    pub fn command(&self) -> Command {
+        let mut cmd = Command::new(expand_home(&self.binary));
+        cmd.arg("--embed");
+        if self.clean {
+            cmd.arg("--clean");
+        }
+        if let Some(init) = &self.init {
+            cmd.arg("-u").arg(expand_home(init));
+        }
+        cmd.args(&self.args).envs(&self.env);
+        if let Some(appname) = &self.appname {
+            cmd.env("NVIM_APPNAME", appname);
+        }
+        if let Some(dir) = self.working_dir() {
+            cmd.current_dir(dir);
+        }
+        cmd
+    }
+}
+
+/// Expand a leading `~/` to the home directory
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// What a session asks for at creation
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LaunchRequest {
+    /// Profile name; overrides the project's
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Project directory; Neovim starts in its working directory and with
+    /// the profile from its `.nvim-web/config.toml`
+    #[serde(default)]
+    pub project: Option<PathBuf>,
+}
+
+/// The launch profiles of the host, from the `[profiles]` section of config.toml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct LaunchProfiles {
+    profiles: HashMap<String, LaunchProfile>,
+}
+
+impl LaunchProfiles {
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+
+    /// Names of the profiles, sorted
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Look up a profile, failing with the list of profiles for unknown names
+    pub fn get(&self, name: &str) -> Result<&LaunchProfile> {
+        self.profiles.get(name).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unknown launch profile '{name}'; available profiles: {}",
+                match self.names().join(", ") {
+                    names if names.is_empty() => "none".to_string(),
+                    names => names,
+                }
+            )
+        })
+    }
+
+    /// Profile a session is launched with, and its name
+    ///
+    /// A project's working directory replaces the profile's.
+    pub fn resolve(&self, request: &LaunchRequest) -> Result<(Option<String>, LaunchProfile)> {
+        let project = match &request.project {
+            Some(path) => {
+                let path = path
+                    .canonicalize()
+                    .with_context(|| format!("Project {} does not exist", path.display()))?;
+                let config = ProjectConfig::load(&path);
+                Some((config.resolved_cwd(&path), config.editor.profile))
+            }
+            None => None,
+        };
+        let name = request
+            .profile
+            .clone()
+            .or_else(|| project.as_ref().and_then(|(_, profile)| profile.clone()));
+
+        let (name, mut profile) = match name {
+            Some(name) => {
+                let profile = self.get(&name)?.clone();
+                (Some(name), profile)
+            }
+            None => match self.profiles.get(DEFAULT_PROFILE) {
+                Some(profile) => (Some(DEFAULT_PROFILE.to_string()), profile.clone()),
+                None => (None, LaunchProfile::default()),
+            },
+        };
+        if let Some((cwd, _)) = project {
+            profile.cwd = Some(cwd.display().to_string());
+        }
+        Ok((name, profile))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profiles() -> LaunchProfiles {
+        toml::from_str(
+            r#"
+[default]
+args = ["-n"]
+
+[team]
+binary = "/opt/nvim/bin/nvim"
+init = "/etc/nvim-web/team.lua"
+appname = "nvim-team"
+cwd = "/srv"
+env = { TEAM = "1" }
+
+[bare]
+clean = true
+"#,
+        )
+        .unwrap()
+    }
+
+    fn argv(profile: &LaunchProfile) -> Vec<String> {
+        let cmd = profile.command();
+        let cmd = cmd.as_std();
+        std::iter::once(cmd.get_program())
+            .chain(cmd.get_args())
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_profile_command() {
+        let profiles = profiles();
+        let team = profiles.get("team").unwrap();
+        assert_eq!(
+            argv(team),
+            [
+                "/opt/nvim/bin/nvim",
+                "--embed",
+                "-u",
+                "/etc/nvim-web/team.lua"
+            ]
+        );
+        let cmd = team.command();
+        let envs: HashMap<_, _> = cmd
+            .as_std()
+            .get_envs()
+            .map(|(k, v)| {
+                (
+                    k.to_string_lossy().into_owned(),
+                    v.map(|v| v.to_string_lossy().into_owned()),
+                )
+            })
+            .collect();
+        assert_eq!(envs["NVIM_APPNAME"].as_deref(), Some("nvim-team"));
+        assert_eq!(envs["TEAM"].as_deref(), Some("1"));
+        assert_eq!(
+            cmd.as_std().get_current_dir(),
+            Some(std::path::Path::new("/srv"))
+        );
+
+        assert_eq!(
+            argv(profiles.get("bare").unwrap()),
+            ["nvim", "--embed", "--clean"]
+        );
+        let err = profiles.get("nope").unwrap_err().to_string();
+        assert!(err.contains("available profiles: bare, default, team"));
+    }
+
+    #[test]
+    fn test_resolve_profile() {
+        let profiles = profiles();
+        let (name, profile) = profiles.resolve(&LaunchRequest::default()).unwrap();
+        assert_eq!(name.as_deref(), Some("default"));
+        assert_eq!(profile.args, ["-n"]);
+
+        let (name, profile) = LaunchProfiles::default()
+            .resolve(&LaunchRequest::default())
+            .unwrap();
+        assert!(name.is_none());
+        assert_eq!(argv(&profile), ["nvim", "--embed"]);
+
+        // A project picks its profile and working directory, a request's
+        // profile wins over the project's
+        let project = tempfile::tempdir().unwrap();
+        std::fs::create_dir(project.path().join(".nvim-web")).unwrap();
+        std::fs::write(
+            project.path().join(".nvim-web/config.toml"),
+            "[editor]\nprofile = \"bare\"\ncwd = \"src\"\n",
+        )
+        .unwrap();
+        let mut request = LaunchRequest {
+            profile: None,
+            project: Some(project.path().to_path_buf()),
+        };
+        let (name, profile) = profiles.resolve(&request).unwrap();
+        assert_eq!(name.as_deref(), Some("bare"));
+        assert!(profile.clean);
+        let src = project.path().canonicalize().unwrap().join("src");
+        assert_eq!(profile.working_dir(), Some(src));
+
+        request.profile = Some("team".to_string());
+        assert_eq!(
+            profiles.resolve(&request).unwrap().0.as_deref(),
+            Some("team")
+        );
+
+        request.project = Some(project.path().join("missing"));
+        assert!(profiles.resolve(&request).is_err());
+    }
+}
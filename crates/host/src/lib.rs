@@ -23,6 +23,9 @@ pub mod auth;
 // OIDC/BeyondCorp authentication
 pub mod oidc;
 
+// Append-only audit log of security-relevant events
+pub mod audit;
+
 // SSH tunnel management
 pub mod tunnel;
 
@@ -37,25 +40,44 @@ pub mod git;
 
 // Embedded UI assets (single-binary distribution)
 pub mod embedded;
+// Runtime Lua plugin for the sessions' Neovim (features, handshake)
+pub mod runtime;
 
 // Project configuration and magic link handling
 pub mod project;
+// Neovim launch profiles (binary, init, NVIM_APPNAME per session)
+pub mod launch;
 
 // Session sharing and snapshots
 pub mod sharing;
 
+// Sessions persisted on disk to be resumed after a restart
+pub mod registry;
+
+// Full session state capture/restore and snapshot archives
+pub mod snapshot;
+
 // Multi-user collaboration (viewers, cursor sync)
 pub mod collaboration;
 
 // CRDT support for real-time collaborative editing
 pub mod crdt;
 
+// Persistent review comments anchored to buffer lines
+pub mod comments;
+
 // Terminal PTY management (portable-pty)
 pub mod terminal;
 
 // Universal tool pipe (replaces hardcoded LLM providers)
 pub mod pipe;
 
+// Cancellable RPCs that stream notifications (LLM, search)
+pub mod requests;
+
+// LLM prompts over OpenAI-compatible APIs (streams through the tool pipe)
+pub mod llm;
+
 // Backend swap (docker, ssh, tcp, vfs hot-swapping)
 pub mod backend_swap;
 
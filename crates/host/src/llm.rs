@@ -0,0 +1,628 @@
+//! LLM prompts over OpenAI-compatible HTTP APIs
+//!
+//! A provider is a base URL, a default model and an optional API key, all
+//! stored per user in [`SettingsStore`], so one user can neither read
+//! another's keys nor send their prompts elsewhere. Requests run `curl` through
+//! [`pipe::run_pipe_streaming`]; the request (including the key) is passed
+//! as a curl config on stdin so it never appears in the process list.
+//!
+//! Tokens are pushed to the browser as notifications on the session's
+//! redraw channel:
+//!
+//! - `llm_token [request_id, text]`
+//! - `llm_done [request_id, reason]` where reason is `"stop"` or `"cancelled"`
+//! - `llm_error [request_id, message]`
+
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use rmpv::Value;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::pipe;
+use crate::requests::StreamingRequests;
+use crate::settings::UserSettings;
+
+/// Setting holding the active provider name
+pub const PROVIDER_SETTING: &str = "llm.provider";
+
+const DEFAULT_PROVIDER: &str = "openai";
+const CURL: &str = "curl";
+
+/// Built-in providers: (name, base URL, default model)
+const BUILTIN_PROVIDERS: &[(&str, &str, Option<&str>)] = &[
+    ("openai", "https://api.openai.com/v1", Some("gpt-4o-mini")),
+    ("ollama", "http://localhost:11434/v1", Some("llama3.2")),
+    ("openrouter", "https://openrouter.ai/api/v1", None),
+];
+
+/// Settings key for a per-provider field, e.g. `llm.api_key.openai`
+pub fn setting_key(field: &str, provider: &str) -> String {
+    format!("llm.{field}.{provider}")
+}
+
+/// Resolved OpenAI-compatible provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provider {
+    pub name: String,
+    pub base_url: String,
+    pub model: Option<String>,
+    pub api_key: Option<String>,
+}
+
+impl Provider {
+    /// Resolve `name` (or the active provider) from a user's settings
+    ///
+    /// Settings override the built-in defaults; unknown providers need
+    /// `llm.base_url.<name>` to be set.
+    pub fn load(store: &UserSettings<'_>, name: Option<&str>) -> Result<Self> {
+        let name = name
+            .map(ToString::to_string)
+            .or_else(|| store.get(PROVIDER_SETTING))
+            .unwrap_or_else(|| DEFAULT_PROVIDER.to_string());
+        let builtin = BUILTIN_PROVIDERS.iter().find(|(n, ..)| *n == name);
+
+        let base_url = store
+            .get(&setting_key("base_url", &name))
+            .or_else(|| builtin.map(|(_, url, _)| (*url).to_string()))
+            .with_context(|| format!("Unknown LLM provider '{name}', set its base_url first"))?;
+        let model = store
+            .get(&setting_key("model", &name))
+            .or_else(|| builtin.and_then(|(.., model)| model.map(ToString::to_string)));
+        let api_key = store
+            .get(&setting_key("api_key", &name))
+            .filter(|key| !key.is_empty());
+
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            name,
+            model,
+            api_key,
+        })
+    }
+
+    /// curl config for a request to `{base_url}/{path}`, POSTing `body` if set
+    fn curl_config(&self, path: &str, body: Option<&str>) -> String {
+        let mut config = format!("url = {}\n", quote(&format!("{}/{path}", self.base_url)));
+        config.push_str("silent\nshow-error\nfail-with-body\nno-buffer\n");
+        if let Some(key) = &self.api_key {
+            config.push_str(&format!(
+                "header = {}\n",
+                quote(&format!("Authorization: Bearer {key}"))
+            ));
+        }
+        if let Some(body) = body {
+            config.push_str("header = \"Content-Type: application/json\"\n");
+            config.push_str(&format!("data-raw = {}\n", quote(body)));
+        }
+        config
+    }
+
+    /// List model ids via `GET /models`
+    pub async fn list_models(&self) -> Result<Vec<String>> {
+        let config = self.curl_config("models", None);
+        let result = pipe::run_pipe(CURL, &curl_args(), &config, None).await?;
+        let body: serde_json::Value = serde_json::from_str(&result.stdout)
+            .ok()
+            .unwrap_or_default();
+        if result.exit_code != 0 {
+            bail!(error_message(&body).unwrap_or_else(|| exit_message(result.exit_code)));
+        }
+
+        let models = body["data"]
+            .as_array()
+            .context("Malformed /models response")?
+            .iter()
+            .filter_map(|m| m["id"].as_str().map(ToString::to_string))
+            .collect();
+        Ok(models)
+    }
+}
+
+/// Chat prompt sent with `llm_prompt`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Prompt {
+    /// (role, content) pairs
+    pub messages: Vec<(String, String)>,
+    /// Overrides the provider's default model
+    pub model: Option<String>,
+    /// Overrides the active provider
+    pub provider: Option<String>,
+}
+
+impl Prompt {
+    /// Parse `prompt` (a string or `[{role, content}, ...]`) and optional
+    /// `{model, provider, system}` options
+    pub fn from_values(prompt: &Value, opts: Option<&Value>) -> Result<Self> {
+        let mut messages = match prompt {
+            Value::String(s) => vec![("user".to_string(), s.as_str().unwrap_or("").to_string())],
+            Value::Array(items) => items
+                .iter()
+                .map(|item| {
+                    let role = map_str(item, "role").unwrap_or("user");
+                    let content = map_str(item, "content").context("Message without content")?;
+                    Ok((role.to_string(), content.to_string()))
+                })
+                .collect::<Result<_>>()?,
+            _ => bail!("Prompt must be a string or a list of messages"),
+        };
+
+        let opt = |key| opts.and_then(|o| map_str(o, key)).map(ToString::to_string);
+        if let Some(system) = opt("system") {
+            messages.insert(0, ("system".to_string(), system));
+        }
+        if messages.is_empty() {
+            bail!("Empty prompt");
+        }
+
+        Ok(Self {
+            messages,
+            model: opt("model"),
+            provider: opt("provider"),
+        })
+    }
+
+    /// Streaming chat completion request body
+    fn body(&self, provider: &Provider) -> Result<String> {
+        let model = self
+            .model
+            .as_ref()
+            .or(provider.model.as_ref())
+            .with_context(|| format!("No model configured for provider '{}'", provider.name))?;
+        let messages: Vec<_> = self
+            .messages
+            .iter()
+            .map(|(role, content)| serde_json::json!({ "role": role, "content": content }))
+            .collect();
+        Ok(serde_json::json!({ "model": model, "stream": true, "messages": messages }).to_string())
+    }
+}
+
+/// Start streaming a completion for `request_id`, notifying on `out`
+///
+/// Fails without spawning if the request id is already in use or no
+/// model is configured.
+pub fn start(
+    requests: &Arc<StreamingRequests>,
+    session_id: &str,
+    request_id: &str,
+    provider: Provider,
+    prompt: &Prompt,
+    out: broadcast::Sender<Vec<u8>>,
+) -> Result<()> {
+    let body = prompt.body(&provider)?;
+    requests.spawn(session_id, request_id, out, |stream| async move {
+        let on_token = |token: &str| {
+            stream.send("llm_token", vec![token.into()]);
+        };
+        match stream_completion(&provider, &body, on_token).await {
+            Ok(()) => stream.finish("llm_done", vec!["stop".into()]),
+            Err(e) => {
+                tracing::warn!(request_id = %stream.request_id(), error = %e, "LLM request failed");
+                stream.finish("llm_error", vec![e.to_string().into()]);
+            }
+        }
+    })
+}
+
+/// Cancel a running completion, returns false if it was not running
+pub fn cancel(
+    requests: &StreamingRequests,
+    session_id: &str,
+    request_id: &str,
+    out: &broadcast::Sender<Vec<u8>>,
+) -> bool {
+    // Aborting drops the pipe future, which kills curl
+    requests.cancel(
+        session_id,
+        request_id,
+        out,
+        "llm_done",
+        vec!["cancelled".into()],
+    )
+}
+
+async fn stream_completion(provider: &Provider, body: &str, on_token: impl Fn(&str)) -> Result<()> {
+    let config = provider.curl_config("chat/completions", Some(body));
+    let (tx, mut rx) = mpsc::channel::<String>(32);
+    let args = curl_args();
+
+    let run = pipe::run_pipe_streaming(CURL, &args, &config, tx);
+    let relay = async {
+        let mut parser = SseParser::default();
+        while let Some(chunk) = rx.recv().await {
+            for token in parser.push(&chunk) {
+                on_token(&token);
+            }
+        }
+        parser.finish();
+        parser
+    };
+    let (status, parser) = tokio::join!(run, relay);
+
+    let status = status?;
+    if let Some(error) = parser.error_message() {
+        bail!(error);
+    }
+    if status != 0 {
+        bail!(exit_message(status));
+    }
+    Ok(())
+}
+
+/// Incremental parser for an OpenAI-style `text/event-stream` body
+///
+/// Lines that are not SSE fields are kept so an error body (sent instead
+/// of a stream on HTTP errors) can be reported.
+#[derive(Debug, Default)]
+struct SseParser {
+    line: String,
+    error: Option<String>,
+    other: String,
+}
+
+impl SseParser {
+    /// Feed a chunk, returning completed content tokens
+    fn push(&mut self, chunk: &str) -> Vec<String> {
+        let mut tokens = Vec::new();
+        for c in chunk.chars() {
+            if c == '\n' {
+                let line = std::mem::take(&mut self.line);
+                tokens.extend(self.line_done(line.trim_end_matches('\r')));
+            } else {
+                self.line.push(c);
+            }
+        }
+        tokens
+    }
+
+    /// Flush an unterminated last line
+    fn finish(&mut self) {
+        let line = std::mem::take(&mut self.line);
+        if !line.is_empty() {
+            self.line_done(&line);
+        }
+    }
+
+    fn line_done(&mut self, line: &str) -> Option<String> {
+        let Some(data) = line.strip_prefix("data:") else {
+            if !line.is_empty() && !line.starts_with(':') && !line.starts_with("event:") {
+                self.other.push_str(line);
+                self.other.push('\n');
+            }
+            return None;
+        };
+        let data = data.trim();
+        if data == "[DONE]" {
+            return None;
+        }
+
+        let event: serde_json::Value = serde_json::from_str(data).ok()?;
+        if let Some(message) = error_message(&event) {
+            self.error = Some(message);
+            return None;
+        }
+        event["choices"][0]["delta"]["content"]
+            .as_str()
+            .filter(|s| !s.is_empty())
+            .map(ToString::to_string)
+    }
+
+    /// Error reported in the stream or in a non-stream body
+    fn error_message(&self) -> Option<String> {
+        self.error.clone().or_else(|| {
+            let body = self.other.trim();
+            let json = serde_json::from_str(body).ok().unwrap_or_default();
+            error_message(&json).or_else(|| (!body.is_empty()).then(|| body.to_string()))
+        })
+    }
+}
+
+/// `{"error": {"message": ...}}` or `{"error": "..."}`
+fn error_message(body: &serde_json::Value) -> Option<String> {
+    let error = body.get("error")?;
+    error["message"]
+        .as_str()
+        .or_else(|| error.as_str())
+        .map(ToString::to_string)
+}
+
+fn exit_message(code: i32) -> String {
+    format!("LLM request failed (curl exit code {code})")
+}
+
+fn curl_args() -> Vec<String> {
+    vec!["--config".to_string(), "-".to_string()]
+}
+
+/// Quote a curl config value
+fn quote(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+    format!("\"{escaped}\"")
+}
+
+fn map_str<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value
+        .as_map()?
+        .iter()
+        .find(|(k, _)| k.as_str() == Some(key))
+        .and_then(|(_, v)| v.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::IntoResponse;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+
+    use nvim_web_protocol::schema::HostMessage;
+
+    use super::*;
+    use crate::settings::SettingsStore;
+
+    const STUB_KEY: &str = "sk-stub";
+
+    async fn chat(
+        headers: HeaderMap,
+        Json(body): Json<serde_json::Value>,
+    ) -> axum::response::Response {
+        if headers.get("authorization").and_then(|v| v.to_str().ok())
+            != Some(&format!("Bearer {STUB_KEY}"))
+        {
+            let error = serde_json::json!({ "error": { "message": "Invalid API key" } });
+            return (StatusCode::UNAUTHORIZED, Json(error)).into_response();
+        }
+
+        let event = |text: &str| {
+            let delta = serde_json::json!({ "choices": [{ "delta": { "content": text } }] });
+            Ok::<_, std::io::Error>(format!("data: {delta}\n\n"))
+        };
+        let body = if body["model"] == "slow" {
+            let ticks = futures::stream::unfold(0, move |n| async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Some((event(&format!("tick{n} ")), n + 1))
+            });
+            axum::body::Body::from_stream(ticks)
+        } else {
+            let prompt = body["messages"][0]["content"].as_str().unwrap_or("");
+            let chunks = vec![
+                event("Hel"),
+                event("lo, "),
+                event(prompt),
+                Ok("data: [DONE]\n\n".to_string()),
+            ];
+            axum::body::Body::from_stream(futures::stream::iter(chunks))
+        };
+        ([("content-type", "text/event-stream")], body).into_response()
+    }
+
+    async fn models() -> Json<serde_json::Value> {
+        Json(serde_json::json!({ "data": [{ "id": "stub-small" }, { "id": "stub-large" }] }))
+    }
+
+    /// Serve a stub OpenAI-compatible API, returning its base URL
+    async fn stub_server() -> String {
+        let app = Router::new()
+            .route("/v1/chat/completions", post(chat))
+            .route("/v1/models", get(models));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{addr}/v1")
+    }
+
+    fn provider(base_url: String, api_key: Option<&str>) -> Provider {
+        Provider {
+            name: "stub".to_string(),
+            base_url,
+            model: Some("stub-small".to_string()),
+            api_key: api_key.map(ToString::to_string),
+        }
+    }
+
+    /// Collect notifications until `llm_done` or `llm_error`
+    async fn collect(rx: &mut broadcast::Receiver<Vec<u8>>) -> Vec<(String, String)> {
+        let mut events = Vec::new();
+        loop {
+            let bytes = tokio::time::timeout(Duration::from_secs(10), rx.recv())
+                .await
+                .expect("timed out waiting for notification")
+                .unwrap();
+            let Ok(HostMessage::Notification { method, params }) = HostMessage::decode(&bytes)
+            else {
+                panic!("expected notification");
+            };
+            let text = params[1].as_str().unwrap().to_string();
+            let done = method != "llm_token";
+            events.push((method, text));
+            if done {
+                return events;
+            }
+        }
+    }
+
+    #[test]
+    fn sse_parser_handles_split_lines() {
+        let mut parser = SseParser::default();
+        let mut tokens = parser.push("data: {\"choices\":[{\"delta\":{\"content\":\"a");
+        tokens.extend(parser.push("b\"}}]}\r\n\r\n: keep-alive\n"));
+        tokens.extend(parser.push("data: {\"choices\":[{\"delta\":{}}]}\ndata: [DONE]\n"));
+        assert_eq!(tokens, vec!["ab"]);
+        assert_eq!(parser.error_message(), None);
+
+        let mut parser = SseParser::default();
+        parser.push("{\"error\": {\"message\": \"quota exceeded\"}}");
+        parser.finish();
+        assert_eq!(parser.error_message().as_deref(), Some("quota exceeded"));
+    }
+
+    #[test]
+    fn prompt_from_values() {
+        let opts = Value::Map(vec![
+            ("system".into(), "be brief".into()),
+            ("model".into(), "m1".into()),
+        ]);
+        let prompt = Prompt::from_values(&"hi".into(), Some(&opts)).unwrap();
+        assert_eq!(
+            prompt.messages,
+            vec![
+                ("system".to_string(), "be brief".to_string()),
+                ("user".to_string(), "hi".to_string())
+            ]
+        );
+        assert_eq!(prompt.model.as_deref(), Some("m1"));
+        assert!(Prompt::from_values(&Value::Nil, None).is_err());
+    }
+
+    #[test]
+    fn provider_resolves_from_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = SettingsStore::open(&dir.path().join("settings.db")).unwrap();
+        let store = UserSettings::new(&settings, Some("alice"));
+
+        let openai = Provider::load(&store, None).unwrap();
+        assert_eq!(openai.base_url, "https://api.openai.com/v1");
+        assert_eq!(openai.api_key, None);
+        assert!(Provider::load(&store, Some("local")).is_err());
+
+        store.set(PROVIDER_SETTING, "local").unwrap();
+        store
+            .set(
+                &setting_key("base_url", "local"),
+                "http://127.0.0.1:8080/v1/",
+            )
+            .unwrap();
+        store
+            .set(&setting_key("api_key", "local"), "secret")
+            .unwrap();
+        let local = Provider::load(&store, None).unwrap();
+        assert_eq!(local.name, "local");
+        assert_eq!(local.base_url, "http://127.0.0.1:8080/v1");
+        assert_eq!(local.model, None);
+        assert_eq!(local.api_key.as_deref(), Some("secret"));
+        assert!(Prompt::from_values(&"hi".into(), None)
+            .unwrap()
+            .body(&local)
+            .is_err());
+
+        // Other users keep the defaults
+        let bob = UserSettings::new(&settings, Some("bob"));
+        assert_eq!(Provider::load(&bob, None).unwrap(), openai);
+    }
+
+    #[tokio::test]
+    async fn prompt_streams_tokens_then_done() {
+        let base_url = stub_server().await;
+        let requests = StreamingRequests::new();
+        let (out, mut rx) = broadcast::channel(64);
+        let prompt = Prompt::from_values(&"world".into(), None).unwrap();
+
+        start(
+            &requests,
+            "s1",
+            "r1",
+            provider(base_url, Some(STUB_KEY)),
+            &prompt,
+            out,
+        )
+        .unwrap();
+        let events = collect(&mut rx).await;
+
+        let text: String = events
+            .iter()
+            .filter(|(method, _)| method == "llm_token")
+            .map(|(_, text)| text.as_str())
+            .collect();
+        assert_eq!(text, "Hello, world");
+        assert_eq!(
+            events.last().unwrap(),
+            &("llm_done".to_string(), "stop".to_string())
+        );
+        assert!(!requests.is_running("s1", "r1"));
+    }
+
+    #[tokio::test]
+    async fn http_error_is_reported() {
+        let base_url = stub_server().await;
+        let requests = StreamingRequests::new();
+        let (out, mut rx) = broadcast::channel(64);
+        let prompt = Prompt::from_values(&"hi".into(), None).unwrap();
+
+        start(
+            &requests,
+            "s1",
+            "r1",
+            provider(base_url, Some("wrong")),
+            &prompt,
+            out,
+        )
+        .unwrap();
+        let events = collect(&mut rx).await;
+        assert_eq!(
+            events,
+            vec![("llm_error".to_string(), "Invalid API key".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn cancel_stops_stream() {
+        let base_url = stub_server().await;
+        let requests = StreamingRequests::new();
+        let (out, mut rx) = broadcast::channel(64);
+        let mut prompt = Prompt::from_values(&"hi".into(), None).unwrap();
+        prompt.model = Some("slow".to_string());
+
+        start(
+            &requests,
+            "s1",
+            "r1",
+            provider(base_url, Some(STUB_KEY)),
+            &prompt,
+            out.clone(),
+        )
+        .unwrap();
+        // Same id cannot be reused while running
+        assert!(start(
+            &requests,
+            "s1",
+            "r1",
+            provider(String::new(), None),
+            &prompt,
+            out.clone()
+        )
+        .is_err());
+
+        // Wait for the stream to start before cancelling
+        let first = rx.recv().await.unwrap();
+        assert!(matches!(
+            HostMessage::decode(&first),
+            Ok(HostMessage::Notification { method, .. }) if method == "llm_token"
+        ));
+        assert!(!cancel(&requests, "s2", "r1", &out));
+        assert!(cancel(&requests, "s1", "r1", &out));
+        assert!(!requests.is_running("s1", "r1"));
+
+        let events = collect(&mut rx).await;
+        assert_eq!(
+            events.last().unwrap(),
+            &("llm_done".to_string(), "cancelled".to_string())
+        );
+        // Nothing arrives after the cancellation
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn list_models_from_stub() {
+        let base_url = stub_server().await;
+        let models = provider(base_url, None).list_models().await.unwrap();
+        assert_eq!(models, vec!["stub-small", "stub-large"]);
+    }
+}
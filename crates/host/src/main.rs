@@ -9,16 +9,22 @@ use axum::{
     Router,
 };
 use nvim_web_host::api;
+use nvim_web_host::audit;
 use nvim_web_host::auth;
 use nvim_web_host::config::Config;
 use nvim_web_host::embedded;
 use nvim_web_host::native;
+use nvim_web_host::oidc;
+use nvim_web_host::registry::SessionRegistry;
+use nvim_web_host::runtime;
 use nvim_web_host::session::AsyncSessionManager;
+use nvim_web_host::sharing;
 use nvim_web_host::transport::{serve_webtransport, WebTransportConfig};
 use nvim_web_host::vfs::{BrowserFsBackend, FsRequestRegistry, LocalFs, VfsManager};
 use nvim_web_host::ws;
 use std::fs::File;
 use std::io::BufReader;
+use std::net::SocketAddr;
 use tokio::signal;
 use tokio::sync::RwLock;
 use tokio_rustls::rustls::ServerConfig;
@@ -639,6 +645,8 @@ async fn main() -> anyhow::Result<()> {
         config.remote.auth_token = Some(token);
     }
 
+    sharing::init(auth::ShareTokens::new(config.session.share_token_bytes));
+
     // === GRACEFUL START ===
     eprintln!("  \x1b[1;33m[init]\x1b[0m   Running startup checks...");
 
@@ -690,6 +698,93 @@ async fn main() -> anyhow::Result<()> {
         }
     };
 
+    // === OIDC LOGIN (optional) ===
+    let oidc_client = match Config::load_auth(&Config::default_config_path()) {
+        Ok(auth_config) if auth_config.enabled => {
+            let issuer = auth_config.issuer.clone();
+            match oidc::OidcClient::new(auth_config).await {
+                Ok(client) => {
+                    let client = Arc::new(client);
+                    client.start_renewal();
+                    eprintln!("  \x1b[1;36m[auth]\x1b[0m   OIDC login required (issuer: {issuer})");
+                    Some(client)
+                }
+                Err(e) => {
+                    eprintln!("  \x1b[1;31m[error]\x1b[0m  Failed to set up OIDC login: {e:#}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Ok(_) => None,
+        Err(e) => {
+            eprintln!("  \x1b[1;31m[error]\x1b[0m  {e:#}");
+            std::process::exit(1);
+        }
+    };
+
+    // === TOOLS (tool_exec) ===
+    let tools = match Config::load_tools(&Config::default_config_path()) {
+        Ok(tools) => {
+            if !tools.is_empty() {
+                eprintln!(
+                    "  \x1b[1;36m[tools]\x1b[0m  tool_exec may run: {}",
+                    tools.names().join(", ")
+                );
+            }
+            tools
+        }
+        Err(e) => {
+            eprintln!("  \x1b[1;31m[error]\x1b[0m  {e:#}");
+            std::process::exit(1);
+        }
+    };
+
+    // === LAUNCH PROFILES ===
+    let profiles = match Config::load_profiles(&Config::default_config_path()) {
+        Ok(profiles) => {
+            if !profiles.is_empty() {
+                eprintln!(
+                    "  \x1b[1;36m[nvim]\x1b[0m   Launch profiles: {}",
+                    profiles.names().join(", ")
+                );
+            }
+            profiles
+        }
+        Err(e) => {
+            eprintln!("  \x1b[1;31m[error]\x1b[0m  {e:#}");
+            std::process::exit(1);
+        }
+    };
+
+    // === RUNTIME PLUGIN ===
+    match runtime::runtime_dir() {
+        Ok(dir) => {
+            let disabled: Vec<&str> = config
+                .plugin
+                .features()
+                .into_iter()
+                .filter_map(|(name, enabled)| (!enabled).then_some(name))
+                .collect();
+            eprintln!(
+                "  \x1b[1;36m[nvim]\x1b[0m   Runtime plugin {} in {}{}",
+                runtime::PLUGIN_VERSION,
+                dir.display(),
+                if disabled.is_empty() {
+                    String::new()
+                } else {
+                    format!(" (off: {})", disabled.join(", "))
+                }
+            );
+        }
+        Err(e) => eprintln!("  \x1b[1;33m[warn]\x1b[0m   {e:#}"),
+    }
+
+    // === AUDIT LOG ===
+    if let Err(e) = audit::init(&config.audit) {
+        eprintln!("  \x1b[1;31m[error]\x1b[0m  Failed to open audit log: {e:#}");
+        std::process::exit(1);
+    }
+
     // Create VFS manager with local filesystem backend
     let vfs = VfsManager::new();
     let home_dir = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
@@ -721,6 +816,28 @@ async fn main() -> anyhow::Result<()> {
 
     // Create async session manager with VFS access
     let mut mgr = AsyncSessionManager::new(vfs_manager.clone());
+    mgr.max_sessions = config.session.max_sessions;
+    mgr.tools = Arc::new(tools);
+    mgr.profiles = Arc::new(profiles);
+    mgr.plugin = Arc::new(config.plugin.clone());
+    if config.session.resume_days > 0 {
+        mgr.registry = match SessionRegistry::new() {
+            Ok(registry) => {
+                let max_age =
+                    std::time::Duration::from_secs(config.session.resume_days * 24 * 3600);
+                if let Err(e) = registry.prune(max_age) {
+                    eprintln!(
+                        "  \x1b[1;33m[warn]\x1b[0m   Failed to prune session registry: {e:#}"
+                    );
+                }
+                Some(registry)
+            }
+            Err(e) => {
+                eprintln!("  \x1b[1;33m[warn]\x1b[0m   Sessions will not be resumable: {e:#}");
+                None
+            }
+        };
+    }
 
     // Configure remote backend if enabled
     if config.remote.enabled {
@@ -742,6 +859,9 @@ async fn main() -> anyhow::Result<()> {
     let session_manager = Arc::new(RwLock::new(mgr));
     let session_manager_shutdown = session_manager.clone();
 
+    // Share links persist across restarts; prune stale ones now and periodically
+    sharing::spawn_cleanup(std::time::Duration::from_secs(600));
+
     print_connection_info(http_port, ws_port, &config.server.bind, true);
 
     // === START EMBEDDED HTTP SERVER (axum) ===
@@ -787,15 +907,19 @@ async fn main() -> anyhow::Result<()> {
     let app_state = api::AppState {
         session_manager: session_manager.clone(),
         ws_port,
+        auth: oidc_client.clone(),
     };
 
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/", get(serve_index))
         .route("/config.js", get(serve_config_js)) // Serve dynamic config
         .route("/*path", get(serve_static))
-        .nest("/api", api::api_router())
-        .with_state(app_state) // Verify this usage?
-        .layer(cors);
+        .nest("/api", api::api_router(app_state.clone()))
+        .with_state(app_state);
+    if let Some(client) = &oidc_client {
+        app = app.nest("/auth", oidc::auth_routes(client.clone()));
+    }
+    let app = app.layer(cors);
 
     let http_addr = format!("{}:{}", config.server.bind, http_port);
     let http_listener = tokio::net::TcpListener::bind(&http_addr).await?;
@@ -808,7 +932,7 @@ async fn main() -> anyhow::Result<()> {
             let tls_acceptor = TlsAcceptor::from(tls_config);
             loop {
                 // Accept TCP connection
-                let (stream, _addr) = match http_listener.accept().await {
+                let (stream, addr) = match http_listener.accept().await {
                     Ok(conn) => conn,
                     Err(e) => {
                         tracing::warn!("HTTP accept error: {}", e);
@@ -832,7 +956,11 @@ async fn main() -> anyhow::Result<()> {
                                     async move {
                                         use tower::ServiceExt; // for oneshot
                                         let (parts, body) = req.into_parts();
-                                        let req = http::Request::from_parts(parts, Body::new(body));
+                                        let mut req =
+                                            http::Request::from_parts(parts, Body::new(body));
+                                        // The access policy checks the client address
+                                        req.extensions_mut()
+                                            .insert(axum::extract::ConnectInfo(addr));
                                         app.oneshot(req).await
                                     }
                                 },
@@ -855,6 +983,7 @@ async fn main() -> anyhow::Result<()> {
             }
         } else {
             // HTTP Mode (Standard Axum)
+            let app = app.into_make_service_with_connect_info::<SocketAddr>();
             if let Err(e) = axum::serve(http_listener, app).await {
                 tracing::error!("HTTP server error: {}", e);
             }
@@ -907,15 +1036,11 @@ async fn main() -> anyhow::Result<()> {
         let session_count = mgr.session_count();
         eprintln!("  \x1b[1;33m[cleanup]\x1b[0m Cleaning up {session_count} sessions...");
 
-        // Trigger graceful shutdown (auto-save) for all sessions
+        // Trigger graceful shutdown (auto-save) for all sessions; this also
+        // closes them, keeping them in the registry to be resumed
         mgr.shutdown_all().await;
-
-        // Clean up all sessions (remove from map)
-        let ids: Vec<String> = mgr.session_ids();
-        for id in ids {
-            mgr.remove_session(&id);
-        }
         drop(mgr); // Explicit drop to satisfy clippy significant_drop_tightening
+        audit::flush();
 
         eprintln!("  \x1b[1;32m[done]\x1b[0m   Later! Stay chill.");
         eprintln!();
@@ -924,9 +1049,13 @@ async fn main() -> anyhow::Result<()> {
     // Run all servers concurrently with shutdown handler
     // Clone session_manager for WebTransport
     let wt_session_manager = session_manager.clone();
+    let wt_fs_registry = fs_registry.clone();
+    let wt_vfs_manager = vfs_manager.clone();
+    let wt_fs_req_tx = fs_req_tx.clone();
+    let wt_auth = oidc_client.clone();
 
     tokio::select! {
-        result = ws::serve_multi_async(session_manager, ws_port, Some(fs_registry), Some(vfs_manager), Some(fs_req_tx), tls_config) => {
+        result = ws::serve_multi_async(session_manager, ws_port, Some(fs_registry), Some(vfs_manager), Some(fs_req_tx), tls_config, oidc_client) => {
             result?;
         }
         _ = http_server => {
@@ -935,7 +1064,15 @@ async fn main() -> anyhow::Result<()> {
         // WebTransport server (if configured)
         _ = async {
             if let Some(wt_config) = webtransport_config {
-                if let Err(e) = serve_webtransport(wt_session_manager, wt_config, None, None).await {
+                if let Err(e) = serve_webtransport(
+                    wt_session_manager,
+                    wt_config,
+                    Some(wt_fs_registry),
+                    Some(wt_vfs_manager),
+                    Some(wt_fs_req_tx),
+                    wt_auth,
+                )
+                .await {
                     eprintln!("  \x1b[1;31m[error]\x1b[0m  WebTransport server error: {e}");
                 }
             } else {
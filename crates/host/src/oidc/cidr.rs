@@ -0,0 +1,220 @@
+//! Client address resolution for access policies
+//!
+//! Parses IPv4/IPv6 CIDR ranges and works out the real client address of a
+//! request: forwarded headers are only believed when the TCP peer is a
+//! trusted proxy, otherwise the peer address itself is the client. Ranges
+//! are parsed when the config is loaded, so an invalid one stops the host
+//! from starting rather than being skipped on every request.
+
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use axum::http::HeaderMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// An IP address range such as `10.0.0.0/8` or `fd00::/8`
+///
+/// A bare address is a range holding just that address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Whether `ip` lies in this range
+    ///
+    /// IPv4-mapped IPv6 addresses (`::ffff:10.0.0.1`) match IPv4 ranges.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, canonical(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                masked(u32::from(net).into(), self.prefix, 32)
+                    == masked(u32::from(ip).into(), self.prefix, 32)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                masked(net.into(), self.prefix, 128) == masked(ip.into(), self.prefix, 128)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let network = canonical(
+            addr.parse::<IpAddr>()
+                .map_err(|_| anyhow::anyhow!("Invalid address in range: {s}"))?,
+        );
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|&p| p <= max)
+                .ok_or_else(|| anyhow::anyhow!("Invalid prefix length in range: {s}"))?,
+            None => max,
+        };
+        Ok(Self { network, prefix })
+    }
+}
+
+impl std::fmt::Display for Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+impl Serialize for Cidr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cidr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// The client address of a request arriving from `peer`
+///
+/// When `peer` is a trusted proxy, `X-Forwarded-For` is read from the right,
+/// skipping further trusted proxies, and the first other address is the
+/// client; `X-Real-IP` is used when there is no `X-Forwarded-For`. Headers
+/// from untrusted peers are ignored, as anyone can set them.
+pub fn client_ip(peer: Option<IpAddr>, headers: &HeaderMap, trusted: &[Cidr]) -> Option<IpAddr> {
+    let peer = canonical(peer?);
+    if !trusted.iter().any(|proxy| proxy.contains(peer)) {
+        return Some(peer);
+    }
+
+    let hops: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect();
+    if hops.is_empty() {
+        let real = headers
+            .get("x-real-ip")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<IpAddr>().ok());
+        return Some(real.map_or(peer, canonical));
+    }
+
+    let mut client = peer;
+    for hop in hops.iter().rev() {
+        // An unreadable hop ends the part of the chain we can vouch for
+        let Ok(hop) = hop.parse::<IpAddr>() else {
+            break;
+        };
+        client = canonical(hop);
+        if !trusted.iter().any(|proxy| proxy.contains(client)) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        v4 => v4,
+    }
+}
+
+fn masked(bits: u128, prefix: u8, width: u8) -> u128 {
+    if prefix == 0 {
+        0
+    } else {
+        bits >> (width - prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let net: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(net.contains(ip("10.1.2.3")));
+        assert!(net.contains(ip("::ffff:10.1.2.3")));
+        assert!(!net.contains(ip("11.0.0.1")));
+        assert!(!net.contains(ip("100.0.0.1")));
+
+        let net: Cidr = "192.168.1.0/24".parse().unwrap();
+        assert!(net.contains(ip("192.168.1.200")));
+        assert!(!net.contains(ip("192.168.2.1")));
+
+        let net: Cidr = "fd00::/8".parse().unwrap();
+        assert!(net.contains(ip("fd12::1")));
+        assert!(!net.contains(ip("fe80::1")));
+        assert!(!net.contains(ip("10.0.0.1")));
+
+        let single: Cidr = "203.0.113.7".parse().unwrap();
+        assert!(single.contains(ip("203.0.113.7")));
+        assert!(!single.contains(ip("203.0.113.8")));
+        assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains(ip("8.8.8.8")));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_cidr_serde() {
+        let ranges: Vec<Cidr> = serde_json::from_str(r#"["10.0.0.0/8", "::1"]"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&ranges).unwrap(),
+            r#"["10.0.0.0/8","::1/128"]"#
+        );
+        assert!(serde_json::from_str::<Vec<Cidr>>(r#"["10.0.0.0/33"]"#).is_err());
+    }
+
+    #[test]
+    fn test_client_ip_ignores_untrusted_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "10.0.0.1".parse().unwrap());
+        headers.insert("x-real-ip", "10.0.0.1".parse().unwrap());
+        let peer = Some(ip("203.0.113.7"));
+        assert_eq!(client_ip(peer, &headers, &[]), peer);
+        assert_eq!(client_ip(None, &headers, &[]), None);
+    }
+
+    #[test]
+    fn test_client_ip_through_trusted_proxies() {
+        let trusted: Vec<Cidr> = ["127.0.0.1", "172.16.0.0/12"]
+            .iter()
+            .map(|range| range.parse().unwrap())
+            .collect();
+        let mut headers = HeaderMap::new();
+        // The client spoofed the first hop; the proxies appended the rest
+        headers.insert(
+            "x-forwarded-for",
+            "10.0.0.1, 198.51.100.4, 172.16.0.9".parse().unwrap(),
+        );
+        let client = client_ip(Some(ip("127.0.0.1")), &headers, &trusted);
+        assert_eq!(client, Some(ip("198.51.100.4")));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", "198.51.100.4".parse().unwrap());
+        let client = client_ip(Some(ip("127.0.0.1")), &headers, &trusted);
+        assert_eq!(client, Some(ip("198.51.100.4")));
+
+        let client = client_ip(Some(ip("127.0.0.1")), &HeaderMap::new(), &trusted);
+        assert_eq!(client, Some(ip("127.0.0.1")));
+    }
+}
@@ -1,17 +1,28 @@
 //! OIDC client implementation
 //!
 //! Handles OAuth2/OIDC flows with a simple reqwest-based implementation.
-//! For production use, consider using the openidconnect crate with full
-//! OIDC discovery and JWT validation.
+//! Endpoints come from OIDC discovery, and the ID token returned by the code
+//! exchange is verified against the provider's JWKS before anyone is logged in.
+//! Refresh tokens stay on the host, which renews sessions before they expire.
 
 use anyhow::Result;
+use axum::http::{HeaderMap, StatusCode};
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 
 use super::config::AuthConfig;
-use super::AuthUser;
+use super::discovery::ProviderMetadata;
+use super::id_token::{IdTokenClaims, IdTokenValidator};
+use super::renewal::{self, AuthWatch, Renewals};
+use super::session::{self, AuthSession, SessionKeys};
+use super::{AuthUser, Grant, PolicyResult};
+
+/// How long a request to the provider may take
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// OIDC client for authentication flows
 pub struct OidcClient {
@@ -19,13 +30,24 @@ pub struct OidcClient {
     http_client: reqwest::Client,
     /// Configuration
     config: AuthConfig,
+    /// Discovered provider endpoints
+    metadata: ProviderMetadata,
+    /// ID token verification with cached JWKS
+    id_tokens: IdTokenValidator,
     /// Pending authentication states (state -> verifier)
     pending: Arc<RwLock<HashMap<String, PendingAuth>>>,
+    /// Session cookie signing keys
+    sessions: SessionKeys,
+    /// Refresh tokens of logged-in sessions
+    renewals: Arc<Renewals>,
+    /// Stamped on the sessions this process issues
+    epoch: String,
 }
 
 /// Pending authentication state
 struct PendingAuth {
     code_verifier: String,
+    nonce: String,
     created_at: std::time::Instant,
 }
 
@@ -39,6 +61,8 @@ struct TokenResponse {
     expires_in: Option<u64>,
     #[serde(default)]
     id_token: Option<String>,
+    #[serde(default)]
+    refresh_token: Option<String>,
 }
 
 /// UserInfo response
@@ -49,19 +73,33 @@ struct UserInfoResponse {
     email: Option<String>,
     #[serde(default)]
     name: Option<String>,
+    /// Every other claim
+    #[serde(flatten)]
+    other: serde_json::Map<String, serde_json::Value>,
 }
 
 impl OidcClient {
     /// Create a new OIDC client from configuration
+    ///
+    /// Fetches the provider metadata from the issuer's discovery document.
     pub async fn new(config: AuthConfig) -> Result<Self> {
         let http_client = reqwest::Client::builder()
             .redirect(reqwest::redirect::Policy::none())
+            .timeout(HTTP_TIMEOUT)
             .build()?;
+        let sessions = SessionKeys::from_config(&config.session)?;
+        let metadata = ProviderMetadata::discover(&http_client, &config.issuer).await?;
+        let id_tokens = IdTokenValidator::new(http_client.clone(), &metadata, &config.client_id);
 
         Ok(Self {
             http_client,
             config,
+            metadata,
+            id_tokens,
             pending: Arc::new(RwLock::new(HashMap::new())),
+            sessions,
+            renewals: Renewals::new(),
+            epoch: generate_random_string(16),
         })
     }
 
@@ -71,15 +109,23 @@ impl OidcClient {
         let code_verifier = generate_code_verifier();
         let code_challenge = generate_code_challenge(&code_verifier);
         let state = generate_random_string(32);
+        let nonce = generate_random_string(32);
 
         // Build authorization URL
+        let separator = if self.metadata.authorization_endpoint.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
         let auth_url = format!(
-            "{}/authorize?response_type=code&client_id={}&redirect_uri={}&scope={}&state={}&code_challenge={}&code_challenge_method=S256",
-            self.config.issuer,
+            "{}{}response_type=code&client_id={}&redirect_uri={}&scope={}&state={}&nonce={}&code_challenge={}&code_challenge_method=S256",
+            self.metadata.authorization_endpoint,
+            separator,
             urlencoding::encode(&self.config.client_id),
             urlencoding::encode(&self.config.redirect_uri),
             urlencoding::encode(&self.config.scopes.join(" ")),
             urlencoding::encode(&state),
+            urlencoding::encode(&nonce),
             urlencoding::encode(&code_challenge),
         );
 
@@ -90,6 +136,7 @@ impl OidcClient {
                 state.clone(),
                 PendingAuth {
                     code_verifier,
+                    nonce,
                     created_at: std::time::Instant::now(),
                 },
             );
@@ -100,8 +147,8 @@ impl OidcClient {
         (auth_url, state)
     }
 
-    /// Exchange authorization code for tokens
-    pub async fn exchange_code(&self, code: &str, state: &str) -> Result<AuthUser> {
+    /// Exchange authorization code for tokens, starting a new session
+    pub async fn exchange_code(&self, code: &str, state: &str) -> Result<AuthSession> {
         // Get pending auth state
         let pending_auth = {
             let mut pending = self.pending.write().await;
@@ -110,67 +157,288 @@ impl OidcClient {
                 .ok_or_else(|| anyhow::anyhow!("Invalid or expired state"))?
         };
 
-        // Build token request
-        let token_url = format!("{}/oauth/token", self.config.issuer);
-        let mut params = HashMap::new();
-        params.insert("grant_type", "authorization_code");
-        params.insert("code", code);
-        params.insert("redirect_uri", &self.config.redirect_uri);
-        params.insert("client_id", &self.config.client_id);
-        params.insert("code_verifier", &pending_auth.code_verifier);
+        let token = self
+            .token_request(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.config.redirect_uri),
+                ("code_verifier", &pending_auth.code_verifier),
+            ])
+            .await
+            .map_err(|e| anyhow::anyhow!("Token exchange failed: {e}"))?;
+
+        // The ID token is what proves who logged in
+        let id_token = token
+            .id_token
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Provider returned no ID token"))?;
+        let claims = self
+            .id_tokens
+            .validate(id_token, Some(&pending_auth.nonce))
+            .await?;
+
+        let exp = expiry(token.expires_in, claims.exp);
+        let mut user = self.user_from_claims(claims);
+        user.exp = exp;
+        self.merge_userinfo(&mut user, &token.access_token).await?;
+
+        let session = AuthSession {
+            sid: generate_random_string(32),
+            user,
+            epoch: self.epoch.clone(),
+        };
+        self.renewals
+            .insert(&session.sid, &session.user, token.refresh_token);
+        Ok(session)
+    }
+
+    /// Session of a request, renewing its tokens if they expire soon
+    ///
+    /// The flag is set when the user in the cookie is out of date, so the
+    /// caller should send a fresh cookie. Returns `None` without a valid
+    /// cookie, or once the session has expired and the user must log in
+    /// again.
+    pub async fn authenticate(&self, headers: &HeaderMap) -> Option<(AuthSession, bool)> {
+        let session = self.sessions.session_from_headers(headers)?;
+        self.current(session).await
+    }
+
+    /// Authenticate a request and check it against the access policy
+    ///
+    /// A handshake `ticket` (see [`SessionKeys::ticket`]) stands in for the
+    /// cookie, for connections where the browser sends none. `peer` is the
+    /// address the request came from. Fails with `401 Unauthorized` without
+    /// a valid session, or `403 Forbidden` if the policy denies the user.
+    pub async fn authorize(
+        &self,
+        headers: &HeaderMap,
+        ticket: Option<&str>,
+        peer: Option<IpAddr>,
+    ) -> Result<(AuthSession, bool), StatusCode> {
+        let session = match ticket {
+            Some(ticket) => self.sessions.redeem(ticket),
+            None => self.sessions.session_from_headers(headers),
+        };
+        let Some(session) = session else {
+            return Err(StatusCode::UNAUTHORIZED);
+        };
+        let (session, stale) = self
+            .current(session)
+            .await
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+
+        let client_ip = self.config.policy.client_ip(peer, headers);
+        match self.config.policy.check(&session.user, client_ip) {
+            PolicyResult::Allowed => Ok((session, stale)),
+            PolicyResult::Denied(reason) => {
+                tracing::warn!(user = %session.user.sub, reason = %reason, "Access denied by policy");
+                Err(StatusCode::FORBIDDEN)
+            }
+        }
+    }
+
+    /// `session` with its current user, or `None` once it has expired
+    async fn current(&self, mut session: AuthSession) -> Option<(AuthSession, bool)> {
+        match self.renew(&session.sid).await {
+            Some(user) => {
+                let stale = user.exp != session.user.exp;
+                session.user = user;
+                Some((session, stale))
+            }
+            // Sessions from before a restart live out their tokens; this
+            // process's own were logged out or have expired
+            None if session.epoch != self.epoch && !session.token_expired() => {
+                Some((session, false))
+            }
+            None => None,
+        }
+    }
+
+    /// Renew a session's tokens if they expire soon
+    ///
+    /// Returns the session's current user, or `None` if the host does not
+    /// know the session or it has just expired. Failed renewals are retried
+    /// until the tokens actually expire.
+    pub async fn renew(&self, sid: &str) -> Option<AuthUser> {
+        let (user, _) = self.renewals.get(sid)?;
+        if !renewal::needs_renewal(&user) {
+            return Some(user);
+        }
+        let renewing = self.renewals.renewing(sid)?;
+        let _renewing = renewing.lock().await;
+        // Another request may have renewed the session meanwhile
+        let (user, refresh_token) = self.renewals.get(sid)?;
+        if !renewal::needs_renewal(&user) {
+            return Some(user);
+        }
 
-        let mut request = self.http_client.post(&token_url).form(&params);
+        let result = match refresh_token {
+            Some(refresh_token) => self.refresh(&user, &refresh_token).await,
+            None => Err(anyhow::anyhow!("Provider issued no refresh token")),
+        };
+        match result {
+            Ok((renewed, refresh_token)) => {
+                tracing::debug!(sub = %renewed.sub, "Renewed session");
+                self.renewals.renewed(sid, &renewed, refresh_token);
+                Some(renewed)
+            }
+            Err(e) if user.exp > session::now_secs() => {
+                tracing::debug!(error = %e, sub = %user.sub, "Session renewal failed, will retry");
+                Some(user)
+            }
+            Err(e) => {
+                tracing::info!(error = %e, sub = %user.sub, "Session expired");
+                self.renewals.expired(sid);
+                None
+            }
+        }
+    }
+
+    /// Redeem a refresh token, returning the renewed user and any new token
+    async fn refresh(
+        &self,
+        user: &AuthUser,
+        refresh_token: &str,
+    ) -> Result<(AuthUser, Option<String>)> {
+        let token = self
+            .token_request(&[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token),
+            ])
+            .await
+            .map_err(|e| anyhow::anyhow!("Token refresh failed: {e}"))?;
+
+        let mut renewed = match token.id_token.as_deref() {
+            Some(id_token) => {
+                let claims = self.id_tokens.validate(id_token, None).await?;
+                if claims.sub != user.sub {
+                    anyhow::bail!("Refreshed ID token names another subject");
+                }
+                self.user_from_claims(claims)
+            }
+            None if token.expires_in.is_some() => user.clone(),
+            None => anyhow::bail!("Refresh response has no expiry"),
+        };
+        renewed.exp = expiry(token.expires_in, renewed.exp);
+        self.merge_userinfo(&mut renewed, &token.access_token)
+            .await?;
+        Ok((renewed, token.refresh_token))
+    }
+
+    /// Post a grant to the token endpoint
+    async fn token_request(&self, grant: &[(&str, &str)]) -> Result<TokenResponse> {
+        let mut params: Vec<(&str, &str)> = grant.to_vec();
+        params.push(("client_id", &self.config.client_id));
+
+        let mut request = self
+            .http_client
+            .post(&self.metadata.token_endpoint)
+            .form(&params);
 
         // Add client secret if configured
         if let Some(ref secret) = self.config.client_secret {
             request = request.basic_auth(&self.config.client_id, Some(secret));
         }
 
-        // Exchange code for token
         let response = request.send().await?;
         if !response.status().is_success() {
             let error = response.text().await.unwrap_or_default();
-            anyhow::bail!("Token exchange failed: {}", error);
+            anyhow::bail!("{}", error);
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Add claims from userinfo that the ID token leaves out
+    async fn merge_userinfo(&self, user: &mut AuthUser, access_token: &str) -> Result<()> {
+        match self.fetch_userinfo(access_token).await {
+            Ok(info) if info.sub == user.sub => {
+                user.email = user.email.take().or(info.email);
+                user.name = user.name.take().or(info.name);
+                for (name, value) in info.other {
+                    if self.policy_uses(&name) {
+                        user.claims.entry(name).or_insert(value);
+                    }
+                }
+            }
+            Ok(_) => anyhow::bail!("UserInfo subject does not match ID token"),
+            Err(e) => tracing::debug!(error = %e, "UserInfo unavailable, using ID token claims"),
         }
+        Ok(())
+    }
 
-        let token: TokenResponse = response.json().await?;
-
-        // Calculate expiration
-        let exp = token
-            .expires_in
-            .map(|d| {
-                std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .map(|t| t.as_secs() + d)
-                    .unwrap_or(0)
-            })
-            .unwrap_or(0);
-
-        // Try to fetch user info
-        let user = self
-            .fetch_userinfo(&token.access_token)
-            .await
-            .unwrap_or_else(|_| AuthUser {
-                sub: format!(
-                    "user_{}",
-                    &token.access_token[..8.min(token.access_token.len())]
-                ),
-                email: None,
-                name: None,
-                groups: vec![],
-                exp,
-            });
+    /// Follow renewals and expiry of a session
+    ///
+    /// Watched sessions are renewed in the background; see
+    /// [`OidcClient::start_renewal`]. `None` if the host does not know the
+    /// session, as after a restart.
+    pub fn watch(&self, sid: &str) -> Option<AuthWatch> {
+        self.renewals.watch(sid)
+    }
+
+    /// Forget a session and its refresh token
+    ///
+    /// Its cookie is no longer accepted, and watchers see it expire.
+    pub fn logout(&self, sid: &str) {
+        self.renewals.remove(sid);
+    }
 
-        Ok(AuthUser { exp, ..user })
+    /// Renew watched sessions in the background until the client is dropped
+    ///
+    /// Also forgets sessions nobody has used for the session max age.
+    pub fn start_renewal(self: &Arc<Self>) {
+        let client = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(renewal::RENEW_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(client) = client.upgrade() else {
+                    break;
+                };
+                for sid in client.renewals.due() {
+                    client.renew(&sid).await;
+                }
+                let max_age = Duration::from_secs(client.config.session.max_age_secs);
+                client.renewals.prune(max_age);
+            }
+        });
+    }
+
+    /// Build the session user from validated ID token claims
+    fn user_from_claims(&self, claims: IdTokenClaims) -> AuthUser {
+        // Only keep what the policy checks, the user travels in a cookie
+        let claims_kept = claims
+            .other
+            .into_iter()
+            .filter(|(name, _)| self.policy_uses(name))
+            .collect();
+        AuthUser {
+            sub: claims.sub,
+            email: claims.email,
+            name: claims.name,
+            groups: claims.groups,
+            exp: claims.exp,
+            claims: claims_kept,
+        }
+    }
+
+    fn policy_uses(&self, claim: &str) -> bool {
+        self.config
+            .policy
+            .required_claim_names()
+            .any(|name| name == claim)
     }
 
     /// Fetch user info from userinfo endpoint
-    async fn fetch_userinfo(&self, access_token: &str) -> Result<AuthUser> {
-        let userinfo_url = format!("{}/userinfo", self.config.issuer);
+    async fn fetch_userinfo(&self, access_token: &str) -> Result<UserInfoResponse> {
+        let userinfo_url = self
+            .metadata
+            .userinfo_endpoint
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Provider has no userinfo endpoint"))?;
 
         let response = self
             .http_client
-            .get(&userinfo_url)
+            .get(userinfo_url)
             .bearer_auth(access_token)
             .send()
             .await?;
@@ -179,21 +447,30 @@ impl OidcClient {
             anyhow::bail!("UserInfo request failed");
         }
 
-        let info: UserInfoResponse = response.json().await?;
-
-        Ok(AuthUser {
-            sub: info.sub,
-            email: info.email,
-            name: info.name,
-            groups: vec![],
-            exp: 0,
-        })
+        Ok(response.json().await?)
     }
 
     /// Get configuration
     pub fn config(&self) -> &AuthConfig {
         &self.config
     }
+
+    /// Get session cookie keys
+    pub fn sessions(&self) -> &SessionKeys {
+        &self.sessions
+    }
+
+    /// Role and limits of a logged-in user
+    pub fn grant(&self, user: &AuthUser) -> Grant {
+        self.config.roles.grant(user)
+    }
+}
+
+/// Expiry of a token response: `expires_in` from now, or else `fallback`
+fn expiry(expires_in: Option<u64>, fallback: u64) -> u64 {
+    expires_in
+        .map(|d| session::now_secs() + d)
+        .unwrap_or(fallback)
 }
 
 /// Generate random code verifier for PKCE
@@ -12,9 +12,11 @@ pub struct AuthConfig {
     pub enabled: bool,
 
     /// OIDC issuer URL (e.g., https://accounts.google.com)
+    #[serde(default)]
     pub issuer: String,
 
     /// OAuth2 client ID
+    #[serde(default)]
     pub client_id: String,
 
     /// OAuth2 client secret (None for PKCE-only flows)
@@ -22,6 +24,7 @@ pub struct AuthConfig {
     pub client_secret: Option<String>,
 
     /// Redirect URI for OAuth callback
+    #[serde(default)]
     pub redirect_uri: String,
 
     /// Scopes to request (default: openid email profile)
@@ -35,6 +38,10 @@ pub struct AuthConfig {
     /// BeyondCorp access policy
     #[serde(default)]
     pub policy: super::AccessPolicy,
+
+    /// Roles of users by OIDC group, and the limits of each role
+    #[serde(default)]
+    pub roles: super::RolePolicy,
 }
 
 fn default_scopes() -> Vec<String> {
@@ -67,6 +74,14 @@ pub struct SessionConfig {
     /// SameSite policy
     #[serde(default = "default_same_site")]
     pub same_site: String,
+
+    /// Keys signing the session cookie, newest first (at least 32 characters)
+    ///
+    /// The first key signs new cookies; the others are still accepted so a
+    /// key can be rotated without ending existing sessions. A generated key
+    /// in ~/.config/nvim-web/session.key is used when this is empty.
+    #[serde(default)]
+    pub signing_keys: Vec<String>,
 }
 
 fn default_cookie_name() -> String {
@@ -93,6 +108,7 @@ impl Default for SessionConfig {
             secure: true,
             http_only: true,
             same_site: default_same_site(),
+            signing_keys: Vec::new(),
         }
     }
 }
@@ -108,6 +124,7 @@ impl Default for AuthConfig {
             scopes: default_scopes(),
             session: SessionConfig::default(),
             policy: super::AccessPolicy::default(),
+            roles: super::RolePolicy::default(),
         }
     }
 }
@@ -129,6 +146,7 @@ impl AuthConfig {
             ],
             session: SessionConfig::default(),
             policy: super::AccessPolicy::default(),
+            roles: super::RolePolicy::default(),
         }
     }
 
@@ -148,6 +166,7 @@ impl AuthConfig {
             ],
             session: SessionConfig::default(),
             policy: super::AccessPolicy::default(),
+            roles: super::RolePolicy::default(),
         }
     }
 
@@ -171,6 +190,7 @@ impl AuthConfig {
             ],
             session: SessionConfig::default(),
             policy: super::AccessPolicy::default(),
+            roles: super::RolePolicy::default(),
         }
     }
 }
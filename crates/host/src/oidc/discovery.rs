@@ -0,0 +1,54 @@
+//! OIDC provider discovery
+//!
+//! Reads the provider's endpoints and signing algorithms from
+//! `<issuer>/.well-known/openid-configuration`.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// The parts of the provider metadata the client uses
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    #[serde(default)]
+    pub userinfo_endpoint: Option<String>,
+    pub jwks_uri: String,
+    /// Algorithms the provider signs ID tokens with
+    #[serde(default = "default_signing_algs")]
+    pub id_token_signing_alg_values_supported: Vec<String>,
+}
+
+fn default_signing_algs() -> Vec<String> {
+    vec!["RS256".to_string()]
+}
+
+impl ProviderMetadata {
+    /// Fetch the metadata of `issuer`
+    ///
+    /// Fails unless the metadata names the same issuer, so a compromised or
+    /// misconfigured discovery document cannot swap in another provider.
+    pub async fn discover(http_client: &reqwest::Client, issuer: &str) -> Result<Self> {
+        let issuer = issuer.trim_end_matches('/');
+        let url = format!("{issuer}/.well-known/openid-configuration");
+        let response = http_client
+            .get(&url)
+            .send()
+            .await
+            .with_context(|| format!("Failed to fetch {url}"))?;
+        if !response.status().is_success() {
+            anyhow::bail!("Discovery failed: {} returned {}", url, response.status());
+        }
+        let metadata: Self = response.json().await.context("Invalid provider metadata")?;
+
+        if metadata.issuer.trim_end_matches('/') != issuer {
+            anyhow::bail!(
+                "Provider metadata names issuer {}, expected {}",
+                metadata.issuer,
+                issuer
+            );
+        }
+        Ok(metadata)
+    }
+}
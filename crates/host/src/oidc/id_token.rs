@@ -0,0 +1,249 @@
+//! ID token validation
+//!
+//! Verifies the signature of ID tokens against the provider's JWKS and checks
+//! the issuer, audience, expiry and nonce. Keys are cached; a token signed
+//! with a key id the cache does not know triggers a refetch, so providers can
+//! rotate keys without a restart.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use jsonwebtoken::jwk::{AlgorithmParameters, Jwk};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+use super::discovery::ProviderMetadata;
+
+/// How long fetched keys are used before they are fetched again
+const JWKS_TTL: Duration = Duration::from_secs(3600);
+
+/// Shortest gap between refetches caused by unknown key ids
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(30);
+
+/// Allowed clock skew for `exp`, `nbf` and `iat`
+const LEEWAY_SECS: u64 = 60;
+
+/// Validated claims of an ID token
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub exp: u64,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    nonce: Option<String>,
+    #[serde(default)]
+    azp: Option<String>,
+    #[serde(default)]
+    aud: Option<Audience>,
+    /// Every other claim
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+/// `aud`, already checked to contain the client id
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    Many(Vec<String>),
+    One(serde::de::IgnoredAny),
+}
+
+/// Fetched signing keys
+#[derive(Default)]
+struct KeySet {
+    keys: Vec<Jwk>,
+    fetched_at: Option<Instant>,
+    /// Last refetch for a key id the cached set did not have
+    last_miss: Option<Instant>,
+}
+
+/// Validates ID tokens of one provider and client
+pub struct IdTokenValidator {
+    http_client: reqwest::Client,
+    issuer: String,
+    client_id: String,
+    jwks_uri: String,
+    algorithms: Vec<Algorithm>,
+    keys: RwLock<KeySet>,
+}
+
+impl IdTokenValidator {
+    pub fn new(http_client: reqwest::Client, metadata: &ProviderMetadata, client_id: &str) -> Self {
+        // Only asymmetric algorithms: an HMAC "public" key would let anyone
+        // who can read the JWKS sign tokens
+        let algorithms = metadata
+            .id_token_signing_alg_values_supported
+            .iter()
+            .filter_map(|alg| alg.parse::<Algorithm>().ok())
+            .filter(|alg| !matches!(alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512))
+            .collect();
+        Self {
+            http_client,
+            issuer: metadata.issuer.clone(),
+            client_id: client_id.to_string(),
+            jwks_uri: metadata.jwks_uri.clone(),
+            algorithms,
+            keys: RwLock::new(KeySet::default()),
+        }
+    }
+
+    /// Verify `token` and return its claims
+    ///
+    /// `nonce` is the value sent with the authorization request; the token
+    /// must carry it. Pass `None` for tokens from a refresh, which need not.
+    pub async fn validate(&self, token: &str, nonce: Option<&str>) -> Result<IdTokenClaims> {
+        let header = jsonwebtoken::decode_header(token).context("Malformed ID token")?;
+        if !self.algorithms.contains(&header.alg) {
+            anyhow::bail!(
+                "ID token signed with unsupported algorithm {:?}",
+                header.alg
+            );
+        }
+        let jwk = self.key(header.kid.as_deref(), header.alg).await?;
+        let key = DecodingKey::from_jwk(&jwk).context("Unusable signing key")?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        validation.leeway = LEEWAY_SECS;
+        let claims = jsonwebtoken::decode::<IdTokenClaims>(token, &key, &validation)
+            .context("Invalid ID token")?
+            .claims;
+
+        // With several audiences the token must say it was issued to us
+        if let Some(Audience::Many(aud)) = &claims.aud {
+            if aud.len() > 1 && claims.azp.as_deref() != Some(self.client_id.as_str()) {
+                anyhow::bail!("ID token issued to another party");
+            }
+        }
+        if let Some(expected) = nonce {
+            if claims.nonce.as_deref() != Some(expected) {
+                anyhow::bail!("ID token nonce does not match");
+            }
+        }
+        Ok(claims)
+    }
+
+    /// The key a token names, refetching the key set if it is unknown
+    async fn key(&self, kid: Option<&str>, alg: Algorithm) -> Result<Jwk> {
+        let refetch = {
+            let set = self.keys.read().await;
+            match find_key(&set.keys, kid, alg) {
+                Some(jwk) if set.fetched_at.is_some_and(|at| at.elapsed() < JWKS_TTL) => {
+                    return Ok(jwk);
+                }
+                Some(_) => true,
+                // Do not let tokens with made-up key ids hammer the provider
+                None => set
+                    .last_miss
+                    .is_none_or(|at| at.elapsed() >= JWKS_MIN_REFRESH),
+            }
+        };
+
+        let mut set = self.keys.write().await;
+        if refetch {
+            if set.fetched_at.is_some() && find_key(&set.keys, kid, alg).is_none() {
+                set.last_miss = Some(Instant::now());
+            }
+            match self.fetch_keys().await {
+                Ok(keys) => {
+                    set.keys = keys;
+                    set.fetched_at = Some(Instant::now());
+                }
+                // A provider outage should not end sessions with known keys
+                Err(e) if find_key(&set.keys, kid, alg).is_some() => {
+                    tracing::warn!(error = %e, "JWKS refresh failed, using cached keys");
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        find_key(&set.keys, kid, alg)
+            .with_context(|| format!("No signing key {}", kid.unwrap_or("for ID token")))
+    }
+
+    async fn fetch_keys(&self) -> Result<Vec<Jwk>> {
+        #[derive(Deserialize)]
+        struct RawJwks {
+            keys: Vec<serde_json::Value>,
+        }
+
+        let response = self
+            .http_client
+            .get(&self.jwks_uri)
+            .send()
+            .await
+            .context("Failed to fetch JWKS")?;
+        if !response.status().is_success() {
+            anyhow::bail!("JWKS request failed: {}", response.status());
+        }
+        let raw: RawJwks = response.json().await.context("Invalid JWKS")?;
+
+        // Skip keys we cannot use (encryption keys, unknown types) instead of
+        // failing the whole set
+        let keys: Vec<Jwk> = raw
+            .keys
+            .into_iter()
+            .filter_map(|key| serde_json::from_value::<Jwk>(key).ok())
+            .filter(|jwk| !matches!(jwk.algorithm, AlgorithmParameters::OctetKey(_)))
+            .filter(|jwk| {
+                !matches!(
+                    jwk.common.public_key_use,
+                    Some(jsonwebtoken::jwk::PublicKeyUse::Encryption)
+                )
+            })
+            .collect();
+        tracing::debug!(count = keys.len(), uri = %self.jwks_uri, "Fetched JWKS");
+        Ok(keys)
+    }
+}
+
+/// The key matching a token's `kid`, or the only key usable with `alg`
+fn find_key(keys: &[Jwk], kid: Option<&str>, alg: Algorithm) -> Option<Jwk> {
+    let usable = |jwk: &&Jwk| {
+        let family_ok = match jwk.algorithm {
+            AlgorithmParameters::RSA(_) => matches!(
+                alg,
+                Algorithm::RS256
+                    | Algorithm::RS384
+                    | Algorithm::RS512
+                    | Algorithm::PS256
+                    | Algorithm::PS384
+                    | Algorithm::PS512
+            ),
+            AlgorithmParameters::EllipticCurve(_) => {
+                matches!(alg, Algorithm::ES256 | Algorithm::ES384)
+            }
+            AlgorithmParameters::OctetKeyPair(_) => alg == Algorithm::EdDSA,
+            AlgorithmParameters::OctetKey(_) => false,
+        };
+        // A key that names its algorithm may only be used with that one
+        let alg_ok = jwk.common.key_algorithm.is_none_or(|key_alg| {
+            key_alg
+                .to_string()
+                .parse::<Algorithm>()
+                .is_ok_and(|a| a == alg)
+        });
+        family_ok && alg_ok
+    };
+    match kid {
+        Some(kid) => keys
+            .iter()
+            .filter(usable)
+            .find(|jwk| jwk.common.key_id.as_deref() == Some(kid))
+            .cloned(),
+        None => {
+            let mut candidates = keys.iter().filter(usable);
+            match (candidates.next(), candidates.next()) {
+                (Some(only), None) => Some(only.clone()),
+                _ => None,
+            }
+        }
+    }
+}
@@ -2,24 +2,39 @@
 //!
 //! Provides enterprise SSO authentication with support for:
 //! - Authorization code flow with PKCE
-//! - Token validation and refresh
+//! - Provider discovery and ID token validation against the JWKS
+//! - Server-side refresh tokens and silent session renewal
+//! - HMAC-signed session cookies with key rotation
 //! - BeyondCorp-style access policies
+//! - Roles from OIDC groups, with per-role session limits
 
+mod cidr;
 mod client;
 mod config;
+mod discovery;
+mod id_token;
 mod middleware;
+mod renewal;
+mod roles;
 mod routes;
+mod session;
 
+pub use cidr::Cidr;
 pub use client::OidcClient;
 pub use config::AuthConfig;
 pub use middleware::auth_middleware;
-pub use routes::auth_routes;
+pub use renewal::{AuthEvent, AuthWatch};
+pub use roles::{Backend, Grant, RoleLimits, RolePolicy, UserRole};
+pub use routes::{auth_routes, LOGIN_URL};
+pub use session::{AuthSession, SessionKeys};
 
+use axum::http::HeaderMap;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::net::IpAddr;
 
 /// Authenticated user information
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AuthUser {
     /// Unique user identifier (sub claim)
     pub sub: String,
@@ -31,6 +46,9 @@ pub struct AuthUser {
     pub groups: Vec<String>,
     /// Token expiration timestamp
     pub exp: u64,
+    /// Other claims named by the access policy's `required_claims`
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub claims: serde_json::Map<String, serde_json::Value>,
 }
 
 impl AuthUser {
@@ -46,6 +64,38 @@ impl AuthUser {
             .map(|e| e.ends_with(&format!("@{domain}")))
             .unwrap_or(false)
     }
+
+    /// Check a `required_claims` entry: `claim` or `claim=value`
+    ///
+    /// A bare claim must be present and not `false`, null or empty. With a
+    /// value, a string claim must equal it and a list claim must contain it.
+    pub fn has_claim(&self, requirement: &str) -> bool {
+        use serde_json::Value;
+
+        let (name, expected) = match requirement.split_once('=') {
+            Some((name, expected)) => (name.trim(), Some(expected.trim())),
+            None => (requirement.trim(), None),
+        };
+        let value = match name {
+            "sub" => Value::from(self.sub.as_str()),
+            "email" => self.email.as_deref().map_or(Value::Null, Value::from),
+            "name" => self.name.as_deref().map_or(Value::Null, Value::from),
+            "groups" => Value::from(self.groups.clone()),
+            _ => self.claims.get(name).cloned().unwrap_or(Value::Null),
+        };
+        let matches = |v: &Value| match v {
+            Value::String(s) => Some(s.as_str()) == expected,
+            other => Some(other.to_string().as_str()) == expected,
+        };
+        match (&value, expected) {
+            (Value::Null | Value::Bool(false), None) => false,
+            (Value::String(s), None) => !s.is_empty(),
+            (Value::Array(items), None) => !items.is_empty(),
+            (_, None) => true,
+            (Value::Array(items), Some(_)) => items.iter().any(matches),
+            (value, Some(_)) => matches(value),
+        }
+    }
 }
 
 /// BeyondCorp access policy
@@ -59,15 +109,32 @@ pub struct AccessPolicy {
     pub allowed_groups: HashSet<String>,
     /// Allowed IP ranges (CIDR notation)
     #[serde(default)]
-    pub allowed_ips: HashSet<String>,
-    /// Require specific claims
+    pub allowed_ips: Vec<Cidr>,
+    /// Proxies (CIDR notation) whose `X-Forwarded-For`/`X-Real-IP` are believed
+    #[serde(default)]
+    pub trusted_proxies: Vec<Cidr>,
+    /// Require specific claims (`claim` or `claim=value`)
     #[serde(default)]
     pub required_claims: HashSet<String>,
 }
 
 impl AccessPolicy {
+    /// Client address of a request from `peer`, see [`cidr::client_ip`]
+    pub fn client_ip(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        cidr::client_ip(peer, headers, &self.trusted_proxies)
+    }
+
+    /// Names of the claims `required_claims` refers to
+    pub fn required_claim_names(&self) -> impl Iterator<Item = &str> {
+        self.required_claims.iter().map(|c| {
+            c.split_once('=')
+                .map_or(c.as_str(), |(name, _)| name)
+                .trim()
+        })
+    }
+
     /// Check if user passes policy
-    pub fn check(&self, user: &AuthUser, client_ip: Option<&str>) -> PolicyResult {
+    pub fn check(&self, user: &AuthUser, client_ip: Option<IpAddr>) -> PolicyResult {
         // Check domain
         if !self.allowed_domains.is_empty() {
             let domain_ok = user
@@ -90,13 +157,16 @@ impl AccessPolicy {
             }
         }
 
-        // Check IP (simple prefix matching for now)
+        // Check claims
+        if let Some(missing) = self.required_claims.iter().find(|c| !user.has_claim(c)) {
+            return PolicyResult::Denied(format!("Required claim not satisfied: {missing}"));
+        }
+
+        // Check IP; an unknown address never matches
         if !self.allowed_ips.is_empty() {
-            if let Some(ip) = client_ip {
-                let ip_ok = self.allowed_ips.iter().any(|cidr| ip.starts_with(cidr));
-                if !ip_ok {
-                    return PolicyResult::Denied("IP address not allowed".to_string());
-                }
+            let ip_ok = client_ip.is_some_and(|ip| self.allowed_ips.iter().any(|r| r.contains(ip)));
+            if !ip_ok {
+                return PolicyResult::Denied("IP address not allowed".to_string());
             }
         }
 
@@ -129,6 +199,7 @@ mod tests {
             name: Some("Alice".to_string()),
             groups: vec!["engineering".to_string()],
             exp: 9999999999,
+            ..Default::default()
         };
 
         assert!(user.has_domain("example.com"));
@@ -148,6 +219,7 @@ mod tests {
             name: None,
             groups: vec![],
             exp: 0,
+            ..Default::default()
         };
 
         let user_denied = AuthUser {
@@ -156,6 +228,7 @@ mod tests {
             name: None,
             groups: vec![],
             exp: 0,
+            ..Default::default()
         };
 
         assert!(policy.check(&user_allowed, None).is_allowed());
@@ -175,6 +248,7 @@ mod tests {
             name: None,
             groups: vec!["admin".to_string()],
             exp: 0,
+            ..Default::default()
         };
 
         let regular = AuthUser {
@@ -183,9 +257,64 @@ mod tests {
             name: None,
             groups: vec!["users".to_string()],
             exp: 0,
+            ..Default::default()
         };
 
         assert!(policy.check(&admin, None).is_allowed());
         assert!(!policy.check(&regular, None).is_allowed());
     }
+
+    #[test]
+    fn test_policy_check_ips() {
+        let policy = AccessPolicy {
+            allowed_ips: vec![
+                "10.0.0.0/8".parse().unwrap(),
+                "2001:db8::/32".parse().unwrap(),
+            ],
+            ..Default::default()
+        };
+        let user = AuthUser::default();
+
+        assert!(policy.check(&user, "10.20.30.40".parse().ok()).is_allowed());
+        assert!(policy.check(&user, "2001:db8::1".parse().ok()).is_allowed());
+        assert!(!policy.check(&user, "100.0.0.1".parse().ok()).is_allowed());
+        assert!(!policy.check(&user, None).is_allowed());
+    }
+
+    #[test]
+    fn test_policy_invalid_range_rejected() {
+        let policy: AccessPolicy =
+            toml::from_str(r#"trusted_proxies = ["127.0.0.1", "10.0.0.0/8"]"#).unwrap();
+        assert_eq!(policy.trusted_proxies.len(), 2);
+        assert!(toml::from_str::<AccessPolicy>(r#"allowed_ips = ["10.0.0.0/33"]"#).is_err());
+        assert!(toml::from_str::<AccessPolicy>(r#"trusted_proxies = ["proxy"]"#).is_err());
+    }
+
+    #[test]
+    fn test_policy_check_required_claims() {
+        let policy = AccessPolicy {
+            required_claims: ["email_verified".to_string(), "hd=example.com".to_string()]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let mut user = AuthUser::default();
+        user.claims
+            .insert("email_verified".to_string(), true.into());
+        user.claims.insert("hd".to_string(), "example.com".into());
+        assert!(policy.check(&user, None).is_allowed());
+
+        user.claims.insert("hd".to_string(), "other.com".into());
+        assert!(!policy.check(&user, None).is_allowed());
+
+        user.claims.insert("hd".to_string(), "example.com".into());
+        user.claims
+            .insert("email_verified".to_string(), false.into());
+        assert!(!policy.check(&user, None).is_allowed());
+
+        user.groups = vec!["ops".to_string()];
+        assert!(user.has_claim("groups=ops"));
+        assert!(!user.has_claim("groups=dev"));
+        assert!(!user.has_claim("missing"));
+    }
 }
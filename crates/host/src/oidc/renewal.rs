@@ -0,0 +1,231 @@
+//! Server-side sessions and their renewal
+//!
+//! Refresh tokens never leave the host: they are kept here under the session
+//! id carried by the signed cookie. Sessions are renewed shortly before their
+//! tokens expire, either when a request arrives or, for sessions a browser
+//! connection is watching, by a background task. Watchers learn when a
+//! session was renewed, and when it has expired for good so the user must log
+//! in again; a new login by the same user then revives the watch. Logging
+//! out counts as expiry.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::broadcast;
+
+use super::AuthUser;
+
+/// Renew sessions whose tokens expire within this many seconds
+pub(super) const RENEW_AHEAD_SECS: u64 = 300;
+
+/// How often the background task looks for watched sessions to renew
+pub(super) const RENEW_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What a watched session went through
+#[derive(Debug, Clone)]
+pub enum AuthEvent {
+    /// Tokens were renewed, or the user logged in again
+    Renewed(AuthUser),
+    /// Tokens expired and could not be renewed: the user must log in again
+    Expired,
+}
+
+/// Broadcast to every watch, which picks out its own session
+#[derive(Debug, Clone)]
+enum Change {
+    Renewed { sid: String, user: AuthUser },
+    Expired { sid: String },
+    LoggedIn { sid: String, user: AuthUser },
+}
+
+struct StoredSession {
+    refresh_token: Option<String>,
+    user: AuthUser,
+    /// Browser connections watching the session
+    watchers: usize,
+    last_used: Instant,
+    /// One renewal of the session at a time, as providers may rotate
+    /// refresh tokens
+    renewing: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// Sessions known to this host
+pub(super) struct Renewals {
+    sessions: Mutex<HashMap<String, StoredSession>>,
+    changes: broadcast::Sender<Change>,
+}
+
+impl Renewals {
+    pub(super) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            sessions: Mutex::new(HashMap::new()),
+            changes: broadcast::channel(64).0,
+        })
+    }
+
+    /// Record a new login
+    pub(super) fn insert(&self, sid: &str, user: &AuthUser, refresh_token: Option<String>) {
+        self.sessions.lock().unwrap().insert(
+            sid.to_string(),
+            StoredSession {
+                refresh_token,
+                user: user.clone(),
+                watchers: 0,
+                last_used: Instant::now(),
+                renewing: Arc::default(),
+            },
+        );
+        let _ = self.changes.send(Change::LoggedIn {
+            sid: sid.to_string(),
+            user: user.clone(),
+        });
+    }
+
+    /// Current user and refresh token of a session, marking it used
+    pub(super) fn get(&self, sid: &str) -> Option<(AuthUser, Option<String>)> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(sid)?;
+        session.last_used = Instant::now();
+        Some((session.user.clone(), session.refresh_token.clone()))
+    }
+
+    /// Lock held while renewing a session
+    pub(super) fn renewing(&self, sid: &str) -> Option<Arc<tokio::sync::Mutex<()>>> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.get(sid).map(|session| session.renewing.clone())
+    }
+
+    /// Store renewed tokens
+    pub(super) fn renewed(&self, sid: &str, user: &AuthUser, refresh_token: Option<String>) {
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(sid) {
+            session.user = user.clone();
+            // Providers that do not rotate refresh tokens return none
+            if refresh_token.is_some() {
+                session.refresh_token = refresh_token;
+            }
+        }
+        let _ = self.changes.send(Change::Renewed {
+            sid: sid.to_string(),
+            user: user.clone(),
+        });
+    }
+
+    /// Forget a session whose tokens can no longer be renewed
+    pub(super) fn expired(&self, sid: &str) {
+        self.sessions.lock().unwrap().remove(sid);
+        let _ = self.changes.send(Change::Expired {
+            sid: sid.to_string(),
+        });
+    }
+
+    /// Forget a session on logout, ending it for its watchers
+    pub(super) fn remove(&self, sid: &str) {
+        self.expired(sid);
+    }
+
+    /// Watched sessions due for renewal
+    pub(super) fn due(&self) -> Vec<String> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, s)| s.watchers > 0 && needs_renewal(&s.user))
+            .map(|(sid, _)| sid.clone())
+            .collect()
+    }
+
+    /// Drop unwatched sessions unused for longer than `max_age`
+    pub(super) fn prune(&self, max_age: Duration) {
+        self.sessions
+            .lock()
+            .unwrap()
+            .retain(|_, s| s.watchers > 0 || s.last_used.elapsed() < max_age);
+    }
+
+    /// Start watching a session; `None` if the host does not know it
+    pub(super) fn watch(self: &Arc<Self>, sid: &str) -> Option<AuthWatch> {
+        let sub = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let session = sessions.get_mut(sid)?;
+            session.watchers += 1;
+            session.user.sub.clone()
+        };
+        Some(AuthWatch {
+            sid: sid.to_string(),
+            sub,
+            expired: false,
+            changes: self.changes.subscribe(),
+            renewals: self.clone(),
+        })
+    }
+
+    fn unwatch(&self, sid: &str) {
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(sid) {
+            session.watchers = session.watchers.saturating_sub(1);
+        }
+    }
+}
+
+/// Whether `user`'s tokens expire soon enough to renew them
+pub(super) fn needs_renewal(user: &AuthUser) -> bool {
+    user.exp > 0 && user.exp <= super::session::now_secs() + RENEW_AHEAD_SECS
+}
+
+/// A browser connection's interest in its session
+///
+/// Keeps the session renewed in the background while it exists.
+pub struct AuthWatch {
+    sid: String,
+    sub: String,
+    expired: bool,
+    changes: broadcast::Receiver<Change>,
+    renewals: Arc<Renewals>,
+}
+
+impl AuthWatch {
+    /// Id of the watched session
+    pub fn sid(&self) -> &str {
+        &self.sid
+    }
+
+    /// Wait for the next change to the session
+    ///
+    /// After [`AuthEvent::Expired`], a new login by the same user moves the
+    /// watch to the new session and yields [`AuthEvent::Renewed`].
+    pub async fn next(&mut self) -> AuthEvent {
+        loop {
+            let change = match self.changes.recv().await {
+                Ok(change) => change,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => {
+                    unreachable!("the watch keeps the sender alive")
+                }
+            };
+            match change {
+                Change::Renewed { sid, user } if sid == self.sid => {
+                    return AuthEvent::Renewed(user);
+                }
+                Change::Expired { sid } if sid == self.sid => {
+                    self.expired = true;
+                    return AuthEvent::Expired;
+                }
+                Change::LoggedIn { sid, user } if self.expired && user.sub == self.sub => {
+                    let Some(moved) = self.renewals.watch(&sid) else {
+                        continue;
+                    };
+                    // The old session is gone; hand over to the new one
+                    *self = moved;
+                    return AuthEvent::Renewed(user);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Drop for AuthWatch {
+    fn drop(&mut self) {
+        self.renewals.unwatch(&self.sid);
+    }
+}
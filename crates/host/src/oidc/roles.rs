@@ -0,0 +1,249 @@
+//! Roles of logged-in users
+//!
+//! Each OIDC group maps to a role: admin, developer or viewer-only. Admins
+//! and developers have their own limits on concurrent sessions, on the
+//! Neovim backends those sessions run on and on the VFS schemes they may
+//! use. Viewer-only users never start sessions and join shared ones as
+//! viewers.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::AuthUser;
+
+/// Role of a logged-in user, least privileged first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UserRole {
+    ViewerOnly,
+    Developer,
+    Admin,
+}
+
+impl UserRole {
+    /// Whether the role may start and edit sessions
+    pub fn can_edit(self) -> bool {
+        self != Self::ViewerOnly
+    }
+}
+
+impl std::fmt::Display for UserRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ViewerOnly => write!(f, "viewer-only"),
+            Self::Developer => write!(f, "developer"),
+            Self::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+/// Where the host runs the Neovim of new sessions
+///
+/// Names other than these are rejected when the config is loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// A process the host spawns
+    Local,
+    /// A `--remote tcp://` Neovim
+    Tcp,
+    /// A `--remote unix://` Neovim
+    Unix,
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Local => write!(f, "local"),
+            Self::Tcp => write!(f, "tcp"),
+            Self::Unix => write!(f, "unix"),
+        }
+    }
+}
+
+/// Limits on the sessions of one role; anything unset is unlimited
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoleLimits {
+    /// Sessions a user may own at once
+    #[serde(default)]
+    pub max_sessions: Option<usize>,
+    /// Neovim backends sessions may run on
+    #[serde(default)]
+    pub backends: Option<HashSet<Backend>>,
+    /// VFS schemes sessions may read and write (`local`, `browser`, `github`, `ssh`)
+    #[serde(default)]
+    pub vfs_schemes: Option<HashSet<String>>,
+}
+
+impl RoleLimits {
+    /// Check that sessions may run on `backend`
+    pub fn check_backend(&self, backend: Backend) -> Result<()> {
+        match &self.backends {
+            Some(allowed) if !allowed.contains(&backend) => {
+                anyhow::bail!("Backend '{backend}' is not allowed for this user")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that sessions may use the VFS backend registered as `scheme`
+    pub fn check_vfs_scheme(&self, scheme: &str) -> Result<()> {
+        match &self.vfs_schemes {
+            Some(allowed) if !allowed.contains(scheme) => {
+                anyhow::bail!("VFS scheme '{scheme}' is not allowed for this user")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Mapping of OIDC groups to roles, and the limits of each role
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RolePolicy {
+    /// Role of users in none of the mapped groups
+    #[serde(default = "default_role")]
+    pub default_role: UserRole,
+
+    /// Role of the members of each group; the most privileged one wins
+    #[serde(default)]
+    pub groups: HashMap<String, UserRole>,
+
+    /// Limits of admins
+    #[serde(default)]
+    pub admin: RoleLimits,
+
+    /// Limits of developers
+    #[serde(default)]
+    pub developer: RoleLimits,
+}
+
+fn default_role() -> UserRole {
+    UserRole::Developer
+}
+
+impl Default for RolePolicy {
+    fn default() -> Self {
+        Self {
+            default_role: default_role(),
+            groups: HashMap::new(),
+            admin: RoleLimits::default(),
+            developer: RoleLimits::default(),
+        }
+    }
+}
+
+impl RolePolicy {
+    /// Role of `user`, from their groups
+    pub fn role_of(&self, user: &AuthUser) -> UserRole {
+        user.groups
+            .iter()
+            .filter_map(|group| self.groups.get(group).copied())
+            .max()
+            .unwrap_or(self.default_role)
+    }
+
+    /// Role and limits of `user`
+    pub fn grant(&self, user: &AuthUser) -> Grant {
+        let role = self.role_of(user);
+        let limits = match role {
+            UserRole::Admin => self.admin.clone(),
+            UserRole::Developer => self.developer.clone(),
+            UserRole::ViewerOnly => RoleLimits {
+                max_sessions: Some(0),
+                ..RoleLimits::default()
+            },
+        };
+        Grant {
+            sub: user.sub.clone(),
+            role,
+            limits,
+        }
+    }
+}
+
+/// A logged-in user with the role and limits their groups give them
+#[derive(Debug, Clone)]
+pub struct Grant {
+    /// Subject (`sub`) of the user
+    pub sub: String,
+    pub role: UserRole,
+    pub limits: RoleLimits,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(groups: &[&str]) -> AuthUser {
+        AuthUser {
+            sub: "alice".to_string(),
+            groups: groups.iter().map(ToString::to_string).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_role_of_groups() {
+        let policy: RolePolicy = toml::from_str(
+            r#"
+default_role = "viewer-only"
+
+[groups]
+ops = "admin"
+engineering = "developer"
+contractors = "viewer-only"
+
+[developer]
+max_sessions = 2
+backends = ["local"]
+vfs_schemes = ["local", "browser"]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(policy.role_of(&user(&[])), UserRole::ViewerOnly);
+        assert_eq!(policy.role_of(&user(&["unknown"])), UserRole::ViewerOnly);
+        assert_eq!(
+            policy.role_of(&user(&["contractors", "engineering"])),
+            UserRole::Developer
+        );
+        assert_eq!(
+            policy.role_of(&user(&["engineering", "ops"])),
+            UserRole::Admin
+        );
+
+        let developer = policy.grant(&user(&["engineering"]));
+        assert_eq!(developer.sub, "alice");
+        assert_eq!(developer.limits.max_sessions, Some(2));
+        assert!(developer.limits.check_backend(Backend::Local).is_ok());
+        assert!(developer.limits.check_backend(Backend::Tcp).is_err());
+        assert!(developer.limits.check_vfs_scheme("browser").is_ok());
+        assert!(developer.limits.check_vfs_scheme("ssh").is_err());
+
+        let admin = policy.grant(&user(&["ops"]));
+        assert!(admin.limits.check_backend(Backend::Unix).is_ok());
+        assert!(admin.limits.check_vfs_scheme("ssh").is_ok());
+
+        let viewer = policy.grant(&user(&[]));
+        assert!(!viewer.role.can_edit());
+        assert_eq!(viewer.limits.max_sessions, Some(0));
+    }
+
+    #[test]
+    fn test_unknown_backend_rejected() {
+        let limits: RoleLimits = toml::from_str(r#"backends = ["local", "unix"]"#).unwrap();
+        assert!(limits.check_backend(Backend::Unix).is_ok());
+        assert!(toml::from_str::<RoleLimits>(r#"backends = ["local", "docker"]"#).is_err());
+    }
+
+    #[test]
+    fn test_default_policy_changes_nothing() {
+        let grant = RolePolicy::default().grant(&user(&["anything"]));
+        assert_eq!(grant.role, UserRole::Developer);
+        assert!(grant.limits.max_sessions.is_none());
+        assert!(grant.limits.check_backend(Backend::Tcp).is_ok());
+        assert!(grant.limits.check_vfs_scheme("github").is_ok());
+    }
+}
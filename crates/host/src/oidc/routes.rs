@@ -5,18 +5,24 @@
 //! - /auth/callback - OAuth2 callback
 //! - /auth/logout - End session
 //! - /auth/me - Get current user info
+//! - /auth/ticket - Get a handshake ticket for a WebTransport connection
 
 use axum::{
-    extract::{Query, State},
-    http::{header, StatusCode},
+    extract::{ConnectInfo, Query, State},
+    http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Redirect, Response},
     routing::get,
     Json, Router,
 };
 use serde::Deserialize;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 
-use super::{AuthUser, OidcClient};
+use super::OidcClient;
+use crate::audit::{self, AuditAction, AuditEvent};
+
+/// Where browsers start a login
+pub const LOGIN_URL: &str = "/auth/login";
 
 /// Shared OIDC client state
 pub type SharedOidcClient = Arc<OidcClient>;
@@ -36,6 +42,7 @@ pub fn auth_routes(client: SharedOidcClient) -> Router {
         .route("/callback", get(callback))
         .route("/logout", get(logout))
         .route("/me", get(me))
+        .route("/ticket", get(ticket))
         .with_state(state)
 }
 
@@ -52,34 +59,36 @@ struct CallbackParams {
     state: String,
 }
 
+/// Client address of a request, for the audit log
+fn client_ip(
+    state: &AuthState,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: &HeaderMap,
+) -> Option<IpAddr> {
+    let peer = peer.map(|ConnectInfo(addr)| addr.ip());
+    audit::client_ip(Some(&state.client), peer, headers)
+}
+
 /// Callback endpoint - exchanges code for tokens
 async fn callback(
     State(state): State<AuthState>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
     Query(params): Query<CallbackParams>,
 ) -> Response {
+    let event = AuditEvent::new(AuditAction::Login).ip(client_ip(&state, peer, &headers));
     match state
         .client
         .exchange_code(&params.code, &params.state)
         .await
     {
-        Ok(user) => {
-            // Create session cookie with user info
-            let session_data = serde_json::to_string(&user).unwrap_or_default();
-            let cookie_config = &state.client.config().session;
-
-            let cookie_value = format!(
-                "{}={}; Max-Age={}; Path=/; {}{}SameSite={}",
-                cookie_config.cookie_name,
-                base64_encode(&session_data),
-                cookie_config.max_age_secs,
-                if cookie_config.secure { "Secure; " } else { "" },
-                if cookie_config.http_only {
-                    "HttpOnly; "
-                } else {
-                    ""
-                },
-                cookie_config.same_site
-            );
+        Ok(session) => {
+            let mut event = event.user(Some(&session.user.sub));
+            if let Some(email) = &session.user.email {
+                event = event.detail("email", email.as_str());
+            }
+            event.record();
+            let cookie_value = state.client.sessions().set_cookie(&session);
 
             // Redirect to home with session cookie
             Response::builder()
@@ -91,6 +100,7 @@ async fn callback(
         }
         Err(e) => {
             tracing::error!(error = %e, "OAuth callback failed");
+            event.failed().detail("error", e.to_string()).record();
             Response::builder()
                 .status(StatusCode::UNAUTHORIZED)
                 .body(axum::body::Body::from(format!(
@@ -101,8 +111,19 @@ async fn callback(
     }
 }
 
-/// Logout endpoint - clears session
-async fn logout(State(state): State<AuthState>) -> Response {
+/// Logout endpoint - clears session and forgets its refresh token
+async fn logout(
+    State(state): State<AuthState>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(session) = state.client.sessions().session_from_headers(&headers) {
+        state.client.logout(&session.sid);
+        AuditEvent::new(AuditAction::Logout)
+            .user(Some(&session.user.sub))
+            .ip(client_ip(&state, peer, &headers))
+            .record();
+    }
     let cookie_name = &state.client.config().session.cookie_name;
 
     // Clear cookie by setting it to empty with immediate expiration
@@ -119,66 +140,34 @@ async fn logout(State(state): State<AuthState>) -> Response {
         .unwrap()
 }
 
-/// Get current user info
-async fn me(
-    State(state): State<AuthState>,
-    headers: axum::http::HeaderMap,
-) -> Result<Json<AuthUser>, StatusCode> {
-    let cookie_name = &state.client.config().session.cookie_name;
-
-    // Extract session cookie
-    let cookies = headers
-        .get(header::COOKIE)
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("");
-
-    let session = cookies
-        .split(';')
-        .filter_map(|c| {
-            let mut parts = c.trim().splitn(2, '=');
-            let name = parts.next()?;
-            let value = parts.next()?;
-            if name == cookie_name {
-                Some(value)
-            } else {
-                None
+/// Get current user info, renewing the session if it expires soon
+async fn me(State(state): State<AuthState>, headers: axum::http::HeaderMap) -> Response {
+    match state.client.authenticate(&headers).await {
+        Some((session, stale)) => {
+            let mut response = Json(&session.user).into_response();
+            if stale {
+                let cookie = state.client.sessions().set_cookie(&session);
+                if let Ok(value) = header::HeaderValue::from_str(&cookie) {
+                    response.headers_mut().insert(header::SET_COOKIE, value);
+                }
             }
-        })
-        .next();
-
-    match session {
-        Some(encoded) => {
-            let decoded = base64_decode(encoded).map_err(|_| StatusCode::UNAUTHORIZED)?;
-            let user: AuthUser =
-                serde_json::from_str(&decoded).map_err(|_| StatusCode::UNAUTHORIZED)?;
-
-            // Check if session is expired
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map(|d| d.as_secs())
-                .unwrap_or(0);
-
-            if user.exp > 0 && user.exp < now {
-                return Err(StatusCode::UNAUTHORIZED);
-            }
-
-            Ok(Json(user))
+            response
         }
-        None => Err(StatusCode::UNAUTHORIZED),
+        None => StatusCode::UNAUTHORIZED.into_response(),
     }
 }
 
-/// Simple base64 encoding for cookies
-fn base64_encode(data: &str) -> String {
-    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
-    URL_SAFE_NO_PAD.encode(data.as_bytes())
-}
-
-/// Simple base64 decoding for cookies
-fn base64_decode(encoded: &str) -> Result<String, &'static str> {
-    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
-    let bytes = URL_SAFE_NO_PAD
-        .decode(encoded)
-        .map_err(|_| "Invalid base64")?;
-    String::from_utf8(bytes).map_err(|_| "Invalid UTF-8")
+/// Handshake ticket for the current session
+///
+/// Browsers send no cookies with WebTransport; the page fetches a ticket and
+/// passes it as `?ticket=` on the connection URL instead.
+async fn ticket(State(state): State<AuthState>, headers: axum::http::HeaderMap) -> Response {
+    match state.client.authenticate(&headers).await {
+        Some((session, _)) => Json(serde_json::json!({
+            "ticket": state.client.sessions().ticket(&session),
+            "expires_in": super::session::TICKET_TTL_SECS,
+        }))
+        .into_response(),
+        None => StatusCode::UNAUTHORIZED.into_response(),
+    }
 }
@@ -0,0 +1,354 @@
+//! Signed session cookies
+//!
+//! The session cookie carries the session id and authenticated user as
+//! `<key id>.<payload>.<signature>`, where the signature is an HMAC-SHA256 of
+//! the key id and payload under a server key. Cookies with an unknown key id,
+//! a bad signature or a lapsed expiry are rejected. Whether the user's
+//! tokens are still valid is up to the caller, which may renew them.
+//!
+//! Browsers do not send cookies with WebTransport, so an authenticated page
+//! can also ask for a ticket: the same signed session, valid for a minute and
+//! only accepted in a connection handshake.
+//!
+//! Keys come from `session.signing_keys` in the auth config, newest first:
+//! the first key signs new cookies and the rest are only used to verify, so a
+//! key can be rotated without logging everyone out. Without configured keys a
+//! random key is kept in ~/.config/nvim-web/session.key.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use axum::http::{header, HeaderMap};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::config::SessionConfig;
+use super::AuthUser;
+use crate::auth;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Shortest signing key accepted from configuration
+const MIN_KEY_LEN: usize = 32;
+
+/// How long a handshake ticket is accepted
+pub const TICKET_TTL_SECS: u64 = 60;
+
+/// A logged-in browser session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthSession {
+    /// Server-side session id, under which the refresh token is kept
+    pub sid: String,
+    pub user: AuthUser,
+    /// Random id of the host process that issued the session
+    #[serde(default)]
+    pub epoch: String,
+}
+
+impl AuthSession {
+    /// Whether the user's tokens have expired
+    pub fn token_expired(&self) -> bool {
+        self.user.exp > 0 && self.user.exp <= now_secs()
+    }
+}
+
+/// Signed contents of a session cookie
+#[derive(Serialize, Deserialize)]
+struct SessionPayload {
+    #[serde(flatten)]
+    session: AuthSession,
+    /// Unix time after which the cookie is no longer accepted
+    expires: u64,
+    /// A handshake ticket rather than a cookie
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    ticket: bool,
+}
+
+/// A signing key and the id that names it in cookies
+struct SigningKey {
+    id: String,
+    secret: Vec<u8>,
+}
+
+impl SigningKey {
+    fn new(secret: &str) -> Self {
+        // Derived from the secret, so ids stay stable across restarts and
+        // reveal nothing about the key
+        let digest = Sha256::digest([b"nvim-web session key:", secret.as_bytes()].concat());
+        Self {
+            id: hex::encode(&digest[..4]),
+            secret: secret.as_bytes().to_vec(),
+        }
+    }
+
+    fn mac(&self, message: &str) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC can take key of any size");
+        mac.update(message.as_bytes());
+        mac
+    }
+}
+
+/// Signs and verifies session cookies
+pub struct SessionKeys {
+    /// Newest first; the first key signs
+    keys: Vec<SigningKey>,
+    config: SessionConfig,
+}
+
+impl SessionKeys {
+    /// Keys from the session config, or the key file if none are configured
+    pub fn from_config(config: &SessionConfig) -> Result<Self> {
+        let secrets = if config.signing_keys.is_empty() {
+            vec![load_or_create_key(&default_key_path()?)?]
+        } else {
+            config.signing_keys.clone()
+        };
+        Self::new(&secrets, config)
+    }
+
+    /// Keys from explicit secrets, newest first
+    pub fn new(secrets: &[String], config: &SessionConfig) -> Result<Self> {
+        if secrets.is_empty() {
+            anyhow::bail!("At least one session signing key is required");
+        }
+        if let Some(short) = secrets.iter().position(|s| s.len() < MIN_KEY_LEN) {
+            anyhow::bail!(
+                "Session signing key {} is shorter than {MIN_KEY_LEN} characters",
+                short + 1
+            );
+        }
+        Ok(Self {
+            keys: secrets.iter().map(|s| SigningKey::new(s)).collect(),
+            config: config.clone(),
+        })
+    }
+
+    /// Cookie value for `session`, valid for the configured max age
+    pub fn encode(&self, session: &AuthSession) -> String {
+        self.sign(&SessionPayload {
+            session: session.clone(),
+            expires: now_secs() + self.config.max_age_secs,
+            ticket: false,
+        })
+    }
+
+    /// Session in a cookie value, `None` if it is forged, tampered with or expired
+    pub fn decode(&self, value: &str) -> Option<AuthSession> {
+        self.verify(value)
+            .filter(|payload| !payload.ticket)
+            .map(|payload| payload.session)
+    }
+
+    /// Short-lived handshake ticket for `session`
+    pub fn ticket(&self, session: &AuthSession) -> String {
+        self.sign(&SessionPayload {
+            session: session.clone(),
+            expires: now_secs() + TICKET_TTL_SECS,
+            ticket: true,
+        })
+    }
+
+    /// Session in a handshake ticket, `None` unless it is a valid ticket
+    pub fn redeem(&self, ticket: &str) -> Option<AuthSession> {
+        self.verify(ticket)
+            .filter(|payload| payload.ticket)
+            .map(|payload| payload.session)
+    }
+
+    fn sign(&self, payload: &SessionPayload) -> String {
+        let json = serde_json::to_vec(payload).unwrap_or_default();
+        let key = &self.keys[0];
+        let signed = format!("{}.{}", key.id, URL_SAFE_NO_PAD.encode(json));
+        let signature = URL_SAFE_NO_PAD.encode(key.mac(&signed).finalize().into_bytes());
+        format!("{signed}.{signature}")
+    }
+
+    fn verify(&self, value: &str) -> Option<SessionPayload> {
+        let (signed, signature) = value.rsplit_once('.')?;
+        let (id, payload) = signed.split_once('.')?;
+        let key = self.keys.iter().find(|k| k.id == id)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        key.mac(signed).verify_slice(&signature).ok()?;
+
+        let payload: SessionPayload =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+        (payload.expires >= now_secs()).then_some(payload)
+    }
+
+    /// Session in the session cookie of a request
+    pub fn session_from_headers(&self, headers: &HeaderMap) -> Option<AuthSession> {
+        headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|cookies| cookies.split(';'))
+            .filter_map(|c| c.trim().split_once('='))
+            .filter(|(name, _)| *name == self.config.cookie_name)
+            // A forged cookie must not shadow a valid one set alongside it
+            .find_map(|(_, value)| self.decode(value))
+    }
+
+    /// `Set-Cookie` header value carrying `session`
+    pub fn set_cookie(&self, session: &AuthSession) -> String {
+        let config = &self.config;
+        format!(
+            "{}={}; Max-Age={}; Path=/; {}{}SameSite={}",
+            config.cookie_name,
+            self.encode(session),
+            config.max_age_secs,
+            if config.secure { "Secure; " } else { "" },
+            if config.http_only { "HttpOnly; " } else { "" },
+            config.same_site
+        )
+    }
+}
+
+fn default_key_path() -> Result<PathBuf> {
+    let config_dir = dirs::config_dir().context("Could not determine config directory")?;
+    Ok(config_dir.join("nvim-web").join("session.key"))
+}
+
+/// Read the key file, creating it with a random key on first use
+fn load_or_create_key(path: &Path) -> Result<String> {
+    if path.exists() {
+        let key = auth::read_token_file(path)?;
+        if key.len() >= MIN_KEY_LEN {
+            return Ok(key);
+        }
+        tracing::warn!(path = %path.display(), "Session key too short, generating a new one");
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let key = auth::generate_secure_token();
+    auth::write_token_file(path, &key)?;
+    Ok(key)
+}
+
+pub(super) fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_A: &str = "0123456789abcdef0123456789abcdef-a";
+    const KEY_B: &str = "0123456789abcdef0123456789abcdef-b";
+
+    fn user() -> AuthSession {
+        AuthSession {
+            sid: "s1".to_string(),
+            user: AuthUser {
+                sub: "u1".to_string(),
+                email: Some("alice@example.com".to_string()),
+                name: None,
+                groups: vec!["users".to_string()],
+                exp: 0,
+                ..Default::default()
+            },
+            epoch: "e1".to_string(),
+        }
+    }
+
+    fn keys(secrets: &[&str]) -> SessionKeys {
+        let secrets: Vec<String> = secrets.iter().map(|s| s.to_string()).collect();
+        SessionKeys::new(&secrets, &SessionConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn test_cookie_roundtrip() {
+        let keys = keys(&[KEY_A]);
+        let decoded = keys.decode(&keys.encode(&user())).unwrap();
+        assert_eq!(decoded.sid, "s1");
+        assert_eq!(decoded.user.sub, "u1");
+        assert_eq!(decoded.user.groups, vec!["users".to_string()]);
+
+        let mut headers = HeaderMap::new();
+        let cookie = format!(
+            "other=1; nvim_web_session=forged; nvim_web_session={}",
+            keys.encode(&user())
+        );
+        headers.insert(header::COOKIE, cookie.parse().unwrap());
+        assert_eq!(keys.session_from_headers(&headers).unwrap().sid, "s1");
+    }
+
+    #[test]
+    fn test_tampered_cookies_rejected() {
+        let keys = keys(&[KEY_A]);
+        let cookie = keys.encode(&user());
+        let (id, rest) = cookie.split_once('.').unwrap();
+        let (_, signature) = rest.split_once('.').unwrap();
+
+        // Same signature over a payload granting another group
+        let mut forged = user();
+        forged.user.groups = vec!["admin".to_string()];
+        let payload = SessionPayload {
+            session: forged,
+            expires: u64::MAX,
+            ticket: false,
+        };
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&payload).unwrap());
+        assert!(keys
+            .decode(&format!("{id}.{payload}.{signature}"))
+            .is_none());
+
+        // The unsigned cookies of older versions
+        let legacy = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&user().user).unwrap());
+        assert!(keys.decode(&legacy).is_none());
+
+        // Signed with a key the server does not have
+        assert!(keys.decode(&self::keys(&[KEY_B]).encode(&user())).is_none());
+
+        // Expired tokens are left to the caller, which may renew them
+        let mut expired = user();
+        expired.user.exp = 1;
+        let decoded = keys.decode(&keys.encode(&expired)).unwrap();
+        assert!(decoded.token_expired());
+
+        // Past the cookie's own expiry
+        let payload = SessionPayload {
+            session: user(),
+            expires: 1,
+            ticket: false,
+        };
+        let json = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&payload).unwrap());
+        let signed = format!("{id}.{json}");
+        let signature = URL_SAFE_NO_PAD.encode(keys.keys[0].mac(&signed).finalize().into_bytes());
+        assert!(keys.decode(&format!("{signed}.{signature}")).is_none());
+    }
+
+    #[test]
+    fn test_tickets_are_not_cookies() {
+        let keys = keys(&[KEY_A]);
+        let ticket = keys.ticket(&user());
+        assert_eq!(keys.redeem(&ticket).unwrap().sid, "s1");
+        assert!(keys.decode(&ticket).is_none());
+        assert!(keys.redeem(&keys.encode(&user())).is_none());
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let old = keys(&[KEY_A]);
+        let rotated = keys(&[KEY_B, KEY_A]);
+        let cookie = old.encode(&user());
+        assert!(rotated.decode(&cookie).is_some());
+        assert!(old.decode(&rotated.encode(&user())).is_none());
+        assert!(SessionKeys::new(&["short".to_string()], &SessionConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_key_file_created_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nvim-web").join("session.key");
+        let key = load_or_create_key(&path).unwrap();
+        assert!(key.len() >= MIN_KEY_LEN);
+        assert_eq!(load_or_create_key(&path).unwrap(), key);
+    }
+}
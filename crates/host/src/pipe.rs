@@ -2,24 +2,264 @@
 //!
 //! Generic CLI spawner for user-configurable tools (LLMs, formatters, etc).
 //! Replaces hardcoded LLM providers with a flexible pipe mechanism.
+//!
+//! Browsers reach it only through the `tool_exec` RPC, which runs tools from
+//! the `[tools]` section of config.toml by name. Each tool has a fixed argv
+//! template whose `{0}`, `{1}`, ... placeholders are filled with the
+//! browser's arguments, so a browser can never pick the program or add
+//! arguments of its own. Tools run without a shell, with a timeout, a cap on
+//! their output and optionally under resource limits.
 
-use anyhow::{Context, Result};
+use std::collections::HashMap;
 use std::process::Stdio;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 use tokio::process::Command;
 use tokio::sync::mpsc;
 
-/// Configuration for a tool invocation
-#[derive(Debug, Clone)]
+/// Host environment variables tools see, besides their own `env`
+const INHERITED_ENV: &[&str] = &["PATH", "HOME", "LANG", "LC_ALL", "TMPDIR"];
+
+/// A tool the browser may run by name
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolConfig {
     /// Command to execute (e.g., "claude", "gemini-cli", "prettier")
     pub command: String,
-    /// Arguments to pass
+    /// Argument templates; `{N}` is replaced by the browser's Nth argument
+    #[serde(default)]
     pub args: Vec<String>,
     /// Working directory (optional)
+    #[serde(default)]
     pub cwd: Option<String>,
     /// Environment variables to set
-    pub env: Vec<(String, String)>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Seconds before the tool is killed
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Largest stdout, and largest stderr, accepted in bytes
+    #[serde(default = "default_max_output_bytes")]
+    pub max_output_bytes: usize,
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    /// `limits` as `setrlimit` takes them, filled in by [`ToolRegistry`]
+    #[serde(skip)]
+    rlimits: Rlimits,
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+fn default_max_output_bytes() -> usize {
+    1024 * 1024
+}
+
+/// Limits applied to a tool's process before it starts; unset is unlimited
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// CPU time in seconds (`RLIMIT_CPU`)
+    #[serde(default)]
+    pub cpu_secs: Option<u64>,
+    /// Address space in MiB (`RLIMIT_AS`)
+    #[serde(default)]
+    pub memory_mb: Option<u64>,
+    /// Open file descriptors (`RLIMIT_NOFILE`)
+    #[serde(default)]
+    pub max_files: Option<u64>,
+    /// Processes of the host's user (`RLIMIT_NPROC`)
+    #[serde(default)]
+    pub max_processes: Option<u64>,
+    /// Forbid gaining privileges through setuid binaries (Linux only)
+    #[serde(default)]
+    pub no_new_privs: bool,
+}
+
+impl ResourceLimits {
+    /// Work out the values to pass to `setrlimit`, in the parent
+    fn rlimits(&self) -> Rlimits {
+        Rlimits {
+            cpu: self.cpu_secs.map(|secs| secs as libc::rlim_t),
+            address_space: self
+                .memory_mb
+                .map(|mb| mb.saturating_mul(1024 * 1024) as libc::rlim_t),
+            files: self.max_files.map(|files| files as libc::rlim_t),
+            processes: self.max_processes.map(|procs| procs as libc::rlim_t),
+            no_new_privs: self.no_new_privs,
+        }
+    }
+}
+
+/// [`ResourceLimits`] ready for a forked child; unset is unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Rlimits {
+    cpu: Option<libc::rlim_t>,
+    address_space: Option<libc::rlim_t>,
+    files: Option<libc::rlim_t>,
+    processes: Option<libc::rlim_t>,
+    no_new_privs: bool,
+}
+
+impl Rlimits {
+    /// Set up the limits in a forked child, before it execs the tool
+    ///
+    /// Only makes system calls: nothing here may allocate or panic.
+    fn apply(&self) -> std::io::Result<()> {
+        let limits = [
+            (libc::RLIMIT_CPU, self.cpu),
+            (libc::RLIMIT_AS, self.address_space),
+            (libc::RLIMIT_NOFILE, self.files),
+            (libc::RLIMIT_NPROC, self.processes),
+        ];
+        for (resource, limit) in limits {
+            let Some(limit) = limit else { continue };
+            let rlimit = libc::rlimit {
+                rlim_cur: limit,
+                rlim_max: limit,
+            };
+            // SAFETY: setrlimit only reads the struct and is async-signal-safe
+            if unsafe { libc::setrlimit(resource, &rlimit) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        if self.no_new_privs {
+            #[cfg(target_os = "linux")]
+            // SAFETY: prctl with PR_SET_NO_NEW_PRIVS takes no pointers
+            if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            #[cfg(not(target_os = "linux"))]
+            return Err(std::io::Error::from(std::io::ErrorKind::Unsupported));
+        }
+        Ok(())
+    }
+}
+
+impl ToolConfig {
+    /// Number of arguments the tool takes: one past its highest placeholder
+    pub fn arity(&self) -> usize {
+        self.args
+            .iter()
+            .flat_map(|template| placeholders(template))
+            .map(|index| index + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Fill the argument templates with the browser's arguments
+    fn argv(&self, params: &[String]) -> Vec<String> {
+        self.args
+            .iter()
+            .map(|template| expand(template, params))
+            .collect()
+    }
+}
+
+/// Indices of the `{N}` placeholders in an argument template
+fn placeholders(template: &str) -> impl Iterator<Item = usize> + '_ {
+    template.split('{').skip(1).filter_map(|part| {
+        let (index, _) = part.split_once('}')?;
+        index.parse().ok()
+    })
+}
+
+/// Replace the `{N}` placeholders of `template` with `params[N]`
+///
+/// Anything else in braces is kept as is.
+fn expand(template: &str, params: &[String]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let param = after.split_once('}').and_then(|(index, tail)| {
+            let value = params.get(index.parse::<usize>().ok()?)?;
+            Some((value, tail))
+        });
+        match param {
+            Some((value, tail)) => {
+                out.push_str(value);
+                rest = tail;
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The tools browsers may run, from the `[tools]` section of config.toml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(
+    from = "HashMap<String, ToolConfig>",
+    into = "HashMap<String, ToolConfig>"
+)]
+pub struct ToolRegistry {
+    tools: HashMap<String, ToolConfig>,
+}
+
+impl From<HashMap<String, ToolConfig>> for ToolRegistry {
+    /// Work out each tool's rlimits once, not in every forked child
+    fn from(mut tools: HashMap<String, ToolConfig>) -> Self {
+        for tool in tools.values_mut() {
+            tool.rlimits = tool.limits.rlimits();
+        }
+        Self { tools }
+    }
+}
+
+impl From<ToolRegistry> for HashMap<String, ToolConfig> {
+    fn from(registry: ToolRegistry) -> Self {
+        registry.tools
+    }
+}
+
+impl ToolRegistry {
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Names of the registered tools, sorted
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.tools.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Look up a tool, failing with the list of tools for unknown names
+    pub fn get(&self, name: &str) -> Result<&ToolConfig> {
+        self.tools.get(name).ok_or_else(|| {
+            if self.tools.is_empty() {
+                anyhow::anyhow!("Tool '{name}' is not registered; no tools are configured")
+            } else {
+                anyhow::anyhow!(
+                    "Tool '{name}' is not registered; available tools: {}",
+                    self.names().join(", ")
+                )
+            }
+        })
+    }
+
+    /// Run the tool `name` with the browser's arguments and `input` on stdin
+    pub async fn run(&self, name: &str, params: &[String], input: &str) -> Result<ToolResult> {
+        let tool = self.get(name)?;
+        let arity = tool.arity();
+        if params.len() != arity {
+            anyhow::bail!(
+                "Tool '{name}' takes {arity} argument(s), got {}",
+                params.len()
+            );
+        }
+        run_tool(tool, &tool.argv(params), input)
+            .await
+            .with_context(|| format!("Tool '{name}' failed"))
+    }
 }
 
 /// Result of a tool execution
@@ -70,7 +310,80 @@ pub async fn run_pipe(
     })
 }
 
+/// Run a registered tool with its argv already expanded
+///
+/// The tool gets a scrubbed environment, its resource limits, and is killed
+/// when it runs past its timeout or writes more than its output cap.
+async fn run_tool(tool: &ToolConfig, argv: &[String], input: &str) -> Result<ToolResult> {
+    let mut cmd = Command::new(&tool.command);
+    cmd.args(argv)
+        .env_clear()
+        .envs(
+            INHERITED_ENV
+                .iter()
+                .filter_map(|name| Some((name, std::env::var_os(name)?))),
+        )
+        .envs(&tool.env)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    if let Some(dir) = &tool.cwd {
+        cmd.current_dir(dir);
+    }
+    let rlimits = tool.rlimits;
+    // SAFETY: the hook only calls setrlimit and prctl, which do not allocate
+    unsafe {
+        cmd.pre_exec(move || rlimits.apply());
+    }
+
+    let mut child = cmd.spawn().context("Failed to spawn tool process")?;
+    let mut stdin = child.stdin.take().context("Tool has no stdin")?;
+    let stdout = child.stdout.take().context("Tool has no stdout")?;
+    let stderr = child.stderr.take().context("Tool has no stderr")?;
+    let input = input.as_bytes().to_vec();
+    let max = tool.max_output_bytes;
+
+    let run = async {
+        // Feed stdin while draining the output, so neither side can block;
+        // going over the cap returns at once, and the dropped child is killed
+        let write = async move {
+            // A tool that exits without reading its input is not an error
+            let _ = stdin.write_all(&input).await;
+            anyhow::Ok(())
+        };
+        let ((), stdout, stderr) =
+            tokio::try_join!(write, read_capped(stdout, max), read_capped(stderr, max))?;
+        let status = child.wait().await.context("Failed to wait for tool")?;
+        anyhow::Ok(ToolResult {
+            stdout: String::from_utf8_lossy(&stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&stderr).into_owned(),
+            exit_code: status.code().unwrap_or(-1),
+        })
+    };
+    tokio::time::timeout(Duration::from_secs(tool.timeout_secs), run)
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out after {}s", tool.timeout_secs))?
+}
+
+/// Read all of `reader`, failing once it has produced more than `max` bytes
+async fn read_capped(reader: impl AsyncRead + Unpin, max: usize) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader
+        .take(max as u64 + 1)
+        .read_to_end(&mut buf)
+        .await
+        .context("Failed to read tool output")?;
+    if buf.len() > max {
+        anyhow::bail!("Output exceeded {max} bytes");
+    }
+    Ok(buf)
+}
+
 /// Run a tool with streaming output
+///
+/// Chunks are split on UTF-8 boundaries. Dropping the returned future
+/// kills the process, which is how callers cancel a running tool.
 pub async fn run_pipe_streaming(
     command: &str,
     args: &[String],
@@ -81,7 +394,8 @@ pub async fn run_pipe_streaming(
     cmd.args(args)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
 
     let mut child = cmd.spawn().context("Failed to spawn tool process")?;
 
@@ -98,18 +412,24 @@ pub async fn run_pipe_streaming(
         let tx = output_tx.clone();
         tokio::spawn(async move {
             let mut buf = [0u8; 1024];
+            let mut pending = Vec::new();
             loop {
                 match stdout.read(&mut buf).await {
-                    Ok(0) => break,
+                    Ok(0) | Err(_) => break,
                     Ok(n) => {
-                        let chunk = String::from_utf8_lossy(&buf[..n]).to_string();
-                        if tx.send(chunk).await.is_err() {
-                            break;
+                        pending.extend_from_slice(&buf[..n]);
+                        let chunk = take_utf8(&mut pending);
+                        if !chunk.is_empty() && tx.send(chunk).await.is_err() {
+                            return;
                         }
                     }
-                    Err(_) => break,
                 }
             }
+            if !pending.is_empty() {
+                let _ = tx
+                    .send(String::from_utf8_lossy(&pending).into_owned())
+                    .await;
+            }
         });
     }
 
@@ -118,6 +438,18 @@ pub async fn run_pipe_streaming(
     Ok(status.code().unwrap_or(-1))
 }
 
+/// Take the decodable prefix of `pending`, keeping an incomplete trailing
+/// UTF-8 sequence for the next read
+fn take_utf8(pending: &mut Vec<u8>) -> String {
+    let valid = match std::str::from_utf8(pending) {
+        Ok(_) => pending.len(),
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(_) => return String::from_utf8_lossy(&std::mem::take(pending)).into_owned(),
+    };
+    let rest = pending.split_off(valid);
+    String::from_utf8(std::mem::replace(pending, rest)).unwrap_or_default()
+}
+
 /// Validate that a command exists and is executable
 pub async fn validate_tool(command: &str) -> bool {
     Command::new("which")
@@ -149,6 +481,121 @@ mod tests {
         assert_eq!(res.stdout.trim(), "test input");
     }
 
+    #[tokio::test]
+    async fn test_run_pipe_streaming_splits_on_char_boundaries() {
+        let input = "é".repeat(1500);
+        let (tx, mut rx) = mpsc::channel(16);
+        let code = run_pipe_streaming("cat", &[], &input, tx).await.unwrap();
+        assert_eq!(code, 0);
+
+        let mut output = String::new();
+        while let Some(chunk) = rx.recv().await {
+            output.push_str(&chunk);
+        }
+        assert_eq!(output, input);
+    }
+
+    fn registry() -> ToolRegistry {
+        toml::from_str(
+            r#"
+[upper]
+command = "tr"
+args = ["a-z", "A-Z"]
+
+[greet]
+command = "sh"
+args = ["-c", "echo \"hello $0 from $GREETING\"", "{0}"]
+env = { GREETING = "nvim-web" }
+
+[slow]
+command = "sleep"
+args = ["5"]
+timeout_secs = 1
+
+[noisy]
+command = "yes"
+max_output_bytes = 4096
+
+[endless]
+command = "sh"
+args = ["-c", "trap '' PIPE; while :; do echo noise; done 2>/dev/null"]
+max_output_bytes = 4096
+timeout_secs = 30
+
+[files]
+command = "sh"
+args = ["-c", "ulimit -n"]
+limits = { max_files = 32, no_new_privs = true }
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_expand_placeholders() {
+        let params = ["a b".to_string(), "$(id)".to_string()];
+        assert_eq!(expand("--file={0}", &params), "--file=a b");
+        assert_eq!(expand("{1}{0}", &params), "$(id)a b");
+        assert_eq!(expand("{x} {2} {", &params), "{x} {2} {");
+
+        let tool: ToolConfig = toml::from_str("command = \"x\"\nargs = [\"{1}\", \"-v\"]").unwrap();
+        assert_eq!(tool.arity(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_registry_runs_tools_by_name() {
+        let tools = registry();
+        let result = tools.run("upper", &[], "shout").await.unwrap();
+        assert_eq!(result.stdout, "SHOUT");
+
+        // Arguments stay single argv entries and the environment is the tool's
+        let result = tools
+            .run("greet", &["$USER; id".to_string()], "")
+            .await
+            .unwrap();
+        assert_eq!(result.stdout.trim(), "hello $USER; id from nvim-web");
+
+        let err = tools.run("rm", &[], "").await.unwrap_err();
+        assert!(err.to_string().contains("available tools: endless, files, greet"));
+        let err = tools
+            .run("upper", &["x".to_string()], "")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("takes 0 argument(s), got 1"));
+    }
+
+    #[tokio::test]
+    async fn test_registry_enforces_limits() {
+        let tools = registry();
+        let err = tools.run("slow", &[], "").await.unwrap_err();
+        assert!(format!("{err:#}").contains("Timed out after 1s"));
+        let err = tools.run("noisy", &[], "").await.unwrap_err();
+        assert!(format!("{err:#}").contains("Output exceeded 4096 bytes"));
+
+        // A tool that never stops writing is killed at the cap, not the timeout
+        let started = std::time::Instant::now();
+        let err = tools.run("endless", &[], "").await.unwrap_err();
+        assert!(format!("{err:#}").contains("Output exceeded 4096 bytes"));
+        assert!(started.elapsed() < Duration::from_secs(10));
+        let result = tools.run("files", &[], "").await.unwrap();
+        assert_eq!(result.stdout.trim(), "32");
+    }
+
+    #[test]
+    fn test_rlimits_worked_out_up_front() {
+        let tools = registry();
+        let files = tools.get("files").unwrap();
+        assert_eq!(files.rlimits.files, Some(32));
+        assert!(files.rlimits.no_new_privs);
+        assert_eq!(tools.get("upper").unwrap().rlimits, Rlimits::default());
+
+        let huge = ResourceLimits {
+            memory_mb: Some(u64::MAX),
+            ..Default::default()
+        };
+        assert_eq!(huge.rlimits().address_space, Some(u64::MAX as libc::rlim_t));
+    }
+
     #[tokio::test]
     async fn test_validate_tool() {
         assert!(validate_tool("echo").await);
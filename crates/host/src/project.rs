@@ -13,6 +13,8 @@ use std::time::{Duration, Instant};
 
 use serde::Deserialize;
 
+use crate::auth;
+
 /// Project configuration from `.nvim-web/config.toml`
 #[derive(Debug, Clone, Default, Deserialize)]
 pub struct ProjectConfig {
@@ -42,6 +44,8 @@ pub struct EditorConfig {
     pub cwd: Option<String>,
     /// File to open on start
     pub init_file: Option<String>,
+    /// Launch profile from the host's `[profiles]` for sessions of this project
+    pub profile: Option<String>,
 }
 
 impl ProjectConfig {
@@ -146,26 +150,23 @@ impl OpenToken {
     }
 }
 
-// Global token storage
+// Global token storage, keyed by token hash
 lazy_static::lazy_static! {
     static ref OPEN_TOKENS: RwLock<HashMap<String, OpenToken>> = RwLock::new(HashMap::new());
 }
 
-use std::sync::atomic::{AtomicU64, Ordering};
-
-/// Atomic counter for unique token generation
-static TOKEN_COUNTER: AtomicU64 = AtomicU64::new(0);
-
 /// Generate a secure random token
 pub fn generate_token() -> String {
-    use std::time::SystemTime;
-    let now = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_nanos();
-    let counter = TOKEN_COUNTER.fetch_add(1, Ordering::SeqCst);
-    let random = u128::from(std::process::id()) ^ now ^ (u128::from(counter) << 64);
-    format!("{random:x}")
+    crate::sharing::share_tokens().generate()
+}
+
+/// Find a stored token, comparing hashes in constant time
+fn find_token<'a, T>(tokens: impl IntoIterator<Item = (&'a String, T)>, token: &str) -> Option<T> {
+    let hash = auth::hash_token(token);
+    tokens
+        .into_iter()
+        .find(|(stored, _)| auth::token_hashes_match(stored, &hash))
+        .map(|(_, open_token)| open_token)
 }
 
 /// Options for creating a magic link token
@@ -207,7 +208,7 @@ pub fn store_token_with_options(
     cleanup_expired_tokens();
 
     if let Ok(mut tokens) = OPEN_TOKENS.write() {
-        tokens.insert(token.clone(), open_token);
+        tokens.insert(auth::hash_token(&token), open_token);
     }
 
     token
@@ -223,7 +224,7 @@ pub fn claim_token_full(
     token: &str,
 ) -> Option<(PathBuf, ProjectConfig, Option<String>, Option<u32>)> {
     if let Ok(mut tokens) = OPEN_TOKENS.write() {
-        if let Some(open_token) = tokens.get_mut(token) {
+        if let Some(open_token) = find_token(tokens.iter_mut(), token) {
             if open_token.is_valid() {
                 match open_token.mode {
                     TokenMode::SingleUse => open_token.claimed = true,
@@ -246,7 +247,7 @@ pub fn claim_token_full(
 /// Get token info without claiming
 pub fn get_token_info(token: &str) -> Option<(PathBuf, ProjectConfig, bool)> {
     if let Ok(tokens) = OPEN_TOKENS.read() {
-        if let Some(open_token) = tokens.get(token) {
+        if let Some(open_token) = find_token(tokens.iter(), token) {
             if !open_token.is_expired() {
                 return Some((
                     open_token.path.clone(),
@@ -278,6 +279,19 @@ mod tests {
         assert_ne!(t1, t2); // Should be unique
     }
 
+    #[test]
+    fn test_tokens_stored_hashed() {
+        let token = store_token(PathBuf::from("/test/hashed"), ProjectConfig::default());
+
+        let tokens = OPEN_TOKENS.read().unwrap();
+        assert!(!tokens.contains_key(&token));
+        assert!(tokens.contains_key(&auth::hash_token(&token)));
+        drop(tokens);
+
+        assert!(get_token_info(&token[..token.len() - 1]).is_none());
+        assert!(get_token_info(&token).is_some());
+    }
+
     #[test]
     fn test_store_and_claim_token() {
         let path = PathBuf::from("/test/project");
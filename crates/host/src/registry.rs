@@ -0,0 +1,229 @@
+//! Registry of sessions, persisted so they can be resumed after a restart
+//!
+//! Every local session is recorded in SQLite when it starts, with its owner,
+//! working directory, launch profile and `mksession` file. Records are kept
+//! up to date as browsers come and go and when the host shuts down. After a
+//! restart, a browser reconnecting with an old `?session=` id gets a freshly
+//! spawned Neovim, in the recorded directory and profile, that sources the
+//! session file (see [`crate::session::AsyncSessionManager::resume_session`]).
+//! Sessions that are closed or time out are forgotten; records older than
+//! `resume_days` are pruned at startup.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::session::SessionId;
+
+/// What is needed to bring a session back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionRecord {
+    pub id: SessionId,
+    /// Subject (`sub`) of the user that created the session
+    pub owner: Option<String>,
+    /// Working directory Neovim was last known to be in
+    pub cwd: Option<PathBuf>,
+    /// Launch profile, `None` for the built-in one
+    pub profile: Option<String>,
+    /// `mksession` file written on shutdown
+    pub session_file: PathBuf,
+    pub last_active: SystemTime,
+}
+
+/// SQLite store of session records
+///
+/// Location: ~/.config/nvim-web/sessions.db
+pub struct SessionRegistry {
+    conn: Mutex<Connection>,
+}
+
+impl SessionRegistry {
+    /// Create or open the default registry
+    pub fn new() -> Result<Self> {
+        let config_dir = dirs::config_dir().context("Could not determine config directory")?;
+        Self::open(&config_dir.join("nvim-web").join("sessions.db"))
+    }
+
+    /// Create or open a registry at `db_path`
+    pub fn open(db_path: &Path) -> Result<Self> {
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent).context("Failed to create config directory")?;
+        }
+        let conn = Connection::open(db_path).context("Failed to open session registry")?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS sessions (
+                id TEXT PRIMARY KEY,
+                owner TEXT,
+                cwd TEXT,
+                profile TEXT,
+                session_file TEXT NOT NULL,
+                last_active INTEGER NOT NULL
+            );",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Record a session, replacing what was known about it
+    pub fn save(&self, record: &SessionRecord) -> Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO sessions
+                (id, owner, cwd, profile, session_file, last_active)
+             VALUES (?, ?, ?, ?, ?, ?)",
+            params![
+                record.id,
+                record.owner,
+                record.cwd.as_ref().map(|cwd| cwd.to_string_lossy()),
+                record.profile,
+                record.session_file.to_string_lossy(),
+                to_millis(record.last_active),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Note that a session was used at `time`
+    pub fn touch(&self, id: &str, time: SystemTime) -> Result<()> {
+        self.conn().execute(
+            "UPDATE sessions SET last_active = ? WHERE id = ?",
+            params![to_millis(time), id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<SessionRecord>> {
+        Ok(self
+            .conn()
+            .query_row(
+                "SELECT id, owner, cwd, profile, session_file, last_active
+                 FROM sessions WHERE id = ?",
+                params![id],
+                record_from_row,
+            )
+            .optional()?)
+    }
+
+    /// All records, most recently active first
+    pub fn list(&self) -> Result<Vec<SessionRecord>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT id, owner, cwd, profile, session_file, last_active
+             FROM sessions ORDER BY last_active DESC",
+        )?;
+        let records = stmt.query_map([], record_from_row)?;
+        Ok(records.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Forget a session and delete its session file
+    pub fn remove(&self, id: &str) -> Result<bool> {
+        let record = self.get(id)?;
+        if let Some(record) = &record {
+            let _ = std::fs::remove_file(&record.session_file);
+        }
+        self.conn()
+            .execute("DELETE FROM sessions WHERE id = ?", params![id])?;
+        Ok(record.is_some())
+    }
+
+    /// Forget sessions not used for `max_age`, returning how many
+    pub fn prune(&self, max_age: Duration) -> Result<usize> {
+        let cutoff = SystemTime::now()
+            .checked_sub(max_age)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let stale: Vec<SessionRecord> = self
+            .list()?
+            .into_iter()
+            .filter(|record| record.last_active < cutoff)
+            .collect();
+        for record in &stale {
+            self.remove(&record.id)?;
+        }
+        Ok(stale.len())
+    }
+}
+
+fn record_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<SessionRecord> {
+    Ok(SessionRecord {
+        id: row.get(0)?,
+        owner: row.get(1)?,
+        cwd: row.get::<_, Option<String>>(2)?.map(PathBuf::from),
+        profile: row.get(3)?,
+        session_file: PathBuf::from(row.get::<_, String>(4)?),
+        last_active: from_millis(row.get(5)?),
+    })
+}
+
+fn to_millis(time: SystemTime) -> i64 {
+    let millis = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    i64::try_from(millis).unwrap_or(i64::MAX)
+}
+
+fn from_millis(millis: i64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_millis(u64::try_from(millis).unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, dir: &Path, last_active: SystemTime) -> SessionRecord {
+        SessionRecord {
+            id: id.to_string(),
+            owner: Some("alice".to_string()),
+            cwd: Some(PathBuf::from("/srv/project")),
+            profile: Some("team".to_string()),
+            session_file: dir.join(format!("{id}.vim")),
+            last_active,
+        }
+    }
+
+    #[test]
+    fn test_registry_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("sessions.db");
+        let now = from_millis(to_millis(SystemTime::now()));
+        let saved = record("a", dir.path(), now);
+        SessionRegistry::open(&db).unwrap().save(&saved).unwrap();
+
+        let registry = SessionRegistry::open(&db).unwrap();
+        assert_eq!(registry.get("a").unwrap(), Some(saved.clone()));
+        assert_eq!(registry.get("b").unwrap(), None);
+
+        let later = now + Duration::from_secs(60);
+        registry.touch("a", later).unwrap();
+        assert_eq!(registry.get("a").unwrap().unwrap().last_active, later);
+
+        std::fs::write(&saved.session_file, "\" session").unwrap();
+        assert!(registry.remove("a").unwrap());
+        assert!(!saved.session_file.exists());
+        assert!(registry.list().unwrap().is_empty());
+        assert!(!registry.remove("a").unwrap());
+    }
+
+    #[test]
+    fn test_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = SessionRegistry::open(&dir.path().join("sessions.db")).unwrap();
+        let now = SystemTime::now();
+        let week = Duration::from_secs(7 * 24 * 3600);
+        registry
+            .save(&record("old", dir.path(), now - 2 * week))
+            .unwrap();
+        registry.save(&record("new", dir.path(), now)).unwrap();
+
+        assert_eq!(registry.prune(week).unwrap(), 1);
+        let ids: Vec<String> = registry.list().unwrap().into_iter().map(|r| r.id).collect();
+        assert_eq!(ids, ["new"]);
+    }
+}
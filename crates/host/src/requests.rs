@@ -0,0 +1,213 @@
+//! Cancellable streaming requests
+//!
+//! Long-running RPCs (LLM prompts, project search) answer immediately and
+//! stream their output as notifications on the session's redraw channel.
+//! Requests are keyed by (session id, request id) so the browser can cancel
+//! them, and nothing is sent for a request after its final notification.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, MutexGuard, PoisonError};
+
+use anyhow::{bail, Result};
+use nvim_web_protocol::schema::HostMessage;
+use rmpv::Value;
+use tokio::sync::broadcast;
+use tokio::task::AbortHandle;
+
+type RequestKey = (String, String);
+
+/// Running requests of one kind (e.g. all LLM prompts)
+#[derive(Default)]
+pub struct StreamingRequests {
+    active: StdMutex<HashMap<RequestKey, (u64, AbortHandle)>>,
+    /// Distinguishes a request from a later one reusing its id
+    next_generation: AtomicU64,
+}
+
+/// Handle passed to a request task for sending its notifications
+pub struct RequestStream {
+    requests: Arc<StreamingRequests>,
+    key: RequestKey,
+    generation: u64,
+    out: broadcast::Sender<Vec<u8>>,
+}
+
+impl StreamingRequests {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Spawn `task` for `request_id`, failing if that id is already running
+    pub fn spawn<F, Fut>(
+        self: &Arc<Self>,
+        session_id: &str,
+        request_id: &str,
+        out: broadcast::Sender<Vec<u8>>,
+        task: F,
+    ) -> Result<()>
+    where
+        F: FnOnce(RequestStream) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let key = (session_id.to_string(), request_id.to_string());
+        // Held across the spawn so the task cannot finish before it is registered
+        let mut active = self.lock();
+        if active.contains_key(&key) {
+            bail!("Request '{request_id}' is already running");
+        }
+
+        let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+        let stream = RequestStream {
+            requests: Arc::clone(self),
+            key: key.clone(),
+            generation,
+            out,
+        };
+        let handle = tokio::spawn(task(stream));
+        active.insert(key, (generation, handle.abort_handle()));
+        Ok(())
+    }
+
+    /// Abort a running request and send its final `method` notification
+    ///
+    /// Returns false if the request was not running.
+    pub fn cancel(
+        &self,
+        session_id: &str,
+        request_id: &str,
+        out: &broadcast::Sender<Vec<u8>>,
+        method: &str,
+        params: Vec<Value>,
+    ) -> bool {
+        let key = (session_id.to_string(), request_id.to_string());
+        let mut active = self.lock();
+        let Some((_, handle)) = active.remove(&key) else {
+            return false;
+        };
+        handle.abort();
+        notify(out, method, request_id, params);
+        true
+    }
+
+    /// Whether a request is still running
+    pub fn is_running(&self, session_id: &str, request_id: &str) -> bool {
+        self.lock()
+            .contains_key(&(session_id.to_string(), request_id.to_string()))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<RequestKey, (u64, AbortHandle)>> {
+        self.active.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl RequestStream {
+    pub fn request_id(&self) -> &str {
+        &self.key.1
+    }
+
+    /// Whether the request has not been cancelled
+    pub fn is_active(&self) -> bool {
+        self.requests
+            .lock()
+            .get(&self.key)
+            .is_some_and(|(generation, _)| *generation == self.generation)
+    }
+
+    /// Send `[method, [request_id, ...params]]` unless cancelled
+    ///
+    /// Holding the lock while sending orders it before any cancellation.
+    pub fn send(&self, method: &str, params: Vec<Value>) -> bool {
+        let active = self.requests.lock();
+        let live = active
+            .get(&self.key)
+            .is_some_and(|(generation, _)| *generation == self.generation);
+        if live {
+            notify(&self.out, method, &self.key.1, params);
+        }
+        live
+    }
+
+    /// Send the final notification and unregister the request
+    pub fn finish(self, method: &str, params: Vec<Value>) {
+        if self.unregister() {
+            notify(&self.out, method, &self.key.1, params);
+        }
+    }
+
+    fn unregister(&self) -> bool {
+        let mut active = self.requests.lock();
+        match active.get(&self.key) {
+            Some((generation, _)) if *generation == self.generation => {
+                active.remove(&self.key);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Drop for RequestStream {
+    fn drop(&mut self) {
+        // Covers tasks that return or panic without calling finish()
+        self.unregister();
+    }
+}
+
+fn notify(out: &broadcast::Sender<Vec<u8>>, method: &str, request_id: &str, params: Vec<Value>) {
+    let mut all = Vec::with_capacity(params.len() + 1);
+    all.push(Value::from(request_id));
+    all.extend(params);
+    let msg = HostMessage::Notification {
+        method: method.to_string(),
+        params: all,
+    };
+    let _ = out.send(msg.encode());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn reused_id_survives_cancelled_task() {
+        let requests = StreamingRequests::new();
+        let (out, mut rx) = broadcast::channel(16);
+
+        requests
+            .spawn("s1", "r1", out.clone(), |stream| async move {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                stream.finish("done", vec![]);
+            })
+            .unwrap();
+        assert!(requests
+            .spawn("s1", "r1", out.clone(), |_| async {})
+            .is_err());
+        assert!(requests.cancel("s1", "r1", &out, "done", vec!["cancelled".into()]));
+
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        requests
+            .spawn("s1", "r1", out.clone(), |stream| async move {
+                let _ = started_tx.send(());
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                drop(stream);
+            })
+            .unwrap();
+        started_rx.await.unwrap();
+        // Let the aborted first task be dropped
+        tokio::task::yield_now().await;
+        assert!(requests.is_running("s1", "r1"));
+
+        let Ok(HostMessage::Notification { method, params }) =
+            HostMessage::decode(&rx.recv().await.unwrap())
+        else {
+            panic!("expected notification");
+        };
+        assert_eq!(method, "done");
+        assert_eq!(params, vec![Value::from("r1"), Value::from("cancelled")]);
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,295 @@
+//! Runtime plugin for the sessions' Neovim
+//!
+//! The Lua plugin in `plugin/` is compiled into the binary and written to
+//! `~/.local/share/nvim-web/runtime/<version>` on first use. Local Neovim
+//! processes start with that directory on their 'runtimepath'. Once the UI
+//! is attached, the host calls `require('nvim-web').handshake()`, which turns
+//! on the integration features the `[plugin]` section of config.toml leaves
+//! enabled (`:VfsStatus`, auto-cd to the git root, cwd sync, recording hooks,
+//! the clipboard provider) and reports the plugin version and the features
+//! it enabled. A different version means another copy of the plugin was
+//! loaded first.
+//!
+//! A Neovim the host did not spawn (`--remote`) may run on another machine,
+//! where the runtime directory does not exist. The host sends such a Neovim
+//! the plugin's Lua modules with the handshake call instead, and registers
+//! them in `package.preload`, which `require` searches before the
+//! 'runtimepath'. Its `plugin/` scripts are not sourced; the handshake runs
+//! `setup()` itself.
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use nvim_rs::{Neovim, Value};
+use rust_embed::RustEmbed;
+
+use crate::config::PluginConfig;
+use crate::session::NvimWriter;
+
+/// Version of the plugin the host ships, `M.version` in `lua/nvim-web/init.lua`
+pub const PLUGIN_VERSION: &str = "1.0.0";
+
+/// The runtime plugin files from the plugin/ directory
+#[derive(RustEmbed)]
+#[folder = "../../plugin/"]
+#[include = "plugin/*.lua"]
+#[include = "lua/**/*.lua"]
+pub struct PluginAssets;
+
+/// Makes the plugin loadable, from the runtime directory or from modules
+/// sent along, and calls the handshake
+const HANDSHAKE_LUA: &str = r"
+local dir, modules, opts = ...
+if type(dir) == 'string' and not vim.tbl_contains(vim.api.nvim_list_runtime_paths(), dir) then
+  vim.opt.runtimepath:append(dir)
+end
+if type(modules) == 'table' then
+  for name, source in pairs(modules) do
+    package.preload[name] = assert((loadstring or load)(source, '@nvim-web/' .. name))
+  end
+end
+return require('nvim-web').handshake(opts)
+";
+
+/// How a Neovim gets the runtime plugin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// From the runtime directory, for a Neovim the host spawned
+    RuntimeDir,
+    /// Sent over RPC, for a Neovim that may not share the host's filesystem
+    Inline,
+}
+
+/// Write the plugin files to `dir`, leaving files that are up to date alone
+pub fn install(dir: &Path) -> Result<()> {
+    for name in PluginAssets::iter() {
+        let file = PluginAssets::get(&name).expect("embedded file listed but missing");
+        let path = dir.join(name.as_ref());
+        if std::fs::read(&path).is_ok_and(|data| data == file.data.as_ref()) {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        std::fs::write(&path, &file.data)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Directory of the runtime plugin, installed on first use
+pub fn runtime_dir() -> Result<&'static Path> {
+    static DIR: OnceLock<std::result::Result<PathBuf, String>> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let dir = dirs::data_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("nvim-web")
+            .join("runtime")
+            .join(PLUGIN_VERSION);
+        install(&dir).map(|()| dir).map_err(|e| format!("{e:#}"))
+    })
+    .as_deref()
+    .map_err(|e| anyhow::anyhow!("Failed to install the runtime plugin: {e}"))
+}
+
+/// Lua module name of an embedded file, e.g. `nvim-web.features`
+fn module_name(path: &str) -> Option<String> {
+    let name = path.strip_prefix("lua/")?.strip_suffix(".lua")?;
+    let name = name.strip_suffix("/init").unwrap_or(name);
+    Some(name.replace('/', "."))
+}
+
+/// The plugin's Lua modules by name, for [`Delivery::Inline`]
+fn inline_modules() -> Value {
+    let modules = PluginAssets::iter()
+        .filter_map(|path| {
+            let name = module_name(&path)?;
+            let file = PluginAssets::get(&path)?;
+            let source = String::from_utf8_lossy(&file.data).into_owned();
+            Some((Value::from(name), Value::from(source)))
+        })
+        .collect();
+    Value::Map(modules)
+}
+
+/// What the plugin reported on handshake
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handshake {
+    pub version: String,
+    /// Features the plugin turned on
+    pub features: Vec<String>,
+}
+
+impl Handshake {
+    /// Read the table `handshake()` returns
+    pub fn from_value(value: &Value) -> Result<Self> {
+        let field = |name: &str| {
+            value
+                .as_map()
+                .and_then(|map| map.iter().find(|(k, _)| k.as_str() == Some(name)))
+                .map(|(_, v)| v)
+        };
+        let version = field("version")
+            .and_then(Value::as_str)
+            .context("Plugin handshake has no version")?
+            .to_string();
+        // An empty Lua table arrives as an array or a map
+        let features = match field("features") {
+            Some(Value::Array(names)) => names
+                .iter()
+                .filter_map(|name| name.as_str().map(ToString::to_string))
+                .collect(),
+            _ => Vec::new(),
+        };
+        Ok(Self { version, features })
+    }
+}
+
+/// Options passed to `handshake()`: the host's channel and feature toggles
+fn handshake_options(channel: i64, config: &PluginConfig) -> Value {
+    let features = config
+        .features()
+        .into_iter()
+        .map(|(name, enabled)| (Value::from(name), Value::from(enabled)))
+        .collect();
+    Value::Map(vec![
+        (Value::from("channel"), Value::from(channel)),
+        (Value::from("features"), Value::Map(features)),
+    ])
+}
+
+/// Load the runtime plugin into an attached Neovim and enable its features
+///
+/// Fails if the plugin cannot be loaded or is not the version the host ships.
+pub async fn handshake(
+    nvim: &Neovim<NvimWriter>,
+    config: &PluginConfig,
+    delivery: Delivery,
+) -> Result<Handshake> {
+    let (dir, modules) = match delivery {
+        Delivery::RuntimeDir => (
+            Value::from(runtime_dir()?.to_string_lossy().as_ref()),
+            Value::Nil,
+        ),
+        Delivery::Inline => (Value::Nil, inline_modules()),
+    };
+    let channel = nvim
+        .get_api_info()
+        .await?
+        .first()
+        .and_then(Value::as_i64)
+        .context("Neovim did not report the host's channel")?;
+    let args = vec![dir, modules, handshake_options(channel, config)];
+    let value = nvim
+        .call(
+            "nvim_exec_lua",
+            vec![Value::from(HANDSHAKE_LUA), Value::Array(args)],
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("RPC call failed: {e:?}"))?
+        .map_err(|e| anyhow::anyhow!("Plugin handshake failed: {e}"))?;
+    let handshake = Handshake::from_value(&value)?;
+    if handshake.version != PLUGIN_VERSION {
+        anyhow::bail!(
+            "Runtime plugin {} was loaded instead of {PLUGIN_VERSION}; \
+             is another copy of nvim-web on the runtimepath?",
+            handshake.version
+        );
+    }
+    Ok(handshake)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plugin_version_matches() {
+        let init = PluginAssets::get("lua/nvim-web/init.lua").unwrap();
+        let init = String::from_utf8_lossy(&init.data);
+        assert!(init.contains(&format!("M.version = \"{PLUGIN_VERSION}\"")));
+        assert!(PluginAssets::get("lua/nvim-web/features.lua").is_some());
+        assert!(PluginAssets::get("plugin/nvim-web.lua").is_some());
+    }
+
+    #[test]
+    fn test_inline_modules() {
+        assert_eq!(
+            module_name("lua/nvim-web/init.lua").as_deref(),
+            Some("nvim-web")
+        );
+        assert_eq!(
+            module_name("lua/nvim-web/features.lua").as_deref(),
+            Some("nvim-web.features")
+        );
+        assert_eq!(module_name("plugin/nvim-web.lua"), None);
+
+        let modules = inline_modules();
+        let names: Vec<&str> = modules
+            .as_map()
+            .unwrap()
+            .iter()
+            .filter_map(|(name, _)| name.as_str())
+            .collect();
+        assert!(names.contains(&"nvim-web"));
+        assert!(names.contains(&"nvim-web.features"));
+        assert!(!names.iter().any(|name| name.starts_with("plugin")));
+    }
+
+    #[test]
+    fn test_install() {
+        let dir = tempfile::tempdir().unwrap();
+        install(dir.path()).unwrap();
+        let features = dir.path().join("lua/nvim-web/features.lua");
+        assert!(features.exists());
+
+        // Stale files are replaced
+        std::fs::write(&features, "-- old").unwrap();
+        install(dir.path()).unwrap();
+        let data = std::fs::read(&features).unwrap();
+        assert_eq!(
+            data,
+            PluginAssets::get("lua/nvim-web/features.lua")
+                .unwrap()
+                .data
+                .as_ref()
+        );
+    }
+
+    #[test]
+    fn test_handshake_values() {
+        let config = PluginConfig {
+            auto_cd: false,
+            ..Default::default()
+        };
+        let options = handshake_options(3, &config);
+        let map = options.as_map().unwrap();
+        assert_eq!(map[0], (Value::from("channel"), Value::from(3)));
+        let features = map[1].1.as_map().unwrap();
+        assert!(features.contains(&(Value::from("auto_cd"), Value::from(false))));
+        assert!(features.contains(&(Value::from("clipboard"), Value::from(true))));
+
+        let reported = Value::Map(vec![
+            (Value::from("version"), Value::from("1.0.0")),
+            (
+                Value::from("features"),
+                Value::Array(vec![Value::from("cwd_sync"), Value::from("clipboard")]),
+            ),
+        ]);
+        assert_eq!(
+            Handshake::from_value(&reported).unwrap(),
+            Handshake {
+                version: "1.0.0".to_string(),
+                features: vec!["cwd_sync".to_string(), "clipboard".to_string()],
+            }
+        );
+        let none = Value::Map(vec![
+            (Value::from("version"), Value::from("1.0.0")),
+            (Value::from("features"), Value::Map(vec![])),
+        ]);
+        assert!(Handshake::from_value(&none).unwrap().features.is_empty());
+        assert!(Handshake::from_value(&Value::Nil).is_err());
+    }
+}
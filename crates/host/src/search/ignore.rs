@@ -0,0 +1,147 @@
+//! `.gitignore` matching for project search
+//!
+//! Supports the common subset of gitignore syntax: comments, negation
+//! (`!`), directory-only rules (trailing `/`), anchored rules (containing
+//! `/`) and `*`/`?`/`**` globs. Deeper `.gitignore` files take precedence
+//! and the last matching rule in a file wins.
+
+use std::sync::Arc;
+
+use globset::{GlobBuilder, GlobMatcher};
+
+struct Rule {
+    glob: GlobMatcher,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// Rules of one `.gitignore`
+pub struct IgnoreFile {
+    /// Directory containing the file, relative to the search root
+    base: String,
+    rules: Vec<Rule>,
+}
+
+impl IgnoreFile {
+    /// Parse a `.gitignore` found in `base` (relative to the search root)
+    pub fn parse(base: &str, content: &str) -> Self {
+        let rules = content.lines().filter_map(parse_rule).collect();
+        Self {
+            base: base.trim_matches('/').to_string(),
+            rules,
+        }
+    }
+
+    /// `Some(true)` if ignored, `Some(false)` if re-included, `None` if no rule matches
+    fn matched(&self, path: &str, is_dir: bool) -> Option<bool> {
+        let path = if self.base.is_empty() {
+            path
+        } else {
+            path.strip_prefix(&self.base)?.strip_prefix('/')?
+        };
+
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.glob.is_match(path))
+            .map(|rule| !rule.negated)
+    }
+}
+
+fn parse_rule(line: &str) -> Option<Rule> {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let (negated, pattern) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line.strip_prefix('\\').unwrap_or(line)),
+    };
+    let (dir_only, pattern) = match pattern.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+    // A slash anywhere but the end anchors the rule to its directory
+    let glob = match pattern.strip_prefix('/') {
+        Some(anchored) => anchored.to_string(),
+        None if pattern.contains('/') => pattern.to_string(),
+        None => format!("**/{pattern}"),
+    };
+    if glob.is_empty() {
+        return None;
+    }
+
+    let glob = GlobBuilder::new(&glob)
+        .literal_separator(true)
+        .build()
+        .ok()?
+        .compile_matcher();
+    Some(Rule {
+        glob,
+        negated,
+        dir_only,
+    })
+}
+
+/// `.gitignore` files in effect for a directory, outermost first
+#[derive(Clone, Default)]
+pub struct IgnoreStack(Vec<Arc<IgnoreFile>>);
+
+impl IgnoreStack {
+    /// Stack for a subdirectory that has its own `.gitignore`
+    pub fn with(&self, file: IgnoreFile) -> Self {
+        let mut files = self.0.clone();
+        files.push(Arc::new(file));
+        Self(files)
+    }
+
+    /// Whether `path` (relative to the search root) is ignored
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        self.0
+            .iter()
+            .rev()
+            .find_map(|file| file.matched(path, is_dir))
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gitignore_rules() {
+        let root = IgnoreFile::parse(
+            "",
+            "# build output\ntarget/\n*.log\n!keep.log\n/dist\ndocs/*.html\n",
+        );
+        let stack = IgnoreStack::default().with(root);
+
+        assert!(stack.is_ignored("target", true));
+        assert!(stack.is_ignored("crates/a/target", true));
+        // Directory-only rule does not apply to files
+        assert!(!stack.is_ignored("target", false));
+        assert!(stack.is_ignored("src/debug.log", false));
+        assert!(!stack.is_ignored("src/keep.log", false));
+        assert!(stack.is_ignored("dist", true));
+        // Anchored rules only match relative to their directory
+        assert!(!stack.is_ignored("src/dist", true));
+        assert!(stack.is_ignored("docs/index.html", false));
+        assert!(!stack.is_ignored("docs/api/index.html", false));
+        assert!(!stack.is_ignored("src/main.rs", false));
+    }
+
+    #[test]
+    fn test_nested_gitignore_overrides_parent() {
+        let stack = IgnoreStack::default()
+            .with(IgnoreFile::parse("", "*.json\n"))
+            .with(IgnoreFile::parse("web", "!package.json\n/local\n"));
+
+        assert!(stack.is_ignored("data.json", false));
+        assert!(!stack.is_ignored("web/package.json", false));
+        assert!(stack.is_ignored("web/other.json", false));
+        assert!(stack.is_ignored("web/local", true));
+        assert!(!stack.is_ignored("local", true));
+    }
+}
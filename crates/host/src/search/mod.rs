@@ -7,8 +7,13 @@ use anyhow::Result;
 use regex::Regex;
 use std::path::Path;
 
+pub mod ignore;
+pub mod project;
+
+pub use project::{ProjectSearch, SearchSummary};
+
 /// Search result with line number and content
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SearchMatch {
     pub line_number: usize,
     pub line_content: String,
@@ -43,9 +48,12 @@ pub fn search_content(
     pattern: &str,
     options: &SearchOptions,
 ) -> Result<Vec<SearchMatch>> {
-    let mut results = Vec::new();
+    let re = build_regex(pattern, options)?;
+    Ok(search_lines(content, &re, options.max_results))
+}
 
-    // Build regex
+/// Build the matcher for `pattern` according to `options`
+fn build_regex(pattern: &str, options: &SearchOptions) -> Result<Regex> {
     let regex_pattern = if options.regex {
         pattern.to_string()
     } else {
@@ -58,7 +66,15 @@ pub fn search_content(
         regex_pattern
     };
 
-    let re = Regex::new(&regex_pattern)?;
+    Ok(Regex::new(&regex_pattern)?)
+}
+
+/// First match of `re` on each line, up to `max_results` lines
+fn search_lines(content: &str, re: &Regex, max_results: usize) -> Vec<SearchMatch> {
+    let mut results = Vec::new();
+    if max_results == 0 {
+        return results;
+    }
 
     for (line_idx, line) in content.lines().enumerate() {
         if let Some(m) = re.find(line) {
@@ -69,13 +85,13 @@ pub fn search_content(
                 match_end: m.end(),
             });
 
-            if results.len() >= options.max_results {
+            if results.len() >= max_results {
                 break;
             }
         }
     }
 
-    Ok(results)
+    results
 }
 
 /// Search file by path
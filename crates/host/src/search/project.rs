@@ -0,0 +1,463 @@
+//! Project-wide search over any VFS backend
+//!
+//! The tree is walked through [`VfsBackend`], so SSH, browser and in-memory
+//! workspaces are searched the same way as the local disk. `.gitignore`
+//! files are honoured as they are found; `.git`, binary and oversized files
+//! are skipped like ripgrep does.
+//!
+//! Results stream to the browser as notifications:
+//!
+//! - `search_results [search_id, [{path, lnum, col, end_col, text}, ...]]`
+//! - `search_done [search_id, {files, matches, truncated, cancelled}]`
+//! - `search_error [search_id, message]`
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use rmpv::Value;
+use tokio::sync::{broadcast, RwLock};
+
+use super::ignore::{IgnoreFile, IgnoreStack};
+use super::{build_regex, search_lines, SearchMatch, SearchOptions};
+use crate::requests::StreamingRequests;
+use crate::session::AsyncSessionManager;
+use crate::vfs::VfsBackend;
+
+/// Files larger than this are not searched
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+/// Leading bytes checked for NUL to detect binary files
+const BINARY_PROBE_LEN: usize = 8192;
+/// Matches per `search_results` notification
+const BATCH_SIZE: usize = 50;
+/// Result limit when the caller does not set one
+pub const DEFAULT_MAX_RESULTS: usize = 2000;
+
+/// A project search request
+pub struct ProjectSearch {
+    pub backend: Arc<dyn VfsBackend>,
+    /// Root as given by the caller (VFS path or alias), prefixed to result paths
+    pub root_uri: String,
+    /// Root within the backend
+    pub root: String,
+    pub pattern: String,
+    /// `max_results` applies to the whole project
+    pub options: SearchOptions,
+    /// Replace Neovim's quickfix list with the results when done
+    pub quickfix: bool,
+}
+
+/// A match in a project file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMatch {
+    /// Path relative to the search root
+    pub path: String,
+    pub line: SearchMatch,
+}
+
+/// Totals reported with `search_done`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SearchSummary {
+    pub files: usize,
+    pub matches: usize,
+    /// Stopped at `max_results`
+    pub truncated: bool,
+}
+
+impl SearchSummary {
+    fn to_value(self, cancelled: bool) -> Value {
+        Value::Map(vec![
+            ("files".into(), uint(self.files)),
+            ("matches".into(), uint(self.matches)),
+            ("truncated".into(), self.truncated.into()),
+            ("cancelled".into(), cancelled.into()),
+        ])
+    }
+}
+
+impl ProjectSearch {
+    /// Walk the project, handing matches to `on_batch` as they accumulate
+    ///
+    /// The walk stops early when `on_batch` returns false.
+    pub async fn run(
+        &self,
+        mut on_batch: impl FnMut(Vec<FileMatch>) -> bool,
+    ) -> Result<SearchSummary> {
+        let re = build_regex(&self.pattern, &self.options)?;
+        let backend = self.backend.as_ref();
+        let mut summary = SearchSummary::default();
+        let mut batch = Vec::new();
+
+        // Depth-first, (backend path, path relative to root, ignores)
+        let mut stack = vec![(self.root.clone(), String::new(), IgnoreStack::default())];
+        'walk: while let Some((dir, rel_dir, mut ignores)) = stack.pop() {
+            let mut names = match backend.list(&dir).await {
+                Ok(names) => names,
+                Err(e) if rel_dir.is_empty() => return Err(e),
+                Err(_) => continue,
+            };
+            names.sort();
+
+            if names.iter().any(|name| name == ".gitignore") {
+                if let Ok(content) = backend.read(&join(&dir, ".gitignore")).await {
+                    let file = IgnoreFile::parse(&rel_dir, &String::from_utf8_lossy(&content));
+                    ignores = ignores.with(file);
+                }
+            }
+
+            let mut subdirs = Vec::new();
+            for name in names {
+                if name == ".git" {
+                    continue;
+                }
+                let path = join(&dir, &name);
+                let rel = join(&rel_dir, &name);
+                let Ok(stat) = backend.stat(&path).await else {
+                    continue;
+                };
+                if ignores.is_ignored(&rel, stat.is_dir) {
+                    continue;
+                }
+                if stat.is_dir {
+                    subdirs.push((path, rel));
+                    continue;
+                }
+                if !stat.is_file || stat.size > MAX_FILE_SIZE {
+                    continue;
+                }
+
+                let Ok(data) = backend.read(&path).await else {
+                    continue;
+                };
+                if data[..data.len().min(BINARY_PROBE_LEN)].contains(&0) {
+                    continue;
+                }
+                summary.files += 1;
+
+                let remaining = self.options.max_results - summary.matches;
+                for line in search_lines(&String::from_utf8_lossy(&data), &re, remaining) {
+                    batch.push(FileMatch {
+                        path: rel.clone(),
+                        line,
+                    });
+                    summary.matches += 1;
+                }
+                if batch.len() >= BATCH_SIZE && !on_batch(std::mem::take(&mut batch)) {
+                    return Ok(summary);
+                }
+                if summary.matches >= self.options.max_results {
+                    summary.truncated = true;
+                    break 'walk;
+                }
+            }
+
+            // Visit subdirectories in name order
+            stack.extend(
+                subdirs
+                    .into_iter()
+                    .rev()
+                    .map(|(path, rel)| (path, rel, ignores.clone())),
+            );
+        }
+
+        if !batch.is_empty() {
+            on_batch(batch);
+        }
+        Ok(summary)
+    }
+
+    /// VFS path of a match, in the form the caller used for the root
+    pub fn uri(&self, m: &FileMatch) -> String {
+        format!("{}/{}", self.root_uri.trim_end_matches('/'), m.path)
+    }
+
+    /// `{path, lnum, col, end_col, text}` with 1-based byte columns
+    fn match_value(&self, m: &FileMatch) -> Value {
+        Value::Map(vec![
+            ("path".into(), self.uri(m).into()),
+            ("lnum".into(), uint(m.line.line_number)),
+            ("col".into(), uint(m.line.match_start + 1)),
+            ("end_col".into(), uint(m.line.match_end + 1)),
+            ("text".into(), m.line.line_content.as_str().into()),
+        ])
+    }
+
+    /// `setqflist()` item; local files use their real path so `:cc` opens them
+    fn quickfix_item(&self, m: &FileMatch) -> Value {
+        let filename = self
+            .backend
+            .host_path(&join(&self.root, &m.path))
+            .map_or_else(|| self.uri(m), |p| p.display().to_string());
+        Value::Map(vec![
+            ("filename".into(), filename.into()),
+            ("lnum".into(), uint(m.line.line_number)),
+            ("col".into(), uint(m.line.match_start + 1)),
+            ("end_col".into(), uint(m.line.match_end + 1)),
+            ("text".into(), m.line.line_content.as_str().into()),
+        ])
+    }
+}
+
+/// Start `search` as `search_id`, streaming results on `out`
+pub fn start(
+    requests: &Arc<StreamingRequests>,
+    manager: &Arc<RwLock<AsyncSessionManager>>,
+    session_id: &str,
+    search_id: &str,
+    search: ProjectSearch,
+    out: broadcast::Sender<Vec<u8>>,
+) -> Result<()> {
+    // Fail invalid patterns in the RPC response rather than a notification
+    build_regex(&search.pattern, &search.options)?;
+    let manager = Arc::clone(manager);
+    let owner = session_id.to_string();
+
+    requests.spawn(session_id, search_id, out, |stream| async move {
+        let mut items = Vec::new();
+        let result = search
+            .run(|batch| {
+                if search.quickfix {
+                    items.extend(batch.iter().map(|m| search.quickfix_item(m)));
+                }
+                let values = batch.iter().map(|m| search.match_value(m)).collect();
+                stream.send("search_results", vec![Value::Array(values)])
+            })
+            .await;
+
+        match result {
+            Ok(summary) => {
+                if search.quickfix && stream.is_active() {
+                    let title = format!("Search: {}", search.pattern);
+                    if let Err(e) = set_quickfix(&manager, &owner, &title, items).await {
+                        tracing::warn!(error = %e, "Failed to populate quickfix list");
+                    }
+                }
+                stream.finish("search_done", vec![summary.to_value(false)]);
+            }
+            Err(e) => stream.finish("search_error", vec![e.to_string().into()]),
+        }
+    })
+}
+
+/// Cancel a running search, returns false if it was not running
+pub fn cancel(
+    requests: &StreamingRequests,
+    session_id: &str,
+    search_id: &str,
+    out: &broadcast::Sender<Vec<u8>>,
+) -> bool {
+    let summary = Value::Map(vec![("cancelled".into(), true.into())]);
+    requests.cancel(session_id, search_id, out, "search_done", vec![summary])
+}
+
+async fn set_quickfix(
+    manager: &Arc<RwLock<AsyncSessionManager>>,
+    session_id: &str,
+    title: &str,
+    items: Vec<Value>,
+) -> Result<()> {
+    let mgr = manager.read().await;
+    let session = mgr
+        .get_session(session_id)
+        .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+    let what = Value::Map(vec![
+        ("title".into(), title.into()),
+        ("items".into(), Value::Array(items)),
+    ]);
+    session
+        .rpc_call(
+            "nvim_call_function",
+            vec![
+                "setqflist".into(),
+                Value::Array(vec![Value::Array(vec![]), " ".into(), what]),
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{name}", dir.trim_end_matches('/'))
+    }
+}
+
+fn uint(n: usize) -> Value {
+    Value::from(u64::try_from(n).unwrap_or(u64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use nvim_web_protocol::schema::HostMessage;
+
+    use super::*;
+    use crate::vfs::{FileStat, MemoryFs, VfsManager};
+
+    /// Memory backend with a per-read delay, like a remote workspace
+    struct SlowFs(MemoryFs);
+
+    #[async_trait]
+    impl VfsBackend for SlowFs {
+        async fn read(&self, path: &str) -> Result<Vec<u8>> {
+            tokio::time::sleep(Duration::from_millis(2)).await;
+            self.0.read(path).await
+        }
+        async fn write(&self, path: &str, data: &[u8]) -> Result<()> {
+            self.0.write(path, data).await
+        }
+        async fn stat(&self, path: &str) -> Result<FileStat> {
+            self.0.stat(path).await
+        }
+        async fn list(&self, path: &str) -> Result<Vec<String>> {
+            self.0.list(path).await
+        }
+    }
+
+    fn project(files: Vec<(&str, &[u8])>, pattern: &str, max_results: usize) -> ProjectSearch {
+        ProjectSearch {
+            backend: Arc::new(MemoryFs::with_files(files)),
+            root_uri: "vfs://mem/proj/".to_string(),
+            root: "/proj".to_string(),
+            pattern: pattern.to_string(),
+            options: SearchOptions {
+                max_results,
+                ..Default::default()
+            },
+            quickfix: false,
+        }
+    }
+
+    async fn collect(search: &ProjectSearch) -> (Vec<String>, SearchSummary) {
+        let mut found = Vec::new();
+        let summary = search
+            .run(|batch| {
+                found.extend(
+                    batch
+                        .iter()
+                        .map(|m| format!("{}:{}", m.path, m.line.line_number)),
+                );
+                true
+            })
+            .await
+            .unwrap();
+        (found, summary)
+    }
+
+    #[tokio::test]
+    async fn walks_tree_honouring_gitignore() {
+        let search = project(
+            vec![
+                ("/proj/.gitignore", b"target/\n*.log\n"),
+                ("/proj/src/main.rs", b"fn main() {\n    todo!()\n}\n"),
+                ("/proj/src/.gitignore", b"gen.rs\n"),
+                ("/proj/src/gen.rs", b"todo"),
+                ("/proj/target/out.rs", b"todo"),
+                ("/proj/app.log", b"TODO"),
+                ("/proj/.git/HEAD", b"todo"),
+                ("/proj/image.png", b"\x89PNG\0todo"),
+                ("/proj/README", b"Todo list\n"),
+            ],
+            "todo",
+            100,
+        );
+
+        let (found, summary) = collect(&search).await;
+        assert_eq!(found, vec!["README:1", "src/main.rs:2"]);
+        // README, main.rs and the two .gitignore files
+        assert_eq!(summary.files, 4);
+        assert!(!summary.truncated);
+    }
+
+    #[tokio::test]
+    async fn stops_at_max_results() {
+        let many = "hit\n".repeat(200);
+        let search = project(
+            vec![("/proj/a", many.as_bytes()), ("/proj/b", b"hit")],
+            "hit",
+            120,
+        );
+
+        let (found, summary) = collect(&search).await;
+        assert_eq!(found.len(), 120);
+        assert_eq!(summary.matches, 120);
+        assert!(summary.truncated);
+    }
+
+    #[tokio::test]
+    async fn streams_batches_and_cancels() {
+        let files: Vec<(String, Vec<u8>)> = (0..400)
+            .map(|i| (format!("/proj/f{i:03}"), b"needle\n".to_vec()))
+            .collect();
+        let mut search = project(vec![], "needle", 1000);
+        search.backend = Arc::new(SlowFs(MemoryFs::with_files(
+            files
+                .iter()
+                .map(|(p, d)| (p.as_str(), d.as_slice()))
+                .collect(),
+        )));
+        assert_eq!(
+            search.uri(&FileMatch {
+                path: "f000".to_string(),
+                line: SearchMatch {
+                    line_number: 1,
+                    line_content: String::new(),
+                    match_start: 0,
+                    match_end: 0,
+                },
+            }),
+            "vfs://mem/proj/f000"
+        );
+
+        let vfs = Arc::new(RwLock::new(VfsManager::new()));
+        let manager = Arc::new(RwLock::new(AsyncSessionManager::new(vfs)));
+        let requests = StreamingRequests::new();
+        let (out, mut rx) = broadcast::channel(64);
+
+        start(&requests, &manager, "s1", "q1", search, out.clone()).unwrap();
+        let first = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let Ok(HostMessage::Notification { method, params }) = HostMessage::decode(&first) else {
+            panic!("expected notification");
+        };
+        assert_eq!(method, "search_results");
+        assert_eq!(params[0], Value::from("q1"));
+        assert_eq!(params[1].as_array().unwrap().len(), BATCH_SIZE);
+
+        assert!(cancel(&requests, "s1", "q1", &out));
+        // Drain: only batches sent before the cancel, then search_done
+        loop {
+            let bytes = rx.recv().await.unwrap();
+            let Ok(HostMessage::Notification { method, params }) = HostMessage::decode(&bytes)
+            else {
+                panic!("expected notification");
+            };
+            if method == "search_done" {
+                let cancelled = params[1]
+                    .as_map()
+                    .unwrap()
+                    .iter()
+                    .any(|(k, v)| k.as_str() == Some("cancelled") && v.as_bool() == Some(true));
+                assert!(cancelled);
+                break;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn invalid_regex_fails_to_start() {
+        let mut search = project(vec![], "(", 10);
+        search.options.regex = true;
+        let vfs = Arc::new(RwLock::new(VfsManager::new()));
+        let manager = Arc::new(RwLock::new(AsyncSessionManager::new(vfs)));
+        let (out, _rx) = broadcast::channel(4);
+        assert!(start(&StreamingRequests::new(), &manager, "s1", "q1", search, out).is_err());
+    }
+}
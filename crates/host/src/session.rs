@@ -4,19 +4,29 @@
 //! redraw events to connected WebSocket clients.
 
 use std::collections::HashMap;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 use futures::io::AsyncWrite;
 use nvim_rs::{Handler, Neovim, Value};
 use std::process::Stdio;
 use tokio::net::TcpStream;
-use tokio::process::Command;
 use tokio::sync::{broadcast, RwLock as TokioRwLock};
 use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 
+use crate::collaboration::SharedCollaborationRegistry;
+use crate::config::PluginConfig;
 use crate::context::ContextManager;
+use crate::crdt::bridge::{self, BridgeHandle, BridgeNotifier, CrdtBridge};
+use crate::launch::{LaunchProfile, LaunchProfiles, LaunchRequest};
+use crate::oidc::{Backend, Grant, RoleLimits};
+use crate::pipe::ToolRegistry;
+use crate::registry::{SessionRecord, SessionRegistry};
+use crate::requests::StreamingRequests;
+use crate::runtime::{Delivery, Handshake};
+use crate::sharing::Snapshot;
+use nvim_web_protocol::schema::{CwdInfo, HostMessage};
 use nvim_web_vfs::VfsManager;
 
 /// Unique session identifier
@@ -27,6 +37,63 @@ pub fn generate_session_id() -> SessionId {
     uuid::Uuid::new_v4().to_string()
 }
 
+/// Who may use a session when OIDC login is enforced
+///
+/// Sessions belong to the user that created them. Others can only join
+/// through a share link, which makes them a guest; guests from read-only
+/// links may only watch. Without login (`owner` is `None`) anyone may attach.
+#[derive(Debug, Clone, Default)]
+pub struct SessionAcl {
+    /// Subject (`sub`) of the user that created the session
+    pub owner: Option<String>,
+    /// Guests by subject, `true` for read-only
+    guests: HashMap<String, bool>,
+}
+
+/// What a user may do with a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionAccess {
+    /// Attach, edit and manage the session
+    Owner,
+    /// Attach and edit
+    Guest,
+    /// Attach as a viewer only
+    Viewer,
+    /// Not even see that the session exists
+    Denied,
+}
+
+impl SessionAcl {
+    pub fn owned_by(owner: Option<String>) -> Self {
+        Self {
+            owner,
+            guests: HashMap::new(),
+        }
+    }
+
+    /// Let `user` in after they used a share link
+    ///
+    /// A user keeps the most access any of their links gave them.
+    pub fn add_guest(&mut self, user: &str, read_only: bool) {
+        let entry = self.guests.entry(user.to_string()).or_insert(read_only);
+        *entry &= read_only;
+    }
+
+    /// Access of `user`, which is `None` when login is not enforced
+    pub fn access(&self, user: Option<&str>) -> SessionAccess {
+        match (self.owner.as_deref(), user) {
+            (None, _) => SessionAccess::Owner,
+            (Some(_), None) => SessionAccess::Denied,
+            (Some(owner), Some(user)) if owner == user => SessionAccess::Owner,
+            (Some(_), Some(user)) => match self.guests.get(user) {
+                Some(false) => SessionAccess::Guest,
+                Some(true) => SessionAccess::Viewer,
+                None => SessionAccess::Denied,
+            },
+        }
+    }
+}
+
 /// The writer type used by nvim-rs with tokio
 /// Using Box<dyn> to support both ChildStdin (local) and TcpStream (remote)
 pub type NvimWriter = Box<dyn AsyncWrite + Send + Unpin + 'static>;
@@ -57,6 +124,8 @@ pub struct RedrawHandler {
     #[allow(dead_code)]
     session_id: String,
     vfs_manager: Arc<TokioRwLock<VfsManager>>,
+    crdt: BridgeNotifier,
+    limits: Arc<RoleLimits>,
 }
 
 impl RedrawHandler {
@@ -65,16 +134,26 @@ impl RedrawHandler {
         redraw_tx: broadcast::Sender<Vec<u8>>,
         requests: RequestMap,
         vfs_manager: Arc<TokioRwLock<VfsManager>>,
+        crdt: BridgeNotifier,
+        limits: Arc<RoleLimits>,
     ) -> Self {
         Self {
             redraw_tx,
             requests,
             session_id,
             vfs_manager,
+            crdt,
+            limits,
         }
     }
 }
 
+/// Fail unless `limits` allow the VFS backend `vfs_path` resolves to
+pub async fn check_vfs_path(limits: &RoleLimits, vfs: &VfsManager, vfs_path: &str) -> Result<()> {
+    let (scheme, _) = vfs.parse_vfs_path(vfs_path).await?;
+    limits.check_vfs_scheme(&scheme)
+}
+
 #[async_trait]
 impl Handler for RedrawHandler {
     type Writer = NvimWriter;
@@ -95,20 +174,11 @@ impl Handler for RedrawHandler {
                 let mut map = self.requests.lock().unwrap();
                 map.insert(req_id, tx);
             }
-            let msg = Value::Array(vec![
-                Value::Integer(2.into()),
-                Value::String("clipboard_read".into()),
-                Value::Array(vec![
-                    Value::Integer(req_id.into()),
-                    Value::String(self.session_id.clone().into()),
-                ]),
-            ]);
-            let mut bytes = Vec::new();
-            if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-                let _ = self.redraw_tx.send(bytes);
-            } else {
-                return Err(Value::String("Failed to encode clipboard request".into()));
-            }
+            let msg = HostMessage::ClipboardRead {
+                request_id: req_id,
+                session_id: self.session_id.clone(),
+            };
+            let _ = self.redraw_tx.send(msg.encode());
             match tokio::time::timeout(Duration::from_secs(5), rx).await {
                 Ok(Ok(val)) => return Ok(val),
                 Ok(Err(_)) => return Err(Value::String("Clipboard request channel closed".into())),
@@ -125,6 +195,9 @@ impl Handler for RedrawHandler {
                 .and_then(|v| v.as_str())
                 .ok_or_else(|| Value::String("vfs_read requires path argument".into()))?;
             let vfs = self.vfs_manager.read().await;
+            check_vfs_path(&self.limits, &vfs, path)
+                .await
+                .map_err(|e| Value::String(format!("VFS read error: {e}").into()))?;
             match vfs.read_file(path).await {
                 Ok(content) => {
                     let text = String::from_utf8_lossy(&content);
@@ -155,6 +228,9 @@ impl Handler for RedrawHandler {
                 .collect::<Vec<_>>()
                 .join("\n");
             let vfs = self.vfs_manager.read().await;
+            check_vfs_path(&self.limits, &vfs, path)
+                .await
+                .map_err(|e| Value::String(format!("VFS write error: {e}").into()))?;
             match vfs.write_file(path, content.as_bytes()).await {
                 Ok(()) => return Ok(Value::Boolean(true)),
                 Err(e) => return Err(Value::String(format!("VFS write error: {e}").into())),
@@ -170,6 +246,9 @@ impl Handler for RedrawHandler {
             let uri = url::Url::parse(path)
                 .map_err(|e| Value::String(format!("Invalid URI: {e}").into()))?;
             let scheme = uri.scheme();
+            self.limits
+                .check_vfs_scheme(scheme)
+                .map_err(|e| Value::String(format!("Delete failed: {e}").into()))?;
             if let Ok(backend) = vfs.get_backend(scheme).await {
                 let p = uri.path().to_string();
                 match crate::vfs::async_ops::remove_dir_all(backend.as_ref(), &p).await {
@@ -185,88 +264,62 @@ impl Handler for RedrawHandler {
     }
 
     async fn handle_notify(&self, name: String, args: Vec<Value>, _neovim: Neovim<Self::Writer>) {
-        if name == "redraw" {
-            let msg = Value::Array(vec![
-                Value::Integer(2.into()),
-                Value::String("redraw".into()),
-                Value::Array(args),
-            ]);
-            let mut bytes = Vec::new();
-            if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-                let _ = self.redraw_tx.send(bytes);
-            }
-        } else if name == "clipboard_write" {
-            let msg = Value::Array(vec![
-                Value::Integer(2.into()),
-                Value::String("clipboard_write".into()),
-                Value::Array(args),
-            ]);
-            let mut bytes = Vec::new();
-            if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-                let _ = self.redraw_tx.send(bytes);
-            }
-        } else if name == "cwd_changed" {
-            let cwd = args.first().and_then(|v| v.as_str()).unwrap_or("~");
-            let file = args.get(1).and_then(|v| v.as_str()).unwrap_or("");
-            let backend = args.get(2).and_then(|v| v.as_str()).unwrap_or("local");
-            let git_branch = args
-                .get(3)
-                .and_then(|v| v.as_str())
-                .filter(|s| !s.is_empty());
-
-            let info_map = vec![
-                (Value::String("cwd".into()), Value::String(cwd.into())),
-                (Value::String("file".into()), Value::String(file.into())),
-                (
-                    Value::String("backend".into()),
-                    Value::String(backend.into()),
-                ),
-                (
-                    Value::String("git_branch".into()),
-                    git_branch.map_or(Value::Nil, |b| Value::String(b.into())),
-                ),
-            ];
-            let msg = Value::Array(vec![Value::String("cwd_info".into()), Value::Map(info_map)]);
-            let mut bytes = Vec::new();
-            if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-                let _ = self.redraw_tx.send(bytes);
+        if self.crdt.notify(&name, &args) {
+            return;
+        }
+        let msg = match name.as_str() {
+            "redraw" => HostMessage::Redraw(args),
+            "clipboard_write" => {
+                // args: [lines, regtype]
+                let lines = args
+                    .first()
+                    .and_then(|v| v.as_array())
+                    .map(|lines| {
+                        lines
+                            .iter()
+                            .filter_map(|l| l.as_str().map(ToString::to_string))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let regtype = args.get(1).and_then(|v| v.as_str()).unwrap_or("");
+                HostMessage::ClipboardWrite {
+                    lines,
+                    regtype: regtype.to_string(),
+                }
             }
-        } else if name == "recording_start" {
-            let register = args.first().and_then(|v| v.as_str()).unwrap_or("q");
-            let msg = Value::Array(vec![
-                Value::String("recording_start".into()),
-                Value::String(register.into()),
-            ]);
-            let mut bytes = Vec::new();
-            if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-                let _ = self.redraw_tx.send(bytes);
+            "cwd_changed" => {
+                let arg = |i: usize| args.get(i).and_then(|v| v.as_str());
+                HostMessage::CwdInfo(CwdInfo {
+                    cwd: arg(0).unwrap_or("~").to_string(),
+                    file: arg(1).unwrap_or("").to_string(),
+                    backend: arg(2).unwrap_or("local").to_string(),
+                    git_branch: arg(3).filter(|s| !s.is_empty()).map(ToString::to_string),
+                })
             }
-        } else if name == "recording_stop" {
-            let msg = Value::Array(vec![Value::String("recording_stop".into())]);
-            let mut bytes = Vec::new();
-            if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-                let _ = self.redraw_tx.send(bytes);
+            "recording_start" => {
+                let register = args.first().and_then(|v| v.as_str()).unwrap_or("q");
+                HostMessage::RecordingStart {
+                    register: register.to_string(),
+                }
             }
-        } else if name == "nvim_web_vfx" {
-            // args: [{'mode': 'pulse'}]
-            if let Some(opts) = args.first().and_then(|v| v.as_map()) {
+            "recording_stop" => HostMessage::RecordingStop,
+            "nvim_web_vfx" => {
+                // args: [{'mode': 'pulse'}]
+                let Some(opts) = args.first().and_then(|v| v.as_map()) else {
+                    return;
+                };
                 let mode = opts
                     .iter()
                     .find(|(k, _)| k.as_str() == Some("mode"))
                     .and_then(|(_, v)| v.as_str())
                     .unwrap_or("railgun");
-
-                let msg = Value::Array(vec![
-                    Value::Integer(2.into()),
-                    Value::String("vfx_change".into()),
-                    Value::Array(vec![Value::String(mode.into())]),
-                ]);
-                let mut bytes = Vec::new();
-                if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-                    let _ = self.redraw_tx.send(bytes);
+                HostMessage::VfxChange {
+                    mode: mode.to_string(),
                 }
             }
-        }
+            _ => return,
+        };
+        let _ = self.redraw_tx.send(msg.encode());
     }
 }
 
@@ -281,6 +334,18 @@ pub struct AsyncSession {
     pub connected: bool,
     pub requests: RequestMap,
     pub context_manager: Option<crate::context::ContextManager>,
+    /// Keeps buffers in sync with collaborators' CRDT documents
+    pub crdt: BridgeHandle,
+    /// Owner and guests of the session
+    pub acl: SessionAcl,
+    /// Limits of the owner's role, unlimited without login
+    pub limits: Arc<RoleLimits>,
+    /// Launch profile Neovim was started with, `None` for the built-in one
+    pub profile: Option<String>,
+    /// What the runtime plugin reported, `None` if it could not be loaded
+    pub plugin: Option<Handshake>,
+    /// Working directory of a local Neovim, as last known
+    pub cwd: Option<std::path::PathBuf>,
 }
 
 async fn exec_viml(nvim: &Neovim<NvimWriter>, script: &str) -> Result<()> {
@@ -296,21 +361,43 @@ async fn exec_viml(nvim: &Neovim<NvimWriter>, script: &str) -> Result<()> {
 
 impl AsyncSession {
     /// Create a new session with either a spawned process or remote connection
-    #[allow(clippy::too_many_lines)]
+    ///
+    /// `id` is a new one, or that of a session being resumed. A spawned
+    /// process is started as `launch` says; a remote Neovim is already
+    /// running and ignores it. Both load the runtime plugin with the features
+    /// `plugin` enables.
+    #[allow(clippy::too_many_lines, clippy::too_many_arguments)]
     pub async fn new(
+        id: SessionId,
         vfs_manager: Arc<TokioRwLock<VfsManager>>,
         context: Option<String>,
         remote_address: Option<String>,
         auth_token: Option<String>,
+        collaboration: SharedCollaborationRegistry,
+        limits: Arc<RoleLimits>,
+        launch: &LaunchProfile,
+        plugin: &PluginConfig,
     ) -> Result<Self> {
-        let id = generate_session_id();
         let id_for_log = id.clone();
         let (redraw_tx, _) = broadcast::channel::<Vec<u8>>(256);
         let requests = Arc::new(Mutex::new(HashMap::new()));
-        let handler =
-            RedrawHandler::new(id.clone(), redraw_tx.clone(), requests.clone(), vfs_manager);
+        let (crdt, crdt_events) = bridge::channel();
+        let handler = RedrawHandler::new(
+            id.clone(),
+            redraw_tx.clone(),
+            requests.clone(),
+            vfs_manager,
+            crdt.notifier(),
+            limits.clone(),
+        );
 
-        let nvim = if let Some(addr) = remote_addr(remote_address.clone()) {
+        let remote = remote_addr(remote_address.clone());
+        let delivery = if remote.is_some() {
+            Delivery::Inline
+        } else {
+            Delivery::RuntimeDir
+        };
+        let nvim = if let Some(addr) = remote {
             eprintln!("SESSION: Connecting to remote Neovim at {addr}...");
 
             if addr.starts_with("unix://") {
@@ -348,26 +435,21 @@ impl AsyncSession {
             }
         } else {
             // Spawn local process
-            let mut cmd = Command::new("nvim");
-            cmd.arg("--embed");
-            if let Ok(home) = std::env::var("HOME") {
-                cmd.current_dir(&home);
-            }
-            if let Ok(cwd) = std::env::current_dir() {
-                let plugin_path = cwd.join("plugin");
-                if plugin_path.exists() {
-                    cmd.args([
-                        "--cmd",
-                        &format!("set runtimepath+={}", plugin_path.to_string_lossy()),
-                    ]);
+            let mut cmd = launch.command();
+            match crate::runtime::runtime_dir() {
+                Ok(dir) => {
+                    cmd.args(["--cmd", &format!("set runtimepath+={}", dir.display())]);
                 }
+                Err(e) => eprintln!("SESSION {id_for_log}: {e:#}"),
             }
             // Use piped stdin/stdout/stderr
             cmd.stdin(Stdio::piped())
                 .stdout(Stdio::piped())
                 .stderr(Stdio::piped());
 
-            let mut child = cmd.spawn()?;
+            let mut child = cmd
+                .spawn()
+                .with_context(|| format!("Failed to start {}", launch.binary))?;
             let stdin = child.stdin.take().expect("Failed to take stdin");
             let stdout = child.stdout.take().expect("Failed to take stdout");
             let stderr = child.stderr.take().expect("Failed to take stderr");
@@ -410,89 +492,34 @@ impl AsyncSession {
         opts.set_multigrid_external(true);
         nvim.ui_attach(80, 24, &opts).await?;
 
-        // Initialize features
-        let check_git: std::result::Result<String, _> =
-            nvim.command_output("silent! command Git").await;
-        if check_git.is_err() || check_git.unwrap_or_default().is_empty() {
-            let _ = nvim
-                .command("command! -nargs=* Git execute '!' . 'git ' . <q-args>")
-                .await;
-        }
+        // Runtime plugin
+        let plugin = match crate::runtime::handshake(&nvim, plugin, delivery).await {
+            Ok(handshake) => {
+                eprintln!(
+                    "SESSION {id}: Runtime plugin {} ({})",
+                    handshake.version,
+                    handshake.features.join(", ")
+                );
+                Some(handshake)
+            }
+            Err(e) => {
+                eprintln!("SESSION {id}: {e:#}");
+                None
+            }
+        };
 
-        // VfsStatus
-        let vfs_status_cmd = r"
-command! VfsStatus call NvimWeb_ShowVfsStatus()
-function! NvimWeb_ShowVfsStatus()
-  let l:buf = expand('%:p')
-  if l:buf =~# '^vfs://browser/'
-    echo 'Backend: browser (OPFS)'
-  elseif l:buf =~# '^vfs://ssh/'
-    echo 'Backend: ssh (remote)'
-  else
-    echo 'Backend: local'
-  endif
-  echo 'Path: ' . (l:buf != '' ? l:buf : '[No Name]')
-  echo 'CWD: ' . getcwd()
-endfunction
-";
-        let _ = exec_viml(&nvim, vfs_status_cmd).await;
-
-        // Auto CD
-        let auto_cd_git = r"
-augroup NvimWebGitCD
-  autocmd!
-  autocmd BufEnter * call NvimWeb_AutoCdToGitRoot()
-augroup END
-function! NvimWeb_AutoCdToGitRoot()
-  let l:file = expand('%:p')
-  if l:file == '' || l:file =~# '^term://' || l:file =~# '^fugitive://'
-    return
-  endif
-  let l:git_root = system('git -C ' . shellescape(expand('%:p:h')) . ' rev-parse --show-toplevel 2>/dev/null')
-  if v:shell_error == 0 && l:git_root != ''
-    execute 'lcd ' . fnameescape(trim(l:git_root))
-  endif
-endfunction
-";
-        let _ = exec_viml(&nvim, auto_cd_git).await;
-
-        // CWD Sync
-        let cwd_sync = r#"
-augroup NvimWebCwdSync
-  autocmd!
-  autocmd DirChanged * call NvimWeb_NotifyCwdChanged()
-  autocmd BufEnter * call NvimWeb_NotifyCwdChanged()
-augroup END
-function! NvimWeb_NotifyCwdChanged()
-  let l:cwd = getcwd()
-  let l:file = expand('%:p')
-  let l:git_branch = ''
-  let l:git_output = system('git -C ' . shellescape(l:cwd) . ' branch --show-current 2>/dev/null')
-  if v:shell_error == 0
-    let l:git_branch = trim(l:git_output)
-  endif
-  let l:backend = 'local'
-  if l:file =~# '^vfs://browser/'
-    let l:backend = 'browser'
-  elseif l:file =~# '^vfs://ssh/'
-    let l:backend = 'ssh'
-  endif
-  call rpcnotify(0, 'cwd_changed', l:cwd, l:file, l:backend, l:git_branch)
-endfunction
-"#;
-        if let Ok(()) = exec_viml(&nvim, cwd_sync).await {
-            let _ = nvim.command("call NvimWeb_NotifyCwdChanged()").await;
-        }
-
-        // Recording
-        let recording_sync = r#"
-augroup NvimWebRecording
-  autocmd!
-  autocmd RecordingEnter * call rpcnotify(0, 'recording_start', reg_recording())
-  autocmd RecordingLeave * call rpcnotify(0, 'recording_stop')
-augroup END
-"#;
-        let _ = exec_viml(&nvim, recording_sync).await;
+        // Collaborative editing
+        tokio::spawn(
+            CrdtBridge::new(
+                id.clone(),
+                nvim.clone(),
+                collaboration,
+                redraw_tx.clone(),
+                crdt_events,
+            )
+            .run(),
+        );
+        let _ = exec_viml(&nvim, bridge::ATTACH_AUTOCMD).await;
 
         // Context
         let mut context_manager = None;
@@ -511,34 +538,6 @@ augroup END
             context_manager = Some(cm);
         }
 
-        // Clipboard & WebBrowse (Lua Injection)
-        // We use Lua for g:clipboard because it supports function callbacks directly in recent Neovim versions.
-        let clipboard_lua = r#"
-lua << EOF
-local function paste()
-  return vim.rpcrequest(0, 'clipboard_read')
-end
-local function copy(lines, regtype)
-  vim.rpcnotify(0, 'clipboard_write', lines, regtype)
-end
-
-vim.g.clipboard = {
-  name = 'nvim-web',
-  copy = {
-    ['+'] = copy,
-    ['*'] = copy,
-  },
-  paste = {
-    ['+'] = paste,
-    ['*'] = paste,
-  },
-  cache_enabled = 1,
-}
-EOF
-command! WebBrowse call rpcnotify(0, 'nvim_web_action', 'browse_files')
-"#;
-        let _ = exec_viml(&nvim, clipboard_lua).await;
-
         eprintln!(
             "SESSION: Created new async session {id} (Remote: {})",
             remote_address.is_some()
@@ -555,9 +554,20 @@ command! WebBrowse call rpcnotify(0, 'nvim_web_action', 'browse_files')
             connections: 0,
             requests,
             context_manager,
+            crdt,
+            acl: SessionAcl::default(),
+            limits,
+            profile: None,
+            plugin,
+            cwd: None,
         })
     }
 
+    /// Fail unless the owner's role allows the VFS backend of `vfs_path`
+    pub async fn check_vfs_path(&self, vfs: &VfsManager, vfs_path: &str) -> Result<()> {
+        check_vfs_path(&self.limits, vfs, vfs_path).await
+    }
+
     pub fn complete_request(&self, req_id: u32, value: Value) {
         let mut map = self.requests.lock().unwrap();
         if let Some(tx) = map.remove(&req_id) {
@@ -599,24 +609,44 @@ command! WebBrowse call rpcnotify(0, 'nvim_web_action', 'browse_files')
     }
 
     /// Gracefully shutdown the session (save buffers and session state)
+    ///
+    /// Disconnected sessions are saved too, so they can be resumed.
     pub async fn shutdown(&self) -> Result<()> {
-        if self.connected {
-            // Write all modified buffers
-            let _ = tokio::time::timeout(Duration::from_secs(2), self.nvim.command("wa")).await;
-
-            // Save full session state (cursor, buffers, undo, windows)
-            let session_file = self.session_file_path();
-            let cmd = format!("mksession! {}", session_file.display());
-            let _ = tokio::time::timeout(Duration::from_secs(2), self.nvim.command(&cmd)).await;
-            eprintln!(
-                "SESSION {}: Saved state to {}",
-                self.id,
-                session_file.display()
-            );
-        }
+        // Write all modified buffers
+        let _ = tokio::time::timeout(Duration::from_secs(2), self.nvim.command("wa")).await;
+
+        // Save full session state (cursor, buffers, undo, windows)
+        let session_file = self.session_file_path();
+        let cmd = format!("mksession! {}", session_file.display());
+        let _ = tokio::time::timeout(Duration::from_secs(2), self.nvim.command(&cmd)).await;
+        eprintln!(
+            "SESSION {}: Saved state to {}",
+            self.id,
+            session_file.display()
+        );
         Ok(())
     }
 
+    /// Neovim's current working directory
+    pub async fn current_dir(&self) -> Result<std::path::PathBuf> {
+        let cwd = self.call_function("getcwd", vec![]).await?;
+        cwd.as_str()
+            .map(std::path::PathBuf::from)
+            .context("getcwd() did not return a path")
+    }
+
+    /// What the registry keeps to resume this session after a restart
+    pub fn record(&self) -> SessionRecord {
+        SessionRecord {
+            id: self.id.clone(),
+            owner: self.acl.owner.clone(),
+            cwd: self.cwd.clone(),
+            profile: self.profile.clone(),
+            session_file: self.session_file_path(),
+            last_active: SystemTime::now() - self.last_active.elapsed(),
+        }
+    }
+
     /// Get the session file path for this session
     pub fn session_file_path(&self) -> std::path::PathBuf {
         let session_dir = std::env::var("HOME")
@@ -653,15 +683,90 @@ command! WebBrowse call rpcnotify(0, 'nvim_web_action', 'browse_files')
         }
         Ok(false)
     }
+
+    /// Restore a snapshot's cwd, reopen its files and place the cursor
+    ///
+    /// Unsaved buffers, layout, registers and marks are restored when the
+    /// snapshot has them. Without a layout, the focused buffer is the
+    /// snapshot's current file, falling back to the cursor's file; the cursor
+    /// (1-based line, 0-based column) is only restored when it lies in the
+    /// focused buffer.
+    pub async fn apply_snapshot(&self, snapshot: &Snapshot) -> Result<()> {
+        if !snapshot.cwd.as_os_str().is_empty() {
+            let cwd = snapshot.cwd.to_string_lossy();
+            self.rpc_call("nvim_set_current_dir", vec![Value::from(cwd.as_ref())])
+                .await?;
+        }
+
+        for file in &snapshot.open_files {
+            let buf = self.add_buffer(file).await?;
+            self.call_function(
+                "setbufvar",
+                vec![buf, Value::from("&buflisted"), Value::from(1)],
+            )
+            .await?;
+        }
+
+        if crate::snapshot::restore(self, snapshot).await? {
+            return Ok(());
+        }
+
+        let cursor = snapshot.cursor.as_ref();
+        let Some(focus) = snapshot
+            .current_file
+            .as_ref()
+            .or(cursor.map(|(file, _, _)| file))
+        else {
+            return Ok(());
+        };
+        let buf = self.add_buffer(focus).await?;
+        self.rpc_call("nvim_set_current_buf", vec![buf]).await?;
+
+        if let Some((_, line, col)) = cursor.filter(|(file, _, _)| file == focus) {
+            // cursor() clamps positions past the end of the buffer
+            self.call_function(
+                "cursor",
+                vec![Value::from(*line), Value::from(col.saturating_add(1))],
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Buffer number for `file`, creating an unloaded buffer if needed
+    async fn add_buffer(&self, file: &str) -> Result<Value> {
+        self.call_function("bufadd", vec![Value::from(file)]).await
+    }
+
+    async fn call_function(&self, name: &str, args: Vec<Value>) -> Result<Value> {
+        self.rpc_call(
+            "nvim_call_function",
+            vec![Value::from(name), Value::Array(args)],
+        )
+        .await
+    }
 }
 
 pub struct AsyncSessionManager {
     sessions: HashMap<SessionId, AsyncSession>,
     pub timeout: Duration,
+    /// Sessions running at once, for all users together
+    pub max_sessions: usize,
+    /// Tools sessions may run through `tool_exec`
+    pub tools: Arc<ToolRegistry>,
+    /// Profiles local Neovim processes are launched with
+    pub profiles: Arc<LaunchProfiles>,
+    /// Runtime plugin features sessions enable
+    pub plugin: Arc<PluginConfig>,
+    /// Where sessions are recorded to be resumed after a restart, if anywhere
+    pub registry: Option<SessionRegistry>,
     pub active_ssh: Option<String>,
     vfs_manager: Arc<TokioRwLock<VfsManager>>,
     pub remote_address: Option<String>,
     pub auth_token: Option<String>,
+    collaboration: SharedCollaborationRegistry,
+    llm: Arc<StreamingRequests>,
+    searches: Arc<StreamingRequests>,
 }
 
 impl AsyncSessionManager {
@@ -669,13 +774,36 @@ impl AsyncSessionManager {
         Self {
             sessions: HashMap::new(),
             timeout: Duration::from_secs(300),
+            max_sessions: crate::config::SessionConfig::default().max_sessions,
+            tools: Arc::new(ToolRegistry::default()),
+            profiles: Arc::new(LaunchProfiles::default()),
+            plugin: Arc::new(PluginConfig::default()),
+            registry: None,
             active_ssh: None,
             vfs_manager,
             remote_address: None,
             auth_token: None,
+            collaboration: crate::collaboration::create_registry(),
+            llm: StreamingRequests::new(),
+            searches: StreamingRequests::new(),
         }
     }
 
+    /// Collaboration registry shared by all connections of all sessions
+    pub fn collaboration(&self) -> SharedCollaborationRegistry {
+        self.collaboration.clone()
+    }
+
+    /// In-flight LLM prompts of all sessions
+    pub fn llm(&self) -> Arc<StreamingRequests> {
+        self.llm.clone()
+    }
+
+    /// Running project searches of all sessions
+    pub fn searches(&self) -> Arc<StreamingRequests> {
+        self.searches.clone()
+    }
+
     pub fn set_active_ssh(&mut self, uri: Option<String>) {
         self.active_ssh = uri;
     }
@@ -689,28 +817,178 @@ impl AsyncSessionManager {
     }
 
     /// Gracefully shutdown all sessions (save buffers)
-    pub async fn shutdown_all(&self) {
+    ///
+    /// Recorded sessions are saved with their working directory and stay in
+    /// the registry, to be resumed after the restart.
+    pub async fn shutdown_all(&mut self) {
         let count = self.sessions.len();
         if count > 0 {
             eprintln!("SESSION: Auto-saving {count} active sessions...");
-            let futures = self.sessions.values().map(|session| session.shutdown());
+            let futures = self.sessions.values_mut().map(|session| async move {
+                let _ = session.shutdown().await;
+                if session.cwd.is_some() {
+                    session.cwd = session.current_dir().await.ok().or(session.cwd.take());
+                }
+            });
             futures::future::join_all(futures).await;
         }
+        for (_, session) in self.sessions.drain() {
+            if session.cwd.is_some() {
+                remember(self.registry.as_ref(), &session);
+            }
+        }
+    }
+
+    /// Backend new sessions run on, as named in role limits
+    pub fn backend_kind(&self) -> Backend {
+        match remote_addr(self.remote_address.clone()) {
+            None => Backend::Local,
+            Some(addr) if addr.starts_with("unix://") => Backend::Unix,
+            Some(_) => Backend::Tcp,
+        }
     }
 
-    pub async fn create_session(&mut self, context: Option<String>) -> Result<SessionId> {
-        let session = AsyncSession::new(
+    /// Check that `owner` may start another session
+    ///
+    /// `owner` is `None` without login, when only `max_sessions` applies.
+    pub fn check_create(&self, owner: Option<&Grant>) -> Result<()> {
+        if self.sessions.len() >= self.max_sessions {
+            anyhow::bail!("Session limit reached ({} running)", self.max_sessions);
+        }
+        let Some(owner) = owner else {
+            return Ok(());
+        };
+        if !owner.role.can_edit() {
+            anyhow::bail!("Role {} cannot start sessions", owner.role);
+        }
+        if let Some(max) = owner.limits.max_sessions {
+            let owned = self
+                .sessions
+                .values()
+                .filter(|session| session.acl.owner.as_deref() == Some(owner.sub.as_str()))
+                .count();
+            if owned >= max {
+                anyhow::bail!(
+                    "Session limit of role {} reached ({max} running)",
+                    owner.role
+                );
+            }
+        }
+        owner.limits.check_backend(self.backend_kind())
+    }
+
+    /// Start a session owned by `owner`, within the limits of their role
+    ///
+    /// `launch` picks the launch profile of a local Neovim.
+    pub async fn create_session(
+        &mut self,
+        context: Option<String>,
+        owner: Option<&Grant>,
+        launch: &LaunchRequest,
+    ) -> Result<SessionId> {
+        self.check_create(owner)?;
+        let (profile, launch) = match self.backend_kind() {
+            Backend::Local => self.profiles.resolve(launch)?,
+            _ => (None, LaunchProfile::default()),
+        };
+        self.spawn_session(generate_session_id(), context, owner, profile, &launch)
+            .await
+    }
+
+    /// Respawn session `id` of an earlier run of the host for `owner`
+    ///
+    /// The new Neovim starts in the recorded working directory with the
+    /// recorded launch profile and sources the session file saved on
+    /// shutdown. `None` when the registry has no record of the session, or
+    /// the record belongs to another user.
+    pub async fn resume_session(
+        &mut self,
+        id: &str,
+        owner: Option<&Grant>,
+    ) -> Result<Option<SessionId>> {
+        let Some(registry) = &self.registry else {
+            return Ok(None);
+        };
+        let Some(record) = registry.get(id)? else {
+            return Ok(None);
+        };
+        if record.owner.as_deref() != owner.map(|owner| owner.sub.as_str())
+            || self.backend_kind() != Backend::Local
+        {
+            return Ok(None);
+        }
+        self.check_create(owner)?;
+        let request = LaunchRequest {
+            profile: record.profile.clone(),
+            project: None,
+        };
+        let (profile, mut launch) = self.profiles.resolve(&request)?;
+        if let Some(cwd) = record.cwd.as_ref().filter(|cwd| cwd.is_dir()) {
+            launch.cwd = Some(cwd.display().to_string());
+        }
+        let id = self
+            .spawn_session(record.id, None, owner, profile, &launch)
+            .await?;
+        if let Some(session) = self.sessions.get(&id) {
+            session.restore_session().await?;
+        }
+        eprintln!("SESSION: Resumed session {id}");
+        Ok(Some(id))
+    }
+
+    /// Start Neovim for session `id` and record it
+    async fn spawn_session(
+        &mut self,
+        id: SessionId,
+        context: Option<String>,
+        owner: Option<&Grant>,
+        profile: Option<String>,
+        launch: &LaunchProfile,
+    ) -> Result<SessionId> {
+        let limits = owner.map(|owner| owner.limits.clone()).unwrap_or_default();
+        let mut session = AsyncSession::new(
+            id,
             self.vfs_manager.clone(),
             context,
             self.remote_address.clone(),
             self.auth_token.clone(),
+            self.collaboration.clone(),
+            Arc::new(limits),
+            launch,
+            &self.plugin,
         )
         .await?;
+        session.acl = SessionAcl::owned_by(owner.map(|owner| owner.sub.clone()));
+        session.profile = profile;
+        if self.backend_kind() == Backend::Local {
+            session.cwd = launch.working_dir();
+            remember(self.registry.as_ref(), &session);
+        }
         let id = session.id.clone();
         self.sessions.insert(id.clone(), session);
         Ok(id)
     }
 
+    /// Spawn a new session for `owner` with the state recorded in `snapshot`
+    pub async fn restore_snapshot(
+        &mut self,
+        snapshot: &Snapshot,
+        owner: Option<&Grant>,
+    ) -> Result<SessionId> {
+        let id = self
+            .create_session(None, owner, &LaunchRequest::default())
+            .await?;
+        let restored = match self.sessions.get(&id) {
+            Some(session) => session.apply_snapshot(snapshot).await,
+            None => Ok(()),
+        };
+        if let Err(e) = restored {
+            self.remove_session(&id);
+            return Err(e.context(format!("Failed to restore snapshot {}", snapshot.id)));
+        }
+        Ok(id)
+    }
+
     pub fn get_session_mut(&mut self, id: &str) -> Option<&mut AsyncSession> {
         self.sessions.get_mut(id)
     }
@@ -723,11 +1001,30 @@ impl AsyncSessionManager {
         self.sessions.contains_key(id)
     }
 
+    /// End a session; it cannot be resumed anymore
     pub fn remove_session(&mut self, id: &str) -> Option<AsyncSession> {
         eprintln!("SESSION: Removing session {id}");
+        self.forget(id);
         self.sessions.remove(id)
     }
 
+    /// Note in the registry that a session was used just now
+    pub fn touch_record(&self, id: &str) {
+        if let Some(registry) = &self.registry {
+            if let Err(e) = registry.touch(id, SystemTime::now()) {
+                eprintln!("SESSION {id}: Registry error: {e:#}");
+            }
+        }
+    }
+
+    fn forget(&self, id: &str) {
+        if let Some(registry) = &self.registry {
+            if let Err(e) = registry.remove(id) {
+                eprintln!("SESSION {id}: Registry error: {e:#}");
+            }
+        }
+    }
+
     pub fn cleanup_stale(&mut self) -> Vec<SessionId> {
         let now = Instant::now();
         let timeout = self.timeout;
@@ -741,6 +1038,7 @@ impl AsyncSessionManager {
             .collect();
         for id in &stale_ids {
             eprintln!("SESSION: Cleaning up stale session {id}");
+            self.forget(id);
             self.sessions.remove(id);
         }
         stale_ids
@@ -757,6 +1055,22 @@ impl AsyncSessionManager {
             .collect()
     }
 
+    /// Sessions `user` may attach to; all of them when login is not enforced
+    pub fn list_sessions_for(&self, user: Option<&str>) -> Vec<SessionInfo> {
+        self.sessions
+            .values()
+            .filter(|session| session.acl.access(user) != SessionAccess::Denied)
+            .map(AsyncSession::to_session_info)
+            .collect()
+    }
+
+    /// Access of `user` to a session, `None` if it is not running
+    pub fn access(&self, id: &str, user: Option<&str>) -> Option<SessionAccess> {
+        self.sessions
+            .get(id)
+            .map(|session| session.acl.access(user))
+    }
+
     pub fn session_ids(&self) -> Vec<String> {
         self.sessions.keys().cloned().collect()
     }
@@ -778,6 +1092,10 @@ pub struct SessionInfo {
     pub age_secs: u64,
     pub connected: bool,
     pub is_active: bool,
+    /// Launch profile, `None` for the built-in one
+    pub profile: Option<String>,
+    /// Runtime plugin version, `None` if it could not be loaded
+    pub plugin: Option<String>,
 }
 
 impl SessionInfo {
@@ -809,6 +1127,18 @@ impl SessionInfo {
                 rmpv::Value::String("is_active".into()),
                 rmpv::Value::Boolean(self.is_active),
             ),
+            (
+                rmpv::Value::String("profile".into()),
+                self.profile
+                    .as_ref()
+                    .map_or(rmpv::Value::Nil, |p| rmpv::Value::String(p.clone().into())),
+            ),
+            (
+                rmpv::Value::String("plugin".into()),
+                self.plugin
+                    .as_ref()
+                    .map_or(rmpv::Value::Nil, |v| rmpv::Value::String(v.clone().into())),
+            ),
         ])
     }
 }
@@ -823,6 +1153,17 @@ impl AsyncSession {
             age_secs: now.duration_since(self.created_at).as_secs(),
             connected: self.connected,
             is_active: self.redraw_tx.receiver_count() > 0,
+            profile: self.profile.clone(),
+            plugin: self.plugin.as_ref().map(|plugin| plugin.version.clone()),
+        }
+    }
+}
+
+/// Record `session` in `registry`, logging failures
+fn remember(registry: Option<&SessionRegistry>, session: &AsyncSession) {
+    if let Some(registry) = registry {
+        if let Err(e) = registry.save(&session.record()) {
+            eprintln!("SESSION {}: Registry error: {e:#}", session.id);
         }
     }
 }
@@ -831,3 +1172,63 @@ impl AsyncSession {
 fn remote_addr(addr: Option<String>) -> Option<String> {
     addr.filter(|s| !s.is_empty())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_acl() {
+        let open = SessionAcl::default();
+        assert_eq!(open.access(None), SessionAccess::Owner);
+        assert_eq!(open.access(Some("alice")), SessionAccess::Owner);
+
+        let mut acl = SessionAcl::owned_by(Some("alice".to_string()));
+        assert_eq!(acl.access(Some("alice")), SessionAccess::Owner);
+        assert_eq!(acl.access(Some("bob")), SessionAccess::Denied);
+        assert_eq!(acl.access(None), SessionAccess::Denied);
+
+        acl.add_guest("bob", true);
+        assert_eq!(acl.access(Some("bob")), SessionAccess::Viewer);
+        acl.add_guest("bob", false);
+        assert_eq!(acl.access(Some("bob")), SessionAccess::Guest);
+        // A later read-only link does not take editing away
+        acl.add_guest("bob", true);
+        assert_eq!(acl.access(Some("bob")), SessionAccess::Guest);
+    }
+
+    #[test]
+    fn test_check_create() {
+        use crate::oidc::UserRole;
+
+        let vfs_manager = Arc::new(TokioRwLock::new(VfsManager::new()));
+        let mut mgr = AsyncSessionManager::new(vfs_manager);
+        let mut developer = Grant {
+            sub: "alice".to_string(),
+            role: UserRole::Developer,
+            limits: RoleLimits::default(),
+        };
+        assert!(mgr.check_create(None).is_ok());
+        assert!(mgr.check_create(Some(&developer)).is_ok());
+
+        developer.limits.max_sessions = Some(0);
+        assert!(mgr.check_create(Some(&developer)).is_err());
+        developer.limits.max_sessions = None;
+
+        developer.limits.backends = Some([Backend::Local].into_iter().collect());
+        assert_eq!(mgr.backend_kind(), Backend::Local);
+        assert!(mgr.check_create(Some(&developer)).is_ok());
+        mgr.set_remote_address("tcp://127.0.0.1:6666".to_string());
+        assert_eq!(mgr.backend_kind(), Backend::Tcp);
+        assert!(mgr.check_create(Some(&developer)).is_err());
+
+        let viewer = Grant {
+            role: UserRole::ViewerOnly,
+            ..developer
+        };
+        assert!(mgr.check_create(Some(&viewer)).is_err());
+
+        mgr.max_sessions = 0;
+        assert!(mgr.check_create(None).is_err());
+    }
+}
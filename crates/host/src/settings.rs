@@ -3,7 +3,7 @@
 //! Stores nvim-web settings in ~/.config/nvim-web/settings.db
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
 use rusqlite::{params, Connection};
@@ -18,14 +18,17 @@ impl SettingsStore {
     ///
     /// Location: ~/.config/nvim-web/settings.db
     pub fn new() -> Result<Self> {
-        let db_path = Self::db_path()?;
+        Self::open(&Self::db_path()?)
+    }
 
+    /// Create or open a settings database at `db_path`
+    pub fn open(db_path: &Path) -> Result<Self> {
         // Ensure parent directory exists
         if let Some(parent) = db_path.parent() {
             std::fs::create_dir_all(parent).context("Failed to create config directory")?;
         }
 
-        let conn = Connection::open(&db_path).context("Failed to open settings database")?;
+        let conn = Connection::open(db_path).context("Failed to open settings database")?;
 
         // Initialize schema
         conn.execute(
@@ -106,6 +109,47 @@ impl SettingsStore {
     }
 }
 
+/// Settings of one user, kept apart from everyone else's
+///
+/// Without login there is a single user, whose settings use the plain keys.
+pub struct UserSettings<'a> {
+    store: &'a SettingsStore,
+    user: Option<&'a str>,
+}
+
+impl<'a> UserSettings<'a> {
+    pub fn new(store: &'a SettingsStore, user: Option<&'a str>) -> Self {
+        Self { store, user }
+    }
+
+    fn key(&self, key: &str) -> String {
+        match self.user {
+            Some(user) => format!("user/{user}/{key}"),
+            None => key.to_string(),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.store.get(&self.key(key))
+    }
+
+    pub fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.store.set(&self.key(key), value)
+    }
+
+    pub fn delete(&self, key: &str) -> Result<()> {
+        self.store.delete(&self.key(key))
+    }
+}
+
+/// Settings the browser may not read or write with `settings_*`
+///
+/// LLM settings hold API keys and decide where prompts and keys are sent,
+/// so they only change through the `llm_*` methods, for the caller alone.
+pub fn is_reserved(key: &str) -> bool {
+    key.starts_with("llm.") || key.starts_with("user/")
+}
+
 /// Default settings
 pub fn defaults() -> HashMap<String, String> {
     let mut map = HashMap::new();
@@ -178,4 +222,26 @@ mod tests {
         assert_eq!(all.get("a"), Some(&"1".to_string()));
         assert_eq!(all.get("b"), Some(&"2".to_string()));
     }
+
+    #[test]
+    fn test_user_settings_are_separate() {
+        let dir = tempdir().unwrap();
+        let store = SettingsStore::open(&dir.path().join("test.db")).unwrap();
+        let alice = UserSettings::new(&store, Some("alice"));
+        let bob = UserSettings::new(&store, Some("bob"));
+
+        alice.set("llm.provider", "ollama").unwrap();
+        assert_eq!(alice.get("llm.provider").as_deref(), Some("ollama"));
+        assert_eq!(bob.get("llm.provider"), None);
+        assert_eq!(store.get("llm.provider"), None);
+        assert!(store.get_all().keys().all(|key| is_reserved(key)));
+
+        UserSettings::new(&store, None)
+            .set("llm.provider", "openai")
+            .unwrap();
+        assert_eq!(store.get("llm.provider").as_deref(), Some("openai"));
+        alice.delete("llm.provider").unwrap();
+        assert_eq!(alice.get("llm.provider"), None);
+        assert!(!is_reserved("font_size"));
+    }
 }
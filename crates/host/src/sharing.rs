@@ -4,19 +4,34 @@
 //! - Share link generation with expiry and use limits
 //! - Workspace snapshots for reproducible state
 //! - API endpoints for sharing
-
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::RwLock;
+//!
+//! Links (with claim counts and expiry) and snapshots are persisted in
+//! SQLite so they survive host restarts. Share tokens are random and only
+//! their SHA-256 hash is stored; the plaintext token is returned once, when
+//! the link is created. Creating, claiming and revoking links is audited,
+//! naming links by the start of their hash.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, PoisonError};
 use std::time::{Duration, SystemTime};
 
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
 
+use crate::audit::{AuditAction, AuditEvent};
+use crate::auth::{self, ShareTokens};
+use crate::snapshot::{BufferSnapshot, MarkSnapshot, RegisterSnapshot, TabSnapshot};
+
 /// Share link configuration
 #[derive(Debug, Clone, Serialize)]
 pub struct ShareLink {
-    /// Unique share token
+    /// Share token, only known when the link is created
+    #[serde(skip_serializing_if = "String::is_empty")]
     pub token: String,
+    /// SHA-256 of the token, as stored
+    #[serde(skip)]
+    pub token_hash: String,
     /// Session ID to share
     pub session_id: String,
     /// When the link was created
@@ -31,6 +46,9 @@ pub struct ShareLink {
     pub read_only: bool,
     /// Optional label for the link
     pub label: Option<String>,
+    /// Subject (`sub`) of the session owner that created the link
+    #[serde(skip)]
+    pub owner: Option<String>,
 }
 
 /// Options for creating a share link
@@ -101,58 +119,345 @@ pub struct Snapshot {
     pub cursor: Option<(String, u32, u32)>,
     /// Optional description
     pub description: Option<String>,
+    /// Contents of buffers with unsaved changes
+    #[serde(default)]
+    pub buffers: Vec<BufferSnapshot>,
+    /// Tab pages with their window layout
+    #[serde(default)]
+    pub tabs: Vec<TabSnapshot>,
+    /// 1-based index of the current tab
+    #[serde(default)]
+    pub current_tab: usize,
+    #[serde(default)]
+    pub registers: Vec<RegisterSnapshot>,
+    #[serde(default)]
+    pub marks: Vec<MarkSnapshot>,
+    /// Subject (`sub`) of the user the snapshot belongs to
+    #[serde(default)]
+    pub owner: Option<String>,
 }
 
-// Share link storage
-lazy_static::lazy_static! {
-    static ref SHARE_LINKS: RwLock<HashMap<String, ShareLink>> = RwLock::new(HashMap::new());
-    static ref SNAPSHOTS: RwLock<HashMap<String, Snapshot>> = RwLock::new(HashMap::new());
+impl Snapshot {
+    /// Empty snapshot of a session with a fresh ID
+    pub fn new(session_id: &str, cwd: PathBuf, description: Option<String>) -> Self {
+        Self {
+            id: generate_snapshot_id(),
+            session_id: session_id.to_string(),
+            created_at: SystemTime::now(),
+            cwd,
+            open_files: Vec::new(),
+            current_file: None,
+            cursor: None,
+            description,
+            buffers: Vec::new(),
+            tabs: Vec::new(),
+            current_tab: 0,
+            registers: Vec::new(),
+            marks: Vec::new(),
+            owner: None,
+        }
+    }
 }
 
-/// Generate a unique share token
-fn generate_share_token() -> String {
-    use std::sync::atomic::{AtomicU64, Ordering};
-    static COUNTER: AtomicU64 = AtomicU64::new(0);
+/// SQLite store for share links and snapshots
+///
+/// Location: ~/.config/nvim-web/sharing.db
+pub struct ShareStore {
+    conn: Connection,
+    /// Generator of new link tokens
+    tokens: ShareTokens,
+}
 
-    let count = COUNTER.fetch_add(1, Ordering::SeqCst);
-    let timestamp = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_micros();
-
-    // Create a short, URL-safe token
-    let raw = format!("{timestamp:x}{count:x}");
-    // Take last 12 characters for brevity
-    raw.chars()
-        .rev()
-        .take(12)
-        .collect::<String>()
-        .chars()
-        .rev()
-        .collect()
+impl ShareStore {
+    /// Create or open the default sharing database
+    pub fn new() -> Result<Self> {
+        let config_dir = dirs::config_dir().context("Could not determine config directory")?;
+        Self::open(&config_dir.join("nvim-web").join("sharing.db"))
+    }
+
+    /// Issue tokens from `tokens` rather than of the default length
+    pub fn with_tokens(mut self, tokens: ShareTokens) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    /// Create or open a sharing database at `db_path`
+    pub fn open(db_path: &Path) -> Result<Self> {
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent).context("Failed to create config directory")?;
+        }
+        let conn = Connection::open(db_path).context("Failed to open sharing database")?;
+        Self::with_connection(conn)
+    }
+
+    /// In-memory store (nothing survives the process)
+    pub fn in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS share_links (
+                token_hash TEXT PRIMARY KEY,
+                session_id TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER,
+                max_uses INTEGER,
+                use_count INTEGER NOT NULL DEFAULT 0,
+                read_only INTEGER NOT NULL,
+                label TEXT,
+                owner TEXT
+            );
+            CREATE INDEX IF NOT EXISTS share_links_session ON share_links (session_id);
+            CREATE TABLE IF NOT EXISTS snapshots (
+                id TEXT PRIMARY KEY,
+                session_id TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                data TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS snapshots_session ON snapshots (session_id);",
+        )?;
+        Ok(Self {
+            conn,
+            tokens: ShareTokens::default(),
+        })
+    }
+
+    pub fn insert_link(&self, link: &ShareLink) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO share_links
+                (token_hash, session_id, created_at, expires_at, max_uses, use_count, read_only,
+                 label, owner)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                link.token_hash,
+                link.session_id,
+                to_millis(link.created_at),
+                link.expires_at.map(to_millis),
+                link.max_uses,
+                link.use_count,
+                link.read_only,
+                link.label,
+                link.owner,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Count a use of a valid link, returning (session id, read-only)
+    pub fn use_link(&self, token: &str) -> Result<Option<(String, bool)>> {
+        self.conn
+            .query_row(
+                "UPDATE share_links SET use_count = use_count + 1
+                 WHERE token_hash = ?1
+                   AND (expires_at IS NULL OR expires_at > ?2)
+                   AND (max_uses IS NULL OR use_count < max_uses)
+                 RETURNING session_id, read_only",
+                params![auth::hash_token(token), to_millis(SystemTime::now())],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(Into::into)
+    }
+
+    pub fn get_link(&self, token: &str) -> Result<Option<ShareLink>> {
+        self.conn
+            .query_row(
+                &format!("SELECT {LINK_COLUMNS} FROM share_links WHERE token_hash = ?"),
+                [auth::hash_token(token)],
+                link_from_row,
+            )
+            .optional()
+            .map_err(Into::into)
+    }
+
+    /// All links of a session, including expired ones
+    pub fn list_links(&self, session_id: &str) -> Result<Vec<ShareLink>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {LINK_COLUMNS} FROM share_links WHERE session_id = ? ORDER BY created_at"
+        ))?;
+        let links = stmt
+            .query_map([session_id], link_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(links)
+    }
+
+    /// Owners recorded on the links of a session
+    pub fn link_owners(&self, session_id: &str) -> Result<Vec<Option<String>>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT owner FROM share_links WHERE session_id = ?")?;
+        let owners = stmt
+            .query_map([session_id], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(owners)
+    }
+
+    pub fn revoke_link(&self, token: &str) -> Result<bool> {
+        let deleted = self.conn.execute(
+            "DELETE FROM share_links WHERE token_hash = ?",
+            [auth::hash_token(token)],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    pub fn insert_snapshot(&self, snapshot: &Snapshot) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO snapshots (id, session_id, created_at, data)
+             VALUES (?, ?, ?, ?)",
+            params![
+                snapshot.id,
+                snapshot.session_id,
+                to_millis(snapshot.created_at),
+                serde_json::to_string(snapshot)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_snapshot(&self, id: &str) -> Result<Option<Snapshot>> {
+        let data: Option<String> = self
+            .conn
+            .query_row("SELECT data FROM snapshots WHERE id = ?", [id], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        data.map(|d| serde_json::from_str(&d).map_err(Into::into))
+            .transpose()
+    }
+
+    pub fn list_snapshots(&self, session_id: &str) -> Result<Vec<Snapshot>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT data FROM snapshots WHERE session_id = ? ORDER BY created_at")?;
+        let rows = stmt.query_map([session_id], |row| row.get::<_, String>(0))?;
+        let mut snapshots = Vec::new();
+        for data in rows {
+            snapshots.push(serde_json::from_str(&data?)?);
+        }
+        Ok(snapshots)
+    }
+
+    pub fn delete_snapshot(&self, id: &str) -> Result<bool> {
+        let deleted = self
+            .conn
+            .execute("DELETE FROM snapshots WHERE id = ?", [id])?;
+        Ok(deleted > 0)
+    }
+
+    /// Delete expired and used-up links, returning how many were removed
+    pub fn cleanup_expired(&self) -> Result<usize> {
+        let removed = self.conn.execute(
+            "DELETE FROM share_links
+             WHERE (expires_at IS NOT NULL AND expires_at <= ?)
+                OR (max_uses IS NOT NULL AND use_count >= max_uses)",
+            [to_millis(SystemTime::now())],
+        )?;
+        Ok(removed)
+    }
 }
 
-/// Generate a snapshot ID
-fn generate_snapshot_id() -> String {
-    use std::sync::atomic::{AtomicU64, Ordering};
-    static COUNTER: AtomicU64 = AtomicU64::new(0);
+const LINK_COLUMNS: &str =
+    "token_hash, session_id, created_at, expires_at, max_uses, use_count, read_only, label, owner";
+
+fn link_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<ShareLink> {
+    Ok(ShareLink {
+        token: String::new(),
+        token_hash: row.get(0)?,
+        session_id: row.get(1)?,
+        created_at: from_millis(row.get(2)?),
+        expires_at: row.get::<_, Option<i64>>(3)?.map(from_millis),
+        max_uses: row.get(4)?,
+        use_count: row.get(5)?,
+        read_only: row.get(6)?,
+        label: row.get(7)?,
+        owner: row.get(8)?,
+    })
+}
 
-    let count = COUNTER.fetch_add(1, Ordering::SeqCst);
-    let timestamp = SystemTime::now()
+fn to_millis(time: SystemTime) -> i64 {
+    let millis = time
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap_or_default()
-        .as_secs();
+        .as_millis();
+    i64::try_from(millis).unwrap_or(i64::MAX)
+}
 
-    format!("snap_{timestamp:x}_{count}")
+fn from_millis(millis: i64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_millis(u64::try_from(millis).unwrap_or(0))
+}
+
+// Process-wide store used by the API handlers
+static STORE: OnceLock<Mutex<ShareStore>> = OnceLock::new();
+
+fn open_default_store(tokens: ShareTokens) -> ShareStore {
+    let store = if cfg!(test) {
+        ShareStore::in_memory()
+    } else {
+        ShareStore::new().or_else(|e| {
+            tracing::warn!(error = %e, "Share links will not persist across restarts");
+            ShareStore::in_memory()
+        })
+    };
+    store
+        .expect("Failed to initialize share store")
+        .with_tokens(tokens)
 }
 
-/// Create a share link for a session
-pub fn create_share_link(session_id: &str, options: ShareOptions) -> ShareLink {
-    let token = generate_share_token();
+/// Open the process-wide store, issuing tokens from `tokens`
+///
+/// Does nothing if the store is already open; until then it opens on first
+/// use with the default token length.
+pub fn init(tokens: ShareTokens) {
+    STORE.get_or_init(|| Mutex::new(open_default_store(tokens)));
+}
+
+/// Generator of share link and project open tokens
+pub fn share_tokens() -> ShareTokens {
+    STORE.get().map_or_else(ShareTokens::default, |store| {
+        store.lock().unwrap_or_else(PoisonError::into_inner).tokens
+    })
+}
+
+/// Run `f` against the shared store, logging failures
+fn with_store<T>(f: impl FnOnce(&ShareStore) -> Result<T>) -> Option<T> {
+    let store = STORE
+        .get_or_init(|| Mutex::new(open_default_store(ShareTokens::default())))
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    f(&store)
+        .map_err(|e| tracing::warn!(error = %e, "Share store error"))
+        .ok()
+}
+
+/// Generate a snapshot ID
+///
+/// IDs are random so that they cannot be guessed from one another.
+pub(crate) fn generate_snapshot_id() -> String {
+    format!("snap_{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Name of a link in the audit log, never enough to use it
+fn audit_id(token_hash: &str) -> &str {
+    &token_hash[..token_hash.len().min(12)]
+}
+
+/// Create a share link for a session on behalf of `actor`
+///
+/// Only a session's owner creates links, so `actor` is recorded as the
+/// link's owner. The returned link carries the plaintext token; it cannot
+/// be recovered later.
+pub fn create_share_link(
+    session_id: &str,
+    options: ShareOptions,
+    actor: Option<&str>,
+) -> ShareLink {
+    let token = share_tokens().generate();
     let now = SystemTime::now();
 
     let link = ShareLink {
-        token: token.clone(),
+        token_hash: auth::hash_token(&token),
+        token,
         session_id: session_id.to_string(),
         created_at: now,
         expires_at: options.ttl_secs.map(|secs| now + Duration::from_secs(secs)),
@@ -160,54 +465,68 @@ pub fn create_share_link(session_id: &str, options: ShareOptions) -> ShareLink {
         use_count: 0,
         read_only: options.read_only,
         label: options.label,
+        owner: actor.map(ToString::to_string),
     };
 
-    if let Ok(mut links) = SHARE_LINKS.write() {
-        links.insert(token.clone(), link.clone());
-    }
-
+    let stored = with_store(|store| store.insert_link(&link));
+    AuditEvent::new(AuditAction::ShareLinkCreated)
+        .user(actor)
+        .session(session_id)
+        .success(stored.is_some())
+        .detail("link", audit_id(&link.token_hash))
+        .detail("read_only", link.read_only)
+        .detail("max_uses", link.max_uses)
+        .detail("ttl_secs", options.ttl_secs)
+        .record();
     link
 }
 
-/// Validate and use a share link
-pub fn use_share_link(token: &str) -> Option<(String, bool)> {
-    let mut links = SHARE_LINKS.write().ok()?;
-    let link = links.get_mut(token)?;
-
-    if !link.is_valid() {
-        return None;
+/// Validate and use a share link on behalf of `actor`
+pub fn use_share_link(token: &str, actor: Option<&str>) -> Option<(String, bool)> {
+    let claimed = with_store(|store| store.use_link(token)).flatten();
+    let mut event = AuditEvent::new(AuditAction::ShareLinkClaimed)
+        .user(actor)
+        .success(claimed.is_some())
+        .detail("link", audit_id(&auth::hash_token(token)));
+    if let Some((session_id, read_only)) = &claimed {
+        event = event.session(session_id).detail("read_only", *read_only);
     }
-
-    link.use_count += 1;
-    Some((link.session_id.clone(), link.read_only))
+    event.record();
+    claimed
 }
 
 /// Get share link info without using it
 pub fn get_share_link(token: &str) -> Option<ShareLink> {
-    let links = SHARE_LINKS.read().ok()?;
-    links.get(token).cloned()
+    with_store(|store| store.get_link(token)).flatten()
 }
 
 /// List all share links for a session
 pub fn list_share_links(session_id: &str) -> Vec<ShareLink> {
-    let links = SHARE_LINKS.read().ok();
-    links
-        .map(|l| {
-            l.values()
-                .filter(|link| link.session_id == session_id && link.is_valid())
-                .cloned()
-                .collect()
-        })
+    with_store(|store| store.list_links(session_id))
         .unwrap_or_default()
+        .into_iter()
+        .filter(ShareLink::is_valid)
+        .collect()
 }
 
-/// Revoke a share link
-pub fn revoke_share_link(token: &str) -> bool {
-    if let Ok(mut links) = SHARE_LINKS.write() {
-        links.remove(token).is_some()
-    } else {
-        false
-    }
+/// Owners recorded on the share links of a session, `None` if the store failed
+pub fn link_owners(session_id: &str) -> Option<Vec<Option<String>>> {
+    with_store(|store| store.link_owners(session_id))
+}
+
+/// Revoke a share link on behalf of `actor`, who must own it
+pub fn revoke_share_link(token: &str, actor: Option<&str>) -> bool {
+    let revoked = with_store(|store| match store.get_link(token)? {
+        Some(link) if owns(link.owner.as_deref(), actor) => store.revoke_link(token),
+        _ => Ok(false),
+    })
+    .unwrap_or(false);
+    AuditEvent::new(AuditAction::ShareLinkRevoked)
+        .user(actor)
+        .success(revoked)
+        .detail("link", audit_id(&auth::hash_token(token)))
+        .record();
+    revoked
 }
 
 /// Create a workspace snapshot
@@ -219,59 +538,65 @@ pub fn create_snapshot(
     cursor: Option<(String, u32, u32)>,
     description: Option<String>,
 ) -> Snapshot {
-    let id = generate_snapshot_id();
-
     let snapshot = Snapshot {
-        id: id.clone(),
-        session_id: session_id.to_string(),
-        created_at: SystemTime::now(),
-        cwd,
         open_files,
         current_file,
         cursor,
-        description,
+        ..Snapshot::new(session_id, cwd, description)
     };
 
-    if let Ok(mut snapshots) = SNAPSHOTS.write() {
-        snapshots.insert(id, snapshot.clone());
-    }
-
+    save_snapshot(&snapshot);
     snapshot
 }
 
+/// Whether `user` may manage what `owner` stored
+///
+/// Without login (`user` is `None`) everything is open, as sessions are.
+/// With login, records must name the user; those stored before owners were
+/// recorded belong to no one.
+pub fn owns(owner: Option<&str>, user: Option<&str>) -> bool {
+    user.is_none() || owner == user
+}
+
+/// Store a snapshot, replacing any with the same ID
+pub fn save_snapshot(snapshot: &Snapshot) -> bool {
+    with_store(|store| store.insert_snapshot(snapshot)).is_some()
+}
+
 /// Get a snapshot by ID
 pub fn get_snapshot(id: &str) -> Option<Snapshot> {
-    let snapshots = SNAPSHOTS.read().ok()?;
-    snapshots.get(id).cloned()
+    with_store(|store| store.get_snapshot(id)).flatten()
 }
 
 /// List snapshots for a session
 pub fn list_snapshots(session_id: &str) -> Vec<Snapshot> {
-    let snapshots = SNAPSHOTS.read().ok();
-    snapshots
-        .map(|s| {
-            s.values()
-                .filter(|snap| snap.session_id == session_id)
-                .cloned()
-                .collect()
-        })
-        .unwrap_or_default()
+    with_store(|store| store.list_snapshots(session_id)).unwrap_or_default()
 }
 
 /// Delete a snapshot
 pub fn delete_snapshot(id: &str) -> bool {
-    if let Ok(mut snapshots) = SNAPSHOTS.write() {
-        snapshots.remove(id).is_some()
-    } else {
-        false
-    }
+    with_store(|store| store.delete_snapshot(id)).unwrap_or(false)
 }
 
-/// Clean up expired share links
-pub fn cleanup_expired() {
-    if let Ok(mut links) = SHARE_LINKS.write() {
-        links.retain(|_, link| link.is_valid());
-    }
+/// Clean up expired and used-up share links, returning how many were removed
+pub fn cleanup_expired() -> usize {
+    with_store(ShareStore::cleanup_expired).unwrap_or(0)
+}
+
+/// Clean up expired links now and then every `period`
+pub fn spawn_cleanup(period: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let removed = tokio::task::spawn_blocking(cleanup_expired)
+                .await
+                .unwrap_or(0);
+            if removed > 0 {
+                tracing::info!(count = removed, "Removed expired share links");
+            }
+        }
+    })
 }
 
 #[cfg(test)]
@@ -288,6 +613,7 @@ mod tests {
                 read_only: true,
                 label: Some("Team share".to_string()),
             },
+            None,
         );
 
         assert_eq!(link.session_id, "test-session");
@@ -304,21 +630,67 @@ mod tests {
                 max_uses: Some(2),
                 ..Default::default()
             },
+            None,
         );
 
         // First use
-        let result = use_share_link(&link.token);
+        let result = use_share_link(&link.token, None);
         assert!(result.is_some());
 
         // Second use
-        let result = use_share_link(&link.token);
+        let result = use_share_link(&link.token, None);
         assert!(result.is_some());
 
         // Third use should fail (max_uses = 2)
-        let result = use_share_link(&link.token);
+        let result = use_share_link(&link.token, None);
         assert!(result.is_none());
     }
 
+    #[test]
+    fn test_tokens_hashed_at_rest() {
+        let store = ShareStore::in_memory().unwrap();
+        let token = ShareTokens::default().generate();
+        let now = SystemTime::now();
+        store
+            .insert_link(&ShareLink {
+                token: token.clone(),
+                token_hash: auth::hash_token(&token),
+                session_id: "s1".to_string(),
+                created_at: now,
+                expires_at: None,
+                max_uses: None,
+                use_count: 0,
+                read_only: true,
+                label: None,
+                owner: None,
+            })
+            .unwrap();
+
+        let stored: String = store
+            .conn
+            .query_row("SELECT token_hash FROM share_links", [], |row| row.get(0))
+            .unwrap();
+        assert_ne!(stored, token);
+        assert!(auth::token_hashes_match(&stored, &auth::hash_token(&token)));
+
+        let listed = store.list_links("s1").unwrap();
+        assert!(listed[0].token.is_empty());
+        assert!(!serde_json::to_string(&listed[0]).unwrap().contains(&stored));
+        assert!(store.use_link(&token).unwrap().is_some());
+        assert!(store.use_link(&token[1..]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_only_owner_revokes() {
+        let link = create_share_link("session-2", ShareOptions::default(), Some("alice"));
+        assert!(!revoke_share_link(&link.token, Some("mallory")));
+        assert!(revoke_share_link(&link.token, Some("alice")));
+
+        assert!(owns(Some("alice"), None));
+        assert!(owns(Some("alice"), Some("alice")));
+        assert!(!owns(None, Some("alice")));
+    }
+
     #[test]
     fn test_snapshot_creation() {
         let snap = create_snapshot(
@@ -330,7 +702,9 @@ mod tests {
             Some("Before refactor".to_string()),
         );
 
-        assert!(!snap.id.is_empty());
+        assert!(snap.id.starts_with("snap_"));
+        assert_eq!(snap.id.len(), "snap_".len() + 32);
+        assert_ne!(snap.id, generate_snapshot_id());
         assert_eq!(snap.session_id, "session-1");
         assert_eq!(snap.open_files.len(), 2);
 
@@ -338,4 +712,75 @@ mod tests {
         let retrieved = get_snapshot(&snap.id);
         assert!(retrieved.is_some());
     }
+
+    #[test]
+    fn test_store_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("sharing.db");
+        let now = SystemTime::now();
+        let link = |token: &str, expires_at, max_uses| ShareLink {
+            token: token.to_string(),
+            token_hash: auth::hash_token(token),
+            session_id: "s1".to_string(),
+            created_at: now,
+            expires_at,
+            max_uses,
+            use_count: 0,
+            read_only: false,
+            label: Some("pairing".to_string()),
+            owner: Some("alice".to_string()),
+        };
+
+        {
+            let store = ShareStore::open(&db_path).unwrap();
+            store
+                .insert_link(&link(
+                    "live",
+                    Some(now + Duration::from_secs(3600)),
+                    Some(2),
+                ))
+                .unwrap();
+            store
+                .insert_link(&link("stale", Some(now - Duration::from_secs(1)), None))
+                .unwrap();
+            assert_eq!(
+                store.use_link("live").unwrap(),
+                Some(("s1".to_string(), false))
+            );
+            assert_eq!(store.use_link("stale").unwrap(), None);
+
+            let snap = Snapshot {
+                id: "snap_1".to_string(),
+                open_files: vec!["main.rs".to_string()],
+                current_file: Some("main.rs".to_string()),
+                cursor: Some(("main.rs".to_string(), 3, 1)),
+                ..Snapshot::new("s1", PathBuf::from("/project"), None)
+            };
+            store.insert_snapshot(&snap).unwrap();
+        }
+
+        let store = ShareStore::open(&db_path).unwrap();
+        let live = store.get_link("live").unwrap().unwrap();
+        assert_eq!(live.use_count, 1);
+        assert_eq!(live.label.as_deref(), Some("pairing"));
+        assert_eq!(live.owner.as_deref(), Some("alice"));
+        assert_eq!(
+            store.link_owners("s1").unwrap(),
+            [Some("alice".to_string())]
+        );
+        assert!(live.is_valid());
+        assert_eq!(store.list_links("s1").unwrap().len(), 2);
+
+        // Second claim uses up the link; cleanup drops it and the expired one
+        assert!(store.use_link("live").unwrap().is_some());
+        assert_eq!(store.use_link("live").unwrap(), None);
+        assert_eq!(store.cleanup_expired().unwrap(), 2);
+        assert!(store.list_links("s1").unwrap().is_empty());
+
+        let snaps = store.list_snapshots("s1").unwrap();
+        assert_eq!(snaps.len(), 1);
+        assert_eq!(snaps[0].cursor, Some(("main.rs".to_string(), 3, 1)));
+        assert!(store.delete_snapshot("snap_1").unwrap());
+        assert!(store.get_snapshot("snap_1").unwrap().is_none());
+    }
 }
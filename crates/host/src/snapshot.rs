@@ -0,0 +1,522 @@
+//! Full-fidelity workspace snapshots
+//!
+//! Captures what `sharing::Snapshot` needs to recreate a session beyond its
+//! file list: unsaved buffer text (as CRDT state), tab and window layout,
+//! registers and marks. Snapshots can be exported to and imported from a
+//! single JSON archive file to move them between hosts.
+//!
+//! File names are stored relative to the snapshot's cwd when they lie
+//! inside it, so archives can be restored on a machine with a different
+//! checkout location.
+
+use anyhow::{bail, Context, Result};
+use rmpv::Value;
+use serde::{Deserialize, Serialize};
+
+use crate::crdt::BufferCrdt;
+use crate::session::AsyncSession;
+use crate::sharing::Snapshot;
+
+/// Identifies snapshot archives
+pub const ARCHIVE_FORMAT: &str = "nvim-web-snapshot";
+
+/// Archive version written by this host
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// Unsaved contents of a buffer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BufferSnapshot {
+    pub file: String,
+    /// `BufferCrdt` state holding the buffer text
+    #[serde(with = "base64_bytes")]
+    pub crdt_state: Vec<u8>,
+}
+
+impl BufferSnapshot {
+    pub fn from_lines(file: String, lines: &[String]) -> Self {
+        let mut crdt = BufferCrdt::new(0);
+        crdt.set_content(&lines.join("\n"));
+        Self {
+            file,
+            crdt_state: crdt.encode_state(),
+        }
+    }
+
+    /// Buffer lines stored in the CRDT state
+    pub fn lines(&self) -> Result<Vec<String>> {
+        let crdt = BufferCrdt::from_state(0, &self.crdt_state)
+            .with_context(|| format!("Invalid buffer state for {}", self.file))?;
+        Ok(crdt.get_content().split('\n').map(String::from).collect())
+    }
+}
+
+/// Tab page with its window layout
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TabSnapshot {
+    pub layout: WindowLayout,
+    /// 1-based index of the focused window, in depth-first order
+    pub current_window: usize,
+}
+
+/// Window split tree, as returned by `winlayout()`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum WindowLayout {
+    Leaf {
+        file: String,
+        /// 1-based line
+        line: u32,
+        /// 0-based byte column
+        col: u32,
+        width: u32,
+        height: u32,
+    },
+    /// Windows side by side
+    Row { children: Vec<WindowLayout> },
+    /// Windows stacked vertically
+    Col { children: Vec<WindowLayout> },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegisterSnapshot {
+    pub name: String,
+    pub lines: Vec<String>,
+    /// Register type as returned by `getregtype()`
+    pub kind: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarkSnapshot {
+    /// Mark name without the leading quote (`a`-`z` or `A`-`Z`)
+    pub name: String,
+    pub file: String,
+    /// 1-based line
+    pub line: u32,
+    /// 0-based byte column
+    pub col: u32,
+}
+
+/// Single-file form of a snapshot
+#[derive(Debug, Serialize, Deserialize)]
+struct Archive {
+    format: String,
+    version: u32,
+    snapshot: Snapshot,
+}
+
+/// Serialize a snapshot as an archive file
+pub fn export_archive(snapshot: &Snapshot) -> Result<Vec<u8>> {
+    let archive = Archive {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        snapshot: snapshot.clone(),
+    };
+    Ok(serde_json::to_vec_pretty(&archive)?)
+}
+
+/// Parse an archive file, checking its format and version
+///
+/// The snapshot gets a fresh ID so it never replaces a local one, and no
+/// owner; it belongs to whoever imports it.
+pub fn import_archive(data: &[u8]) -> Result<Snapshot> {
+    let archive: Archive = serde_json::from_slice(data).context("Invalid snapshot archive")?;
+    if archive.format != ARCHIVE_FORMAT {
+        bail!("Not a snapshot archive: format '{}'", archive.format);
+    }
+    if archive.version > ARCHIVE_VERSION {
+        bail!(
+            "Snapshot archive version {} is newer than supported version {ARCHIVE_VERSION}",
+            archive.version
+        );
+    }
+    // Validate buffer states up front rather than failing halfway through a restore
+    for buffer in &archive.snapshot.buffers {
+        buffer.lines()?;
+    }
+    Ok(Snapshot {
+        id: crate::sharing::generate_snapshot_id(),
+        owner: None,
+        ..archive.snapshot
+    })
+}
+
+/// State read from Neovim by `CAPTURE_LUA`
+#[derive(Deserialize)]
+struct Captured {
+    cwd: String,
+    open_files: Vec<String>,
+    current_file: String,
+    cursor: (u32, u32),
+    buffers: Vec<CapturedBuffer>,
+    tabs: Vec<TabSnapshot>,
+    current_tab: usize,
+    registers: Vec<RegisterSnapshot>,
+    marks: Vec<MarkSnapshot>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CapturedBuffer {
+    file: String,
+    lines: Vec<String>,
+}
+
+/// State handed to `RESTORE_LUA`
+#[derive(Serialize)]
+struct Restore<'a> {
+    buffers: Vec<CapturedBuffer>,
+    tabs: &'a [TabSnapshot],
+    current_tab: usize,
+    registers: &'a [RegisterSnapshot],
+    marks: &'a [MarkSnapshot],
+}
+
+/// Read the full state of a running session into a new snapshot
+pub async fn capture(session: &AsyncSession, description: Option<String>) -> Result<Snapshot> {
+    let value = session
+        .rpc_call(
+            "nvim_exec_lua",
+            vec![Value::from(CAPTURE_LUA), Value::Array(vec![])],
+        )
+        .await?;
+    let mut bytes = Vec::new();
+    rmpv::encode::write_value(&mut bytes, &value)?;
+    let captured: Captured =
+        rmp_serde::from_slice(&bytes).context("Unexpected session state from Neovim")?;
+
+    let current_file = (!captured.current_file.is_empty()).then_some(captured.current_file);
+    let cursor = current_file
+        .clone()
+        .map(|file| (file, captured.cursor.0, captured.cursor.1));
+    let buffers = captured
+        .buffers
+        .into_iter()
+        .map(|buffer| BufferSnapshot::from_lines(buffer.file, &buffer.lines))
+        .collect();
+
+    Ok(Snapshot {
+        open_files: captured.open_files,
+        current_file,
+        cursor,
+        buffers,
+        tabs: captured.tabs,
+        current_tab: captured.current_tab,
+        registers: captured.registers,
+        marks: captured.marks,
+        ..Snapshot::new(&session.id, captured.cwd.into(), description)
+    })
+}
+
+/// Restore unsaved buffers, layout, registers and marks of a snapshot
+///
+/// Expects the cwd to be restored already. Returns false if the snapshot
+/// has no layout, in which case the caller focuses the current file itself.
+pub async fn restore(session: &AsyncSession, snapshot: &Snapshot) -> Result<bool> {
+    let buffers = snapshot
+        .buffers
+        .iter()
+        .map(|buffer| {
+            Ok(CapturedBuffer {
+                file: buffer.file.clone(),
+                lines: buffer.lines()?,
+            })
+        })
+        .collect::<Result<_>>()?;
+    let state = Restore {
+        buffers,
+        tabs: &snapshot.tabs,
+        current_tab: snapshot.current_tab,
+        registers: &snapshot.registers,
+        marks: &snapshot.marks,
+    };
+    let bytes = rmp_serde::to_vec_named(&state)?;
+    let state = rmpv::decode::read_value(&mut bytes.as_slice())?;
+
+    session
+        .rpc_call(
+            "nvim_exec_lua",
+            vec![Value::from(RESTORE_LUA), Value::Array(vec![state])],
+        )
+        .await?;
+    Ok(!snapshot.tabs.is_empty())
+}
+
+const CAPTURE_LUA: &str = r#"
+local api, fn = vim.api, vim.fn
+local cwd = fn.getcwd(-1, -1)
+
+local function rel(name)
+  if name:sub(1, #cwd + 1) == cwd .. '/' then
+    return name:sub(#cwd + 2)
+  end
+  return name
+end
+
+local function file(buf)
+  local name = api.nvim_buf_get_name(buf)
+  if name == '' or vim.bo[buf].buftype ~= '' then
+    return ''
+  end
+  return rel(name)
+end
+
+local state = {
+  cwd = cwd,
+  open_files = {},
+  current_file = file(0),
+  cursor = api.nvim_win_get_cursor(0),
+  buffers = {},
+  tabs = {},
+  current_tab = fn.tabpagenr(),
+  registers = {},
+  marks = {},
+}
+
+for _, buf in ipairs(api.nvim_list_bufs()) do
+  local name = file(buf)
+  if name ~= '' and vim.bo[buf].buflisted then
+    table.insert(state.open_files, name)
+    if api.nvim_buf_is_loaded(buf) and vim.bo[buf].modified then
+      table.insert(state.buffers, { file = name, lines = api.nvim_buf_get_lines(buf, 0, -1, false) })
+    end
+    for _, m in ipairs(fn.getmarklist(buf)) do
+      if m.mark:match("^'[a-z]$") then
+        table.insert(state.marks, { name = m.mark:sub(2), file = name, line = m.pos[2], col = math.max(m.pos[3] - 1, 0) })
+      end
+    end
+  end
+end
+
+for _, m in ipairs(fn.getmarklist()) do
+  if m.mark:match("^'[A-Z]$") and m.file then
+    local name = rel(fn.fnamemodify(m.file, ':p'))
+    table.insert(state.marks, { name = m.mark:sub(2), file = name, line = m.pos[2], col = math.max(m.pos[3] - 1, 0) })
+  end
+end
+
+-- Unnamed register last: setting it on restore also points it at its source
+for _, r in ipairs(vim.split('0123456789abcdefghijklmnopqrstuvwxyz-/"', '')) do
+  local lines = fn.getreg(r, 1, 1)
+  if #lines > 0 then
+    table.insert(state.registers, { name = r, lines = lines, kind = fn.getregtype(r) })
+  end
+end
+
+for tabnr, tab in ipairs(api.nvim_list_tabpages()) do
+  local focused = api.nvim_tabpage_get_win(tab)
+  local index, current = 0, 1
+  local function walk(node)
+    if node[1] == 'leaf' then
+      local win = node[2]
+      index = index + 1
+      if win == focused then
+        current = index
+      end
+      local pos = api.nvim_win_get_cursor(win)
+      return {
+        kind = 'leaf',
+        file = file(api.nvim_win_get_buf(win)),
+        line = pos[1],
+        col = pos[2],
+        width = api.nvim_win_get_width(win),
+        height = api.nvim_win_get_height(win),
+      }
+    end
+    local children = {}
+    for _, child in ipairs(node[2]) do
+      table.insert(children, walk(child))
+    end
+    return { kind = node[1], children = children }
+  end
+  local layout = walk(fn.winlayout(tabnr))
+  table.insert(state.tabs, { layout = layout, current_window = current })
+end
+
+return state
+"#;
+
+const RESTORE_LUA: &str = r#"
+local api, fn = vim.api, vim.fn
+local state = ...
+
+local function load(file)
+  local buf = fn.bufadd(file)
+  fn.bufload(buf)
+  vim.bo[buf].buflisted = true
+  return buf
+end
+
+for _, b in ipairs(state.buffers) do
+  api.nvim_buf_set_lines(load(b.file), 0, -1, false, b.lines)
+end
+
+local leaves = {}
+local function build(node)
+  if node.kind == 'leaf' then
+    local win = api.nvim_get_current_win()
+    if node.file ~= '' then
+      api.nvim_win_set_buf(win, load(node.file))
+    end
+    pcall(api.nvim_win_set_cursor, win, { node.line, node.col })
+    table.insert(leaves, { win = win, width = node.width, height = node.height })
+    return { win }
+  end
+  local split = node.kind == 'row' and 'belowright vsplit' or 'belowright split'
+  local wins = { api.nvim_get_current_win() }
+  for _ = 2, #node.children do
+    vim.cmd(split)
+    table.insert(wins, api.nvim_get_current_win())
+  end
+  local all = {}
+  for i, child in ipairs(node.children) do
+    api.nvim_set_current_win(wins[i])
+    vim.list_extend(all, build(child))
+  end
+  return all
+end
+
+local focus = {}
+for i, tab in ipairs(state.tabs) do
+  if i > 1 then
+    vim.cmd('tabnew')
+  end
+  leaves = {}
+  local wins = build(tab.layout)
+  -- Sizes only settle once every split of the tab exists
+  for _, leaf in ipairs(leaves) do
+    pcall(api.nvim_win_set_width, leaf.win, leaf.width)
+    pcall(api.nvim_win_set_height, leaf.win, leaf.height)
+  end
+  focus[i] = wins[tab.current_window] or wins[1]
+end
+if #state.tabs > 0 then
+  local tab = math.min(math.max(state.current_tab, 1), #state.tabs)
+  api.nvim_set_current_win(focus[tab])
+end
+
+for _, r in ipairs(state.registers) do
+  fn.setreg(r.name, r.lines, r.kind)
+end
+
+for _, m in ipairs(state.marks) do
+  pcall(api.nvim_buf_set_mark, load(m.file), m.name, m.line, m.col, {})
+end
+"#;
+
+/// Serde adapter storing bytes as base64 text
+mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        let leaf = |file: &str, line| WindowLayout::Leaf {
+            file: file.to_string(),
+            line,
+            col: 0,
+            width: 40,
+            height: 20,
+        };
+        Snapshot {
+            open_files: vec!["main.rs".to_string(), "lib.rs".to_string()],
+            current_file: Some("main.rs".to_string()),
+            cursor: Some(("main.rs".to_string(), 2, 4)),
+            buffers: vec![BufferSnapshot::from_lines(
+                "main.rs".to_string(),
+                &["fn main() {".to_string(), String::new(), "}".to_string()],
+            )],
+            tabs: vec![TabSnapshot {
+                layout: WindowLayout::Row {
+                    children: vec![leaf("main.rs", 2), leaf("lib.rs", 1)],
+                },
+                current_window: 1,
+            }],
+            current_tab: 1,
+            registers: vec![RegisterSnapshot {
+                name: "a".to_string(),
+                lines: vec!["yanked".to_string()],
+                kind: "v".to_string(),
+            }],
+            marks: vec![MarkSnapshot {
+                name: "A".to_string(),
+                file: "lib.rs".to_string(),
+                line: 1,
+                col: 3,
+            }],
+            ..Snapshot::new("s1", PathBuf::from("/project"), None)
+        }
+    }
+
+    #[test]
+    fn test_buffer_lines_roundtrip() {
+        let lines = vec![
+            "a".to_string(),
+            String::new(),
+            "ü".to_string(),
+            String::new(),
+        ];
+        let buffer = BufferSnapshot::from_lines("x".to_string(), &lines);
+        assert_eq!(buffer.lines().unwrap(), lines);
+    }
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let original = snapshot();
+        let data = export_archive(&original).unwrap();
+        let imported = import_archive(&data).unwrap();
+
+        assert_ne!(imported.id, original.id);
+
+        assert_eq!(imported.open_files, original.open_files);
+        assert_eq!(imported.buffers, original.buffers);
+        assert_eq!(imported.tabs, original.tabs);
+        assert_eq!(imported.registers, original.registers);
+        assert_eq!(imported.marks, original.marks);
+        assert_eq!(
+            imported.buffers[0].lines().unwrap(),
+            vec!["fn main() {", "", "}"]
+        );
+    }
+
+    #[test]
+    fn test_import_rejects_foreign_files() {
+        assert!(import_archive(b"{}").is_err());
+
+        let mut archive: serde_json::Value =
+            serde_json::from_slice(&export_archive(&snapshot()).unwrap()).unwrap();
+        archive["version"] = (ARCHIVE_VERSION + 1).into();
+        let err = import_archive(&serde_json::to_vec(&archive).unwrap()).unwrap_err();
+        assert!(err.to_string().contains("newer"));
+
+        archive["version"] = ARCHIVE_VERSION.into();
+        archive["snapshot"]["buffers"][0]["crdt_state"] = "////".into();
+        assert!(import_archive(&serde_json::to_vec(&archive).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_snapshots_without_full_state_still_load() {
+        let json = r#"{
+            "id": "snap_old", "session_id": "s1",
+            "created_at": {"secs_since_epoch": 1, "nanos_since_epoch": 0},
+            "cwd": "/project", "open_files": ["a.rs"], "current_file": null,
+            "cursor": null, "description": null
+        }"#;
+        let snapshot: Snapshot = serde_json::from_str(json).unwrap();
+        assert!(snapshot.buffers.is_empty());
+        assert!(snapshot.tabs.is_empty());
+    }
+}
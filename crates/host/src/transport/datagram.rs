@@ -0,0 +1,516 @@
+//! Datagram handling shared by the WebSocket and WebTransport servers
+//!
+//! Every browser connection joins its session's `SessionViewers` as a peer.
+//! Cursor datagrams update that peer's position and are relayed to the
+//! other peers; heartbeats are echoed back so clients can measure RTT.
+//! Input datagrams only reach Neovim from the session's driver.
+//!
+//! Everything else the session's participants do (joining, roles, control,
+//! chat, comments, WebRTC signaling) is relayed as typed [`HostMessage`]s on the
+//! reliable channel.
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+
+use anyhow::{anyhow, bail, Result};
+use nvim_web_protocol::datagram::{CursorUpdate, Datagram, SeqFilter};
+use nvim_web_protocol::schema::{HostMessage, Participant};
+use tokio::sync::{broadcast, RwLock};
+
+use crate::collaboration::{CollabEvent, Role, SharedCollaborationRegistry, SignalType};
+use crate::comments;
+use crate::session::AsyncSessionManager;
+
+/// Longest chat message accepted, in characters
+const MAX_CHAT_LEN: usize = 4096;
+/// Longest display name accepted, in characters
+const MAX_NAME_LEN: usize = 64;
+/// Largest WebRTC signaling payload relayed, in bytes (SDP with many
+/// candidates stays well below this)
+const MAX_SIGNAL_LEN: usize = 64 * 1024;
+
+/// Something to send to a peer's browser
+#[derive(Debug, PartialEq)]
+pub(crate) enum Outgoing {
+    /// Cursor update, droppable
+    Datagram(Datagram),
+    /// Participant change, chat or signal; must arrive
+    Message(HostMessage),
+}
+
+/// Collaboration peer attached to one browser connection
+pub(crate) struct Peer {
+    pub id: String,
+    /// Logged-in user of the connection, for the audit log
+    pub user: Option<String>,
+    /// Client address of the connection, for the audit log
+    pub ip: Option<IpAddr>,
+    session_id: String,
+    registry: SharedCollaborationRegistry,
+    /// Drops reordered incoming cursor datagrams
+    cursor_seq: StdMutex<SeqFilter>,
+    /// Sequence for datagrams relayed to this peer
+    out_seq: AtomicU32,
+}
+
+impl Peer {
+    /// Register a new peer for `session_id` and subscribe to its events
+    pub async fn join(
+        manager: &Arc<RwLock<AsyncSessionManager>>,
+        session_id: &str,
+        role: Role,
+    ) -> (Self, broadcast::Receiver<CollabEvent>) {
+        let registry = manager.read().await.collaboration();
+        let id = uuid::Uuid::new_v4().to_string();
+
+        let events = {
+            let mut reg = registry.write().await;
+            let viewers = reg.get_or_create(session_id);
+            let events = viewers.subscribe();
+            viewers.add_viewer(id.clone(), None, role);
+            events
+        };
+
+        let peer = Self {
+            id,
+            user: None,
+            ip: None,
+            session_id: session_id.to_string(),
+            registry,
+            cursor_seq: StdMutex::new(SeqFilter::default()),
+            out_seq: AtomicU32::new(0),
+        };
+        (peer, events)
+    }
+
+    /// Unregister the peer, dropping the session entry once it is empty
+    pub async fn leave(&self) {
+        let mut reg = self.registry.write().await;
+        if let Some(viewers) = reg.get_mut(&self.session_id) {
+            viewers.remove_viewer(&self.id);
+            if viewers.count() == 0 {
+                reg.remove_session(&self.session_id);
+            }
+        }
+    }
+
+    /// Current role, `Viewer` once the session entry is gone
+    pub async fn role(&self) -> Role {
+        let reg = self.registry.read().await;
+        reg.get(&self.session_id)
+            .and_then(|viewers| viewers.role(&self.id))
+            .unwrap_or(Role::Viewer)
+    }
+
+    /// Whether this peer's keystrokes reach Neovim
+    pub async fn can_drive(&self) -> bool {
+        let reg = self.registry.read().await;
+        reg.get(&self.session_id)
+            .is_some_and(|viewers| viewers.can_drive(&self.id))
+    }
+
+    /// Everyone in the session, sent to the browser when it connects
+    pub async fn presence(&self) -> HostMessage {
+        let reg = self.registry.read().await;
+        let viewers = reg.get(&self.session_id);
+        let mut participants: Vec<Participant> = viewers
+            .map(|viewers| viewers.list_viewers())
+            .unwrap_or_default()
+            .iter()
+            .map(Participant::from)
+            .collect();
+        participants.sort_by(|a, b| a.id.cmp(&b.id));
+        HostMessage::Presence {
+            self_id: self.id.clone(),
+            driver: viewers.and_then(|viewers| viewers.driver().map(String::from)),
+            participants,
+        }
+    }
+
+    /// The session's review comments, sent to the browser when it connects
+    pub async fn comments(&self, manager: &Arc<RwLock<AsyncSessionManager>>) -> HostMessage {
+        let mgr = manager.read().await;
+        let comments = match mgr.get_session(&self.session_id) {
+            Some(session) => comments::list(session, &self.registry)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::warn!(session_id = %self.session_id, error = %e, "Failed to list comments");
+                    Vec::new()
+                }),
+            None => Vec::new(),
+        };
+        HostMessage::Comments(comments)
+    }
+
+    /// Change the name other participants see, empty to clear it
+    pub async fn set_name(&self, name: &str) -> Result<()> {
+        let name = name.trim();
+        if name.chars().count() > MAX_NAME_LEN {
+            bail!("Name is longer than {MAX_NAME_LEN} characters");
+        }
+        let mut reg = self.registry.write().await;
+        let viewers = reg
+            .get_mut(&self.session_id)
+            .ok_or_else(|| anyhow!("Session not found"))?;
+        viewers.set_name(&self.id, (!name.is_empty()).then(|| name.to_string()))
+    }
+
+    /// Send a chat message to everyone, or only to `to`
+    pub async fn chat(&self, message: &str, to: Option<&str>) -> Result<()> {
+        if message.is_empty() || message.chars().count() > MAX_CHAT_LEN {
+            bail!("Chat messages must be 1 to {MAX_CHAT_LEN} characters");
+        }
+        let reg = self.registry.read().await;
+        let viewers = reg
+            .get(&self.session_id)
+            .ok_or_else(|| anyhow!("Session not found"))?;
+        if let Some(to) = to {
+            if viewers.viewer(to).is_none() {
+                bail!("Unknown participant: {to}");
+            }
+        }
+        viewers.send_chat(&self.id, to, message.to_string());
+        Ok(())
+    }
+
+    /// Relay a WebRTC signaling payload to another participant
+    ///
+    /// The host only forwards offers, answers and ICE candidates; media and
+    /// data flow directly between the browsers.
+    pub async fn signal(&self, to: &str, kind: &str, payload: String) -> Result<()> {
+        let signal_type: SignalType = kind.parse()?;
+        if payload.len() > MAX_SIGNAL_LEN {
+            bail!("Signal payload is larger than {MAX_SIGNAL_LEN} bytes");
+        }
+        if to == self.id {
+            bail!("Cannot signal yourself");
+        }
+        let reg = self.registry.read().await;
+        let viewers = reg
+            .get(&self.session_id)
+            .ok_or_else(|| anyhow!("Session not found"))?;
+        if viewers.viewer(to).is_none() {
+            bail!("Unknown participant: {to}");
+        }
+        viewers.send_signal(&self.id, to, signal_type, payload);
+        Ok(())
+    }
+
+    /// Record keystrokes this peer sent to Neovim
+    pub async fn record_input(&self, keys: &str) {
+        let mut reg = self.registry.write().await;
+        if let Some(viewers) = reg.get_mut(&self.session_id) {
+            viewers.record_input(&self.id, keys);
+        }
+    }
+
+    /// Handle a datagram received from this peer
+    ///
+    /// Returns a datagram to send back (heartbeat echo). Input is only
+    /// applied while this peer is the driver.
+    pub async fn handle(
+        &self,
+        manager: &Arc<RwLock<AsyncSessionManager>>,
+        datagram: Datagram,
+    ) -> Result<Option<Datagram>> {
+        match datagram {
+            Datagram::Cursor(update) => {
+                let fresh = self
+                    .cursor_seq
+                    .lock()
+                    .is_ok_and(|mut filter| filter.accept(update.seq));
+                if fresh {
+                    let mut reg = self.registry.write().await;
+                    if let Some(viewers) = reg.get_mut(&self.session_id) {
+                        viewers.update_cursor(&self.id, update.position);
+                    }
+                }
+                Ok(None)
+            }
+            Datagram::Input(keys) => {
+                if self.can_drive().await {
+                    let mgr = manager.read().await;
+                    if let Some(session) = mgr.get_session(&self.session_id) {
+                        session.input(&keys).await?;
+                        self.record_input(&keys).await;
+                    }
+                }
+                Ok(None)
+            }
+            heartbeat @ Datagram::Heartbeat { .. } => Ok(Some(heartbeat)),
+        }
+    }
+
+    /// Map a collaboration event to what this peer should see, if anything
+    ///
+    /// Private chat only reaches its sender and recipient, and signals only
+    /// their target.
+    pub fn outgoing(&self, event: &CollabEvent) -> Option<Outgoing> {
+        let message = match event {
+            CollabEvent::CursorMoved {
+                viewer_id,
+                position,
+            } if *viewer_id != self.id => {
+                return Some(Outgoing::Datagram(Datagram::Cursor(CursorUpdate {
+                    seq: self.out_seq.fetch_add(1, Ordering::Relaxed),
+                    position: *position,
+                    peer_id: viewer_id.clone(),
+                })));
+            }
+            CollabEvent::ViewerJoined(info) if info.id != self.id => {
+                HostMessage::PeerInfo(info.into())
+            }
+            CollabEvent::ViewerUpdated(info) => HostMessage::PeerInfo(info.into()),
+            CollabEvent::ViewerLeft(id) => HostMessage::PeerLeft {
+                peer_id: id.clone(),
+            },
+            CollabEvent::RoleChanged { viewer_id, role } => HostMessage::RoleChanged {
+                peer_id: viewer_id.clone(),
+                role: role.to_string(),
+            },
+            CollabEvent::ControlRequested { viewer_id } => HostMessage::ControlRequested {
+                peer_id: viewer_id.clone(),
+            },
+            CollabEvent::ControlChanged { driver } => HostMessage::ControlChanged {
+                driver: driver.clone(),
+            },
+            CollabEvent::ChatMessage {
+                from,
+                to,
+                message,
+                timestamp,
+            } if to.is_none() || *from == self.id || to.as_deref() == Some(&self.id) => {
+                HostMessage::Chat {
+                    from: from.clone(),
+                    message: message.clone(),
+                    timestamp: *timestamp,
+                    to: to.clone(),
+                }
+            }
+            CollabEvent::WebRtcSignal {
+                from,
+                to,
+                signal_type,
+                payload,
+            } if *to == self.id => HostMessage::Signal {
+                from: from.clone(),
+                kind: signal_type.as_str().to_string(),
+                payload: payload.clone(),
+            },
+            CollabEvent::CommentChanged(comment) => HostMessage::Comment(comment.clone()),
+            CollabEvent::CommentDeleted(id) => HostMessage::CommentDeleted { id: id.clone() },
+            _ => return None,
+        };
+        Some(Outgoing::Message(message))
+    }
+
+    /// Wait for the next item to relay to this peer, `None` once closed
+    pub async fn next_outgoing(
+        &self,
+        events: &mut broadcast::Receiver<CollabEvent>,
+    ) -> Option<Outgoing> {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if let Some(outgoing) = self.outgoing(&event) {
+                        return Some(outgoing);
+                    }
+                }
+                // Resend presence so a lagging browser catches up
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    return Some(Outgoing::Message(self.presence().await));
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nvim_web_protocol::datagram::CursorPosition;
+
+    fn manager() -> Arc<RwLock<AsyncSessionManager>> {
+        let vfs = Arc::new(RwLock::new(crate::vfs::VfsManager::new()));
+        Arc::new(RwLock::new(AsyncSessionManager::new(vfs)))
+    }
+
+    fn cursor(seq: u32, row: u32) -> Datagram {
+        Datagram::Cursor(CursorUpdate {
+            seq,
+            position: CursorPosition {
+                row,
+                col: 3,
+                grid: 1,
+            },
+            peer_id: String::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn cursor_is_relayed_to_other_peers_only() {
+        let manager = manager();
+        let (alice, mut alice_rx) = Peer::join(&manager, "s1", Role::Owner).await;
+        let (bob, _bob_rx) = Peer::join(&manager, "s1", Role::Viewer).await;
+
+        bob.handle(&manager, cursor(1, 10)).await.unwrap();
+        // Reordered datagram is dropped
+        bob.handle(&manager, cursor(0, 99)).await.unwrap();
+
+        let mut relayed = Vec::new();
+        while let Ok(event) = alice_rx.try_recv() {
+            if matches!(event, CollabEvent::CursorMoved { .. }) {
+                // Own cursor is never echoed back
+                assert!(bob.outgoing(&event).is_none());
+            }
+            if let Some(Outgoing::Datagram(datagram)) = alice.outgoing(&event) {
+                relayed.push(datagram);
+            }
+        }
+        assert_eq!(relayed.len(), 1);
+        let Datagram::Cursor(update) = &relayed[0] else {
+            panic!("expected cursor datagram");
+        };
+        assert_eq!(update.peer_id, bob.id);
+        assert_eq!(update.position.row, 10);
+
+        alice.leave().await;
+        bob.leave().await;
+        let registry = manager.read().await.collaboration();
+        assert!(registry.read().await.get("s1").is_none());
+    }
+
+    #[tokio::test]
+    async fn heartbeat_is_echoed() {
+        let manager = manager();
+        let (peer, _rx) = Peer::join(&manager, "s1", Role::Viewer).await;
+        let beat = Datagram::Heartbeat { timestamp_ms: 42 };
+        let reply = peer.handle(&manager, beat.clone()).await.unwrap();
+        assert_eq!(reply, Some(beat));
+    }
+
+    #[tokio::test]
+    async fn only_the_driver_may_type() {
+        let manager = manager();
+        let (owner, _owner_rx) = Peer::join(&manager, "s1", Role::Owner).await;
+        let (editor, _editor_rx) = Peer::join(&manager, "s1", Role::Editor).await;
+        assert!(owner.can_drive().await);
+        assert!(!editor.can_drive().await);
+
+        let registry = manager.read().await.collaboration();
+        registry
+            .write()
+            .await
+            .get_mut("s1")
+            .unwrap()
+            .grant_control(&editor.id)
+            .unwrap();
+        assert!(editor.can_drive().await);
+        assert!(!owner.can_drive().await);
+        assert_eq!(editor.role().await, Role::Editor);
+    }
+
+    fn messages(peer: &Peer, rx: &mut broadcast::Receiver<CollabEvent>) -> Vec<HostMessage> {
+        let mut messages = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            if let Some(Outgoing::Message(message)) = peer.outgoing(&event) {
+                messages.push(message);
+            }
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn presence_changes_are_relayed() {
+        let manager = manager();
+        let (alice, mut alice_rx) = Peer::join(&manager, "s1", Role::Owner).await;
+        // Own join is not announced
+        assert!(messages(&alice, &mut alice_rx).is_empty());
+
+        let (bob, _bob_rx) = Peer::join(&manager, "s1", Role::Viewer).await;
+        bob.set_name("  Bob ").await.unwrap();
+        assert!(bob.set_name(&"x".repeat(MAX_NAME_LEN + 1)).await.is_err());
+        bob.leave().await;
+
+        let received = messages(&alice, &mut alice_rx);
+        assert_eq!(received.len(), 3);
+        assert!(
+            matches!(&received[0], HostMessage::PeerInfo(p) if p.id == bob.id && p.role == "viewer")
+        );
+        assert!(
+            matches!(&received[1], HostMessage::PeerInfo(p) if p.name.as_deref() == Some("Bob"))
+        );
+        assert_eq!(
+            received[2],
+            HostMessage::PeerLeft {
+                peer_id: bob.id.clone()
+            }
+        );
+
+        let HostMessage::Presence {
+            self_id,
+            driver,
+            participants,
+        } = alice.presence().await
+        else {
+            panic!("expected presence");
+        };
+        assert_eq!(self_id, alice.id);
+        assert_eq!(driver, None);
+        assert_eq!(participants.len(), 1);
+        assert_eq!(participants[0].role, "owner");
+    }
+
+    #[tokio::test]
+    async fn private_chat_and_signals_reach_only_their_target() {
+        let manager = manager();
+        let (alice, mut alice_rx) = Peer::join(&manager, "s1", Role::Owner).await;
+        let (bob, mut bob_rx) = Peer::join(&manager, "s1", Role::Viewer).await;
+        let (carol, mut carol_rx) = Peer::join(&manager, "s1", Role::Viewer).await;
+        messages(&alice, &mut alice_rx);
+        messages(&bob, &mut bob_rx);
+        messages(&carol, &mut carol_rx);
+
+        alice.chat("hello all", None).await.unwrap();
+        alice.chat("psst", Some(&bob.id)).await.unwrap();
+        alice.signal(&bob.id, "offer", "v=0".into()).await.unwrap();
+        assert!(alice.chat("", None).await.is_err());
+        assert!(alice.chat("hi", Some("nobody")).await.is_err());
+        assert!(alice.signal(&bob.id, "bye", String::new()).await.is_err());
+        assert!(alice
+            .signal(&alice.id, "offer", String::new())
+            .await
+            .is_err());
+        assert!(alice
+            .signal(&bob.id, "offer", "x".repeat(MAX_SIGNAL_LEN + 1))
+            .await
+            .is_err());
+
+        let chats = |received: &[HostMessage]| {
+            received
+                .iter()
+                .filter_map(|m| match m {
+                    HostMessage::Chat { message, .. } => Some(message.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let to_alice = messages(&alice, &mut alice_rx);
+        let to_bob = messages(&bob, &mut bob_rx);
+        let to_carol = messages(&carol, &mut carol_rx);
+        assert_eq!(chats(&to_alice), ["hello all", "psst"]);
+        assert_eq!(chats(&to_bob), ["hello all", "psst"]);
+        assert_eq!(chats(&to_carol), ["hello all"]);
+
+        assert!(to_bob.contains(&HostMessage::Signal {
+            from: alice.id.clone(),
+            kind: "offer".into(),
+            payload: "v=0".into(),
+        }));
+        assert!(!to_carol
+            .iter()
+            .any(|m| matches!(m, HostMessage::Signal { .. })));
+    }
+}
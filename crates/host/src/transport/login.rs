@@ -0,0 +1,92 @@
+//! OIDC login of browser connections
+//!
+//! Shared by the WebSocket and WebTransport servers. A connection is
+//! authorized once, during its handshake; afterwards it watches the login
+//! session so the browser can be told when the login expires and when it has
+//! been restored. While the login is expired the connection stays open but
+//! its input is ignored.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use axum::http::{HeaderMap, StatusCode};
+use nvim_web_protocol::schema::HostMessage;
+
+use crate::audit::{self, AuditAction, AuditEvent};
+use crate::oidc::{AuthEvent, AuthWatch, Grant, OidcClient, LOGIN_URL};
+
+/// Login of one browser connection
+#[derive(Default)]
+pub(crate) struct LoginWatch {
+    /// `None` without login, or for sessions from before a host restart
+    watch: Option<AuthWatch>,
+    expired: bool,
+}
+
+impl LoginWatch {
+    /// Authorize a connection from its handshake
+    ///
+    /// Returns the logged-in user with their role, or `None` when `auth` is
+    /// not configured and anyone may connect. Failures are audited; the
+    /// browser should be sent [`login_required`] before the connection is
+    /// closed.
+    pub(crate) async fn authorize(
+        auth: Option<&Arc<OidcClient>>,
+        headers: &HeaderMap,
+        ticket: Option<&str>,
+        peer: Option<IpAddr>,
+    ) -> Result<(Option<Grant>, Self), StatusCode> {
+        let Some(auth) = auth else {
+            return Ok((None, Self::default()));
+        };
+        let (session, _) = auth
+            .authorize(headers, ticket, peer)
+            .await
+            .inspect_err(|status| {
+                AuditEvent::new(AuditAction::ConnectionRejected)
+                    .ip(audit::client_ip(Some(auth), peer, headers))
+                    .detail("status", status.as_u16())
+                    .record();
+            })?;
+        let login = Self {
+            watch: auth.watch(&session.sid),
+            expired: false,
+        };
+        Ok((Some(auth.grant(&session.user)), login))
+    }
+
+    /// Whether the user must log in again before input is accepted
+    pub(crate) fn is_expired(&self) -> bool {
+        self.expired
+    }
+
+    /// Wait until the browser needs to hear about the login
+    ///
+    /// Yields `auth_required` when the login expires and `auth_restored`
+    /// once the user has logged in again; renewals are silent.
+    pub(crate) async fn next_message(&mut self) -> HostMessage {
+        let Some(watch) = self.watch.as_mut() else {
+            return std::future::pending().await;
+        };
+        loop {
+            match watch.next().await {
+                AuthEvent::Expired => {
+                    self.expired = true;
+                    return login_required();
+                }
+                AuthEvent::Renewed(_) if self.expired => {
+                    self.expired = false;
+                    return HostMessage::AuthRestored;
+                }
+                AuthEvent::Renewed(_) => {}
+            }
+        }
+    }
+}
+
+/// Tells the browser to log in
+pub(crate) fn login_required() -> HostMessage {
+    HostMessage::AuthRequired {
+        login_url: LOGIN_URL.to_string(),
+    }
+}
@@ -3,17 +3,23 @@
 //! Provides a unified interface for different transport protocols,
 //! enabling automatic fallback and protocol selection.
 
+mod datagram;
+mod login;
 mod websocket;
 mod webtransport;
 
 use async_trait::async_trait;
 use bytes::Bytes;
+use nvim_web_protocol::datagram::Datagram;
 use std::sync::Arc;
 use tokio::sync::{mpsc, RwLock};
 
 pub use websocket::WebSocketTransport;
 pub use webtransport::{serve_webtransport, WebTransportConfig};
 
+pub(crate) use datagram::{Outgoing, Peer};
+pub(crate) use login::{login_required, LoginWatch};
+
 /// Message types for transport layer
 #[derive(Debug, Clone)]
 pub enum TransportMessage {
@@ -51,6 +57,11 @@ pub trait Transport: Send + Sync {
     /// Falls back to reliable send if datagrams not supported.
     async fn send_datagram(&self, data: Bytes) -> anyhow::Result<()>;
 
+    /// Encode and send a typed datagram (cursor, input, heartbeat)
+    async fn send_typed_datagram(&self, datagram: &Datagram) -> anyhow::Result<()> {
+        self.send_datagram(Bytes::from(datagram.encode())).await
+    }
+
     /// Subscribe to incoming messages
     fn subscribe(&self) -> mpsc::Receiver<TransportMessage>;
 
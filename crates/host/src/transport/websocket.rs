@@ -4,6 +4,7 @@
 
 use async_trait::async_trait;
 use bytes::Bytes;
+use nvim_web_protocol::datagram;
 use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::sync::{mpsc, Mutex};
 use tokio_tungstenite::tungstenite::Message;
@@ -41,10 +42,12 @@ impl WebSocketTransport {
     pub async fn feed(&self, msg: Message) -> anyhow::Result<()> {
         match msg {
             Message::Binary(data) => {
-                let _ = self
-                    .rx_tx
-                    .send(TransportMessage::Reliable(data.into()))
-                    .await;
+                // Datagrams arrive wrapped in a reliable envelope
+                let msg = match datagram::decode_envelope(&data) {
+                    Some(raw) => TransportMessage::Datagram(raw.into()),
+                    None => TransportMessage::Reliable(data.into()),
+                };
+                let _ = self.rx_tx.send(msg).await;
             }
             Message::Text(text) => {
                 let _ = self
@@ -88,7 +91,9 @@ impl Transport for WebSocketTransport {
 
     async fn send_datagram(&self, data: Bytes) -> anyhow::Result<()> {
         // WebSocket doesn't support datagrams, fallback to reliable
-        self.send_reliable(data).await
+        // inside an envelope so the peer can tell them apart
+        self.send_reliable(Bytes::from(datagram::encode_envelope(&data)))
+            .await
     }
 
     fn subscribe(&self) -> mpsc::Receiver<TransportMessage> {
@@ -128,3 +133,32 @@ impl Transport for WebSocketTransport {
         "websocket"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nvim_web_protocol::datagram::Datagram;
+
+    #[tokio::test]
+    async fn datagrams_fall_back_to_reliable_envelope() {
+        let (tx, mut out) = mpsc::channel(4);
+        let (transport, mut incoming) = WebSocketTransport::new(tx);
+
+        let beat = Datagram::Heartbeat { timestamp_ms: 7 };
+        transport.send_typed_datagram(&beat).await.unwrap();
+
+        let Some(Message::Binary(sent)) = out.recv().await else {
+            panic!("expected binary frame");
+        };
+        assert_eq!(Datagram::from_envelope(&sent), Some(Ok(beat.clone())));
+
+        // Feeding the envelope back yields a datagram, not a reliable message
+        transport.feed(Message::Binary(sent)).await.unwrap();
+        match incoming.recv().await {
+            Some(TransportMessage::Datagram(raw)) => {
+                assert_eq!(Datagram::decode(&raw), Ok(beat));
+            }
+            other => panic!("unexpected message: {other:?}"),
+        }
+    }
+}
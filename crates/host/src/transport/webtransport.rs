@@ -5,15 +5,26 @@
 //! - Unreliable datagrams (for cursor/input events)
 //! - 0-RTT connection establishment
 
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use tokio::sync::RwLock;
+use rmpv::Value;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{broadcast, mpsc, RwLock};
 use tracing::{info, warn};
-use wtransport::{Connection, Endpoint, Identity, ServerConfig};
+use wtransport::{Connection, Endpoint, Identity, SendStream, ServerConfig, VarInt};
 
+use nvim_web_protocol::datagram::Datagram;
+use nvim_web_protocol::schema::{HostMessage, PROTOCOL_VERSION};
+
+use super::{login_required, LoginWatch, Outgoing, Peer};
+use crate::collaboration::{CollabEvent, Role};
+use crate::oidc::OidcClient;
 use crate::session::AsyncSessionManager;
 use crate::vfs::{FsRequestRegistry, VfsManager};
+use crate::ws::{attach_session, handle_browser_message, ConnectionInfo, RateLimiter};
 
 /// WebTransport server configuration
 #[derive(Debug, Clone)]
@@ -62,15 +73,75 @@ impl WebTransportConfig {
     }
 }
 
+/// Largest reliable frame accepted from a client (file drops included)
+const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+/// Heartbeat configuration (mirrors the WebSocket server)
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Shared server state handed to every WebTransport session
+#[derive(Clone)]
+struct ServerContext {
+    session_manager: Arc<RwLock<AsyncSessionManager>>,
+    fs_registry: Option<Arc<FsRequestRegistry>>,
+    vfs_manager: Option<Arc<RwLock<VfsManager>>>,
+    fs_request_tx: Option<broadcast::Sender<Vec<u8>>>,
+    auth: Option<Arc<OidcClient>>,
+}
+
+/// Per-connection state shared between the stream and datagram tasks
+struct ConnectionState {
+    ctx: ServerContext,
+    session_id: String,
+    peer: Peer,
+    rate_limiter: StdMutex<RateLimiter>,
+    last_activity: StdMutex<Instant>,
+    /// Set while the user must log in again; input is ignored meanwhile
+    login_expired: AtomicBool,
+}
+
+impl ConnectionState {
+    fn mark_active(&self) {
+        if let Ok(mut last) = self.last_activity.lock() {
+            *last = Instant::now();
+        }
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_activity
+            .lock()
+            .map_or(Duration::ZERO, |last| last.elapsed())
+    }
+
+    fn accepts_input(&self) -> bool {
+        !self.login_expired.load(Ordering::Relaxed) && self.within_rate_limit()
+    }
+
+    fn within_rate_limit(&self) -> bool {
+        let allowed = self
+            .rate_limiter
+            .lock()
+            .is_ok_and(|mut limiter| limiter.try_consume());
+        if !allowed {
+            warn!(session_id = %self.session_id, "Rate limit exceeded, dropping message");
+        }
+        allowed
+    }
+}
+
 /// Start WebTransport server
 ///
 /// Listens for incoming WebTransport connections and routes them
-/// to the session manager.
+/// to the session manager. With `auth` set, sessions must be logged in;
+/// browsers send no cookies here, so they pass a ticket from `/auth/ticket`.
 pub async fn serve_webtransport(
     session_manager: Arc<RwLock<AsyncSessionManager>>,
     config: WebTransportConfig,
-    _fs_registry: Option<Arc<FsRequestRegistry>>,
-    _vfs_manager: Option<Arc<RwLock<VfsManager>>>,
+    fs_registry: Option<Arc<FsRequestRegistry>>,
+    vfs_manager: Option<Arc<RwLock<VfsManager>>>,
+    fs_request_tx: Option<broadcast::Sender<Vec<u8>>>,
+    auth: Option<Arc<OidcClient>>,
 ) -> Result<()> {
     // Load identity from PEM files
     let identity = Identity::load_pemfiles(&config.cert_path, &config.key_path).await?;
@@ -85,13 +156,21 @@ pub async fn serve_webtransport(
 
     info!(port = config.port, "WebTransport server listening");
 
+    let ctx = ServerContext {
+        session_manager,
+        fs_registry,
+        vfs_manager,
+        fs_request_tx,
+        auth,
+    };
+
     // Accept loop
     loop {
         let incoming_session = server.accept().await;
-        let session_manager = session_manager.clone();
+        let ctx = ctx.clone();
 
         tokio::spawn(async move {
-            if let Err(e) = handle_webtransport_session(incoming_session, session_manager).await {
+            if let Err(e) = handle_webtransport_session(incoming_session, ctx).await {
                 warn!(error = %e, "WebTransport session error");
             }
         });
@@ -99,14 +178,24 @@ pub async fn serve_webtransport(
 }
 
 /// Handle a single WebTransport session
+///
+/// Protocol:
+/// - The server opens one unidirectional stream and writes the
+///   `["session", id, is_viewer, protocol_version]` frame followed by
+///   redraw/VFS pushes, or a single `["protocol_mismatch", ...]` or
+///   `auth_required` frame
+/// - Every client-opened bidirectional stream carries request frames;
+///   RPC responses are written back on the same stream
+/// - Datagrams carry fire-and-forget cursor/input/heartbeat events
+///
+/// Reliable frames are msgpack payloads prefixed with a big-endian u32 length.
 async fn handle_webtransport_session(
     incoming_session: wtransport::endpoint::IncomingSession,
-    session_manager: Arc<RwLock<AsyncSessionManager>>,
+    ctx: ServerContext,
 ) -> Result<()> {
     // Wait for the session request
     let incoming_request = incoming_session.await?;
 
-    // Extract path before consuming incoming_request
     let path = incoming_request.path().to_string();
     let authority = incoming_request.authority().to_string();
 
@@ -116,57 +205,263 @@ async fn handle_webtransport_session(
         "WebTransport session request"
     );
 
-    // Accept the connection (consumes incoming_request)
-    let connection = incoming_request.accept().await?;
+    let mut conn_info = ConnectionInfo::from_request(&path, incoming_request.origin());
+    if !conn_info.origin_valid {
+        warn!(origin = ?conn_info.origin, "Rejected WebTransport session from invalid origin");
+        incoming_request.forbidden().await;
+        return Err(anyhow::anyhow!("Invalid origin"));
+    }
 
-    info!("WebTransport connection established");
+    let headers: axum::http::HeaderMap = incoming_request
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.parse().ok()?, value.parse().ok()?)))
+        .collect();
+    let peer_addr = Some(incoming_request.remote_address().ip());
+    conn_info.client_ip = crate::audit::client_ip(ctx.auth.as_deref(), peer_addr, &headers);
 
-    // Parse session ID from path (e.g., /?session=abc123)
-    let session_id = parse_session_id(&path);
+    // Accept the connection (consumes incoming_request)
+    let connection = incoming_request.accept().await?;
 
-    // Get or create session - for now just log
-    // Full integration with AsyncSessionManager will be done in a follow-up
-    if let Some(ref id) = session_id {
-        info!(session_id = %id, "Session ID parsed from path");
+    // Refuse clients that are not logged in, telling them where to log in
+    let login = LoginWatch::authorize(
+        ctx.auth.as_ref(),
+        &headers,
+        conn_info.ticket.as_deref(),
+        peer_addr,
+    )
+    .await;
+    let mut login = match login {
+        Ok((user, login)) => {
+            conn_info.user = user;
+            login
+        }
+        Err(status) => {
+            warn!(status = %status, "Rejected WebTransport session without a valid login");
+            if let Ok(opening) = connection.open_uni().await {
+                if let Ok(mut stream) = opening.await {
+                    let _ = write_frame(&mut stream, &login_required().encode()).await;
+                    let _ = stream.finish().await;
+                }
+            }
+            connection.close(VarInt::from_u32(3), b"login required");
+            return Err(anyhow::anyhow!("Not logged in"));
+        }
+    };
+
+    // Refuse incompatible UI builds before touching any session
+    if let Err(e) = conn_info.check_protocol() {
+        warn!(error = %e, "Rejected WebTransport client with incompatible protocol version");
+        if let Ok(opening) = connection.open_uni().await {
+            if let Ok(mut stream) = opening.await {
+                let _ = write_frame(&mut stream, &HostMessage::protocol_mismatch().encode()).await;
+                let _ = stream.finish().await;
+            }
+        }
+        connection.close(VarInt::from_u32(2), b"protocol version mismatch");
+        return Err(e.into());
     }
 
-    // Store reference to session manager for future use
-    let _manager = session_manager;
+    let (session_id, role) = match attach_session(&ctx.session_manager, &conn_info).await {
+        Ok(attached) => attached,
+        Err(e) => {
+            connection.close(VarInt::from_u32(1), e.to_string().as_bytes());
+            return Err(e);
+        }
+    };
+
+    let is_viewer = role == Role::Viewer;
+    info!(
+        session_id = %session_id,
+        is_viewer = is_viewer,
+        user = ?conn_info.user.as_ref().map(|grant| &grant.sub),
+        "WebTransport session connected"
+    );
 
-    // Spawn tasks for handling streams and datagrams
     let conn = Arc::new(connection);
 
+    // Server -> client stream, starting with the session handshake
+    let mut push_stream = conn.open_uni().await?.await?;
+    let session_msg = HostMessage::Session {
+        session_id: session_id.clone(),
+        is_viewer,
+        protocol_version: PROTOCOL_VERSION,
+    };
+    write_frame(&mut push_stream, &session_msg.encode()).await?;
+
+    let redraw_rx = {
+        let mgr = ctx.session_manager.read().await;
+        mgr.get_session(&session_id)
+            .map(crate::session::AsyncSession::subscribe)
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?
+    };
+    let fs_rx = ctx.fs_request_tx.as_ref().map(broadcast::Sender::subscribe);
+
+    // Join the session as a collaboration peer
+    let (mut peer, collab_rx) = Peer::join(&ctx.session_manager, &session_id, role).await;
+    peer.user = conn_info.user.as_ref().map(|grant| grant.sub.clone());
+    peer.ip = conn_info.client_ip;
+
+    let state = Arc::new(ConnectionState {
+        ctx: ctx.clone(),
+        session_id: session_id.clone(),
+        peer,
+        // Rate limiter: 1000 burst, 100/sec sustained
+        rate_limiter: StdMutex::new(RateLimiter::default_ws()),
+        last_activity: StdMutex::new(Instant::now()),
+        login_expired: AtomicBool::new(false),
+    });
+
+    // Collaboration messages share the push stream with redraws
+    let (collab_tx, collab_push_rx) = mpsc::unbounded_channel();
+    let push_task = tokio::spawn(forward_pushes(
+        push_stream,
+        redraw_rx,
+        fs_rx,
+        collab_push_rx,
+        state.clone(),
+    ));
+
     // Handle bidirectional streams (for RPC)
-    let stream_conn = conn.clone();
-    let stream_task = tokio::spawn(async move { handle_bidirectional_streams(stream_conn).await });
+    let stream_task = tokio::spawn(handle_bidirectional_streams(conn.clone(), state.clone()));
 
     // Handle datagrams (for cursor/input)
-    let datagram_conn = conn.clone();
-    let datagram_task = tokio::spawn(async move { handle_datagrams(datagram_conn).await });
-
-    // Wait for either task to complete (connection closed)
-    tokio::select! {
-        result = stream_task => {
-            if let Err(e) = result {
-                warn!(error = %e, "Stream task panicked");
+    let datagram_task = tokio::spawn(handle_datagrams(conn.clone(), state.clone()));
+
+    // Tell the client when its login expires or is restored
+    let login_tx = collab_tx.clone();
+    let login_state = state.clone();
+    let login_task = tokio::spawn(async move {
+        loop {
+            let message = login.next_message().await;
+            login_state
+                .login_expired
+                .store(login.is_expired(), Ordering::Relaxed);
+            if login_tx.send(message.encode()).is_err() {
+                break;
             }
         }
-        result = datagram_task => {
-            if let Err(e) = result {
-                warn!(error = %e, "Datagram task panicked");
+    });
+
+    // Relay other participants' cursors as datagrams, everything else
+    // over the push stream
+    let collab_task = tokio::spawn(forward_collab(
+        conn.clone(),
+        collab_rx,
+        collab_tx,
+        state.clone(),
+    ));
+
+    let mut heartbeat_interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+
+    // Wait for any task to complete (connection closed) or heartbeat timeout
+    loop {
+        tokio::select! {
+            _ = heartbeat_interval.tick() => {
+                if state.idle_for() > HEARTBEAT_TIMEOUT {
+                    warn!(
+                        session_id = %session_id,
+                        elapsed_secs = state.idle_for().as_secs(),
+                        "WebTransport heartbeat timeout, triggering auto-save"
+                    );
+                    let mgr = ctx.session_manager.read().await;
+                    if let Some(session) = mgr.get_session(&session_id) {
+                        let _ = session.rpc_call("nvim_command", vec![Value::String("silent! w".into())]).await;
+                        let _ = session.rpc_call("nvim_command", vec![Value::String("silent! mksession! ~/.local/state/nvim/sessions/auto.vim".into())]).await;
+                    }
+                    drop(mgr);
+                    conn.close(VarInt::from_u32(0), b"heartbeat timeout");
+                    break;
+                }
             }
+            _ = conn.closed() => break,
+        }
+    }
+
+    push_task.abort();
+    stream_task.abort();
+    datagram_task.abort();
+    collab_task.abort();
+    login_task.abort();
+    state.peer.leave().await;
+
+    // Mark session as disconnected
+    {
+        let mut mgr = ctx.session_manager.write().await;
+        if let Some(session) = mgr.get_session_mut(&session_id) {
+            session.connected = false;
+            session.touch();
         }
+        mgr.touch_record(&session_id);
     }
 
-    info!("WebTransport session closed");
+    info!(session_id = %session_id, "WebTransport session closed");
     Ok(())
 }
 
+/// Forward Neovim redraws, VFS requests and collaboration messages to the
+/// client push stream
+async fn forward_pushes(
+    mut push_stream: SendStream,
+    mut redraw_rx: broadcast::Receiver<Vec<u8>>,
+    mut fs_rx: Option<broadcast::Receiver<Vec<u8>>>,
+    mut collab_rx: mpsc::UnboundedReceiver<Vec<u8>>,
+    state: Arc<ConnectionState>,
+) {
+    const LAG_RECOVERY_DEBOUNCE: Duration = Duration::from_secs(2);
+    let mut last_lag_recovery = Instant::now();
+
+    loop {
+        let fs_next = async {
+            match fs_rx.as_mut() {
+                Some(rx) => rx.recv().await,
+                None => std::future::pending().await,
+            }
+        };
+
+        let bytes = tokio::select! {
+            msg = redraw_rx.recv() => match msg {
+                Ok(bytes) => bytes,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!(
+                        session_id = %state.session_id,
+                        dropped_messages = n,
+                        "Redraw messages lagged, requesting full resync"
+                    );
+                    if last_lag_recovery.elapsed() >= LAG_RECOVERY_DEBOUNCE {
+                        last_lag_recovery = Instant::now();
+                        let mgr = state.ctx.session_manager.read().await;
+                        if let Some(session) = mgr.get_session(&state.session_id) {
+                            let _ = session.request_redraw().await;
+                        }
+                    }
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            msg = fs_next => match msg {
+                Ok(bytes) => bytes,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => {
+                    fs_rx = None;
+                    continue;
+                }
+            },
+            Some(bytes) = collab_rx.recv() => bytes,
+        };
+
+        if let Err(e) = write_frame(&mut push_stream, &bytes).await {
+            warn!(session_id = %state.session_id, error = %e, "Push failed, stopping sender");
+            break;
+        }
+    }
+}
+
 /// Handle bidirectional streams for RPC communication
-async fn handle_bidirectional_streams(conn: Arc<Connection>) -> Result<()> {
+async fn handle_bidirectional_streams(conn: Arc<Connection>, state: Arc<ConnectionState>) {
     loop {
         // Accept incoming bidirectional stream
-        let (mut send, mut recv) = match conn.accept_bi().await {
+        let (send, recv) = match conn.accept_bi().await {
             Ok(stream) => stream,
             Err(e) => {
                 // Connection closed
@@ -176,45 +471,70 @@ async fn handle_bidirectional_streams(conn: Arc<Connection>) -> Result<()> {
         };
 
         // Spawn handler for this stream
+        let state = state.clone();
         tokio::spawn(async move {
-            // Read request
-            let mut buf = vec![0u8; 65536];
-            match recv.read(&mut buf).await {
-                Ok(Some(n)) => {
-                    let data = &buf[..n];
-
-                    // Process message (integration point: ws/commands.rs handlers)
-                    let response = process_webtransport_message(data).await;
-
-                    // Send response
-                    if let Err(e) = send.write_all(&response).await {
-                        warn!(error = %e, "Failed to send response");
-                    }
-                }
-                Ok(None) => {
-                    // Stream closed
-                }
-                Err(e) => {
-                    warn!(error = %e, "Stream read error");
-                }
+            if let Err(e) = handle_request_stream(send, recv, &state).await {
+                warn!(session_id = %state.session_id, error = %e, "Stream error");
             }
         });
     }
+}
+
+/// Read request frames from one stream until the client finishes it
+async fn handle_request_stream<W, R>(
+    mut send: W,
+    mut recv: R,
+    state: &ConnectionState,
+) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    R: AsyncRead + Unpin,
+{
+    while let Some(data) = read_frame(&mut recv).await? {
+        state.mark_active();
+
+        if !state.accepts_input() {
+            continue;
+        }
+
+        let ctx = &state.ctx;
+        match handle_browser_message(
+            &state.session_id,
+            &ctx.session_manager,
+            &state.peer,
+            ctx.fs_registry.as_ref(),
+            ctx.vfs_manager.as_ref(),
+            data,
+        )
+        .await
+        {
+            Ok(Some(response)) => write_frame(&mut send, &response).await?,
+            Ok(None) => {}
+            Err(_e) => {
+                // Message handling errors are routine
+            }
+        }
+
+        // Touch session
+        let mut mgr = ctx.session_manager.write().await;
+        if let Some(session) = mgr.get_session_mut(&state.session_id) {
+            session.touch();
+        }
+    }
 
     Ok(())
 }
 
 /// Handle unreliable datagrams for low-latency events
-async fn handle_datagrams(conn: Arc<Connection>) -> Result<()> {
+async fn handle_datagrams(conn: Arc<Connection>, state: Arc<ConnectionState>) {
     loop {
         match conn.receive_datagram().await {
             Ok(datagram) => {
                 // Datagrams are used for cursor position updates, input events
                 // These are fire-and-forget, no response needed
-                let data = datagram.payload();
+                state.mark_active();
 
-                // Parse and handle datagram message
-                if let Err(e) = handle_datagram_message(&data).await {
+                if let Err(e) = handle_datagram_message(&conn, &state, &datagram.payload()).await {
                     warn!(error = %e, "Datagram processing error");
                 }
             }
@@ -225,46 +545,86 @@ async fn handle_datagrams(conn: Arc<Connection>) -> Result<()> {
             }
         }
     }
+}
+
+/// Handle datagram message (cursor updates, input events, heartbeats)
+///
+/// See `nvim_web_protocol::datagram` for the wire format.
+async fn handle_datagram_message(
+    conn: &Connection,
+    state: &ConnectionState,
+    data: &[u8],
+) -> Result<()> {
+    let datagram = Datagram::decode(data)?;
+    if !state.accepts_input() {
+        return Ok(());
+    }
+
+    let reply = state
+        .peer
+        .handle(&state.ctx.session_manager, datagram)
+        .await?;
+    if let Some(reply) = reply {
+        conn.send_datagram(reply.encode())?;
+    }
 
     Ok(())
 }
 
-/// Parse session ID from request path
-fn parse_session_id(path: &str) -> Option<String> {
-    // Parse query string: /?session=abc123
-    let query = path.split('?').nth(1)?;
-    for pair in query.split('&') {
-        let mut parts = pair.split('=');
-        if parts.next() == Some("session") {
-            return parts.next().map(String::from);
+/// Send collaboration events for this peer until the connection closes
+///
+/// Starts with the current participants and comments. Cursors go out as datagrams, other
+/// messages are handed to the push stream.
+async fn forward_collab(
+    conn: Arc<Connection>,
+    mut events: broadcast::Receiver<CollabEvent>,
+    push_tx: mpsc::UnboundedSender<Vec<u8>>,
+    state: Arc<ConnectionState>,
+) {
+    let comments = state.peer.comments(&state.ctx.session_manager).await;
+    for message in [state.peer.presence().await, comments] {
+        if push_tx.send(message.encode()).is_err() {
+            return;
+        }
+    }
+    while let Some(outgoing) = state.peer.next_outgoing(&mut events).await {
+        match outgoing {
+            // Dropped datagrams are fine, the next cursor update supersedes them
+            Outgoing::Datagram(datagram) => {
+                if let Err(e) = conn.send_datagram(datagram.encode()) {
+                    tracing::debug!(error = %e, "Datagram send failed");
+                }
+            }
+            Outgoing::Message(message) => {
+                if push_tx.send(message.encode()).is_err() {
+                    break;
+                }
+            }
         }
     }
-    None
 }
 
-/// Process a WebTransport message (similar to WebSocket handler)
-async fn process_webtransport_message(data: &[u8]) -> Vec<u8> {
-    // Decode msgpack message using rmpv directly (not serde)
-    let _msg: Result<rmpv::Value, _> = rmpv::decode::read_value(&mut &data[..]);
-
-    // Integration point: Route to ws/commands.rs handlers
-    // The same handlers used by WebSocket can be reused here
-
-    vec![]
+/// Read one length-prefixed frame, `None` on clean end of stream
+async fn read_frame<R: AsyncRead + Unpin>(recv: &mut R) -> Result<Option<Vec<u8>>> {
+    let len = match recv.read_u32().await {
+        Ok(len) => len as usize,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if len > MAX_FRAME_SIZE {
+        return Err(anyhow::anyhow!("Frame too large: {len} bytes"));
+    }
+    let mut buf = vec![0u8; len];
+    recv.read_exact(&mut buf).await?;
+    Ok(Some(buf))
 }
 
-/// Handle datagram message (cursor updates, input events)
-async fn handle_datagram_message(_data: &[u8]) -> Result<()> {
-    // Parse datagram type and payload
-    // Datagram format: [type: u8, payload...]
-    // Types:
-    //   0x01 = Cursor position
-    //   0x02 = Input event
-    //   0x03 = Heartbeat
-
-    // For ultra-low-latency events that don't need reliability
-    // Integrate with collaboration.rs for cursor sync
-
+/// Write one length-prefixed frame
+async fn write_frame<W: AsyncWrite + Unpin>(send: &mut W, data: &[u8]) -> Result<()> {
+    let len = u32::try_from(data.len())?;
+    send.write_all(&len.to_be_bytes()).await?;
+    send.write_all(data).await?;
+    send.flush().await?;
     Ok(())
 }
 
@@ -272,6 +632,10 @@ async fn handle_datagram_message(_data: &[u8]) -> Result<()> {
 mod tests {
     use super::*;
 
+    fn parse_session_id(path: &str) -> Option<String> {
+        ConnectionInfo::from_request(path, None).session_id
+    }
+
     #[test]
     fn test_parse_session_id() {
         assert_eq!(parse_session_id("/?session=abc123"), Some("abc123".into()));
@@ -283,6 +647,55 @@ mod tests {
         assert_eq!(parse_session_id("/?other=value"), None);
     }
 
+    #[test]
+    fn test_connection_info_viewer_and_origin() {
+        let info = ConnectionInfo::from_request("/?view=abc", Some("http://localhost:8080"));
+        assert!(info.is_viewer);
+        assert_eq!(info.view_session_id.as_deref(), Some("abc"));
+        assert!(info.origin_valid);
+
+        let info = ConnectionInfo::from_request("/?session=abc", Some("http://evil.com"));
+        assert!(!info.origin_valid);
+    }
+
+    #[test]
+    fn test_connection_info_protocol_version() {
+        let current = format!("/?protocol={PROTOCOL_VERSION}");
+        assert!(ConnectionInfo::from_request(&current, None)
+            .check_protocol()
+            .is_ok());
+
+        // Legacy clients without a version are still accepted
+        assert!(ConnectionInfo::from_request("/", None)
+            .check_protocol()
+            .is_ok());
+
+        let future = format!("/?protocol={}", PROTOCOL_VERSION + 1);
+        assert!(ConnectionInfo::from_request(&future, None)
+            .check_protocol()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_frame_roundtrip() {
+        let (mut a, mut b) = tokio::io::duplex(1024);
+        write_frame(&mut a, b"hello").await.unwrap();
+        write_frame(&mut a, b"").await.unwrap();
+        drop(a);
+
+        assert_eq!(read_frame(&mut b).await.unwrap(), Some(b"hello".to_vec()));
+        assert_eq!(read_frame(&mut b).await.unwrap(), Some(Vec::new()));
+        assert_eq!(read_frame(&mut b).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_frame_rejects_oversized() {
+        let (mut a, mut b) = tokio::io::duplex(64);
+        let len = u32::try_from(MAX_FRAME_SIZE + 1).unwrap();
+        a.write_all(&len.to_be_bytes()).await.unwrap();
+        assert!(read_frame(&mut b).await.is_err());
+    }
+
     #[test]
     fn test_generate_self_signed() {
         let result = WebTransportConfig::generate_self_signed(9002);
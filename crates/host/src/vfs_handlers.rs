@@ -63,7 +63,8 @@ impl TreeEntry {
 ///
 /// # Protocol
 /// When the browser requests to open a VFS file, this handler:
-/// 1. Reads the file content via `VfsManager`
+/// 1. Checks the session owner's role may use the VFS backend, then reads
+///    the file content via `VfsManager`
 /// 2. If file > 1MB, truncate to first 100KB with indicator
 /// 3. Creates a new buffer in Neovim
 /// 4. Sets the buffer content
@@ -80,6 +81,7 @@ pub async fn handle_open_vfs(
     vfs_manager: &VfsManager,
 ) -> Result<u32> {
     // Read file content via VFS
+    session.check_vfs_path(vfs_manager, vfs_path).await?;
     let content = vfs_manager.read_file(vfs_path).await?;
 
     // Handle large files with truncation
@@ -219,6 +221,7 @@ pub async fn handle_write_vfs(
     };
 
     // Write to VFS
+    session.check_vfs_path(vfs_manager, vfs_path).await?;
     vfs_manager.write_file(vfs_path, content.as_bytes()).await?;
 
     // Mark buffer as not modified
@@ -294,6 +297,7 @@ pub async fn handle_delete_vfs(
     // Parse URI to get backend
     let uri = url::Url::parse(vfs_path).map_err(|e| anyhow::anyhow!("Invalid URI: {e}"))?;
     let scheme = uri.scheme();
+    session.limits.check_vfs_scheme(scheme)?;
 
     let vfs = vfs_manager; // We have &VfsManager
                            // We need to access the inner backend. VfsManager.get_backend(scheme) -> Result<Arc<dyn VfsBackend>>
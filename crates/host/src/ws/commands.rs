@@ -1,103 +1,158 @@
 //! Browser message command handlers
 //!
 //! Handles RPC requests, VFS operations, settings, and legacy messages.
+//! Messages are checked against the sending peer's role first: only the
+//! driver's input reaches Neovim and only editors may change buffers.
 
 use std::path::Path;
 use std::sync::Arc;
 
 use anyhow::Result;
+use nvim_web_protocol::schema::{BrowserMessage, CwdInfo, HostMessage, Participant};
 use rmpv::Value;
 use tokio::sync::RwLock;
 
+use crate::audit::{AuditAction, AuditEvent};
+use crate::collaboration::Role;
+use crate::comments;
+use crate::crdt::SyncMessage;
 use crate::git;
-use crate::session::AsyncSessionManager;
-use crate::settings::SettingsStore;
+use crate::llm::{self, Prompt, Provider};
+use crate::search::{self, ProjectSearch, SearchOptions};
+use crate::session::{AsyncSession, AsyncSessionManager};
+use crate::settings::{self, SettingsStore, UserSettings};
+use crate::transport::Peer;
 use crate::vfs::{FsRequestRegistry, VfsManager};
 use crate::vfs_handlers;
 
 /// Handle messages from browser
 ///
-/// Protocol envelope: [type, ...payload]
-/// - Type 0: RPC request [0, id, method, params] -> responds with [1, id, error, result]
-/// - Type "input": fire-and-forget input ["input", keys]
-/// - Type "resize": fire-and-forget resize ["resize", cols, rows]
+/// Messages are decoded into [`BrowserMessage`]; see `nvim_web_protocol::schema`
+/// for the wire format. RPC requests are answered with `[1, id, error, result]`,
+/// everything else is fire-and-forget.
 ///
 /// Returns optional response bytes to send back to browser
-#[allow(clippy::too_many_lines)]
-#[allow(clippy::significant_drop_tightening)]
-#[tracing::instrument(skip(manager, fs_registry, vfs_manager, data), level = "debug")]
-pub async fn handle_browser_message(
+#[tracing::instrument(skip(manager, peer, fs_registry, vfs_manager, data), level = "debug")]
+pub(crate) async fn handle_browser_message(
     session_id: &str,
     manager: &Arc<RwLock<AsyncSessionManager>>,
+    peer: &Peer,
     fs_registry: Option<&Arc<FsRequestRegistry>>,
     vfs_manager: Option<&Arc<RwLock<VfsManager>>>,
     data: Vec<u8>,
 ) -> Result<Option<Vec<u8>>> {
-    let mut cursor = std::io::Cursor::new(data);
-    let msg = rmpv::decode::read_value(&mut cursor)?;
-
-    if let Value::Array(arr) = msg {
-        if arr.is_empty() {
+    let msg = match BrowserMessage::decode(&data) {
+        Ok(msg) => msg,
+        Err(e) => {
+            tracing::warn!(session_id = %session_id, error = %e, "Dropping malformed browser message");
             return Ok(None);
         }
+    };
 
-        // Check for RPC request (type 0)
-        if let Value::Integer(msg_type) = &arr[0] {
-            if msg_type.as_i64() == Some(0) && arr.len() >= 4 {
-                return handle_rpc_request(session_id, manager, vfs_manager, &arr).await;
-            }
-
-            // Type 3: FS response from browser [3, id, ok, result]
-            if msg_type.as_i64() == Some(3) && arr.len() >= 4 {
-                return handle_fs_response(fs_registry, &arr).await;
-            }
+    if !permitted(peer, &msg).await {
+        tracing::debug!(session_id = %session_id, peer_id = %peer.id, "Dropping message not allowed for the peer's role");
+        return Ok(None);
+    }
+    if let BrowserMessage::Input(keys) | BrowserMessage::Paste(keys) = &msg {
+        peer.record_input(keys).await;
+    }
 
-            // Type 2: Notification (used for clipboard response)
-            if msg_type.as_i64() == Some(2) && arr.len() >= 3 {
-                return handle_notification(session_id, manager, &arr).await;
-            }
+    match msg {
+        BrowserMessage::Rpc { id, method, params } => {
+            handle_rpc_request(session_id, manager, peer, vfs_manager, id, &method, params).await
         }
-
-        // Legacy string-based messages (including terminal and LLM)
-        if arr.len() >= 1 {
-            if let Value::String(method) = &arr[0] {
-                // Check for terminal messages
-                if let Some(m) = method.as_str() {
-                    if m.starts_with("terminal_") {
-                        // Terminal messages need output channel - pass None for now
-                        // Real integration would pass the WebSocket sender
-                        return handle_terminal_message(session_id, &arr, None).await;
-                    }
-                    if m.starts_with("llm_") {
-                        // LLM messages handled separately via RPC
-                    }
-                }
-            }
-            if arr.len() >= 2 {
-                return handle_legacy_message(session_id, manager, &arr).await;
-            }
+        BrowserMessage::FsResponse { id, ok, result } => {
+            handle_fs_response(fs_registry, id, ok, result).await
+        }
+        BrowserMessage::ClipboardReadResponse {
+            request_id,
+            content,
+            session_id: response_session_id,
+        } => {
+            handle_clipboard_response(
+                session_id,
+                manager,
+                request_id,
+                content,
+                &response_session_id,
+            )
+            .await
+        }
+        BrowserMessage::CrdtSync { buffer_id, message } => {
+            handle_crdt_sync(session_id, manager, buffer_id, message).await
+        }
+        // Relayed to the other participants through the collaboration channel
+        BrowserMessage::Chat { message, to } => {
+            peer.chat(&message, to.as_deref()).await?;
+            Ok(None)
+        }
+        BrowserMessage::Signal { to, kind, payload } => {
+            peer.signal(&to, &kind, payload).await?;
+            Ok(None)
         }
+        BrowserMessage::SetName(name) => {
+            peer.set_name(&name).await?;
+            Ok(None)
+        }
+        msg @ (BrowserMessage::CommentAdd { .. }
+        | BrowserMessage::CommentEdit { .. }
+        | BrowserMessage::CommentResolve { .. }
+        | BrowserMessage::CommentDelete(_)) => {
+            handle_comment(session_id, manager, peer, msg).await?;
+            Ok(None)
+        }
+        BrowserMessage::Notification { method, .. } => {
+            tracing::debug!(method = %method, "Ignoring unhandled browser notification");
+            Ok(None)
+        }
+        // Terminal messages need output channel - pass None for now
+        // Real integration would pass the WebSocket sender
+        msg @ (BrowserMessage::TerminalSpawn { .. }
+        | BrowserMessage::TerminalInput(_)
+        | BrowserMessage::TerminalClose) => handle_terminal_message(session_id, msg, None).await,
+        // Datagrams are handled by the connection before reaching here
+        BrowserMessage::Datagram(_) => Ok(None),
+        msg => handle_input_message(session_id, manager, msg).await,
     }
+}
 
-    Ok(None)
+/// Whether `peer` may send `msg` with its current role
+async fn permitted(peer: &Peer, msg: &BrowserMessage) -> bool {
+    match msg {
+        // Participant management checks the caller's role itself
+        BrowserMessage::Rpc { method, .. } if method.starts_with("collab_") => true,
+        BrowserMessage::CrdtSync {
+            message: SyncMessage::Update { .. } | SyncMessage::SyncStep2 { .. },
+            ..
+        } => peer.role().await.can_edit(),
+        BrowserMessage::CrdtSync { .. }
+        | BrowserMessage::Chat { .. }
+        | BrowserMessage::Signal { .. }
+        | BrowserMessage::SetName(_)
+        | BrowserMessage::CommentDelete(_)
+        | BrowserMessage::Notification { .. }
+        | BrowserMessage::Datagram(_) => true,
+        // Deleting checks authorship itself
+        BrowserMessage::CommentAdd { .. }
+        | BrowserMessage::CommentEdit { .. }
+        | BrowserMessage::CommentResolve { .. } => peer.role().await.can_comment(),
+        // BrowserFS lives in the owner's browser
+        BrowserMessage::FsResponse { .. } => peer.role().await == Role::Owner,
+        _ => peer.can_drive().await,
+    }
 }
 
 /// Handle RPC request: [0, id, method, params] -> [1, id, error, result]
-#[tracing::instrument(skip(manager, vfs_manager, arr), fields(method), level = "debug")]
+#[tracing::instrument(skip(manager, peer, vfs_manager, params), level = "debug")]
 async fn handle_rpc_request(
     session_id: &str,
     manager: &Arc<RwLock<AsyncSessionManager>>,
+    peer: &Peer,
     vfs_manager: Option<&Arc<RwLock<VfsManager>>>,
-    arr: &[Value],
+    id: u64,
+    method: &str,
+    params: Vec<Value>,
 ) -> Result<Option<Vec<u8>>> {
-    let id = arr[1].clone();
-    let method = arr[2].as_str().unwrap_or("");
-    let params = if let Value::Array(p) = &arr[3] {
-        p.clone()
-    } else {
-        vec![]
-    };
-
     // Check for VFS/settings methods first (handle locally)
     let vfs_result = match method {
         "vfs_open" if vfs_manager.is_some() => {
@@ -106,13 +161,25 @@ async fn handle_rpc_request(
         "vfs_write" if vfs_manager.is_some() => {
             handle_vfs_write(session_id, manager, vfs_manager.unwrap(), &params).await
         }
-        "vfs_list" if vfs_manager.is_some() => handle_vfs_list(vfs_manager.unwrap(), &params).await,
+        "vfs_list" if vfs_manager.is_some() => {
+            handle_vfs_list(session_id, manager, vfs_manager.unwrap(), &params).await
+        }
         "settings_get" => handle_settings_get(&params),
         "settings_set" => handle_settings_set(&params),
         "settings_all" => handle_settings_all(),
         "get_cwd_info" => handle_get_cwd_info(session_id, manager).await,
         "get_session_id" => Some((Value::Nil, Value::String(session_id.to_string().into()))),
-        "tool_exec" => handle_tool_exec(&params).await,
+        "tool_exec" => handle_tool_exec(session_id, manager, peer, &params).await,
+        "search" if vfs_manager.is_some() => {
+            handle_search(session_id, manager, vfs_manager.unwrap(), &params).await
+        }
+        "search_cancel" => handle_search_cancel(session_id, manager, &params).await,
+        "llm_prompt" => handle_llm_prompt(session_id, manager, peer, &params).await,
+        "llm_cancel" => handle_llm_cancel(session_id, manager, &params).await,
+        "llm_list_models" => handle_llm_list_models(peer, &params).await,
+        "llm_set_key" => handle_llm_set_key(peer, &params),
+        "llm_set_provider" => handle_llm_set_provider(peer, &params),
+        m if m.starts_with("collab_") => handle_collab(session_id, manager, peer, m, &params).await,
         _ => None, // Not a VFS/settings method, forward to Neovim
     };
 
@@ -131,13 +198,9 @@ async fn handle_rpc_request(
         }
     };
 
-    // Build response: [1, id, error, result]
-    let response = Value::Array(vec![Value::Integer(1.into()), id, error, result]);
-
-    let mut bytes = Vec::new();
-    rmpv::encode::write_value(&mut bytes, &response)?;
-
-    Ok(Some(bytes))
+    Ok(Some(
+        HostMessage::RpcResponse { id, error, result }.encode(),
+    ))
 }
 
 /// Handle VFS open: vfs_open(vfs_path) -> bufnr
@@ -186,12 +249,23 @@ async fn handle_vfs_write(
 
 /// Handle VFS list: vfs_list(path, depth) -> tree entries
 async fn handle_vfs_list(
+    session_id: &str,
+    manager: &Arc<RwLock<AsyncSessionManager>>,
     vfs_manager: &Arc<RwLock<VfsManager>>,
     params: &[Value],
 ) -> Option<(Value, Value)> {
     let path = params.first().and_then(|v| v.as_str()).unwrap_or("/");
     let depth = usize::try_from(params.get(1).and_then(Value::as_u64).unwrap_or(1)).unwrap_or(1);
 
+    let allowed = manager
+        .read()
+        .await
+        .get_session(session_id)
+        .map(|session| session.limits.check_vfs_scheme("local"))?;
+    if let Err(e) = allowed {
+        return Some((Value::String(e.to_string().into()), Value::Nil));
+    }
+
     let vfs = vfs_manager.read().await;
     if let Ok(backend) = vfs.get_backend("local").await {
         Some(
@@ -208,6 +282,9 @@ async fn handle_vfs_list(
 /// Handle settings_get(key) -> value
 fn handle_settings_get(params: &[Value]) -> Option<(Value, Value)> {
     let key = params.first().and_then(|v| v.as_str()).unwrap_or("");
+    if settings::is_reserved(key) {
+        return Some((Value::Nil, Value::Nil));
+    }
 
     Some(match SettingsStore::new() {
         Ok(store) => {
@@ -224,6 +301,12 @@ fn handle_settings_get(params: &[Value]) -> Option<(Value, Value)> {
 fn handle_settings_set(params: &[Value]) -> Option<(Value, Value)> {
     let key = params.first().and_then(|v| v.as_str()).unwrap_or("");
     let value = params.get(1).and_then(|v| v.as_str()).unwrap_or("");
+    if settings::is_reserved(key) {
+        return Some((
+            Value::String(format!("Setting '{key}' is reserved").into()),
+            Value::Nil,
+        ));
+    }
 
     Some(match SettingsStore::new() {
         Ok(store) => match store.set(key, value) {
@@ -241,6 +324,7 @@ fn handle_settings_all() -> Option<(Value, Value)> {
             let all = store.get_all();
             let map: Vec<(Value, Value)> = all
                 .into_iter()
+                .filter(|(k, _)| !settings::is_reserved(k))
                 .map(|(k, v)| (Value::String(k.into()), Value::String(v.into())))
                 .collect();
             (Value::Nil, Value::Map(map))
@@ -299,49 +383,52 @@ async fn handle_get_cwd_info(
         "local"
     };
 
-    // Build response map
-    let map = vec![
-        (Value::String("cwd".into()), Value::String(cwd.into())),
-        (
-            Value::String("file".into()),
-            Value::String(current_file.into()),
-        ),
-        (
-            Value::String("backend".into()),
-            Value::String(backend.into()),
-        ),
-        (
-            Value::String("git_branch".into()),
-            git_branch.map_or(Value::Nil, |b| Value::String(b.into())),
-        ),
-    ];
-
-    Some((Value::Nil, Value::Map(map)))
+    let info = CwdInfo {
+        cwd,
+        file: current_file,
+        backend: backend.to_string(),
+        git_branch,
+    };
+    Some((Value::Nil, info.to_value()))
 }
 
-/// Handle tool_exec(command, args, input) -> {stdout, stderr, exit_code}
-/// Universal pipe for executing arbitrary CLI tools
-async fn handle_tool_exec(params: &[Value]) -> Option<(Value, Value)> {
-    use crate::pipe;
-
-    let command = params.first().and_then(|v| v.as_str()).unwrap_or("");
-    let args: Vec<String> = params
+/// Handle tool_exec(tool, args, input) -> {stdout, stderr, exit_code}
+/// Runs a tool registered in `[tools]` by name; every run is audited
+async fn handle_tool_exec(
+    session_id: &str,
+    manager: &Arc<RwLock<AsyncSessionManager>>,
+    peer: &Peer,
+    params: &[Value],
+) -> Option<(Value, Value)> {
+    let tool = params.first().and_then(|v| v.as_str()).unwrap_or("");
+    // Arguments fill placeholders by position, so none may be dropped
+    let args: Option<Vec<String>> = params
         .get(1)
         .and_then(|v| v.as_array())
-        .map(|arr| {
-            arr.iter()
-                .filter_map(|v| v.as_str().map(String::from))
-                .collect()
-        })
-        .unwrap_or_default();
+        .map(|arr| arr.iter().map(|v| v.as_str().map(String::from)).collect())
+        .unwrap_or(Some(Vec::new()));
     let input = params.get(2).and_then(|v| v.as_str()).unwrap_or("");
 
-    if command.is_empty() {
-        return Some((Value::String("Missing command".into()), Value::Nil));
+    if tool.is_empty() {
+        return Some((Value::String("Missing tool name".into()), Value::Nil));
     }
+    let Some(args) = args else {
+        return Some((
+            Value::String("Tool arguments must be strings".into()),
+            Value::Nil,
+        ));
+    };
 
-    match pipe::run_pipe(command, &args, input, None).await {
+    let event = AuditEvent::new(AuditAction::ToolExec)
+        .user(peer.user.as_deref())
+        .ip(peer.ip)
+        .session(session_id)
+        .detail("tool", tool)
+        .detail("args", args.clone());
+    let tools = manager.read().await.tools.clone();
+    match tools.run(tool, &args, input).await {
         Ok(result) => {
+            event.detail("exit_code", result.exit_code).record();
             let map = vec![
                 (
                     Value::String("stdout".into()),
@@ -358,22 +445,377 @@ async fn handle_tool_exec(params: &[Value]) -> Option<(Value, Value)> {
             ];
             Some((Value::Nil, Value::Map(map)))
         }
-        Err(e) => Some((Value::String(e.to_string().into()), Value::Nil)),
+        Err(e) => {
+            let error = format!("{e:#}");
+            event.failed().detail("error", error.as_str()).record();
+            Some((Value::String(error.into()), Value::Nil))
+        }
+    }
+}
+
+/// Resolve an LLM provider from the settings of the peer's user
+fn load_provider(peer: &Peer, name: Option<&str>) -> Result<Provider> {
+    let store = SettingsStore::new()?;
+    Provider::load(&UserSettings::new(&store, peer.user.as_deref()), name)
+}
+
+/// Handle llm_prompt(request_id, prompt, opts?) -> true
+/// Tokens stream back as llm_token/llm_done/llm_error notifications
+async fn handle_llm_prompt(
+    session_id: &str,
+    manager: &Arc<RwLock<AsyncSessionManager>>,
+    peer: &Peer,
+    params: &[Value],
+) -> Option<(Value, Value)> {
+    let request_id = params.first().and_then(|v| v.as_str()).unwrap_or("");
+    if request_id.is_empty() {
+        return Some((Value::String("Missing request id".into()), Value::Nil));
+    }
+
+    let result = async {
+        let prompt = Prompt::from_values(params.get(1).unwrap_or(&Value::Nil), params.get(2))?;
+        let provider = load_provider(peer, prompt.provider.as_deref())?;
+        let mgr = manager.read().await;
+        let session = mgr
+            .get_session(session_id)
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+        llm::start(
+            &mgr.llm(),
+            session_id,
+            request_id,
+            provider,
+            &prompt,
+            session.redraw_tx.clone(),
+        )
+    }
+    .await;
+
+    Some(match result {
+        Ok(()) => (Value::Nil, Value::Boolean(true)),
+        Err(e) => (Value::String(e.to_string().into()), Value::Nil),
+    })
+}
+
+/// Handle llm_cancel(request_id) -> bool (false if not running)
+async fn handle_llm_cancel(
+    session_id: &str,
+    manager: &Arc<RwLock<AsyncSessionManager>>,
+    params: &[Value],
+) -> Option<(Value, Value)> {
+    let request_id = params.first().and_then(|v| v.as_str()).unwrap_or("");
+    let mgr = manager.read().await;
+    let session = mgr.get_session(session_id)?;
+    let cancelled = llm::cancel(&mgr.llm(), session_id, request_id, &session.redraw_tx);
+    Some((Value::Nil, Value::Boolean(cancelled)))
+}
+
+/// Handle llm_list_models(provider?) -> [model, ...]
+async fn handle_llm_list_models(peer: &Peer, params: &[Value]) -> Option<(Value, Value)> {
+    let name = params.first().and_then(|v| v.as_str());
+    let result = async {
+        let provider = load_provider(peer, name)?;
+        provider.list_models().await
+    }
+    .await;
+
+    Some(match result {
+        Ok(models) => (
+            Value::Nil,
+            Value::Array(models.into_iter().map(Value::from).collect()),
+        ),
+        Err(e) => (Value::String(e.to_string().into()), Value::Nil),
+    })
+}
+
+/// Handle llm_set_key(provider, key) -> true; an empty key removes it
+///
+/// Keys belong to the logged-in user and are only used for their prompts.
+fn handle_llm_set_key(peer: &Peer, params: &[Value]) -> Option<(Value, Value)> {
+    let provider = params.first().and_then(|v| v.as_str()).unwrap_or("");
+    let key = params.get(1).and_then(|v| v.as_str()).unwrap_or("");
+    if provider.is_empty() {
+        return Some((Value::String("Missing provider".into()), Value::Nil));
+    }
+
+    let result = SettingsStore::new().and_then(|store| {
+        let store = UserSettings::new(&store, peer.user.as_deref());
+        let setting = llm::setting_key("api_key", provider);
+        if key.is_empty() {
+            store.delete(&setting)
+        } else {
+            store.set(&setting, key)
+        }
+    });
+    Some(match result {
+        Ok(()) => (Value::Nil, Value::Boolean(true)),
+        Err(e) => (Value::String(e.to_string().into()), Value::Nil),
+    })
+}
+
+/// Handle llm_set_provider(name, {base_url?, model?}) -> true
+/// Makes `name` the logged-in user's active provider, optionally configuring it
+fn handle_llm_set_provider(peer: &Peer, params: &[Value]) -> Option<(Value, Value)> {
+    let name = params.first().and_then(|v| v.as_str()).unwrap_or("");
+    if name.is_empty() {
+        return Some((Value::String("Missing provider".into()), Value::Nil));
+    }
+    let opts = params.get(1).and_then(|v| v.as_map());
+
+    let result = SettingsStore::new().and_then(|store| {
+        let store = UserSettings::new(&store, peer.user.as_deref());
+        for (field, value) in opts.into_iter().flatten() {
+            if let (Some(field @ ("base_url" | "model")), Some(value)) =
+                (field.as_str(), value.as_str())
+            {
+                store.set(&llm::setting_key(field, name), value)?;
+            }
+        }
+        // Validate before switching so a typo does not break prompts
+        Provider::load(&store, Some(name))?;
+        store.set(llm::PROVIDER_SETTING, name)
+    });
+    Some(match result {
+        Ok(()) => (Value::Nil, Value::Boolean(true)),
+        Err(e) => (Value::String(e.to_string().into()), Value::Nil),
+    })
+}
+
+/// Handle search(search_id, pattern, opts?) -> true
+///
+/// `opts`: `{path, regex, case_sensitive, max_results, quickfix}`. `path` is
+/// a VFS path or alias and defaults to Neovim's cwd. Matches stream back as
+/// search_results/search_done/search_error notifications.
+async fn handle_search(
+    session_id: &str,
+    manager: &Arc<RwLock<AsyncSessionManager>>,
+    vfs_manager: &Arc<RwLock<VfsManager>>,
+    params: &[Value],
+) -> Option<(Value, Value)> {
+    let search_id = params.first().and_then(|v| v.as_str()).unwrap_or("");
+    let pattern = params.get(1).and_then(|v| v.as_str()).unwrap_or("");
+    let opts = params.get(2);
+    let opt = |key: &str| {
+        opts.and_then(Value::as_map)?
+            .iter()
+            .find(|(k, _)| k.as_str() == Some(key))
+            .map(|(_, v)| v)
+    };
+    if search_id.is_empty() || pattern.is_empty() {
+        return Some((
+            Value::String("Missing search id or pattern".into()),
+            Value::Nil,
+        ));
+    }
+
+    let result = async {
+        let mgr = manager.read().await;
+        let session = mgr
+            .get_session(session_id)
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+        let root_uri = match opt("path").and_then(Value::as_str) {
+            Some(path) => path.to_string(),
+            None => cwd_search_root(session).await?,
+        };
+        let vfs = vfs_manager.read().await;
+        session.check_vfs_path(&vfs, &root_uri).await?;
+        let (backend, root) = vfs.resolve_backend(&root_uri).await?;
+        drop(vfs);
+
+        let options = SearchOptions {
+            case_insensitive: !opt("case_sensitive")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            regex: opt("regex").and_then(Value::as_bool).unwrap_or(false),
+            max_results: opt("max_results")
+                .and_then(Value::as_u64)
+                .and_then(|n| usize::try_from(n).ok())
+                .unwrap_or(search::project::DEFAULT_MAX_RESULTS),
+        };
+        let search = ProjectSearch {
+            backend,
+            root_uri,
+            root,
+            pattern: pattern.to_string(),
+            options,
+            quickfix: opt("quickfix").and_then(Value::as_bool).unwrap_or(false),
+        };
+        search::project::start(
+            &mgr.searches(),
+            manager,
+            session_id,
+            search_id,
+            search,
+            session.redraw_tx.clone(),
+        )
+    }
+    .await;
+
+    Some(match result {
+        Ok(()) => (Value::Nil, Value::Boolean(true)),
+        Err(e) => (Value::String(e.to_string().into()), Value::Nil),
+    })
+}
+
+/// Neovim's cwd as a path on the local backend, which is rooted at $HOME
+async fn cwd_search_root(session: &AsyncSession) -> Result<String> {
+    let cwd = session
+        .rpc_call(
+            "nvim_call_function",
+            vec![Value::String("getcwd".into()), Value::Array(vec![])],
+        )
+        .await?;
+    let cwd = cwd.as_str().unwrap_or_default();
+    let home = std::env::var("HOME").unwrap_or_default();
+    let relative = Path::new(cwd)
+        .strip_prefix(&home)
+        .map_err(|_| anyhow::anyhow!("cwd {cwd} is outside the local backend, pass a path"))?;
+    Ok(format!("vfs://local/{}", relative.display()))
+}
+
+/// Handle search_cancel(search_id) -> bool (false if not running)
+async fn handle_search_cancel(
+    session_id: &str,
+    manager: &Arc<RwLock<AsyncSessionManager>>,
+    params: &[Value],
+) -> Option<(Value, Value)> {
+    let search_id = params.first().and_then(|v| v.as_str()).unwrap_or("");
+    let mgr = manager.read().await;
+    let session = mgr.get_session(session_id)?;
+    let cancelled =
+        search::project::cancel(&mgr.searches(), session_id, search_id, &session.redraw_tx);
+    Some((Value::Nil, Value::Boolean(cancelled)))
+}
+
+/// Handle participant management: collab_*(...) -> state or nil
+///
+/// - `collab_participants()` -> `{self, driver, requests, participants}`
+/// - `collab_request_control()` / `collab_release_control()`
+/// - `collab_grant_control(peer_id)` / `collab_revoke_control()` (owners)
+/// - `collab_set_role(peer_id, role)` (owners)
+async fn handle_collab(
+    session_id: &str,
+    manager: &Arc<RwLock<AsyncSessionManager>>,
+    peer: &Peer,
+    method: &str,
+    params: &[Value],
+) -> Option<(Value, Value)> {
+    let registry = manager.read().await.collaboration();
+    let mut reg = registry.write().await;
+    let Some(viewers) = reg.get_mut(session_id) else {
+        return Some((Value::String("Session not found".into()), Value::Nil));
+    };
+    let target = params.first().and_then(Value::as_str).unwrap_or("");
+    let is_owner = viewers.role(&peer.id) == Some(Role::Owner);
+
+    let result = match method {
+        "collab_participants" => {
+            let str_values =
+                |ids: &[String]| Value::Array(ids.iter().map(|id| id.as_str().into()).collect());
+            return Some((
+                Value::Nil,
+                Value::Map(vec![
+                    ("self".into(), peer.id.as_str().into()),
+                    (
+                        "driver".into(),
+                        viewers.driver().map_or(Value::Nil, Value::from),
+                    ),
+                    ("requests".into(), str_values(viewers.control_requests())),
+                    (
+                        "participants".into(),
+                        Value::Array(
+                            viewers
+                                .list_viewers()
+                                .iter()
+                                .map(|v| Participant::from(v).to_value())
+                                .collect(),
+                        ),
+                    ),
+                ]),
+            ));
+        }
+        "collab_request_control" => viewers.request_control(&peer.id),
+        "collab_release_control" => {
+            return Some((Value::Nil, viewers.release_control(&peer.id).into()));
+        }
+        "collab_grant_control" | "collab_revoke_control" | "collab_set_role" if !is_owner => {
+            Err(anyhow::anyhow!("Only owners can manage participants"))
+        }
+        "collab_grant_control" => viewers.grant_control(target),
+        "collab_revoke_control" => {
+            viewers.revoke_control();
+            Ok(())
+        }
+        "collab_set_role" => params
+            .get(1)
+            .and_then(Value::as_str)
+            .unwrap_or("")
+            .parse()
+            .and_then(|role| viewers.set_role(target, role)),
+        // Never forwarded to Neovim: any role may call collab_*
+        _ => Err(anyhow::anyhow!("Unknown method: {method}")),
+    };
+
+    Some(match result {
+        Ok(()) => (Value::Nil, Value::Boolean(true)),
+        Err(e) => (Value::String(e.to_string().into()), Value::Nil),
+    })
+}
+
+/// Add, edit, resolve or delete a review comment
+///
+/// Only the author may edit a comment; the author or an owner may delete it.
+async fn handle_comment(
+    session_id: &str,
+    manager: &Arc<RwLock<AsyncSessionManager>>,
+    peer: &Peer,
+    msg: BrowserMessage,
+) -> Result<()> {
+    let mgr = manager.read().await;
+    let session = mgr
+        .get_session(session_id)
+        .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+    let registry = mgr.collaboration();
+    match msg {
+        BrowserMessage::CommentAdd {
+            body,
+            parent,
+            target,
+        } => {
+            comments::add(
+                session,
+                &registry,
+                &peer.id,
+                &body,
+                parent.as_deref(),
+                target,
+            )
+            .await?;
+        }
+        BrowserMessage::CommentEdit { id, body } => {
+            comments::edit(session, &registry, &peer.id, &id, &body).await?;
+        }
+        BrowserMessage::CommentResolve { id, resolved } => {
+            comments::resolve(session, &registry, &id, resolved).await?;
+        }
+        BrowserMessage::CommentDelete(id) => {
+            let is_owner = peer.role().await == Role::Owner;
+            comments::delete(session, &registry, &peer.id, &id, is_owner).await?;
+        }
+        _ => {}
     }
+    Ok(())
 }
 
 /// Handle FS response from browser: [3, id, ok, result]
 async fn handle_fs_response(
     fs_registry: Option<&Arc<FsRequestRegistry>>,
-    arr: &[Value],
+    id: u64,
+    ok: bool,
+    result: Value,
 ) -> Result<Option<Vec<u8>>> {
     if let Some(registry) = fs_registry {
-        let id = arr[1].as_u64().unwrap_or(0);
-        let ok = arr[2].as_bool().unwrap_or(false);
-        let result = &arr[3];
-
         if ok {
-            registry.resolve(id, Ok(result.clone())).await;
+            registry.resolve(id, Ok(result)).await;
         } else {
             let err_msg = result.as_str().unwrap_or("Unknown FS error");
             registry
@@ -384,193 +826,165 @@ async fn handle_fs_response(
     Ok(None)
 }
 
-/// Handle notification: [2, method, params]
-async fn handle_notification(
+/// Handle clipboard_read_response notification: [2, method, [req_id, content, session_id]]
+async fn handle_clipboard_response(
     session_id: &str,
     manager: &Arc<RwLock<AsyncSessionManager>>,
-    arr: &[Value],
+    request_id: u32,
+    content: Value,
+    response_session_id: &str,
 ) -> Result<Option<Vec<u8>>> {
-    if let Value::String(method) = &arr[1] {
-        if method.as_str() == Some("clipboard_read_response") {
-            if let Value::Array(params) = &arr[2] {
-                if params.len() >= 3 {
-                    let req_id = u32::try_from(params[0].as_u64().unwrap_or(0)).unwrap_or(0);
-                    let content = &params[1];
-                    let response_session_id = params[2].as_str();
-
-                    if response_session_id != Some(session_id) {
-                        tracing::warn!(expected = %session_id, got = ?response_session_id, "Blocked clipboard response from wrong session");
-                        return Ok(None);
-                    }
+    if response_session_id != session_id {
+        tracing::warn!(expected = %session_id, got = %response_session_id, "Blocked clipboard response from wrong session");
+        return Ok(None);
+    }
 
-                    let mgr = manager.read().await;
-                    if let Some(session) = mgr.get_session(session_id) {
-                        session.complete_request(req_id, content.clone());
-                    }
-                }
-            }
-        }
+    let mgr = manager.read().await;
+    if let Some(session) = mgr.get_session(session_id) {
+        session.complete_request(request_id, content);
     }
     Ok(None)
 }
 
-/// Handle legacy string-based messages: ["input", keys] or ["resize", cols, rows]
-async fn handle_legacy_message(
+/// Merge a browser's edits into a buffer and answer its sync request
+async fn handle_crdt_sync(
     session_id: &str,
     manager: &Arc<RwLock<AsyncSessionManager>>,
-    arr: &[Value],
+    buffer_id: u64,
+    message: SyncMessage,
 ) -> Result<Option<Vec<u8>>> {
-    if let Value::String(method) = &arr[0] {
-        let mgr = manager.read().await;
-        let session = mgr
-            .get_session(session_id)
-            .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+    let mgr = manager.read().await;
+    let session = mgr
+        .get_session(session_id)
+        .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+    let reply = session.crdt.sync(buffer_id, message).await?;
+    Ok(reply.map(|message| HostMessage::CrdtSync { buffer_id, message }.encode()))
+}
 
-        match method.as_str() {
-            Some("input") => {
-                if let Value::String(keys) = &arr[1] {
-                    if let Some(key_str) = keys.as_str() {
-                        session.input(key_str).await?;
-                    }
-                }
-            }
-            Some("resize") => {
-                if arr.len() >= 3 {
-                    if let (Value::Integer(cols), Value::Integer(rows)) = (&arr[1], &arr[2]) {
-                        let cols = cols.as_i64().unwrap_or(80);
-                        let rows = rows.as_i64().unwrap_or(24);
-                        session.resize(cols, rows).await?;
-                    }
-                }
-            }
+/// Handle input messages: keys, paste, resize, mouse, scroll and file drops
+#[allow(clippy::too_many_lines)]
+async fn handle_input_message(
+    session_id: &str,
+    manager: &Arc<RwLock<AsyncSessionManager>>,
+    msg: BrowserMessage,
+) -> Result<Option<Vec<u8>>> {
+    let mgr = manager.read().await;
+    let session = mgr
+        .get_session(session_id)
+        .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+
+    match msg {
+        BrowserMessage::Input(keys) => {
+            session.input(&keys).await?;
+        }
+        BrowserMessage::Paste(text) => {
+            // nvim_paste(data, crlf, phase): -1 pastes in a single call
+            let _ = session
+                .rpc_call(
+                    "nvim_paste",
+                    vec![
+                        Value::String(text.into()),
+                        Value::Boolean(true),
+                        Value::Integer((-1).into()),
+                    ],
+                )
+                .await;
+        }
+        BrowserMessage::Resize { cols, rows } => {
+            session.resize(i64::from(cols), i64::from(rows)).await?;
+        }
+        BrowserMessage::Mouse {
+            button,
+            action,
+            modifier,
+            row,
+            col,
+        } => {
+            // grid=0 for global coordinates
+            input_mouse(session, &button, &action, &modifier, 0, row, col).await;
+        }
+        BrowserMessage::Scroll {
+            direction,
+            modifier,
+            row,
+            col,
+        } => {
+            // button="wheel", action=direction
+            input_mouse(session, "wheel", &direction, &modifier, 0, row, col).await;
+        }
+        BrowserMessage::InputMouse {
+            button,
+            action,
+            modifier,
+            grid,
+            row,
+            col,
+        } => {
+            input_mouse(session, &button, &action, &modifier, grid, row, col).await;
+        }
+        BrowserMessage::FileDrop { name, data } if !data.is_empty() => {
+            // 1. Get CWD from Neovim to save file in correct location
+            let cwd_res = session
+                .rpc_call(
+                    "nvim_call_function",
+                    vec![Value::String("getcwd".into()), Value::Array(vec![])],
+                )
+                .await;
 
-            Some("mouse") => {
-                // ["mouse", button, action, modifier, row, col]
-                if arr.len() >= 6 {
-                    let button = arr[1].as_str().unwrap_or("left");
-                    let action = arr[2].as_str().unwrap_or("press");
-                    let modifier = arr[3].as_str().unwrap_or("");
-                    let row = arr[4].as_i64().unwrap_or(0);
-                    let col = arr[5].as_i64().unwrap_or(0);
-
-                    // grid=0 for global coordinates
-                    let _ = session
-                        .rpc_call(
-                            "nvim_input_mouse",
-                            vec![
-                                Value::String(button.into()),
-                                Value::String(action.into()),
-                                Value::String(modifier.into()),
-                                Value::Integer(0.into()),
-                                Value::Integer(row.into()),
-                                Value::Integer(col.into()),
-                            ],
-                        )
-                        .await;
-                }
-            }
-            Some("scroll") => {
-                // ["scroll", direction, modifier, row, col]
-                if arr.len() >= 5 {
-                    let direction = arr[1].as_str().unwrap_or("up");
-                    let modifier = arr[2].as_str().unwrap_or("");
-                    let row = arr[3].as_i64().unwrap_or(0);
-                    let col = arr[4].as_i64().unwrap_or(0);
-
-                    // button="wheel", action=direction
-                    let _ = session
-                        .rpc_call(
-                            "nvim_input_mouse",
-                            vec![
-                                Value::String("wheel".into()),
-                                Value::String(direction.into()),
-                                Value::String(modifier.into()),
-                                Value::Integer(0.into()),
-                                Value::Integer(row.into()),
-                                Value::Integer(col.into()),
-                            ],
-                        )
-                        .await;
-                }
-            }
-            Some("input_mouse") => {
-                // Multigrid mouse input: ["input_mouse", button, action, modifier, grid, row, col]
-                if arr.len() >= 7 {
-                    let button = arr[1].as_str().unwrap_or("left");
-                    let action = arr[2].as_str().unwrap_or("press");
-                    let modifier = arr[3].as_str().unwrap_or("");
-                    let grid = arr[4].as_i64().unwrap_or(1);
-                    let row = arr[5].as_i64().unwrap_or(0);
-                    let col = arr[6].as_i64().unwrap_or(0);
-
-                    // Call nvim_input_mouse(button, action, modifier, grid, row, col)
-                    let _ = session
-                        .rpc_call(
-                            "nvim_input_mouse",
-                            vec![
-                                Value::String(button.into()),
-                                Value::String(action.into()),
-                                Value::String(modifier.into()),
-                                Value::Integer(grid.into()),
-                                Value::Integer(row.into()),
-                                Value::Integer(col.into()),
-                            ],
-                        )
-                        .await;
-                }
-            }
-            Some("file_drop") => {
-                // ["file_drop", filename, data]
-                if arr.len() >= 3 {
-                    // 1. Parse Args
-                    let filename = arr[1].as_str().unwrap_or("dropped_file");
-                    let data = if let Value::Binary(bytes) = &arr[2] {
-                        bytes.clone()
-                    } else {
-                        vec![]
-                    };
-
-                    if !data.is_empty() {
-                        // 2. Get CWD from Neovim to save file in correct location
-                        let cwd_res = session
-                            .rpc_call(
-                                "nvim_call_function",
-                                vec![Value::String("getcwd".into()), Value::Array(vec![])],
-                            )
-                            .await;
-
-                        let cwd = cwd_res
-                            .ok()
-                            .and_then(|v| v.as_str().map(ToString::to_string))
-                            .unwrap_or_else(|| ".".to_string());
-
-                        let path = std::path::Path::new(&cwd).join(filename);
-
-                        // 3. Write file to disk
-                        // Note: Using standard fs for simplicity, but in async context tokio::fs is better
-                        // multithreaded runtime makes this acceptable for small files
-                        if let Err(e) = std::fs::write(&path, &data) {
-                            eprintln!("Failed to write dropped file: {e}");
-                        } else {
-                            eprintln!("Saved dropped file to: {}", path.display());
-
-                            // 4. Open file in Neovim
-                            let _ = session
-                                .rpc_call(
-                                    "nvim_command",
-                                    vec![Value::String(format!("edit {}", path.display()).into())],
-                                )
-                                .await;
-                        }
-                    }
-                }
+            let cwd = cwd_res
+                .ok()
+                .and_then(|v| v.as_str().map(ToString::to_string))
+                .unwrap_or_else(|| ".".to_string());
+
+            let path = std::path::Path::new(&cwd).join(name);
+
+            // 2. Write file to disk
+            // Note: Using standard fs for simplicity, but in async context tokio::fs is better
+            // multithreaded runtime makes this acceptable for small files
+            if let Err(e) = std::fs::write(&path, &data) {
+                eprintln!("Failed to write dropped file: {e}");
+            } else {
+                eprintln!("Saved dropped file to: {}", path.display());
+
+                // 3. Open file in Neovim
+                let _ = session
+                    .rpc_call(
+                        "nvim_command",
+                        vec![Value::String(format!("edit {}", path.display()).into())],
+                    )
+                    .await;
             }
-            _ => {}
         }
+        _ => {}
     }
     Ok(None)
 }
 
+/// Call nvim_input_mouse(button, action, modifier, grid, row, col)
+async fn input_mouse(
+    session: &AsyncSession,
+    button: &str,
+    action: &str,
+    modifier: &str,
+    grid: i64,
+    row: i64,
+    col: i64,
+) {
+    let _ = session
+        .rpc_call(
+            "nvim_input_mouse",
+            vec![
+                Value::String(button.into()),
+                Value::String(action.into()),
+                Value::String(modifier.into()),
+                Value::Integer(grid.into()),
+                Value::Integer(row.into()),
+                Value::Integer(col.into()),
+            ],
+        )
+        .await;
+}
+
 // ============================================================================
 // Terminal PTY message handlers
 // ============================================================================
@@ -587,83 +1001,51 @@ static TERMINAL_MANAGER: Lazy<StdMutex<TerminalManager>> =
 /// Returns optional output message to send back, plus spawns output streaming task
 pub async fn handle_terminal_message(
     session_id: &str,
-    arr: &[Value],
+    msg: BrowserMessage,
     output_tx: Option<tokio::sync::mpsc::Sender<Vec<u8>>>,
 ) -> Result<Option<Vec<u8>>> {
-    if let Value::String(method) = &arr[0] {
-        match method.as_str() {
-            Some("terminal_spawn") => {
-                // ["terminal_spawn", cols, rows]
-                let cols = arr.get(1).and_then(Value::as_u64).unwrap_or(80) as u16;
-                let rows = arr.get(2).and_then(Value::as_u64).unwrap_or(24) as u16;
-
-                let result = {
-                    let mut mgr = TERMINAL_MANAGER.lock().unwrap();
-                    mgr.create_session(session_id, cols, rows)
-                };
-
-                match result {
-                    Ok(()) => {
-                        // Start streaming output to browser
-                        if let Some(tx) = output_tx {
-                            let sid = session_id.to_string();
-                            tokio::spawn(async move {
-                                stream_terminal_output(&sid, tx).await;
-                            });
-                        }
-
-                        // Send success response
-                        let response = Value::Array(vec![
-                            Value::String("terminal_spawned".into()),
-                            Value::Boolean(true),
-                        ]);
-                        let mut bytes = Vec::new();
-                        rmpv::encode::write_value(&mut bytes, &response)?;
-                        return Ok(Some(bytes));
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to spawn terminal: {}", e);
-                        let response = Value::Array(vec![
-                            Value::String("terminal_spawned".into()),
-                            Value::Boolean(false),
-                            Value::String(e.to_string().into()),
-                        ]);
-                        let mut bytes = Vec::new();
-                        rmpv::encode::write_value(&mut bytes, &response)?;
-                        return Ok(Some(bytes));
+    match msg {
+        BrowserMessage::TerminalSpawn { cols, rows } => {
+            let result = {
+                let mut mgr = TERMINAL_MANAGER.lock().unwrap();
+                mgr.create_session(session_id, cols, rows)
+            };
+
+            let error = match result {
+                Ok(()) => {
+                    // Start streaming output to browser
+                    if let Some(tx) = output_tx {
+                        let sid = session_id.to_string();
+                        tokio::spawn(async move {
+                            stream_terminal_output(&sid, tx).await;
+                        });
                     }
+                    None
                 }
-            }
-            Some("terminal_input") => {
-                // [\"terminal_input\", data_bytes]
-                let session_arc = {
-                    let mgr = TERMINAL_MANAGER.lock().unwrap();
-                    mgr.get_session(session_id)
-                };
-
-                if let Some(session) = session_arc {
-                    let data_vec = if let Some(Value::Binary(data)) = arr.get(1) {
-                        Some(data.clone())
-                    } else if let Some(Value::String(s)) = arr.get(1) {
-                        s.as_str().map(|t| t.as_bytes().to_vec())
-                    } else {
-                        None
-                    };
-
-                    if let Some(data) = data_vec {
-                        let sess = session.lock().await;
-                        let _ = sess.send_input(data).await;
-                    }
+                Err(e) => {
+                    tracing::error!("Failed to spawn terminal: {}", e);
+                    Some(e.to_string())
                 }
+            };
+            return Ok(Some(HostMessage::TerminalSpawned { error }.encode()));
+        }
+        BrowserMessage::TerminalInput(data) => {
+            let session_arc = {
+                let mgr = TERMINAL_MANAGER.lock().unwrap();
+                mgr.get_session(session_id)
+            };
+
+            if let Some(session) = session_arc {
+                let sess = session.lock().await;
+                let _ = sess.send_input(data).await;
             }
-            Some("terminal_close") => {
-                // ["terminal_close"]
-                let mut mgr = TERMINAL_MANAGER.lock().unwrap();
-                mgr.remove_session(session_id);
-                tracing::info!("Terminal closed for session: {}", session_id);
-            }
-            _ => {}
         }
+        BrowserMessage::TerminalClose => {
+            let mut mgr = TERMINAL_MANAGER.lock().unwrap();
+            mgr.remove_session(session_id);
+            tracing::info!("Terminal closed for session: {}", session_id);
+        }
+        _ => {}
     }
     Ok(None)
 }
@@ -684,16 +1066,9 @@ async fn stream_terminal_output(session_id: &str, tx: tokio::sync::mpsc::Sender<
         if let Some(mut rx) = sess.take_output_rx() {
             drop(sess);
             while let Some(data) = rx.recv().await {
-                // Send as ["terminal_output", binary_data]
-                let msg = Value::Array(vec![
-                    Value::String("terminal_output".into()),
-                    Value::Binary(data),
-                ]);
-                let mut bytes = Vec::new();
-                if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-                    if tx.send(bytes).await.is_err() {
-                        break;
-                    }
+                let bytes = HostMessage::TerminalOutput(data).encode();
+                if tx.send(bytes).await.is_err() {
+                    break;
                 }
             }
         }
@@ -3,6 +3,7 @@
 //! Manages individual WebSocket connections including handshake,
 //! session management, and bidirectional message bridging.
 
+use std::net::IpAddr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -16,12 +17,21 @@ use tokio_tungstenite::tungstenite::{
     Message,
 };
 
-use crate::session::AsyncSessionManager;
+use crate::audit::{self, AuditAction, AuditEvent};
+use crate::collaboration::Role;
+use crate::launch::LaunchRequest;
+use crate::oidc::{Grant, OidcClient};
+use crate::session::{AsyncSessionManager, SessionAccess};
+use crate::transport::{login_required, LoginWatch, Outgoing, Peer};
 use crate::vfs::{FsRequestRegistry, VfsManager};
+use nvim_web_protocol::datagram::Datagram;
+use nvim_web_protocol::schema::{self, HostMessage, ProtocolError, PROTOCOL_VERSION};
 
 use super::commands::handle_browser_message;
 use super::protocol::{
-    parse_context_from_uri, parse_session_id_from_uri, parse_view_id_from_uri, validate_origin,
+    parse_context_from_uri, parse_profile_from_uri, parse_project_from_uri,
+    parse_protocol_version_from_uri, parse_session_id_from_uri, parse_ticket_from_uri,
+    parse_view_id_from_uri, validate_origin,
 };
 use super::rate_limit::RateLimiter;
 
@@ -34,17 +44,187 @@ pub struct ConnectionInfo {
     pub origin_valid: bool,
     pub is_viewer: bool,
     pub context: Option<String>,
+    /// Protocol version requested via `?protocol=`, `None` for legacy clients
+    pub protocol_version: Option<u32>,
+    /// Login handshake ticket from `?ticket=`, for clients that send no cookie
+    pub ticket: Option<String>,
+    /// Logged-in user and their role, `None` when login is not enforced
+    pub user: Option<Grant>,
+    /// Client address, behind trusted proxies the forwarded one
+    pub client_ip: Option<IpAddr>,
+    /// Launch profile and project from `?profile=` and `?project=`, for new sessions
+    pub launch: LaunchRequest,
+}
+
+impl ConnectionInfo {
+    /// Build connection info from the request URI and `Origin` header
+    ///
+    /// Shared by the WebSocket and WebTransport handshakes.
+    pub fn from_request(uri: &str, origin: Option<&str>) -> Self {
+        let mut info = Self::default();
+
+        // Check for viewer mode first (?view=session_id)
+        if let Some(view_id) = parse_view_id_from_uri(uri) {
+            info.view_session_id = Some(view_id);
+            info.is_viewer = true;
+        } else {
+            info.session_id = parse_session_id_from_uri(uri);
+        }
+
+        // Extract context (URL)
+        info.context = parse_context_from_uri(uri);
+        info.protocol_version = parse_protocol_version_from_uri(uri);
+        info.ticket = parse_ticket_from_uri(uri);
+        info.launch = LaunchRequest {
+            profile: parse_profile_from_uri(uri),
+            project: parse_project_from_uri(uri).map(Into::into),
+        };
+
+        // Extract and validate origin
+        if let Some(origin) = origin {
+            info.origin = Some(origin.to_string());
+            info.origin_valid = validate_origin(origin);
+        } else {
+            // No origin header = same-origin request (OK)
+            info.origin_valid = true;
+        }
+
+        info
+    }
+
+    /// Check the client's protocol version against this host
+    ///
+    /// Clients that predate versioning send no version and are accepted;
+    /// they ignore the extra handshake field.
+    pub fn check_protocol(&self) -> Result<(), ProtocolError> {
+        match self.protocol_version {
+            Some(version) => schema::check_version(version),
+            None => {
+                tracing::warn!("Client did not announce a protocol version");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Attach a connection to a Neovim session
+///
+/// Viewers join an existing session with the viewer role. Regular clients
+/// reconnect to `?session=<id>` when it is still alive, resume it when it
+/// ran before the host restarted, otherwise a new session is created for
+/// `info.user`. With login enforced, only sessions
+/// the user may access are joined: the owner's own, or ones shared with them,
+/// where read-only guests and viewer-only users become viewers. Others are
+/// treated as if they did not exist. New sessions are subject to the limits
+/// of the user's role. Returns `(session_id, role)`.
+pub(crate) async fn attach_session(
+    manager: &Arc<RwLock<AsyncSessionManager>>,
+    info: &ConnectionInfo,
+) -> Result<(String, Role)> {
+    let attached = join_session(manager, info).await;
+    let user = info.user.as_ref().map(|grant| grant.sub.as_str());
+    let event = AuditEvent::new(AuditAction::SessionAttached)
+        .user(user)
+        .ip(info.client_ip);
+    match &attached {
+        Ok((session_id, role)) => event.session(session_id).detail("role", role.to_string()),
+        Err(e) => {
+            let requested = info.view_session_id.as_ref().or(info.session_id.as_ref());
+            let event = event.failed().detail("error", e.to_string());
+            match requested {
+                Some(session_id) => event.session(session_id),
+                None => event,
+            }
+        }
+    }
+    .record();
+    attached
+}
+
+async fn join_session(
+    manager: &Arc<RwLock<AsyncSessionManager>>,
+    info: &ConnectionInfo,
+) -> Result<(String, Role)> {
+    let user = info.user.as_ref().map(|grant| grant.sub.as_str());
+    let can_edit = info.user.as_ref().is_none_or(|grant| grant.role.can_edit());
+    if info.is_viewer {
+        // Viewer mode: join existing session in read-only mode
+        let view_id = info.view_session_id.clone().unwrap_or_default();
+        let mgr = manager.read().await;
+        let session = mgr
+            .get_session(&view_id)
+            .filter(|session| session.acl.access(user) != SessionAccess::Denied);
+        let Some(session) = session else {
+            tracing::warn!("Viewer requested non-existent session");
+            return Err(anyhow::anyhow!("Session not found for viewing"));
+        };
+        // Request redraw to sync viewer
+        let _ = session.request_redraw().await;
+        return Ok((view_id, Role::Viewer));
+    }
+
+    // Regular mode: get or create session
+    let mut mgr = manager.write().await;
+
+    // Try to reconnect to existing session
+    if let Some(ref existing_id) = info.session_id {
+        let role = match mgr.access(existing_id, user) {
+            Some(SessionAccess::Owner | SessionAccess::Guest) if !can_edit => Some(Role::Viewer),
+            Some(SessionAccess::Owner) => Some(Role::Owner),
+            Some(SessionAccess::Guest) => Some(Role::Editor),
+            Some(SessionAccess::Viewer) => Some(Role::Viewer),
+            Some(SessionAccess::Denied) | None => None,
+        };
+        if let (Some(role), Some(session)) = (role, mgr.get_session_mut(existing_id)) {
+            session.connected = true;
+            session.touch();
+            // Request redraw to sync UI state
+            let _ = session.request_redraw().await;
+            mgr.touch_record(existing_id);
+            return Ok((existing_id.clone(), role));
+        }
+
+        // Not running anymore: bring it back if it ran before a restart
+        if !mgr.has_session(existing_id) {
+            match mgr.resume_session(existing_id, info.user.as_ref()).await {
+                Ok(Some(session_id)) => {
+                    if let Some(session) = mgr.get_session_mut(&session_id) {
+                        session.connected = true;
+                    }
+                    return Ok((session_id, Role::Owner));
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!(error = %e, "Failed to resume session"),
+            }
+        }
+    }
+
+    let session_id = create_new_session(
+        &mut mgr,
+        info.context.clone(),
+        info.user.as_ref(),
+        &info.launch,
+    )
+    .await?;
+    Ok((session_id, Role::Owner))
 }
 
 /// Handle a single WebSocket connection
+///
+/// With `auth` configured the handshake must carry a valid login, from the
+/// session cookie or a `?ticket=`; `peer_addr` is checked against the access
+/// policy.
 #[allow(clippy::too_many_lines)]
 #[allow(clippy::significant_drop_tightening)]
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_connection<S>(
     stream: S,
+    peer_addr: Option<IpAddr>,
     manager: Arc<RwLock<AsyncSessionManager>>,
     fs_registry: Option<Arc<FsRequestRegistry>>,
     vfs_manager: Option<Arc<RwLock<VfsManager>>>,
     fs_request_tx: Option<broadcast::Sender<Vec<u8>>>,
+    auth: Option<Arc<OidcClient>>,
 ) -> Result<()>
 where
     S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
@@ -52,6 +232,8 @@ where
     // Capture connection info during handshake
     let conn_info = Arc::new(std::sync::Mutex::new(ConnectionInfo::default()));
     let conn_info_clone = conn_info.clone();
+    let headers = Arc::new(std::sync::Mutex::new(http::HeaderMap::new()));
+    let headers_clone = headers.clone();
 
     // Accept WebSocket with header callback
     let callback = move |req: &Request,
@@ -59,30 +241,14 @@ where
           -> std::result::Result<Response, http::Response<Option<String>>> {
         let mut info = conn_info_clone.lock().unwrap();
 
-        // Extract session ID or view ID from URI
-        let uri = req.uri().to_string();
-
-        // Check for viewer mode first (?view=session_id)
-        if let Some(view_id) = parse_view_id_from_uri(&uri) {
-            info.view_session_id = Some(view_id);
-            info.is_viewer = true;
-        } else {
-            info.session_id = parse_session_id_from_uri(&uri);
-        }
-
-        // Extract context (URL)
-        info.context = parse_context_from_uri(&uri);
-
-        // Extract and validate origin
-        if let Some(origin) = req.headers().get("origin") {
-            if let Ok(origin_str) = origin.to_str() {
-                info.origin = Some(origin_str.to_string());
-                info.origin_valid = validate_origin(origin_str);
-            }
-        } else {
-            // No origin header = same-origin request (OK)
-            info.origin_valid = true;
-        }
+        // A non-ASCII origin maps to "" so it fails validation
+        let origin = req
+            .headers()
+            .get("origin")
+            .map(|o| o.to_str().unwrap_or_default());
+        *info = ConnectionInfo::from_request(&req.uri().to_string(), origin);
+        // Cookies and forwarded addresses, checked once the socket is up
+        *headers_clone.lock().unwrap() = req.headers().clone();
 
         Ok(response)
     };
@@ -91,7 +257,7 @@ where
     let (mut ws_tx, mut ws_rx) = ws.split();
 
     // Check origin validation result
-    let info = conn_info.lock().unwrap().clone();
+    let mut info = conn_info.lock().unwrap().clone();
     if !info.origin_valid {
         tracing::warn!(
             origin = ?info.origin,
@@ -101,64 +267,58 @@ where
         return Err(anyhow::anyhow!("Invalid origin"));
     }
 
-    // Handle viewer mode or regular session
-    let (session_id, is_viewer) = if info.is_viewer {
-        // Viewer mode: join existing session in read-only mode
-        let view_id = info.view_session_id.unwrap();
-        let mgr = manager.read().await;
-        if mgr.has_session(&view_id) {
-            // Request redraw to sync viewer
-            if let Some(session) = mgr.get_session(&view_id) {
-                let _ = session.request_redraw().await;
+    // Refuse clients that are not logged in, telling them where to log in
+    let headers = std::mem::take(&mut *headers.lock().unwrap());
+    info.client_ip = audit::client_ip(auth.as_deref(), peer_addr, &headers);
+    let mut login =
+        match LoginWatch::authorize(auth.as_ref(), &headers, info.ticket.as_deref(), peer_addr)
+            .await
+        {
+            Ok((user, login)) => {
+                info.user = user;
+                login
             }
-            (view_id, true)
-        } else {
-            tracing::warn!("Viewer requested non-existent session");
-            let _ = ws_tx.close().await;
-            return Err(anyhow::anyhow!("Session not found for viewing"));
-        }
-    } else {
-        // Regular mode: get or create session
-        let session_id = {
-            let mut mgr = manager.write().await;
-
-            // Try to reconnect to existing session
-            if let Some(ref existing_id) = info.session_id {
-                if mgr.has_session(existing_id) {
-                    if let Some(session) = mgr.get_session_mut(existing_id) {
-                        session.connected = true;
-                        session.touch();
-                        // Restore session state (cursor, buffers, undo)
-                        let _ = session.restore_session().await;
-                        // Request redraw to sync UI state
-                        let _ = session.request_redraw().await;
-                    }
-                    existing_id.clone()
-                } else {
-                    create_new_session(&mut mgr, info.context.clone()).await?
-                }
-            } else {
-                create_new_session(&mut mgr, info.context.clone()).await?
+            Err(status) => {
+                tracing::warn!(status = %status, "Rejected connection without a valid login");
+                let _ = ws_tx.send(Message::Binary(login_required().encode())).await;
+                let _ = ws_tx.close().await;
+                return Err(anyhow::anyhow!("Not logged in"));
             }
         };
-        (session_id, false)
+
+    // Refuse incompatible UI builds before touching any session
+    if let Err(e) = info.check_protocol() {
+        tracing::warn!(error = %e, "Rejected client with incompatible protocol version");
+        let mismatch = HostMessage::protocol_mismatch().encode();
+        let _ = ws_tx.send(Message::Binary(mismatch)).await;
+        let _ = ws_tx.close().await;
+        return Err(e.into());
+    }
+
+    // Handle viewer mode or regular session
+    let (session_id, role) = match attach_session(&manager, &info).await {
+        Ok(attached) => attached,
+        Err(e) => {
+            let _ = ws_tx.close().await;
+            return Err(e);
+        }
     };
 
+    let is_viewer = role == Role::Viewer;
     tracing::info!(
         session_id = %session_id,
         is_viewer = is_viewer,
+        user = ?info.user.as_ref().map(|grant| &grant.sub),
         "Session connected"
     );
 
-    // Send session ID and viewer status to client
-    let session_msg = Value::Array(vec![
-        Value::String("session".into()),
-        Value::String(session_id.clone().into()),
-        Value::Boolean(is_viewer),
-    ]);
-    let mut bytes = Vec::new();
-    rmpv::encode::write_value(&mut bytes, &session_msg)?;
-    ws_tx.send(Message::Binary(bytes)).await?;
+    // Send session ID, viewer status and protocol version to client
+    let session_msg = HostMessage::Session {
+        session_id: session_id.clone(),
+        is_viewer,
+        protocol_version: PROTOCOL_VERSION,
+    };
+    ws_tx.send(Message::Binary(session_msg.encode())).await?;
 
     // Get redraw receiver
     let mut redraw_rx = {
@@ -244,6 +404,39 @@ where
         None
     };
 
+    // Join the session as a collaboration peer and relay what other
+    // participants do, starting with who is already here and the comments
+    let (mut peer, mut collab_rx) = Peer::join(&manager, &session_id, role).await;
+    peer.user = info.user.as_ref().map(|grant| grant.sub.clone());
+    peer.ip = info.client_ip;
+    let peer = Arc::new(peer);
+    let collab_peer = peer.clone();
+    let ws_tx_collab = ws_tx.clone();
+    let collab_manager = manager.clone();
+    let collab_handle = tokio::spawn(async move {
+        let mut initial = vec![
+            Outgoing::Message(collab_peer.presence().await),
+            Outgoing::Message(collab_peer.comments(&collab_manager).await),
+        ]
+        .into_iter();
+        let mut next = initial.next();
+        while let Some(outgoing) = next {
+            let bytes = match outgoing {
+                Outgoing::Datagram(datagram) => datagram.to_envelope(),
+                Outgoing::Message(message) => message.encode(),
+            };
+            let mut tx = ws_tx_collab.lock().await;
+            if tx.send(Message::Binary(bytes)).await.is_err() {
+                break;
+            }
+            drop(tx);
+            next = match initial.next() {
+                Some(outgoing) => Some(outgoing),
+                None => collab_peer.next_outgoing(&mut collab_rx).await,
+            };
+        }
+    });
+
     // Rate limiter: 1000 burst, 100/sec sustained
     let mut rate_limiter = RateLimiter::default_ws();
 
@@ -294,6 +487,14 @@ where
                 }
             }
 
+            // Login expired or restored
+            message = login.next_message() => {
+                let mut tx = ws_tx.lock().await;
+                if tx.send(Message::Binary(message.encode())).await.is_err() {
+                    break;
+                }
+            }
+
             // WebSocket message
             msg = ws_rx.next() => {
                 match msg {
@@ -301,8 +502,20 @@ where
                         // Update activity timestamp
                         last_activity = Instant::now();
 
-                        // Viewers can only receive, not send input
-                        if is_viewer {
+                        // Nothing reaches the session until the user logs in again
+                        if login.is_expired() {
+                            continue;
+                        }
+
+                        // Datagram fallback envelope (cursor, input, heartbeat)
+                        if let Some(datagram) = Datagram::from_envelope(&data) {
+                            let (Ok(datagram), true) = (datagram, rate_limiter.try_consume()) else {
+                                continue;
+                            };
+                            if let Ok(Some(reply)) = peer.handle(&manager_clone, datagram).await {
+                                let mut tx = ws_tx.lock().await;
+                                let _ = tx.send(Message::Binary(reply.to_envelope())).await;
+                            }
                             continue;
                         }
 
@@ -318,6 +531,7 @@ where
                         match handle_browser_message(
                             &session_id_clone,
                             &manager_clone,
+                            &peer,
                             fs_registry.as_ref(),
                             vfs_manager.as_ref(),
                             data
@@ -365,6 +579,8 @@ where
 
     // Clean up sender tasks
     sender_handle.abort();
+    collab_handle.abort();
+    peer.leave().await;
     if let Some(handle) = fs_sender_handle {
         handle.abort();
     }
@@ -376,6 +592,7 @@ where
             session.connected = false;
             session.touch();
         }
+        mgr.touch_record(&session_id);
     }
 
     tracing::info!(session_id = %session_id, "Client disconnected");
@@ -386,8 +603,10 @@ where
 pub async fn create_new_session(
     mgr: &mut AsyncSessionManager,
     context: Option<String>,
+    owner: Option<&Grant>,
+    launch: &LaunchRequest,
 ) -> Result<String> {
-    match mgr.create_session(context).await {
+    match mgr.create_session(context, owner, launch).await {
         Ok(id) => {
             if let Some(session) = mgr.get_session_mut(&id) {
                 session.connected = true;
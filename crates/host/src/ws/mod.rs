@@ -21,6 +21,7 @@ use anyhow::Result;
 use tokio::net::TcpListener;
 use tokio::sync::RwLock;
 
+use crate::oidc::OidcClient;
 use crate::session::AsyncSessionManager;
 use crate::vfs::{FsRequestRegistry, VfsManager};
 
@@ -28,6 +29,10 @@ use crate::vfs::{FsRequestRegistry, VfsManager};
 pub use connection::ConnectionInfo;
 pub use protocol::ALLOWED_ORIGINS;
 
+// Shared with the WebTransport server
+pub(crate) use commands::handle_browser_message;
+pub(crate) use connection::attach_session;
+
 /// Main async WebSocket server
 ///
 /// # Arguments
@@ -36,6 +41,7 @@ pub use protocol::ALLOWED_ORIGINS;
 /// * `fs_registry` - Optional `FsRequestRegistry` for `BrowserFs` support
 /// * `vfs_manager` - Optional `VfsManager` for VFS operations
 /// * `fs_request_tx` - Optional `broadcast::Sender` for forwarding VFS requests
+/// * `auth` - OIDC client; when set, connections must be logged in
 pub async fn serve_multi_async(
     session_manager: Arc<RwLock<AsyncSessionManager>>,
     port: u16,
@@ -43,6 +49,7 @@ pub async fn serve_multi_async(
     vfs_manager: Option<Arc<RwLock<VfsManager>>>,
     fs_request_tx: Option<tokio::sync::broadcast::Sender<Vec<u8>>>,
     tls_config: Option<Arc<tokio_rustls::rustls::ServerConfig>>,
+    auth: Option<Arc<OidcClient>>,
 ) -> Result<()> {
     let addr = format!("127.0.0.1:{port}");
     let listener = TcpListener::bind(&addr).await?;
@@ -65,19 +72,21 @@ pub async fn serve_multi_async(
 
     loop {
         match listener.accept().await {
-            Ok((stream, _addr)) => {
+            Ok((stream, addr)) => {
                 let manager = session_manager.clone();
                 let registry = fs_registry.clone();
                 let vfs = vfs_manager.clone();
                 let request_tx = fs_request_tx.clone();
                 let acceptor = acceptor.clone();
+                let auth = auth.clone();
+                let peer = Some(addr.ip());
 
                 tokio::spawn(async move {
                     if let Some(acceptor) = acceptor {
                         match acceptor.accept(stream).await {
                             Ok(tls_stream) => {
                                 if let Err(e) = connection::handle_connection(
-                                    tls_stream, manager, registry, vfs, request_tx,
+                                    tls_stream, peer, manager, registry, vfs, request_tx, auth,
                                 )
                                 .await
                                 {
@@ -90,7 +99,7 @@ pub async fn serve_multi_async(
                         }
                     } else {
                         if let Err(e) = connection::handle_connection(
-                            stream, manager, registry, vfs, request_tx,
+                            stream, peer, manager, registry, vfs, request_tx, auth,
                         )
                         .await
                         {
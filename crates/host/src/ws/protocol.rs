@@ -2,6 +2,7 @@
 //!
 //! Handles URI parsing and origin validation.
 
+use nvim_web_protocol::schema::PROTOCOL_QUERY_PARAM;
 use url::Url;
 
 /// Allowed origins for WebSocket connections
@@ -52,6 +53,82 @@ pub fn parse_view_id_from_uri(uri: &str) -> Option<String> {
     None
 }
 
+/// Parse a login handshake ticket from URI query string
+/// Format: /?ticket=<ticket>
+pub fn parse_ticket_from_uri(uri: &str) -> Option<String> {
+    if let Some(query_start) = uri.find('?') {
+        let query = &uri[query_start + 1..];
+        for param in query.split('&') {
+            if let Some(eq_pos) = param.find('=') {
+                let key = &param[..eq_pos];
+                let value = &param[eq_pos + 1..];
+                if key == "ticket" && !value.is_empty() {
+                    return Some(value.to_string());
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Parse the browser's protocol version from URI query string
+/// Format: /?protocol=<version>
+pub fn parse_protocol_version_from_uri(uri: &str) -> Option<u32> {
+    if let Some(query_start) = uri.find('?') {
+        let query = &uri[query_start + 1..];
+        for param in query.split('&') {
+            if let Some(eq_pos) = param.find('=') {
+                let key = &param[..eq_pos];
+                let value = &param[eq_pos + 1..];
+                if key == PROTOCOL_QUERY_PARAM {
+                    // An unparseable version is treated as version 0 so it fails negotiation
+                    return Some(value.parse().unwrap_or(0));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Parse the launch profile from URI query string
+/// Format: /?profile=<name>
+pub fn parse_profile_from_uri(uri: &str) -> Option<String> {
+    if let Some(query_start) = uri.find('?') {
+        let query = &uri[query_start + 1..];
+        for param in query.split('&') {
+            if let Some(eq_pos) = param.find('=') {
+                let key = &param[..eq_pos];
+                let value = &param[eq_pos + 1..];
+                if key == "profile" && !value.is_empty() {
+                    return Some(value.to_string());
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Parse the project directory from URI query string
+/// Format: /?project=<url_encoded_path>
+pub fn parse_project_from_uri(uri: &str) -> Option<String> {
+    if let Some(query_start) = uri.find('?') {
+        let query = &uri[query_start + 1..];
+        for param in query.split('&') {
+            if let Some(eq_pos) = param.find('=') {
+                let key = &param[..eq_pos];
+                let value = &param[eq_pos + 1..];
+                if key == "project" && !value.is_empty() {
+                    return percent_encoding::percent_decode_str(value)
+                        .decode_utf8()
+                        .ok()
+                        .map(|s| s.into_owned());
+                }
+            }
+        }
+    }
+    None
+}
+
 /// Parse context URL from URI query string
 /// Format: /?context=<url_encoded_url>
 pub fn parse_context_from_uri(uri: &str) -> Option<String> {
@@ -170,6 +247,41 @@ mod tests {
         );
         assert_eq!(parse_context_from_uri("/?foo=bar"), None);
     }
+
+    #[test]
+    fn test_parse_protocol_version() {
+        assert_eq!(
+            parse_protocol_version_from_uri("/?session=abc&protocol=3"),
+            Some(3)
+        );
+        assert_eq!(parse_protocol_version_from_uri("/?protocol=x"), Some(0));
+        assert_eq!(parse_protocol_version_from_uri("/?session=abc"), None);
+    }
+
+    #[test]
+    fn test_parse_ticket() {
+        assert_eq!(
+            parse_ticket_from_uri("/?session=abc&ticket=k1.x.y"),
+            Some("k1.x.y".to_string())
+        );
+        assert_eq!(parse_ticket_from_uri("/?ticket="), None);
+        assert_eq!(parse_ticket_from_uri("/?session=abc"), None);
+    }
+
+    #[test]
+    fn test_parse_launch() {
+        assert_eq!(
+            parse_profile_from_uri("/?session=new&profile=team"),
+            Some("team".to_string())
+        );
+        assert_eq!(parse_profile_from_uri("/?profile="), None);
+        assert_eq!(
+            parse_project_from_uri("/?project=%2Fsrv%2Fmy%20repo"),
+            Some("/srv/my repo".to_string())
+        );
+        assert_eq!(parse_project_from_uri("/?session=abc"), None);
+    }
+
     #[cfg(test)]
     mod fuzz_tests {
         use super::*;
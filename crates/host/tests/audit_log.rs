@@ -0,0 +1,115 @@
+//! Audit log of the REST API
+//!
+//! SSH requests are recorded with the logged-in user, and only admins may
+//! read the log back.
+
+mod common;
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::Router;
+use common::identity_provider::{nonce_of, IdentityProvider, CLIENT_ID};
+use nvim_web_host::api::{self, AppState};
+use nvim_web_host::audit;
+use nvim_web_host::config::AuditConfig;
+use nvim_web_host::oidc::{AuthConfig, AuthSession, OidcClient};
+use nvim_web_host::session::AsyncSessionManager;
+use nvim_web_vfs::VfsManager;
+use serde_json::Value;
+use tokio::sync::RwLock;
+use tower::ServiceExt;
+
+async fn login(client: &OidcClient) -> AuthSession {
+    let (url, state) = client.authorize_url().await;
+    client.exchange_code(&nonce_of(&url), &state).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_ssh_requests_audited_for_admins() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit.jsonl");
+    audit::init(&AuditConfig {
+        enabled: true,
+        path: Some(path.display().to_string()),
+        syslog: None,
+    })
+    .unwrap();
+
+    let provider = IdentityProvider::start().await;
+    let mut config = AuthConfig {
+        enabled: true,
+        issuer: provider.issuer.clone(),
+        client_id: CLIENT_ID.to_string(),
+        redirect_uri: "http://localhost:8080/auth/callback".to_string(),
+        roles: toml::from_str("[groups]\nops = \"admin\"\n").unwrap(),
+        ..Default::default()
+    };
+    config.session.signing_keys = vec!["session-key-for-tests-0123456789abcdef".to_string()];
+    let auth = Arc::new(OidcClient::new(config).await.unwrap());
+
+    let vfs_manager = Arc::new(RwLock::new(VfsManager::new()));
+    let state = AppState {
+        session_manager: Arc::new(RwLock::new(AsyncSessionManager::new(vfs_manager))),
+        ws_port: 0,
+        auth: Some(auth.clone()),
+    };
+    let app = Router::new()
+        .nest("/api", api::api_router(state.clone()))
+        .with_state(state);
+    let send = |request: axum::http::request::Builder, session: &AuthSession, body: &str| {
+        let cookie = format!("nvim_web_session={}", auth.sessions().encode(session));
+        let request = request
+            .header(header::COOKIE, cookie)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        app.clone().oneshot(request)
+    };
+
+    // Nothing listens on port 1, so the test fails, and is recorded as such
+    let developer = login(&auth).await;
+    let ssh = r#"{"host":"127.0.0.1","port":1,"user":"deploy","password":"hunter2"}"#;
+    let response = send(Request::post("/api/ssh/test"), &developer, ssh)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = send(Request::post("/api/ssh/disconnect"), &developer, "")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    audit::flush();
+    let logged = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(logged.lines().count(), 2);
+    assert!(!logged.contains("hunter2"));
+
+    // Developers may not read the log
+    let response = send(Request::get("/api/audit"), &developer, "")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    provider.set_claim("groups", serde_json::json!(["ops"]));
+    let admin = login(&auth).await;
+    let response = send(Request::get("/api/audit?action=ssh_test"), &admin, "")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    let events = body["events"].as_array().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["user"], "alice");
+    assert_eq!(events[0]["success"], false);
+    assert_eq!(events[0]["details"]["host"], "127.0.0.1");
+    assert_eq!(events[0]["details"]["ssh_user"], "deploy");
+
+    let response = send(Request::get("/api/audit?since=yesterday"), &admin, "")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,237 @@
+//! Stand-in OIDC identity provider for testing
+#![allow(dead_code)] // Test utilities may not all be used in every test
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::{Form, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde_json::{json, Map, Value};
+
+/// Client id the provider issues tokens to
+pub const CLIENT_ID: &str = "nvim-web";
+
+/// An ES256 signing key with its public JWK
+pub struct SigningKey {
+    pub kid: String,
+    encoding: EncodingKey,
+    jwk: Value,
+}
+
+impl SigningKey {
+    pub fn generate(kid: &str) -> Self {
+        let pair = rcgen::KeyPair::generate().unwrap();
+        // Uncompressed P-256 point: 0x04 || x || y
+        let point = pair.public_key_raw();
+        let jwk = json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+            "kid": kid,
+            "use": "sig",
+            "alg": "ES256",
+        });
+        Self {
+            kid: kid.to_string(),
+            encoding: EncodingKey::from_ec_pem(pair.serialize_pem().as_bytes()).unwrap(),
+            jwk,
+        }
+    }
+}
+
+/// What the provider does on the next token request
+pub struct ProviderState {
+    pub issuer: String,
+    /// Keys served from the JWKS endpoint
+    pub published: Vec<Arc<SigningKey>>,
+    /// Key that signs ID tokens, published or not
+    pub signer: Arc<SigningKey>,
+    /// Claims merged over the defaults; `null` removes a claim
+    pub overrides: Map<String, Value>,
+    /// Header algorithm override, to present forged tokens
+    pub forge_hs256: bool,
+    /// Number of JWKS requests served
+    pub jwks_fetches: usize,
+    /// Access token lifetime reported by the token endpoint
+    pub expires_in: u64,
+    /// Refresh tokens that may still be redeemed, each only once
+    pub refresh_tokens: HashSet<String>,
+    /// Number of refresh grants served
+    pub refreshes: usize,
+}
+
+/// A running stand-in provider
+#[derive(Clone)]
+pub struct IdentityProvider {
+    pub issuer: String,
+    pub state: Arc<Mutex<ProviderState>>,
+}
+
+impl IdentityProvider {
+    /// Serve discovery, JWKS, token and userinfo endpoints on a free port
+    pub async fn start() -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let key = Arc::new(SigningKey::generate("key-1"));
+        let state = Arc::new(Mutex::new(ProviderState {
+            issuer: issuer.clone(),
+            published: vec![key.clone()],
+            signer: key,
+            overrides: Map::new(),
+            forge_hs256: false,
+            jwks_fetches: 0,
+            expires_in: 3600,
+            refresh_tokens: HashSet::new(),
+            refreshes: 0,
+        }));
+
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/token", post(token))
+            .route("/userinfo", get(userinfo))
+            .with_state(state.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        Self { issuer, state }
+    }
+
+    /// Sign ID tokens with a new key from now on, publishing it too
+    pub fn rotate_key(&self, kid: &str) {
+        let key = Arc::new(SigningKey::generate(kid));
+        let mut state = self.state.lock().unwrap();
+        state.published.push(key.clone());
+        state.signer = key;
+    }
+
+    /// Refuse every refresh token issued so far
+    pub fn revoke_refresh_tokens(&self) {
+        self.state.lock().unwrap().refresh_tokens.clear();
+    }
+
+    pub fn set_claim(&self, name: &str, value: Value) {
+        self.state
+            .lock()
+            .unwrap()
+            .overrides
+            .insert(name.to_string(), value);
+    }
+}
+
+/// The `nonce` parameter of an authorization URL
+///
+/// Tests pass it back as the authorization code: the provider puts the
+/// code into the ID token as its nonce, as if the user had logged in.
+pub fn nonce_of(authorize_url: &str) -> String {
+    let url = url::Url::parse(authorize_url).unwrap();
+    url.query_pairs()
+        .find(|(name, _)| name == "nonce")
+        .map(|(_, value)| value.into_owned())
+        .unwrap()
+}
+
+type Shared = State<Arc<Mutex<ProviderState>>>;
+
+async fn discovery(State(state): Shared) -> Json<Value> {
+    let issuer = state.lock().unwrap().issuer.clone();
+    Json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{issuer}/authorize"),
+        "token_endpoint": format!("{issuer}/token"),
+        "userinfo_endpoint": format!("{issuer}/userinfo"),
+        "jwks_uri": format!("{issuer}/jwks"),
+        "id_token_signing_alg_values_supported": ["ES256"],
+    }))
+}
+
+async fn jwks(State(state): Shared) -> Json<Value> {
+    let mut state = state.lock().unwrap();
+    state.jwks_fetches += 1;
+    let keys: Vec<Value> = state.published.iter().map(|k| k.jwk.clone()).collect();
+    Json(json!({ "keys": keys }))
+}
+
+async fn token(
+    State(state): Shared,
+    Form(form): Form<std::collections::HashMap<String, String>>,
+) -> Result<Json<Value>, StatusCode> {
+    let mut state = state.lock().unwrap();
+    // Logins carry the nonce as their code; refreshed ID tokens have none
+    let nonce = match form.get("grant_type").map(String::as_str) {
+        Some("authorization_code") => {
+            Some(form.get("code").cloned().ok_or(StatusCode::BAD_REQUEST)?)
+        }
+        Some("refresh_token") => {
+            let token = form.get("refresh_token").ok_or(StatusCode::BAD_REQUEST)?;
+            if !state.refresh_tokens.remove(token) {
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            state.refreshes += 1;
+            None
+        }
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+    let refresh_token = format!("refresh-{}", rand::random::<u64>());
+    state.refresh_tokens.insert(refresh_token.clone());
+
+    let now = jsonwebtoken::get_current_timestamp();
+    let mut claims = json!({
+        "iss": state.issuer,
+        "aud": CLIENT_ID,
+        "sub": "alice",
+        "email": "alice@example.com",
+        "groups": ["engineering"],
+        "iat": now,
+        "exp": now + 300,
+    });
+    if let Some(nonce) = nonce {
+        claims["nonce"] = nonce.into();
+    }
+    for (name, value) in &state.overrides {
+        match value {
+            Value::Null => {
+                claims.as_object_mut().unwrap().remove(name);
+            }
+            value => claims[name] = value.clone(),
+        }
+    }
+
+    let id_token = if state.forge_hs256 {
+        let header = Header {
+            kid: Some(state.signer.kid.clone()),
+            ..Header::new(Algorithm::HS256)
+        };
+        let key = EncodingKey::from_secret(b"anyone can make this up");
+        jsonwebtoken::encode(&header, &claims, &key).unwrap()
+    } else {
+        let header = Header {
+            kid: Some(state.signer.kid.clone()),
+            ..Header::new(Algorithm::ES256)
+        };
+        jsonwebtoken::encode(&header, &claims, &state.signer.encoding).unwrap()
+    };
+
+    Ok(Json(json!({
+        "access_token": "stand-in-access-token",
+        "token_type": "Bearer",
+        "expires_in": state.expires_in,
+        "id_token": id_token,
+        "refresh_token": refresh_token,
+    })))
+}
+
+async fn userinfo(State(state): Shared) -> Json<Value> {
+    let sub = state
+        .lock()
+        .unwrap()
+        .overrides
+        .get("sub")
+        .cloned()
+        .unwrap_or_else(|| "alice".into());
+    Json(json!({ "sub": sub, "name": "Alice", "email_verified": true }))
+}
@@ -188,3 +188,95 @@ impl MockBrowserFs {
         Ok(buf)
     }
 }
+
+/// Headless browser connected to the host's WebSocket server
+///
+/// Speaks the typed protocol without rendering anything, so tests can act
+/// as one or more session participants.
+pub struct MockClient {
+    ws: tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >,
+    pub session_id: String,
+}
+
+impl MockClient {
+    /// Connect to `ws://127.0.0.1:<port>/<query>` and wait for the session message
+    pub async fn connect(port: u16, query: &str) -> anyhow::Result<Self> {
+        Self::connect_with_headers(port, query, &[]).await
+    }
+
+    /// Connect with extra handshake headers, such as a session cookie
+    pub async fn connect_with_headers(
+        port: u16,
+        query: &str,
+        headers: &[(&'static str, String)],
+    ) -> anyhow::Result<Self> {
+        use nvim_web_protocol::schema::HostMessage;
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        let mut request = format!("ws://127.0.0.1:{port}/{query}").into_client_request()?;
+        for (name, value) in headers {
+            request.headers_mut().insert(*name, value.parse()?);
+        }
+        let (ws, _) = tokio_tungstenite::connect_async(request).await?;
+        let mut client = Self {
+            ws,
+            session_id: String::new(),
+        };
+        client.session_id = client
+            .expect(|msg| match msg {
+                HostMessage::Session { session_id, .. } => Some(session_id),
+                _ => None,
+            })
+            .await?;
+        Ok(client)
+    }
+
+    /// Send a browser message
+    pub async fn send(
+        &mut self,
+        msg: &nvim_web_protocol::schema::BrowserMessage,
+    ) -> anyhow::Result<()> {
+        use futures::SinkExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        self.ws.send(Message::Binary(msg.encode())).await?;
+        Ok(())
+    }
+
+    /// Skip host messages until `pick` accepts one, failing after 5 seconds
+    pub async fn expect<T>(
+        &mut self,
+        mut pick: impl FnMut(nvim_web_protocol::schema::HostMessage) -> Option<T>,
+    ) -> anyhow::Result<T> {
+        use futures::StreamExt;
+        use nvim_web_protocol::schema::HostMessage;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(5);
+        loop {
+            let frame = tokio::time::timeout_at(deadline, self.ws.next())
+                .await
+                .map_err(|_| anyhow::anyhow!("Timed out waiting for host message"))?
+                .ok_or_else(|| anyhow::anyhow!("Connection closed"))??;
+            if let Message::Binary(bytes) = frame {
+                if let Some(found) = HostMessage::decode(&bytes).ok().and_then(&mut pick) {
+                    return Ok(found);
+                }
+            }
+        }
+    }
+
+    /// Whether no message accepted by `pick` arrives within `wait`
+    pub async fn quiet(
+        &mut self,
+        wait: std::time::Duration,
+        pick: impl FnMut(nvim_web_protocol::schema::HostMessage) -> Option<()>,
+    ) -> bool {
+        !matches!(
+            tokio::time::timeout(wait, self.expect(pick)).await,
+            Ok(Ok(()))
+        )
+    }
+}
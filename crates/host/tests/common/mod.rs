@@ -2,6 +2,7 @@
 #![allow(unused_imports)] // Re-exports may not be used in all test files
 
 pub mod harness;
+pub mod identity_provider;
 pub mod mock_browser;
 
 pub use harness::TestHarness;
@@ -0,0 +1,329 @@
+//! OIDC login enforced on the REST API and WebSocket server
+//!
+//! Users log in against the stand-in identity provider; sessions belong to
+//! whoever created them.
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::Router;
+use common::identity_provider::{nonce_of, IdentityProvider, CLIENT_ID};
+use common::mock_browser::MockClient;
+use futures::StreamExt;
+use nvim_web_host::api::{self, AppState};
+use nvim_web_host::oidc::{AuthConfig, AuthSession, OidcClient};
+use nvim_web_host::session::AsyncSessionManager;
+use nvim_web_protocol::schema::{HostMessage, PROTOCOL_VERSION};
+use nvim_web_vfs::VfsManager;
+use serde_json::Value;
+use tokio::sync::RwLock;
+use tokio_tungstenite::tungstenite::Message;
+use tower::ServiceExt;
+
+fn nvim_available() -> bool {
+    std::process::Command::new("nvim")
+        .arg("--version")
+        .output()
+        .is_ok_and(|out| out.status.success())
+}
+
+async fn client(provider: &IdentityProvider) -> Arc<OidcClient> {
+    let mut config = AuthConfig {
+        enabled: true,
+        issuer: provider.issuer.clone(),
+        client_id: CLIENT_ID.to_string(),
+        redirect_uri: "http://localhost:8080/auth/callback".to_string(),
+        ..Default::default()
+    };
+    config.session.signing_keys = vec!["session-key-for-tests-0123456789abcdef".to_string()];
+    Arc::new(OidcClient::new(config).await.unwrap())
+}
+
+async fn login(client: &OidcClient) -> AuthSession {
+    let (url, state) = client.authorize_url().await;
+    client.exchange_code(&nonce_of(&url), &state).await.unwrap()
+}
+
+fn cookie(client: &OidcClient, session: &AuthSession) -> String {
+    format!("nvim_web_session={}", client.sessions().encode(session))
+}
+
+fn manager() -> Arc<RwLock<AsyncSessionManager>> {
+    let vfs_manager = Arc::new(RwLock::new(VfsManager::new()));
+    Arc::new(RwLock::new(AsyncSessionManager::new(vfs_manager)))
+}
+
+/// Start a WebSocket server requiring login on a free port
+async fn start_server(auth: Arc<OidcClient>) -> u16 {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    tokio::spawn(nvim_web_host::ws::serve_multi_async(
+        manager(),
+        port,
+        None,
+        None,
+        None,
+        None,
+        Some(auth),
+    ));
+    for _ in 0..50 {
+        if tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .is_ok()
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    port
+}
+
+/// First message of a connection, which the host must then close
+async fn first_message(port: u16, query: &str) -> HostMessage {
+    let url = format!("ws://127.0.0.1:{port}/{query}");
+    let (mut ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    let frame = tokio::time::timeout(Duration::from_secs(5), ws.next())
+        .await
+        .expect("no message")
+        .unwrap()
+        .unwrap();
+    let Message::Binary(bytes) = frame else {
+        panic!("unexpected frame {frame:?}");
+    };
+    let closed = tokio::time::timeout(Duration::from_secs(5), ws.next()).await;
+    assert!(
+        matches!(closed, Ok(Some(Ok(Message::Close(_))) | None)),
+        "connection left open"
+    );
+    HostMessage::decode(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn test_api_requires_login() {
+    let provider = IdentityProvider::start().await;
+    let auth = client(&provider).await;
+    let state = AppState {
+        session_manager: manager(),
+        ws_port: 0,
+        auth: Some(auth.clone()),
+    };
+    let app = Router::new()
+        .nest("/api", api::api_router(state.clone()))
+        .with_state(state);
+    let get = |path: &str, cookie: Option<&str>| {
+        let mut request = Request::get(path);
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        app.clone().oneshot(request.body(Body::empty()).unwrap())
+    };
+
+    assert_eq!(
+        get("/api/sessions", None).await.unwrap().status(),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        get("/api/health", None).await.unwrap().status(),
+        StatusCode::OK
+    );
+
+    let session = login(&auth).await;
+    let response = get("/api/sessions", Some(&cookie(&auth, &session)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["sessions"], serde_json::json!([]));
+
+    // The policy applies as well
+    provider.set_claim("email", "mallory@evil.example".into());
+    let mut config = auth.config().clone();
+    config.policy.allowed_domains = ["example.com".to_string()].into_iter().collect();
+    let strict = Arc::new(OidcClient::new(config).await.unwrap());
+    let state = AppState {
+        session_manager: manager(),
+        ws_port: 0,
+        auth: Some(strict.clone()),
+    };
+    let app = Router::new()
+        .nest("/api", api::api_router(state.clone()))
+        .with_state(state);
+    let session = login(&strict).await;
+    let request = Request::get("/api/sessions")
+        .header(header::COOKIE, cookie(&strict, &session))
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(
+        app.oneshot(request).await.unwrap().status(),
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[tokio::test]
+async fn test_unknown_sessions_have_no_owner() {
+    let provider = IdentityProvider::start().await;
+    let auth = client(&provider).await;
+    let state = AppState {
+        session_manager: manager(),
+        ws_port: 0,
+        auth: Some(auth.clone()),
+    };
+    let app = Router::new()
+        .nest("/api", api::api_router(state.clone()))
+        .with_state(state);
+
+    // Nothing is recorded for the id, so no one may manage it
+    provider.set_claim("sub", "bob".into());
+    let bob = login(&auth).await;
+    let id = uuid::Uuid::new_v4().to_string();
+    for (method, path) in [
+        ("GET", format!("/api/sessions/{id}/shares")),
+        ("POST", format!("/api/sessions/{id}/share")),
+        ("POST", format!("/api/sessions/{id}/snapshot")),
+    ] {
+        let request = Request::builder()
+            .method(method)
+            .uri(&path)
+            .header(header::COOKIE, cookie(&auth, &bob))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{method} {path}");
+    }
+}
+
+#[tokio::test]
+async fn test_roles_limit_session_creation() {
+    let provider = IdentityProvider::start().await;
+    let mut config = client(&provider).await.config().clone();
+    config.roles = toml::from_str(
+        r#"
+[groups]
+contractors = "viewer-only"
+
+[developer]
+backends = ["tcp"]
+vfs_schemes = ["local"]
+"#,
+    )
+    .unwrap();
+    let auth = Arc::new(OidcClient::new(config).await.unwrap());
+    let state = AppState {
+        session_manager: manager(),
+        ws_port: 0,
+        auth: Some(auth.clone()),
+    };
+    let app = Router::new()
+        .nest("/api", api::api_router(state.clone()))
+        .with_state(state);
+    let post = |path: &str, session: &AuthSession| {
+        let request = Request::post(path)
+            .header(header::COOKIE, cookie(&auth, session))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"host":"example.com","user":"alice"}"#))
+            .unwrap();
+        app.clone().oneshot(request)
+    };
+
+    // Developers may only run sessions on a remote Neovim, and not use SSH
+    let developer = login(&auth).await;
+    let response = post("/api/sessions", &developer).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = post("/api/ssh/connect", &developer).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Viewer-only users never start sessions
+    provider.set_claim("groups", serde_json::json!(["contractors"]));
+    let contractor = login(&auth).await;
+    let response = post("/api/sessions", &contractor).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert!(body["error"].as_str().unwrap().contains("viewer-only"));
+}
+
+#[tokio::test]
+async fn test_websocket_requires_login() {
+    let provider = IdentityProvider::start().await;
+    let auth = client(&provider).await;
+    let port = start_server(auth.clone()).await;
+
+    let query = format!("?protocol={PROTOCOL_VERSION}");
+    match first_message(port, &query).await {
+        HostMessage::AuthRequired { login_url } => assert_eq!(login_url, "/auth/login"),
+        msg => panic!("expected auth_required, got {msg:?}"),
+    }
+
+    // A cookie is no ticket
+    let session = login(&auth).await;
+    let value = auth.sessions().encode(&session);
+    let query = format!("?ticket={value}&protocol={PROTOCOL_VERSION}");
+    assert!(matches!(
+        first_message(port, &query).await,
+        HostMessage::AuthRequired { .. }
+    ));
+}
+
+#[tokio::test]
+async fn test_sessions_belong_to_their_owner() {
+    if !nvim_available() {
+        eprintln!("nvim not found, skipping session ownership test");
+        return;
+    }
+
+    let provider = IdentityProvider::start().await;
+    let auth = client(&provider).await;
+    let port = start_server(auth.clone()).await;
+
+    let alice = login(&auth).await;
+    provider.set_claim("sub", "bob".into());
+    let bob = login(&auth).await;
+    provider.state.lock().unwrap().overrides.clear();
+
+    let query = format!("?protocol={PROTOCOL_VERSION}");
+    let alice_client =
+        MockClient::connect_with_headers(port, &query, &[("cookie", cookie(&auth, &alice))])
+            .await
+            .unwrap();
+
+    // Bob cannot attach to, or watch, Alice's session
+    let query = format!(
+        "?session={}&protocol={PROTOCOL_VERSION}",
+        alice_client.session_id
+    );
+    let bob_client =
+        MockClient::connect_with_headers(port, &query, &[("cookie", cookie(&auth, &bob))])
+            .await
+            .unwrap();
+    assert_ne!(bob_client.session_id, alice_client.session_id);
+
+    let query = format!(
+        "?view={}&protocol={PROTOCOL_VERSION}",
+        alice_client.session_id
+    );
+    let watching =
+        MockClient::connect_with_headers(port, &query, &[("cookie", cookie(&auth, &bob))]).await;
+    assert!(watching.is_err());
+
+    // A ticket stands in for Alice's cookie
+    let ticket = auth.sessions().ticket(&alice);
+    let query = format!(
+        "?session={}&ticket={ticket}&protocol={PROTOCOL_VERSION}",
+        alice_client.session_id
+    );
+    let again = MockClient::connect(port, &query).await.unwrap();
+    assert_eq!(again.session_id, alice_client.session_id);
+}
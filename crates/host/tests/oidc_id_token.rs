@@ -0,0 +1,116 @@
+//! OIDC login against a stand-in identity provider
+//!
+//! The provider serves discovery, a JWKS with generated ES256 keys and a
+//! token endpoint whose ID tokens the tests can tamper with.
+
+mod common;
+
+use common::identity_provider::{nonce_of, IdentityProvider, SigningKey, CLIENT_ID};
+use nvim_web_host::oidc::{AuthConfig, OidcClient};
+use serde_json::{json, Value};
+
+async fn client(provider: &IdentityProvider) -> OidcClient {
+    let mut config = AuthConfig {
+        enabled: true,
+        issuer: provider.issuer.clone(),
+        client_id: CLIENT_ID.to_string(),
+        redirect_uri: "http://localhost:8080/auth/callback".to_string(),
+        ..Default::default()
+    };
+    config.policy.required_claims = ["email_verified".to_string()].into_iter().collect();
+    config.session.signing_keys = vec!["session-key-for-tests-0123456789abcdef".to_string()];
+    OidcClient::new(config).await.unwrap()
+}
+
+/// Run the login flow, returning the result of the code exchange
+async fn login(client: &OidcClient) -> anyhow::Result<nvim_web_host::oidc::AuthUser> {
+    let (url, state) = client.authorize_url().await;
+    Ok(client.exchange_code(&nonce_of(&url), &state).await?.user)
+}
+
+#[tokio::test]
+async fn test_login_with_valid_id_token() {
+    let provider = IdentityProvider::start().await;
+    let client = client(&provider).await;
+
+    let (url, _) = client.authorize_url().await;
+    assert!(url.starts_with(&format!("{}/authorize?", provider.issuer)));
+
+    let user = login(&client).await.unwrap();
+    assert_eq!(user.sub, "alice");
+    assert_eq!(user.email.as_deref(), Some("alice@example.com"));
+    assert_eq!(user.groups, vec!["engineering".to_string()]);
+    // From userinfo, which the ID token lacks
+    assert_eq!(user.name.as_deref(), Some("Alice"));
+    assert_eq!(user.claims.get("email_verified"), Some(&json!(true)));
+}
+
+#[tokio::test]
+async fn test_invalid_id_tokens_rejected() {
+    let provider = IdentityProvider::start().await;
+    let client = client(&provider).await;
+
+    let cases: [(&str, Value); 4] = [
+        ("aud", json!("someone-else")),
+        ("iss", json!("https://evil.example.com")),
+        ("exp", json!(1_000_000)),
+        ("nonce", json!("replayed")),
+    ];
+    for (claim, value) in cases {
+        provider.set_claim(claim, value);
+        assert!(login(&client).await.is_err(), "accepted bad {claim}");
+        provider.state.lock().unwrap().overrides.clear();
+    }
+
+    // Several audiences need an authorized party naming us
+    provider.set_claim("aud", json!([CLIENT_ID, "other"]));
+    assert!(login(&client).await.is_err());
+    provider.set_claim("azp", json!(CLIENT_ID));
+    assert!(login(&client).await.is_ok());
+    provider.state.lock().unwrap().overrides.clear();
+
+    // Signed with a key the provider never published
+    let published = provider.state.lock().unwrap().signer.clone();
+    provider.state.lock().unwrap().signer = SigningKey::generate(&published.kid).into();
+    assert!(login(&client).await.is_err());
+    provider.state.lock().unwrap().signer = published;
+
+    // HMAC-signed with a made-up secret
+    provider.state.lock().unwrap().forge_hs256 = true;
+    assert!(login(&client).await.is_err());
+    provider.state.lock().unwrap().forge_hs256 = false;
+
+    assert!(login(&client).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_follows_key_rotation() {
+    let provider = IdentityProvider::start().await;
+    let client = client(&provider).await;
+
+    login(&client).await.unwrap();
+    login(&client).await.unwrap();
+    assert_eq!(
+        provider.state.lock().unwrap().jwks_fetches,
+        1,
+        "keys are cached"
+    );
+
+    provider.rotate_key("key-2");
+    login(&client).await.unwrap();
+    assert_eq!(provider.state.lock().unwrap().jwks_fetches, 2);
+}
+
+#[tokio::test]
+async fn test_discovery_must_name_the_issuer() {
+    let provider = IdentityProvider::start().await;
+    provider.state.lock().unwrap().issuer = "https://accounts.example.com".to_string();
+
+    let mut config = AuthConfig {
+        issuer: provider.issuer.clone(),
+        client_id: CLIENT_ID.to_string(),
+        ..Default::default()
+    };
+    config.session.signing_keys = vec!["session-key-for-tests-0123456789abcdef".to_string()];
+    assert!(OidcClient::new(config).await.is_err());
+}
@@ -0,0 +1,160 @@
+//! Silent renewal of OIDC sessions with server-side refresh tokens
+
+mod common;
+
+use std::time::Duration;
+
+use axum::http::{header, HeaderMap};
+use common::identity_provider::{nonce_of, IdentityProvider, CLIENT_ID};
+use nvim_web_host::oidc::{AuthConfig, AuthEvent, AuthSession, OidcClient};
+
+async fn client(provider: &IdentityProvider) -> OidcClient {
+    let mut config = AuthConfig {
+        enabled: true,
+        issuer: provider.issuer.clone(),
+        client_id: CLIENT_ID.to_string(),
+        redirect_uri: "http://localhost:8080/auth/callback".to_string(),
+        ..Default::default()
+    };
+    config.session.signing_keys = vec!["session-key-for-tests-0123456789abcdef".to_string()];
+    OidcClient::new(config).await.unwrap()
+}
+
+async fn login(client: &OidcClient) -> AuthSession {
+    let (url, state) = client.authorize_url().await;
+    client.exchange_code(&nonce_of(&url), &state).await.unwrap()
+}
+
+/// Request headers carrying the session cookie
+fn cookie(client: &OidcClient, session: &AuthSession) -> HeaderMap {
+    let value = format!("nvim_web_session={}", client.sessions().encode(session));
+    let mut headers = HeaderMap::new();
+    headers.insert(header::COOKIE, value.parse().unwrap());
+    headers
+}
+
+async fn next_event(watch: &mut nvim_web_host::oidc::AuthWatch) -> AuthEvent {
+    tokio::time::timeout(Duration::from_secs(5), watch.next())
+        .await
+        .expect("no auth event")
+}
+
+#[tokio::test]
+async fn test_session_renewed_before_expiry() {
+    let provider = IdentityProvider::start().await;
+    let client = client(&provider).await;
+
+    // Tokens about to expire are renewed on the next request
+    provider.state.lock().unwrap().expires_in = 60;
+    let session = login(&client).await;
+    provider.state.lock().unwrap().expires_in = 3600;
+
+    let (renewed, stale) = client
+        .authenticate(&cookie(&client, &session))
+        .await
+        .unwrap();
+    assert!(stale, "renewed sessions need a new cookie");
+    assert_eq!(renewed.sid, session.sid);
+    assert_eq!(renewed.user.sub, "alice");
+    assert!(renewed.user.exp > session.user.exp + 3000);
+    assert_eq!(provider.state.lock().unwrap().refreshes, 1);
+
+    // The new cookie is current and the refresh token was rotated
+    let (_, stale) = client
+        .authenticate(&cookie(&client, &renewed))
+        .await
+        .unwrap();
+    assert!(!stale);
+    assert_eq!(provider.state.lock().unwrap().refresh_tokens.len(), 1);
+
+    // An old cookie is brought up to date from the server-side session
+    let (current, stale) = client
+        .authenticate(&cookie(&client, &session))
+        .await
+        .unwrap();
+    assert!(stale);
+    assert_eq!(current.user.exp, renewed.user.exp);
+    assert_eq!(provider.state.lock().unwrap().refreshes, 1);
+}
+
+#[tokio::test]
+async fn test_failed_renewal_waits_for_expiry() {
+    let provider = IdentityProvider::start().await;
+    let client = client(&provider).await;
+
+    provider.state.lock().unwrap().expires_in = 60;
+    let session = login(&client).await;
+    provider.revoke_refresh_tokens();
+
+    // Still valid for a minute, so the session survives the failed refresh
+    let (current, stale) = client
+        .authenticate(&cookie(&client, &session))
+        .await
+        .unwrap();
+    assert!(!stale);
+    assert_eq!(current.user.exp, session.user.exp);
+    assert!(client.watch(&session.sid).is_some());
+}
+
+#[tokio::test]
+async fn test_watch_sees_expiry_and_new_login() {
+    let provider = IdentityProvider::start().await;
+    let client = client(&provider).await;
+
+    provider.state.lock().unwrap().expires_in = 0;
+    let session = login(&client).await;
+    let mut watch = client.watch(&session.sid).unwrap();
+
+    // Expired tokens and a refused refresh end the session
+    provider.revoke_refresh_tokens();
+    assert!(client.renew(&session.sid).await.is_none());
+    assert!(matches!(next_event(&mut watch).await, AuthEvent::Expired));
+    assert!(client
+        .authenticate(&cookie(&client, &session))
+        .await
+        .is_none());
+
+    // Someone else logging in does not restore the watch
+    provider.state.lock().unwrap().expires_in = 3600;
+    provider.set_claim("sub", "mallory".into());
+    login(&client).await;
+    provider.state.lock().unwrap().overrides.clear();
+
+    // Logging in again restores it and moves it to the new session
+    let again = login(&client).await;
+    match next_event(&mut watch).await {
+        AuthEvent::Renewed(user) => assert_eq!(user.sub, "alice"),
+        AuthEvent::Expired => panic!("expected renewal"),
+    }
+    assert_eq!(watch.sid(), again.sid);
+}
+
+#[tokio::test]
+async fn test_logout_ends_session() {
+    let provider = IdentityProvider::start().await;
+    let client = client(&provider).await;
+
+    let session = login(&client).await;
+    let headers = cookie(&client, &session);
+    let mut watch = client.watch(&session.sid).unwrap();
+    assert!(client.authenticate(&headers).await.is_some());
+
+    // A copy of the cookie stops working, and connections are told
+    client.logout(&session.sid);
+    assert!(matches!(next_event(&mut watch).await, AuthEvent::Expired));
+    assert!(client.authenticate(&headers).await.is_none());
+    let ticket = client.sessions().ticket(&session);
+    assert!(client
+        .authorize(&HeaderMap::new(), Some(&ticket), None)
+        .await
+        .is_err());
+
+    // Sessions issued before a restart live out their tokens
+    let mut earlier = login(&client).await;
+    client.logout(&earlier.sid);
+    earlier.epoch = "restarted".to_string();
+    assert!(client
+        .authenticate(&cookie(&client, &earlier))
+        .await
+        .is_some());
+}
@@ -20,7 +20,7 @@ async fn test_origin_validation() {
     }
 
     tokio::spawn(async move {
-        if let Err(e) = serve_multi_async(manager, 9003, None, None, None, None).await {
+        if let Err(e) = serve_multi_async(manager, 9003, None, None, None, None, None).await {
             eprintln!("Server error: {e}");
         }
     });
@@ -0,0 +1,94 @@
+//! Resuming sessions after a host restart
+//!
+//! A session recorded in the registry comes back under its old id, in its
+//! working directory and with its open files, once the manager that ran it
+//! has shut down.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use nvim_web_host::launch::LaunchRequest;
+use nvim_web_host::registry::SessionRegistry;
+use nvim_web_host::session::AsyncSessionManager;
+use nvim_web_vfs::VfsManager;
+use rmpv::Value;
+use tokio::sync::RwLock;
+
+fn nvim_available() -> bool {
+    std::process::Command::new("nvim")
+        .arg("--version")
+        .output()
+        .is_ok_and(|out| out.status.success())
+}
+
+fn manager(db: &std::path::Path) -> AsyncSessionManager {
+    let mut manager = AsyncSessionManager::new(Arc::new(RwLock::new(VfsManager::new())));
+    manager.registry = Some(SessionRegistry::open(db).unwrap());
+    manager
+}
+
+async fn eval(manager: &AsyncSessionManager, id: &str, expr: &str) -> Value {
+    manager
+        .get_session(id)
+        .unwrap()
+        .rpc_call("nvim_eval", vec![Value::from(expr)])
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_resume_after_restart() {
+    if !nvim_available() {
+        eprintln!("nvim not found, skipping session resume test");
+        return;
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("sessions.db");
+    let cwd = dir.path().canonicalize().unwrap();
+    std::fs::write(cwd.join("notes.txt"), "one\ntwo\n").unwrap();
+
+    let mut first = manager(&db);
+    let id = first
+        .create_session(None, None, &LaunchRequest::default())
+        .await
+        .unwrap();
+    let session = first.get_session(&id).unwrap();
+    let edit = format!("cd {} | edit notes.txt", cwd.display());
+    session
+        .rpc_call("nvim_command", vec![Value::from(edit)])
+        .await
+        .unwrap();
+    first.shutdown_all().await;
+    assert!(!first.has_session(&id));
+    drop(first);
+
+    let record = SessionRegistry::open(&db)
+        .unwrap()
+        .get(&id)
+        .unwrap()
+        .unwrap();
+    assert_eq!(record.cwd, Some(cwd.clone()));
+    assert!(record.session_file.exists());
+
+    let mut second = manager(&db);
+    assert_eq!(second.resume_session("unknown", None).await.unwrap(), None);
+    let resumed = second.resume_session(&id, None).await.unwrap();
+    assert_eq!(resumed.as_deref(), Some(id.as_str()));
+
+    let cwd_value = eval(&second, &id, "getcwd(-1)").await;
+    assert_eq!(cwd_value.as_str().map(PathBuf::from), Some(cwd));
+    assert_eq!(
+        eval(&second, &id, "expand('%:t')").await.as_str(),
+        Some("notes.txt")
+    );
+
+    // Closed sessions are forgotten
+    second.remove_session(&id);
+    assert!(SessionRegistry::open(&db)
+        .unwrap()
+        .get(&id)
+        .unwrap()
+        .is_none());
+    assert!(!record.session_file.exists());
+}
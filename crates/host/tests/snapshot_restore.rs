@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use nvim_web_host::session::AsyncSessionManager;
+use nvim_web_host::sharing::Snapshot;
+use nvim_web_host::snapshot;
+use nvim_web_vfs::VfsManager;
+use rmpv::Value;
+use tokio::sync::RwLock;
+
+fn nvim_available() -> bool {
+    std::process::Command::new("nvim")
+        .arg("--version")
+        .output()
+        .is_ok_and(|out| out.status.success())
+}
+
+async fn eval(manager: &AsyncSessionManager, id: &str, expr: &str) -> Value {
+    manager
+        .get_session(id)
+        .unwrap()
+        .rpc_call("nvim_eval", vec![Value::from(expr)])
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_restore_snapshot_reopens_files() {
+    if !nvim_available() {
+        eprintln!("nvim not found, skipping snapshot restore test");
+        return;
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let cwd = dir.path().canonicalize().unwrap();
+    std::fs::write(cwd.join("main.rs"), "fn main() {\n    run();\n}\n").unwrap();
+    std::fs::write(cwd.join("lib.rs"), "pub fn run() {}\n").unwrap();
+
+    let snapshot = Snapshot {
+        open_files: vec!["main.rs".to_string(), "lib.rs".to_string()],
+        current_file: Some("main.rs".to_string()),
+        cursor: Some(("main.rs".to_string(), 2, 4)),
+        ..Snapshot::new("original", cwd.clone(), None)
+    };
+
+    let vfs_manager = Arc::new(RwLock::new(VfsManager::new()));
+    let mut manager = AsyncSessionManager::new(vfs_manager);
+    let id = manager.restore_snapshot(&snapshot, None).await.unwrap();
+    assert!(manager.has_session(&id));
+
+    let cwd_value = eval(&manager, &id, "getcwd(-1)").await;
+    assert_eq!(cwd_value.as_str().map(PathBuf::from), Some(cwd));
+    assert_eq!(
+        eval(&manager, &id, "expand('%:t')").await.as_str(),
+        Some("main.rs")
+    );
+    assert_eq!(
+        eval(&manager, &id, "[line('.'), col('.')]").await,
+        Value::from(vec![Value::from(2), Value::from(5)])
+    );
+    assert_eq!(
+        eval(&manager, &id, "buflisted('lib.rs')").await.as_i64(),
+        Some(1)
+    );
+
+    manager.remove_session(&id);
+}
+
+#[tokio::test]
+async fn test_capture_restores_unsaved_layout_and_registers() {
+    if !nvim_available() {
+        eprintln!("nvim not found, skipping snapshot capture test");
+        return;
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let cwd = dir.path().canonicalize().unwrap();
+    std::fs::write(cwd.join("a.txt"), "one\ntwo\nthree\n").unwrap();
+    std::fs::write(cwd.join("b.txt"), "saved\n").unwrap();
+
+    let vfs_manager = Arc::new(RwLock::new(VfsManager::new()));
+    let mut manager = AsyncSessionManager::new(vfs_manager);
+    let source = manager
+        .restore_snapshot(&Snapshot::new("src", cwd.clone(), None), None)
+        .await
+        .unwrap();
+    let script = "edit a.txt | call setline(2, 'edited') | vsplit b.txt | \
+                  call setreg('q', ['macro'], 'l') | mark B | wincmd l | call cursor(3, 2)";
+    manager
+        .get_session(&source)
+        .unwrap()
+        .rpc_call("nvim_command", vec![Value::from(script)])
+        .await
+        .unwrap();
+
+    let captured = snapshot::capture(manager.get_session(&source).unwrap(), None)
+        .await
+        .unwrap();
+    assert_eq!(captured.buffers.len(), 1);
+    assert_eq!(captured.buffers[0].file, "a.txt");
+    assert_eq!(captured.current_file.as_deref(), Some("a.txt"));
+
+    // Round-trip through an archive as if moved to another host
+    let archive = snapshot::export_archive(&captured).unwrap();
+    let imported = snapshot::import_archive(&archive).unwrap();
+    let restored = manager.restore_snapshot(&imported, None).await.unwrap();
+
+    assert_eq!(
+        eval(&manager, &restored, "getline(1, '$')").await,
+        Value::from(vec![
+            Value::from("one"),
+            Value::from("edited"),
+            Value::from("three")
+        ])
+    );
+    assert_eq!(
+        eval(&manager, &restored, "&modified").await.as_i64(),
+        Some(1)
+    );
+    assert_eq!(
+        eval(&manager, &restored, "winnr('$')").await.as_i64(),
+        Some(2)
+    );
+    assert_eq!(
+        eval(&manager, &restored, "[line('.'), col('.')]").await,
+        Value::from(vec![Value::from(3), Value::from(2)])
+    );
+    assert_eq!(
+        eval(&manager, &restored, "getreg('q')").await.as_str(),
+        Some("macro\n")
+    );
+    assert_eq!(
+        eval(
+            &manager,
+            &restored,
+            "fnamemodify(bufname(getpos(\"'B\")[0]), ':t')"
+        )
+        .await
+        .as_str(),
+        Some("b.txt")
+    );
+
+    manager.remove_session(&source);
+    manager.remove_session(&restored);
+}
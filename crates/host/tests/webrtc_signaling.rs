@@ -0,0 +1,152 @@
+//! WebRTC signaling relay between two headless browsers
+//!
+//! The host only forwards offers, answers and ICE candidates between the
+//! participants of a session; the payloads are opaque to it.
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::mock_browser::MockClient;
+use nvim_web_host::session::AsyncSessionManager;
+use nvim_web_protocol::schema::{BrowserMessage, HostMessage, PROTOCOL_VERSION};
+use nvim_web_vfs::VfsManager;
+use tokio::sync::RwLock;
+
+fn nvim_available() -> bool {
+    std::process::Command::new("nvim")
+        .arg("--version")
+        .output()
+        .is_ok_and(|out| out.status.success())
+}
+
+/// Start a WebSocket server on a free port
+async fn start_server() -> u16 {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let vfs_manager = Arc::new(RwLock::new(VfsManager::new()));
+    let manager = Arc::new(RwLock::new(AsyncSessionManager::new(vfs_manager)));
+    tokio::spawn(nvim_web_host::ws::serve_multi_async(
+        manager, port, None, None, None, None, None,
+    ));
+    for _ in 0..50 {
+        if tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .is_ok()
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    port
+}
+
+/// Wait for our own participant id in the presence message
+async fn self_id(client: &mut MockClient) -> String {
+    client
+        .expect(|msg| match msg {
+            HostMessage::Presence { self_id, .. } => Some(self_id),
+            _ => None,
+        })
+        .await
+        .unwrap()
+}
+
+fn signal(to: &str, kind: &str, payload: &str) -> BrowserMessage {
+    BrowserMessage::Signal {
+        to: to.to_string(),
+        kind: kind.to_string(),
+        payload: payload.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_offer_answer_and_candidates_are_relayed() {
+    if !nvim_available() {
+        eprintln!("nvim not found, skipping signaling test");
+        return;
+    }
+
+    let port = start_server().await;
+    let query = format!("?protocol={PROTOCOL_VERSION}");
+    let mut alice = MockClient::connect(port, &query).await.unwrap();
+    let alice_id = self_id(&mut alice).await;
+
+    let query = format!("?session={}&protocol={PROTOCOL_VERSION}", alice.session_id);
+    let mut bob = MockClient::connect(port, &query).await.unwrap();
+    assert_eq!(bob.session_id, alice.session_id);
+    let bob_id = self_id(&mut bob).await;
+
+    let query = format!("?view={}&protocol={PROTOCOL_VERSION}", alice.session_id);
+    let mut carol = MockClient::connect(port, &query).await.unwrap();
+    self_id(&mut carol).await;
+
+    // Alice learns about Bob and starts the handshake
+    let joined = bob_id.clone();
+    alice
+        .expect(|msg| match msg {
+            HostMessage::PeerInfo(p) if p.id == joined => Some(()),
+            _ => None,
+        })
+        .await
+        .unwrap();
+    alice
+        .send(&signal(&bob_id, "offer", "v=0 offer"))
+        .await
+        .unwrap();
+
+    let from_alice = |kind: &'static str, id: String| {
+        move |msg| match msg {
+            HostMessage::Signal {
+                from,
+                kind: k,
+                payload,
+            } if from == id && k == kind => Some(payload),
+            _ => None,
+        }
+    };
+    let offer = bob
+        .expect(from_alice("offer", alice_id.clone()))
+        .await
+        .unwrap();
+    assert_eq!(offer, "v=0 offer");
+
+    bob.send(&signal(&alice_id, "answer", "v=0 answer"))
+        .await
+        .unwrap();
+    bob.send(&signal(&alice_id, "ice_candidate", r#"{"candidate":"c1"}"#))
+        .await
+        .unwrap();
+    let answer = alice
+        .expect(from_alice("answer", bob_id.clone()))
+        .await
+        .unwrap();
+    assert_eq!(answer, "v=0 answer");
+    let candidate = alice
+        .expect(from_alice("ice_candidate", bob_id.clone()))
+        .await
+        .unwrap();
+    assert_eq!(candidate, r#"{"candidate":"c1"}"#);
+
+    // Signals never reach bystanders, and malformed ones are dropped
+    alice.send(&signal(&bob_id, "hangup", "bye")).await.unwrap();
+    assert!(
+        carol
+            .quiet(Duration::from_millis(300), |msg| match msg {
+                HostMessage::Signal { .. } => Some(()),
+                _ => None,
+            })
+            .await
+    );
+    assert!(
+        bob.quiet(Duration::from_millis(300), |msg| match msg {
+            HostMessage::Signal { .. } => Some(()),
+            _ => None,
+        })
+        .await
+    );
+}
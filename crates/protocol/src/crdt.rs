@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 /// Sync message types for CRDT collaboration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum SyncMessage {
     /// Step 1: Client sends its state vector
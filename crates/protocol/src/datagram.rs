@@ -0,0 +1,298 @@
+//! Datagram codec for low-latency events
+//!
+//! Datagrams are fire-and-forget and may be dropped or reordered, so the
+//! wire format is a fixed binary layout rather than MessagePack:
+//!
+//! ```text
+//! [type: u8][payload...]
+//!   0x01 Cursor     seq:u32 grid:u32 row:u32 col:u32 peer:utf8 (rest)
+//!   0x02 Input      keys:utf8 (rest)
+//!   0x03 Heartbeat  timestamp_ms:u64
+//! ```
+//!
+//! All integers are big-endian. Transports without datagram support
+//! (WebSocket) carry the same bytes inside a reliable `["datagram", bin]`
+//! MessagePack envelope.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Cursor position datagram type byte
+pub const DATAGRAM_CURSOR: u8 = 0x01;
+/// Input event datagram type byte
+pub const DATAGRAM_INPUT: u8 = 0x02;
+/// Heartbeat datagram type byte
+pub const DATAGRAM_HEARTBEAT: u8 = 0x03;
+
+/// Method name of the reliable fallback envelope
+pub const DATAGRAM_ENVELOPE: &str = "datagram";
+
+/// MessagePack prefix of `["datagram", ...]` (fixarray(2), fixstr(8))
+const ENVELOPE_PREFIX: &[u8] = b"\x92\xa8datagram";
+
+/// Cursor position in the editor grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CursorPosition {
+    pub row: u32,
+    pub col: u32,
+    pub grid: u32,
+}
+
+/// Cursor update for one collaboration peer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CursorUpdate {
+    /// Sender sequence number, used to drop reordered updates
+    pub seq: u32,
+    pub position: CursorPosition,
+    /// Peer that moved; empty when sent by a browser about itself
+    pub peer_id: String,
+}
+
+/// Typed datagram
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Datagram {
+    Cursor(CursorUpdate),
+    /// Keys in Neovim notation
+    Input(String),
+    /// Keep-alive; the host echoes it back so the sender can measure RTT
+    Heartbeat { timestamp_ms: u64 },
+}
+
+/// Datagram decoding error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatagramError {
+    Empty,
+    UnknownType(u8),
+    Truncated,
+    InvalidUtf8,
+}
+
+impl fmt::Display for DatagramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty datagram"),
+            Self::UnknownType(t) => write!(f, "unknown datagram type: {t:#04x}"),
+            Self::Truncated => write!(f, "truncated datagram"),
+            Self::InvalidUtf8 => write!(f, "datagram payload is not valid UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for DatagramError {}
+
+impl Datagram {
+    /// Encode to the binary wire format
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::Cursor(update) => {
+                let mut buf = Vec::with_capacity(17 + update.peer_id.len());
+                buf.push(DATAGRAM_CURSOR);
+                buf.extend_from_slice(&update.seq.to_be_bytes());
+                buf.extend_from_slice(&update.position.grid.to_be_bytes());
+                buf.extend_from_slice(&update.position.row.to_be_bytes());
+                buf.extend_from_slice(&update.position.col.to_be_bytes());
+                buf.extend_from_slice(update.peer_id.as_bytes());
+                buf
+            }
+            Self::Input(keys) => {
+                let mut buf = Vec::with_capacity(1 + keys.len());
+                buf.push(DATAGRAM_INPUT);
+                buf.extend_from_slice(keys.as_bytes());
+                buf
+            }
+            Self::Heartbeat { timestamp_ms } => {
+                let mut buf = Vec::with_capacity(9);
+                buf.push(DATAGRAM_HEARTBEAT);
+                buf.extend_from_slice(&timestamp_ms.to_be_bytes());
+                buf
+            }
+        }
+    }
+
+    /// Decode from the binary wire format
+    pub fn decode(data: &[u8]) -> Result<Self, DatagramError> {
+        let (&kind, payload) = data.split_first().ok_or(DatagramError::Empty)?;
+        match kind {
+            DATAGRAM_CURSOR => {
+                if payload.len() < 16 {
+                    return Err(DatagramError::Truncated);
+                }
+                let peer_id = std::str::from_utf8(&payload[16..])
+                    .map_err(|_| DatagramError::InvalidUtf8)?
+                    .to_string();
+                Ok(Self::Cursor(CursorUpdate {
+                    seq: read_u32(payload, 0),
+                    position: CursorPosition {
+                        grid: read_u32(payload, 4),
+                        row: read_u32(payload, 8),
+                        col: read_u32(payload, 12),
+                    },
+                    peer_id,
+                }))
+            }
+            DATAGRAM_INPUT => std::str::from_utf8(payload)
+                .map(|keys| Self::Input(keys.to_string()))
+                .map_err(|_| DatagramError::InvalidUtf8),
+            DATAGRAM_HEARTBEAT => {
+                let bytes: [u8; 8] = payload
+                    .get(..8)
+                    .and_then(|b| b.try_into().ok())
+                    .ok_or(DatagramError::Truncated)?;
+                Ok(Self::Heartbeat {
+                    timestamp_ms: u64::from_be_bytes(bytes),
+                })
+            }
+            other => Err(DatagramError::UnknownType(other)),
+        }
+    }
+
+    /// Encode wrapped in the reliable `["datagram", bin]` envelope
+    pub fn to_envelope(&self) -> Vec<u8> {
+        encode_envelope(&self.encode())
+    }
+
+    /// Decode a reliable envelope, `None` if `data` is not one
+    pub fn from_envelope(data: &[u8]) -> Option<Result<Self, DatagramError>> {
+        decode_envelope(data).map(|raw| Self::decode(&raw))
+    }
+}
+
+/// Wrap raw datagram bytes in the reliable `["datagram", bin]` envelope
+pub fn encode_envelope(raw: &[u8]) -> Vec<u8> {
+    let msg = rmpv::Value::Array(vec![
+        rmpv::Value::String(DATAGRAM_ENVELOPE.into()),
+        rmpv::Value::Binary(raw.to_vec()),
+    ]);
+    let mut bytes = Vec::with_capacity(raw.len() + ENVELOPE_PREFIX.len() + 5);
+    // Writing to a Vec cannot fail
+    let _ = rmpv::encode::write_value(&mut bytes, &msg);
+    bytes
+}
+
+/// Unwrap raw datagram bytes from a reliable envelope
+///
+/// Only the fixed prefix is inspected for other messages, so this is
+/// cheap to call on every incoming frame.
+pub fn decode_envelope(data: &[u8]) -> Option<Vec<u8>> {
+    let rest = data.strip_prefix(ENVELOPE_PREFIX)?;
+    match rmpv::decode::read_value(&mut &rest[..]).ok()? {
+        rmpv::Value::Binary(raw) => Some(raw),
+        _ => None,
+    }
+}
+
+/// Whether `seq` is newer than `last`, tolerating u32 wrap-around
+pub const fn seq_is_newer(seq: u32, last: u32) -> bool {
+    (seq.wrapping_sub(last) as i32) > 0
+}
+
+/// Drops datagrams that arrive out of order
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SeqFilter {
+    last: Option<u32>,
+}
+
+impl SeqFilter {
+    /// Returns true (and records `seq`) if it is newer than anything seen
+    pub fn accept(&mut self, seq: u32) -> bool {
+        match self.last {
+            Some(last) if !seq_is_newer(seq, last) => false,
+            _ => {
+                self.last = Some(seq);
+                true
+            }
+        }
+    }
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor(seq: u32, peer: &str) -> Datagram {
+        Datagram::Cursor(CursorUpdate {
+            seq,
+            position: CursorPosition {
+                row: 12,
+                col: 40,
+                grid: 1,
+            },
+            peer_id: peer.to_string(),
+        })
+    }
+
+    #[test]
+    fn roundtrip_all_types() {
+        for d in [
+            cursor(7, ""),
+            cursor(u32::MAX, "peer-1"),
+            Datagram::Input("<C-w>v".to_string()),
+            Datagram::Heartbeat {
+                timestamp_ms: 1_700_000_000_123,
+            },
+        ] {
+            assert_eq!(Datagram::decode(&d.encode()), Ok(d));
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(Datagram::decode(&[]), Err(DatagramError::Empty));
+        assert_eq!(
+            Datagram::decode(&[0x7f]),
+            Err(DatagramError::UnknownType(0x7f))
+        );
+        assert_eq!(
+            Datagram::decode(&[DATAGRAM_CURSOR, 0, 0]),
+            Err(DatagramError::Truncated)
+        );
+        assert_eq!(
+            Datagram::decode(&[DATAGRAM_HEARTBEAT, 1]),
+            Err(DatagramError::Truncated)
+        );
+        assert_eq!(
+            Datagram::decode(&[DATAGRAM_INPUT, 0xff]),
+            Err(DatagramError::InvalidUtf8)
+        );
+    }
+
+    #[test]
+    fn envelope_roundtrip() {
+        let d = cursor(3, "abc");
+        assert_eq!(Datagram::from_envelope(&d.to_envelope()), Some(Ok(d)));
+
+        // Regular messages are not envelopes
+        let mut input = Vec::new();
+        rmpv::encode::write_value(
+            &mut input,
+            &rmpv::Value::Array(vec!["input".into(), "i".into()]),
+        )
+        .unwrap();
+        assert_eq!(Datagram::from_envelope(&input), None);
+    }
+
+    #[test]
+    fn seq_filter_drops_stale() {
+        let mut filter = SeqFilter::default();
+        assert!(filter.accept(5));
+        assert!(!filter.accept(4));
+        assert!(!filter.accept(5));
+        assert!(filter.accept(6));
+
+        // Wrap-around
+        let mut filter = SeqFilter::default();
+        assert!(filter.accept(u32::MAX));
+        assert!(filter.accept(0));
+        assert!(!filter.accept(u32::MAX - 1));
+    }
+}
@@ -5,6 +5,8 @@
 pub mod messages;
 pub mod rpc;
 pub mod crdt;
+pub mod datagram;
+pub mod schema;
 
 pub use messages::*;
 pub use rpc::*;
@@ -26,7 +26,8 @@ pub enum InternalMethod {
     LlmSetProvider, // llm_set_provider
 
     // Search
-    Search, // search
+    Search,       // search
+    SearchCancel, // search_cancel
 
     Unknown(String),
 }
@@ -50,6 +51,7 @@ impl From<&str> for InternalMethod {
             "llm_set_key" => Self::LlmSetKey,
             "llm_set_provider" => Self::LlmSetProvider,
             "search" => Self::Search,
+            "search_cancel" => Self::SearchCancel,
             other => Self::Unknown(other.to_string()),
         }
     }
@@ -74,6 +76,7 @@ impl std::fmt::Display for InternalMethod {
             Self::LlmSetKey => "llm_set_key",
             Self::LlmSetProvider => "llm_set_provider",
             Self::Search => "search",
+            Self::SearchCancel => "search_cancel",
             Self::Unknown(s) => s,
         };
         write!(f, "{s}")
@@ -0,0 +1,1891 @@
+//! Typed browser <-> host messages
+//!
+//! Every message exchanged over the WebSocket / WebTransport channel is
+//! described here once, so the host and UI no longer hand-parse
+//! `rmpv::Value` arrays. The wire format is unchanged MessagePack:
+//!
+//! ```text
+//! Browser -> Host
+//!   [0, id, method, params]                   RPC request
+//!   [2, method, params]                       notification
+//!   [2, "crdt_sync", [message, buffer_id]]    CRDT sync for a buffer
+//!   [2, "collab_chat", [message, to?]]  [2, "collab_signal", [to, kind, payload]]
+//!   [2, "collab_name", [name]]
+//!   [2, "comment_add", [body, parent?, target?]]  [2, "comment_edit", [id, body]]
+//!   [2, "comment_resolve", [id, resolved]]  [2, "comment_delete", [id]]
+//!   [3, id, ok, result]                       BrowserFS response
+//!   ["input", keys]  ["paste", text]  ["resize", cols, rows]
+//!   ["mouse", button, action, modifier, row, col]
+//!   ["scroll", direction, modifier, row, col]
+//!   ["input_mouse", button, action, modifier, grid, row, col]
+//!   ["file_drop", name, bin]
+//!   ["terminal_spawn", cols, rows]  ["terminal_input", bin]  ["terminal_close"]
+//!
+//! Host -> Browser
+//!   ["session", id, is_viewer, protocol_version]
+//!   ["protocol_mismatch", host_version, min_version]
+//!   [1, id, error, result]                    RPC response
+//!   [2, method, params]                       notification (redraw, clipboard_*, ...)
+//!   [2, "crdt_sync", [message, buffer_id]]    CRDT sync for a buffer
+//!   [2, "collab_presence", [self_id, driver?, [participant, ...]]]
+//!   [2, "collab_peer", [participant]]  [2, "collab_left", [peer_id]]
+//!   [2, "collab_role", [peer_id, role]]  [2, "collab_control", [driver?]]
+//!   [2, "collab_control_request", [peer_id]]
+//!   [2, "collab_chat", [from, message, timestamp, to?]]
+//!   [2, "collab_signal", [from, kind, payload]]
+//!   [2, "comments", [[comment, ...]]]  [2, "comment", [comment]]
+//!   [2, "comment_deleted", [id]]
+//!   [2, "auth_required", [login_url]]  [2, "auth_restored", []]
+//!   [2, id, [op, namespace, path, bin?]]      BrowserFS request
+//!   ["cwd_info", map]  ["recording_start", reg]  ["recording_stop"]
+//!   ["terminal_spawned", ok, error?]  ["terminal_output", bin]
+//! ```
+//!
+//! Decoding is strict: a known message with a missing or mistyped field is
+//! an error rather than a silently defaulted value.
+
+use std::fmt;
+
+use rmpv::Value;
+use serde::{Deserialize, Serialize};
+
+use crate::crdt::SyncMessage;
+use crate::datagram::{decode_envelope, DATAGRAM_ENVELOPE};
+
+/// Protocol version spoken by this build
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Oldest peer version this build still understands
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// URL query parameter carrying the browser's protocol version
+pub const PROTOCOL_QUERY_PARAM: &str = "protocol";
+
+/// Check that a peer's protocol version is supported by this build
+pub fn check_version(remote: u32) -> Result<(), ProtocolError> {
+    if (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&remote) {
+        Ok(())
+    } else {
+        Err(ProtocolError::VersionMismatch {
+            local: PROTOCOL_VERSION,
+            remote,
+        })
+    }
+}
+
+/// Message decoding error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// Bytes are not valid MessagePack
+    Malformed(String),
+    /// Top-level value is not a non-empty array
+    NotAnArray,
+    /// Tag or method is not part of the protocol
+    UnknownMessage(String),
+    MissingField {
+        message: &'static str,
+        field: &'static str,
+    },
+    InvalidField {
+        message: &'static str,
+        field: &'static str,
+    },
+    /// Peer speaks an unsupported protocol version
+    VersionMismatch { local: u32, remote: u32 },
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed(e) => write!(f, "malformed message: {e}"),
+            Self::NotAnArray => write!(f, "message is not a non-empty array"),
+            Self::UnknownMessage(tag) => write!(f, "unknown message: {tag}"),
+            Self::MissingField { message, field } => {
+                write!(f, "{message}: missing field `{field}`")
+            }
+            Self::InvalidField { message, field } => {
+                write!(f, "{message}: invalid field `{field}`")
+            }
+            Self::VersionMismatch { local, remote } => write!(
+                f,
+                "protocol version mismatch: local {local} (min {MIN_PROTOCOL_VERSION}), remote {remote}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+/// Message sent by the browser to the host
+#[derive(Debug, Clone, PartialEq)]
+pub enum BrowserMessage {
+    /// RPC request, answered with [`HostMessage::RpcResponse`]
+    Rpc {
+        id: u64,
+        method: String,
+        params: Vec<Value>,
+    },
+    /// Answer to a [`HostMessage::FsRequest`]; `result` is the error string when `!ok`
+    FsResponse {
+        id: u64,
+        ok: bool,
+        result: Value,
+    },
+    /// Answer to a [`HostMessage::ClipboardRead`]
+    ClipboardReadResponse {
+        request_id: u32,
+        content: Value,
+        session_id: String,
+    },
+    /// CRDT sync message for a Neovim buffer
+    CrdtSync {
+        buffer_id: u64,
+        message: SyncMessage,
+    },
+    /// Chat message; `to` addresses a single participant
+    Chat {
+        message: String,
+        to: Option<String>,
+    },
+    /// WebRTC signaling payload for one participant
+    Signal {
+        to: String,
+        /// `offer`, `answer` or `ice_candidate`
+        kind: String,
+        payload: String,
+    },
+    /// Display name shown to the other participants
+    SetName(String),
+    /// New comment, or a reply when `parent` is set
+    CommentAdd {
+        body: String,
+        parent: Option<String>,
+        target: CommentTarget,
+    },
+    CommentEdit {
+        id: String,
+        body: String,
+    },
+    CommentResolve {
+        id: String,
+        resolved: bool,
+    },
+    CommentDelete(String),
+    /// Any other notification
+    Notification {
+        method: String,
+        params: Vec<Value>,
+    },
+    /// Keys in Neovim notation
+    Input(String),
+    Paste(String),
+    Resize {
+        cols: u32,
+        rows: u32,
+    },
+    /// Mouse event in global grid coordinates
+    Mouse {
+        button: String,
+        action: String,
+        modifier: String,
+        row: i64,
+        col: i64,
+    },
+    Scroll {
+        direction: String,
+        modifier: String,
+        row: i64,
+        col: i64,
+    },
+    /// Multigrid mouse event
+    InputMouse {
+        button: String,
+        action: String,
+        modifier: String,
+        grid: i64,
+        row: i64,
+        col: i64,
+    },
+    FileDrop {
+        name: String,
+        data: Vec<u8>,
+    },
+    TerminalSpawn {
+        cols: u16,
+        rows: u16,
+    },
+    TerminalInput(Vec<u8>),
+    TerminalClose,
+    /// Raw datagram carried in the reliable envelope
+    Datagram(Vec<u8>),
+}
+
+/// Working directory and file shown in the status bar
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CwdInfo {
+    pub cwd: String,
+    pub file: String,
+    /// `local`, `browser` or `ssh`
+    pub backend: String,
+    pub git_branch: Option<String>,
+}
+
+impl CwdInfo {
+    /// Build the MessagePack map `{cwd, file, backend, git_branch}`
+    pub fn to_value(&self) -> Value {
+        Value::Map(vec![
+            ("cwd".into(), self.cwd.as_str().into()),
+            ("file".into(), self.file.as_str().into()),
+            ("backend".into(), self.backend.as_str().into()),
+            (
+                "git_branch".into(),
+                self.git_branch.as_deref().map_or(Value::Nil, Value::from),
+            ),
+        ])
+    }
+
+    /// Parse the MessagePack map form
+    pub fn from_value(value: Value) -> Result<Self, ProtocolError> {
+        let mut bytes = Vec::new();
+        rmpv::encode::write_value(&mut bytes, &value)
+            .map_err(|e| ProtocolError::Malformed(e.to_string()))?;
+        rmp_serde::from_slice(&bytes).map_err(|_| ProtocolError::InvalidField {
+            message: "cwd_info",
+            field: "info",
+        })
+    }
+}
+
+/// Session participant, as shown in the presence list
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Participant {
+    pub id: String,
+    pub name: Option<String>,
+    /// `viewer`, `commenter`, `editor` or `owner`
+    pub role: String,
+    /// CSS colour for the participant's cursor
+    pub color: String,
+}
+
+impl Participant {
+    /// Build the MessagePack map `{id, name, role, color}`
+    pub fn to_value(&self) -> Value {
+        Value::Map(vec![
+            ("id".into(), self.id.as_str().into()),
+            (
+                "name".into(),
+                self.name.as_deref().map_or(Value::Nil, Value::from),
+            ),
+            ("role".into(), self.role.as_str().into()),
+            ("color".into(), self.color.as_str().into()),
+        ])
+    }
+
+    /// Parse the MessagePack map form
+    pub fn from_value(value: &Value) -> Result<Self, ProtocolError> {
+        let mut bytes = Vec::new();
+        rmpv::encode::write_value(&mut bytes, value)
+            .map_err(|e| ProtocolError::Malformed(e.to_string()))?;
+        rmp_serde::from_slice(&bytes).map_err(|_| ProtocolError::InvalidField {
+            message: "participant",
+            field: "participant",
+        })
+    }
+}
+
+/// Where a new comment is anchored
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CommentTarget {
+    /// Not anchored to any file
+    #[default]
+    General,
+    /// The cursor line (or visual selection) of the current Neovim buffer
+    Cursor,
+    /// 1-based inclusive line range of a file
+    Lines {
+        path: String,
+        start_line: u32,
+        end_line: u32,
+    },
+}
+
+impl CommentTarget {
+    /// nil, `"cursor"` or the map `{path, start_line, end_line}`
+    pub fn to_value(&self) -> Value {
+        match self {
+            Self::General => Value::Nil,
+            Self::Cursor => "cursor".into(),
+            Self::Lines {
+                path,
+                start_line,
+                end_line,
+            } => Value::Map(vec![
+                ("path".into(), path.as_str().into()),
+                ("start_line".into(), (*start_line).into()),
+                ("end_line".into(), (*end_line).into()),
+            ]),
+        }
+    }
+
+    fn from_value(value: Option<&Value>) -> Result<Self, ProtocolError> {
+        let invalid = ProtocolError::InvalidField {
+            message: "comment_add",
+            field: "target",
+        };
+        match value {
+            None => Ok(Self::General),
+            Some(Value::String(s)) if s.as_str() == Some("cursor") => Ok(Self::Cursor),
+            Some(Value::Map(map)) => {
+                let get = |key: &str| {
+                    map.iter()
+                        .find(|(k, _)| k.as_str() == Some(key))
+                        .map(|(_, v)| v)
+                };
+                let line = |key: &str| get(key).and_then(int_from::<u32>);
+                match (get("path").and_then(Value::as_str), line("start_line")) {
+                    (Some(path), Some(start_line)) => Ok(Self::Lines {
+                        path: path.to_string(),
+                        start_line,
+                        end_line: line("end_line").unwrap_or(start_line),
+                    }),
+                    _ => Err(invalid),
+                }
+            }
+            Some(_) => Err(invalid),
+        }
+    }
+}
+
+/// Review comment or reply in a shared session
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Comment {
+    pub id: String,
+    /// Thread root this comment replies to
+    pub parent: Option<String>,
+    /// Participant id of the author
+    pub author_id: String,
+    /// Display name of the author when the comment was written
+    pub author: String,
+    pub body: String,
+    /// File the thread is anchored to; `None` for general comments
+    pub path: Option<String>,
+    /// 1-based inclusive line range, kept up to date as the file is edited
+    pub start_line: u32,
+    pub end_line: u32,
+    pub resolved: bool,
+    /// Milliseconds since the Unix epoch
+    pub created_at: u64,
+}
+
+impl Comment {
+    /// Build the MessagePack map form
+    pub fn to_value(&self) -> Value {
+        let optional = |v: &Option<String>| v.as_deref().map_or(Value::Nil, Value::from);
+        Value::Map(vec![
+            ("id".into(), self.id.as_str().into()),
+            ("parent".into(), optional(&self.parent)),
+            ("author_id".into(), self.author_id.as_str().into()),
+            ("author".into(), self.author.as_str().into()),
+            ("body".into(), self.body.as_str().into()),
+            ("path".into(), optional(&self.path)),
+            ("start_line".into(), self.start_line.into()),
+            ("end_line".into(), self.end_line.into()),
+            ("resolved".into(), self.resolved.into()),
+            ("created_at".into(), self.created_at.into()),
+        ])
+    }
+
+    /// Parse the MessagePack map form
+    pub fn from_value(value: &Value) -> Result<Self, ProtocolError> {
+        let mut bytes = Vec::new();
+        rmpv::encode::write_value(&mut bytes, value)
+            .map_err(|e| ProtocolError::Malformed(e.to_string()))?;
+        rmp_serde::from_slice(&bytes).map_err(|_| ProtocolError::InvalidField {
+            message: "comment",
+            field: "comment",
+        })
+    }
+}
+
+/// Image overlay command (`nvim_web_image` notification)
+///
+/// Positions and sizes are in grid cells.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageCommand {
+    Show {
+        id: String,
+        url: String,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+    Hide {
+        id: String,
+    },
+    Clear,
+}
+
+/// Message sent by the host to the browser
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostMessage {
+    /// Handshake; `protocol_version` is 0 for hosts that predate versioning
+    Session {
+        session_id: String,
+        is_viewer: bool,
+        protocol_version: u32,
+    },
+    /// Sent instead of `Session` when the browser's version is unsupported
+    ProtocolMismatch {
+        host_version: u32,
+        min_version: u32,
+    },
+    RpcResponse {
+        id: u64,
+        error: Value,
+        result: Value,
+    },
+    /// Neovim UI events, passed through verbatim
+    Redraw(Vec<Value>),
+    OptionSet {
+        name: String,
+        value: Value,
+    },
+    ClipboardRead {
+        request_id: u32,
+        session_id: String,
+    },
+    ClipboardWrite {
+        lines: Vec<String>,
+        regtype: String,
+    },
+    CwdInfo(CwdInfo),
+    RecordingStart {
+        register: String,
+    },
+    RecordingStop,
+    VfxChange {
+        mode: String,
+    },
+    /// CRDT sync message for a Neovim buffer
+    CrdtSync {
+        buffer_id: u64,
+        message: SyncMessage,
+    },
+    Image(ImageCommand),
+    /// Custom UI action (`nvim_web_action`), e.g. `browse_files`
+    Action {
+        name: String,
+        args: Vec<Value>,
+    },
+    /// BrowserFS operation, answered with [`BrowserMessage::FsResponse`]
+    FsRequest {
+        id: u64,
+        operation: String,
+        namespace: String,
+        path: String,
+        data: Option<Vec<u8>>,
+    },
+    /// Result of `terminal_spawn`; `error` is set on failure
+    TerminalSpawned {
+        error: Option<String>,
+    },
+    TerminalOutput(Vec<u8>),
+    /// Participants present when joining; `driver` holds edit control
+    Presence {
+        self_id: String,
+        driver: Option<String>,
+        participants: Vec<Participant>,
+    },
+    /// A participant joined or changed
+    PeerInfo(Participant),
+    PeerLeft {
+        peer_id: String,
+    },
+    RoleChanged {
+        peer_id: String,
+        role: String,
+    },
+    /// Edit control moved; `None` hands it back to the owners
+    ControlChanged {
+        driver: Option<String>,
+    },
+    ControlRequested {
+        peer_id: String,
+    },
+    /// Chat message; `to` is set for private messages
+    Chat {
+        from: String,
+        message: String,
+        /// Milliseconds since the Unix epoch
+        timestamp: u64,
+        to: Option<String>,
+    },
+    /// WebRTC signaling payload from another participant
+    Signal {
+        from: String,
+        kind: String,
+        payload: String,
+    },
+    /// Every comment in the session, sent on connect
+    Comments(Vec<Comment>),
+    /// A comment was added or changed
+    Comment(Comment),
+    /// A comment and its replies were deleted
+    CommentDeleted {
+        id: String,
+    },
+    /// The login session expired; the connection stays open until the user
+    /// logs in again at `login_url`
+    AuthRequired {
+        login_url: String,
+    },
+    /// The user logged in again after [`HostMessage::AuthRequired`]
+    AuthRestored,
+    /// Raw datagram carried in the reliable envelope
+    Datagram(Vec<u8>),
+    /// Any other notification
+    Notification {
+        method: String,
+        params: Vec<Value>,
+    },
+}
+
+impl BrowserMessage {
+    /// Build the MessagePack value for this message
+    pub fn to_value(&self) -> Value {
+        match self {
+            Self::Rpc { id, method, params } => Value::Array(vec![
+                0.into(),
+                (*id).into(),
+                method.as_str().into(),
+                Value::Array(params.clone()),
+            ]),
+            Self::FsResponse { id, ok, result } => {
+                Value::Array(vec![3.into(), (*id).into(), (*ok).into(), result.clone()])
+            }
+            Self::ClipboardReadResponse {
+                request_id,
+                content,
+                session_id,
+            } => notification(
+                "clipboard_read_response",
+                vec![
+                    (*request_id).into(),
+                    content.clone(),
+                    session_id.as_str().into(),
+                ],
+            ),
+            Self::CrdtSync { buffer_id, message } => crdt_sync(*buffer_id, message),
+            Self::Chat { message, to } => {
+                let mut params = vec![message.as_str().into()];
+                params.extend(to.iter().map(|to| Value::from(to.as_str())));
+                notification("collab_chat", params)
+            }
+            Self::Signal { to, kind, payload } => notification(
+                "collab_signal",
+                vec![
+                    to.as_str().into(),
+                    kind.as_str().into(),
+                    payload.as_str().into(),
+                ],
+            ),
+            Self::SetName(name) => notification("collab_name", vec![name.as_str().into()]),
+            Self::CommentAdd {
+                body,
+                parent,
+                target,
+            } => notification(
+                "comment_add",
+                vec![
+                    body.as_str().into(),
+                    parent.as_deref().map_or(Value::Nil, Value::from),
+                    target.to_value(),
+                ],
+            ),
+            Self::CommentEdit { id, body } => notification(
+                "comment_edit",
+                vec![id.as_str().into(), body.as_str().into()],
+            ),
+            Self::CommentResolve { id, resolved } => notification(
+                "comment_resolve",
+                vec![id.as_str().into(), (*resolved).into()],
+            ),
+            Self::CommentDelete(id) => notification("comment_delete", vec![id.as_str().into()]),
+            Self::Notification { method, params } => notification(method, params.clone()),
+            Self::Input(keys) => tagged("input", vec![keys.as_str().into()]),
+            Self::Paste(text) => tagged("paste", vec![text.as_str().into()]),
+            Self::Resize { cols, rows } => tagged("resize", vec![(*cols).into(), (*rows).into()]),
+            Self::Mouse {
+                button,
+                action,
+                modifier,
+                row,
+                col,
+            } => tagged(
+                "mouse",
+                vec![
+                    button.as_str().into(),
+                    action.as_str().into(),
+                    modifier.as_str().into(),
+                    (*row).into(),
+                    (*col).into(),
+                ],
+            ),
+            Self::Scroll {
+                direction,
+                modifier,
+                row,
+                col,
+            } => tagged(
+                "scroll",
+                vec![
+                    direction.as_str().into(),
+                    modifier.as_str().into(),
+                    (*row).into(),
+                    (*col).into(),
+                ],
+            ),
+            Self::InputMouse {
+                button,
+                action,
+                modifier,
+                grid,
+                row,
+                col,
+            } => tagged(
+                "input_mouse",
+                vec![
+                    button.as_str().into(),
+                    action.as_str().into(),
+                    modifier.as_str().into(),
+                    (*grid).into(),
+                    (*row).into(),
+                    (*col).into(),
+                ],
+            ),
+            Self::FileDrop { name, data } => tagged(
+                "file_drop",
+                vec![name.as_str().into(), Value::Binary(data.clone())],
+            ),
+            Self::TerminalSpawn { cols, rows } => {
+                tagged("terminal_spawn", vec![(*cols).into(), (*rows).into()])
+            }
+            Self::TerminalInput(data) => {
+                tagged("terminal_input", vec![Value::Binary(data.clone())])
+            }
+            Self::TerminalClose => tagged("terminal_close", vec![]),
+            Self::Datagram(raw) => tagged(DATAGRAM_ENVELOPE, vec![Value::Binary(raw.clone())]),
+        }
+    }
+
+    /// Parse a MessagePack value
+    pub fn from_value(value: Value) -> Result<Self, ProtocolError> {
+        let items = into_items(value)?;
+        if let Some(kind) = items[0].as_u64() {
+            return Self::from_typed(kind, items);
+        }
+        let tag = items[0]
+            .as_str()
+            .ok_or(ProtocolError::NotAnArray)?
+            .to_string();
+        let f = Fields::new("browser message", &items[1..]);
+
+        Ok(match tag.as_str() {
+            "input" => Self::Input(f.named("input").string("keys")?),
+            "paste" => Self::Paste(f.named("paste").string("text")?),
+            "resize" => {
+                let mut f = f.named("resize");
+                Self::Resize {
+                    cols: f.int("cols")?,
+                    rows: f.int("rows")?,
+                }
+            }
+            "mouse" => {
+                let mut f = f.named("mouse");
+                Self::Mouse {
+                    button: f.string("button")?,
+                    action: f.string("action")?,
+                    modifier: f.string("modifier")?,
+                    row: f.int("row")?,
+                    col: f.int("col")?,
+                }
+            }
+            "scroll" => {
+                let mut f = f.named("scroll");
+                Self::Scroll {
+                    direction: f.string("direction")?,
+                    modifier: f.string("modifier")?,
+                    row: f.int("row")?,
+                    col: f.int("col")?,
+                }
+            }
+            "input_mouse" => {
+                let mut f = f.named("input_mouse");
+                Self::InputMouse {
+                    button: f.string("button")?,
+                    action: f.string("action")?,
+                    modifier: f.string("modifier")?,
+                    grid: f.int("grid")?,
+                    row: f.int("row")?,
+                    col: f.int("col")?,
+                }
+            }
+            "file_drop" => {
+                let mut f = f.named("file_drop");
+                Self::FileDrop {
+                    name: f.string("name")?,
+                    data: f.bytes("data")?,
+                }
+            }
+            "terminal_spawn" => {
+                let mut f = f.named("terminal_spawn");
+                Self::TerminalSpawn {
+                    cols: f.int("cols")?,
+                    rows: f.int("rows")?,
+                }
+            }
+            // Older UIs send terminal input as a string
+            "terminal_input" => {
+                Self::TerminalInput(f.named("terminal_input").text_or_bytes("data")?)
+            }
+            "terminal_close" => Self::TerminalClose,
+            DATAGRAM_ENVELOPE => Self::Datagram(f.named(DATAGRAM_ENVELOPE).bytes("data")?),
+            other => return Err(ProtocolError::UnknownMessage(other.to_string())),
+        })
+    }
+
+    fn from_typed(kind: u64, items: Vec<Value>) -> Result<Self, ProtocolError> {
+        match kind {
+            0 => {
+                let mut f = Fields::new("rpc request", &items[1..]);
+                Ok(Self::Rpc {
+                    id: f.int("id")?,
+                    method: f.string("method")?,
+                    params: f.array("params")?,
+                })
+            }
+            2 => {
+                let mut f = Fields::new("notification", &items[1..]);
+                let method = f.string("method")?;
+                let params = f.array("params")?;
+                match method.as_str() {
+                    "clipboard_read_response" => {
+                        let mut f = Fields::new("clipboard_read_response", &params);
+                        Ok(Self::ClipboardReadResponse {
+                            request_id: f.int("request_id")?,
+                            content: f.value("content")?.clone(),
+                            session_id: f.string("session_id")?,
+                        })
+                    }
+                    "crdt_sync" => {
+                        let (buffer_id, message) = crdt_sync_from_params(&params)?;
+                        Ok(Self::CrdtSync { buffer_id, message })
+                    }
+                    "collab_chat" => {
+                        let mut f = Fields::new("collab_chat", &params);
+                        Ok(Self::Chat {
+                            message: f.string("message")?,
+                            to: f.optional_string("to")?,
+                        })
+                    }
+                    "collab_signal" => {
+                        let mut f = Fields::new("collab_signal", &params);
+                        Ok(Self::Signal {
+                            to: f.string("to")?,
+                            kind: f.string("kind")?,
+                            payload: f.string("payload")?,
+                        })
+                    }
+                    "collab_name" => Ok(Self::SetName(
+                        Fields::new("collab_name", &params).string("name")?,
+                    )),
+                    "comment_add" => {
+                        let mut f = Fields::new("comment_add", &params);
+                        Ok(Self::CommentAdd {
+                            body: f.string("body")?,
+                            parent: f.optional_string("parent")?,
+                            target: CommentTarget::from_value(f.optional())?,
+                        })
+                    }
+                    "comment_edit" => {
+                        let mut f = Fields::new("comment_edit", &params);
+                        Ok(Self::CommentEdit {
+                            id: f.string("id")?,
+                            body: f.string("body")?,
+                        })
+                    }
+                    "comment_resolve" => {
+                        let mut f = Fields::new("comment_resolve", &params);
+                        Ok(Self::CommentResolve {
+                            id: f.string("id")?,
+                            resolved: f.bool("resolved")?,
+                        })
+                    }
+                    "comment_delete" => Ok(Self::CommentDelete(
+                        Fields::new("comment_delete", &params).string("id")?,
+                    )),
+                    _ => Ok(Self::Notification { method, params }),
+                }
+            }
+            3 => {
+                let mut f = Fields::new("fs response", &items[1..]);
+                Ok(Self::FsResponse {
+                    id: f.int("id")?,
+                    ok: f.bool("ok")?,
+                    result: f.value("result")?.clone(),
+                })
+            }
+            other => Err(ProtocolError::UnknownMessage(format!("type {other}"))),
+        }
+    }
+
+    /// Encode to MessagePack bytes
+    pub fn encode(&self) -> Vec<u8> {
+        encode_value(&self.to_value())
+    }
+
+    /// Decode from MessagePack bytes
+    pub fn decode(data: &[u8]) -> Result<Self, ProtocolError> {
+        // Fast path for the hot datagram envelope
+        if let Some(raw) = decode_envelope(data) {
+            return Ok(Self::Datagram(raw));
+        }
+        Self::from_value(decode_value(data)?)
+    }
+}
+
+impl HostMessage {
+    /// Handshake rejection sent to a browser with an unsupported version
+    pub const fn protocol_mismatch() -> Self {
+        Self::ProtocolMismatch {
+            host_version: PROTOCOL_VERSION,
+            min_version: MIN_PROTOCOL_VERSION,
+        }
+    }
+
+    /// Build the MessagePack value for this message
+    pub fn to_value(&self) -> Value {
+        match self {
+            Self::Session {
+                session_id,
+                is_viewer,
+                protocol_version,
+            } => tagged(
+                "session",
+                vec![
+                    session_id.as_str().into(),
+                    (*is_viewer).into(),
+                    (*protocol_version).into(),
+                ],
+            ),
+            Self::ProtocolMismatch {
+                host_version,
+                min_version,
+            } => tagged(
+                "protocol_mismatch",
+                vec![(*host_version).into(), (*min_version).into()],
+            ),
+            Self::RpcResponse { id, error, result } => {
+                Value::Array(vec![1.into(), (*id).into(), error.clone(), result.clone()])
+            }
+            Self::Redraw(events) => notification("redraw", events.clone()),
+            Self::OptionSet { name, value } => {
+                notification("option_set", vec![name.as_str().into(), value.clone()])
+            }
+            Self::ClipboardRead {
+                request_id,
+                session_id,
+            } => notification(
+                "clipboard_read",
+                vec![(*request_id).into(), session_id.as_str().into()],
+            ),
+            Self::ClipboardWrite { lines, regtype } => notification(
+                "clipboard_write",
+                vec![
+                    Value::Array(lines.iter().map(|l| l.as_str().into()).collect()),
+                    regtype.as_str().into(),
+                ],
+            ),
+            Self::CwdInfo(info) => tagged("cwd_info", vec![info.to_value()]),
+            Self::RecordingStart { register } => {
+                tagged("recording_start", vec![register.as_str().into()])
+            }
+            Self::RecordingStop => tagged("recording_stop", vec![]),
+            Self::VfxChange { mode } => notification("vfx_change", vec![mode.as_str().into()]),
+            Self::CrdtSync { buffer_id, message } => crdt_sync(*buffer_id, message),
+            Self::Image(cmd) => notification("nvim_web_image", image_to_values(cmd)),
+            Self::Action { name, args } => {
+                let mut params = vec![name.as_str().into()];
+                params.extend(args.iter().cloned());
+                notification("nvim_web_action", params)
+            }
+            Self::FsRequest {
+                id,
+                operation,
+                namespace,
+                path,
+                data,
+            } => {
+                let mut params = vec![
+                    operation.as_str().into(),
+                    namespace.as_str().into(),
+                    path.as_str().into(),
+                ];
+                params.extend(data.iter().map(|d| Value::Binary(d.clone())));
+                Value::Array(vec![2.into(), (*id).into(), Value::Array(params)])
+            }
+            Self::TerminalSpawned { error } => {
+                let mut fields = vec![error.is_none().into()];
+                fields.extend(error.iter().map(|e| Value::from(e.as_str())));
+                tagged("terminal_spawned", fields)
+            }
+            Self::TerminalOutput(data) => {
+                tagged("terminal_output", vec![Value::Binary(data.clone())])
+            }
+            Self::Presence {
+                self_id,
+                driver,
+                participants,
+            } => notification(
+                "collab_presence",
+                vec![
+                    self_id.as_str().into(),
+                    driver.as_deref().map_or(Value::Nil, Value::from),
+                    Value::Array(participants.iter().map(Participant::to_value).collect()),
+                ],
+            ),
+            Self::PeerInfo(participant) => {
+                notification("collab_peer", vec![participant.to_value()])
+            }
+            Self::PeerLeft { peer_id } => {
+                notification("collab_left", vec![peer_id.as_str().into()])
+            }
+            Self::RoleChanged { peer_id, role } => notification(
+                "collab_role",
+                vec![peer_id.as_str().into(), role.as_str().into()],
+            ),
+            Self::ControlChanged { driver } => notification(
+                "collab_control",
+                vec![driver.as_deref().map_or(Value::Nil, Value::from)],
+            ),
+            Self::ControlRequested { peer_id } => {
+                notification("collab_control_request", vec![peer_id.as_str().into()])
+            }
+            Self::Chat {
+                from,
+                message,
+                timestamp,
+                to,
+            } => {
+                let mut params = vec![
+                    from.as_str().into(),
+                    message.as_str().into(),
+                    (*timestamp).into(),
+                ];
+                params.extend(to.iter().map(|to| Value::from(to.as_str())));
+                notification("collab_chat", params)
+            }
+            Self::Signal {
+                from,
+                kind,
+                payload,
+            } => notification(
+                "collab_signal",
+                vec![
+                    from.as_str().into(),
+                    kind.as_str().into(),
+                    payload.as_str().into(),
+                ],
+            ),
+            Self::Comments(comments) => notification(
+                "comments",
+                vec![Value::Array(
+                    comments.iter().map(Comment::to_value).collect(),
+                )],
+            ),
+            Self::Comment(comment) => notification("comment", vec![comment.to_value()]),
+            Self::CommentDeleted { id } => {
+                notification("comment_deleted", vec![id.as_str().into()])
+            }
+            Self::AuthRequired { login_url } => {
+                notification("auth_required", vec![login_url.as_str().into()])
+            }
+            Self::AuthRestored => notification("auth_restored", vec![]),
+            Self::Datagram(raw) => tagged(DATAGRAM_ENVELOPE, vec![Value::Binary(raw.clone())]),
+            Self::Notification { method, params } => notification(method, params.clone()),
+        }
+    }
+
+    /// Parse a MessagePack value
+    pub fn from_value(value: Value) -> Result<Self, ProtocolError> {
+        let items = into_items(value)?;
+        match items[0].as_u64() {
+            Some(1) => {
+                let mut f = Fields::new("rpc response", &items[1..]);
+                return Ok(Self::RpcResponse {
+                    id: f.int("id")?,
+                    error: f.value("error")?.clone(),
+                    result: f.value("result")?.clone(),
+                });
+            }
+            Some(2) => return Self::from_notification(&items[1..]),
+            Some(other) => return Err(ProtocolError::UnknownMessage(format!("type {other}"))),
+            None => {}
+        }
+
+        let tag = items[0]
+            .as_str()
+            .ok_or(ProtocolError::NotAnArray)?
+            .to_string();
+        let f = Fields::new("host message", &items[1..]);
+
+        Ok(match tag.as_str() {
+            "session" => {
+                let mut f = f.named("session");
+                Self::Session {
+                    session_id: f.string("session_id")?,
+                    is_viewer: f.bool("is_viewer")?,
+                    // Hosts before versioning omit the field
+                    protocol_version: f.optional_int("protocol_version")?.unwrap_or(0),
+                }
+            }
+            "protocol_mismatch" => {
+                let mut f = f.named("protocol_mismatch");
+                Self::ProtocolMismatch {
+                    host_version: f.int("host_version")?,
+                    min_version: f.int("min_version")?,
+                }
+            }
+            "cwd_info" => Self::CwdInfo(CwdInfo::from_value(
+                f.named("cwd_info").value("info")?.clone(),
+            )?),
+            "recording_start" => Self::RecordingStart {
+                register: f.named("recording_start").string("register")?,
+            },
+            "recording_stop" => Self::RecordingStop,
+            "terminal_spawned" => {
+                let mut f = f.named("terminal_spawned");
+                let ok = f.bool("ok")?;
+                let error = f.optional_string("error")?;
+                Self::TerminalSpawned {
+                    error: if ok {
+                        None
+                    } else {
+                        Some(error.unwrap_or_default())
+                    },
+                }
+            }
+            "terminal_output" => Self::TerminalOutput(f.named("terminal_output").bytes("data")?),
+            DATAGRAM_ENVELOPE => Self::Datagram(f.named(DATAGRAM_ENVELOPE).bytes("data")?),
+            other => return Err(ProtocolError::UnknownMessage(other.to_string())),
+        })
+    }
+
+    fn from_notification(items: &[Value]) -> Result<Self, ProtocolError> {
+        // BrowserFS requests reuse type 2 with an integer id in place of the method
+        if items.first().and_then(Value::as_u64).is_some() {
+            let mut f = Fields::new("fs request", items);
+            let id = f.int("id")?;
+            let params = f.array("params")?;
+            let mut f = Fields::new("fs request", &params);
+            return Ok(Self::FsRequest {
+                id,
+                operation: f.string("operation")?,
+                namespace: f.string("namespace")?,
+                path: f.string("path")?,
+                data: f.optional_bytes("data")?,
+            });
+        }
+
+        let mut f = Fields::new("notification", items);
+        let method = f.string("method")?;
+        let params = f.array("params")?;
+
+        Ok(match method.as_str() {
+            "redraw" => Self::Redraw(params),
+            "option_set" => {
+                let mut f = Fields::new("option_set", &params);
+                Self::OptionSet {
+                    name: f.string("name")?,
+                    value: f.value("value")?.clone(),
+                }
+            }
+            "clipboard_read" => {
+                let mut f = Fields::new("clipboard_read", &params);
+                Self::ClipboardRead {
+                    request_id: f.int("request_id")?,
+                    session_id: f.string("session_id")?,
+                }
+            }
+            "clipboard_write" => {
+                let mut f = Fields::new("clipboard_write", &params);
+                Self::ClipboardWrite {
+                    lines: f.strings("lines")?,
+                    regtype: f.optional_string("regtype")?.unwrap_or_default(),
+                }
+            }
+            "vfx_change" => Self::VfxChange {
+                mode: Fields::new("vfx_change", &params).string("mode")?,
+            },
+            "crdt_sync" => {
+                let (buffer_id, message) = crdt_sync_from_params(&params)?;
+                Self::CrdtSync { buffer_id, message }
+            }
+            "nvim_web_image" => Self::Image(image_from_values(&params)?),
+            "nvim_web_action" => {
+                let mut f = Fields::new("nvim_web_action", &params);
+                Self::Action {
+                    name: f.string("name")?,
+                    args: params[1..].to_vec(),
+                }
+            }
+            "collab_presence" => {
+                let mut f = Fields::new("collab_presence", &params);
+                Self::Presence {
+                    self_id: f.string("self_id")?,
+                    driver: f.optional_string("driver")?,
+                    participants: f
+                        .array("participants")?
+                        .iter()
+                        .map(Participant::from_value)
+                        .collect::<Result<_, _>>()?,
+                }
+            }
+            "collab_peer" => Self::PeerInfo(Participant::from_value(
+                Fields::new("collab_peer", &params).value("participant")?,
+            )?),
+            "collab_left" => Self::PeerLeft {
+                peer_id: Fields::new("collab_left", &params).string("peer_id")?,
+            },
+            "collab_role" => {
+                let mut f = Fields::new("collab_role", &params);
+                Self::RoleChanged {
+                    peer_id: f.string("peer_id")?,
+                    role: f.string("role")?,
+                }
+            }
+            "collab_control" => Self::ControlChanged {
+                driver: Fields::new("collab_control", &params).optional_string("driver")?,
+            },
+            "collab_control_request" => Self::ControlRequested {
+                peer_id: Fields::new("collab_control_request", &params).string("peer_id")?,
+            },
+            "collab_chat" => {
+                let mut f = Fields::new("collab_chat", &params);
+                Self::Chat {
+                    from: f.string("from")?,
+                    message: f.string("message")?,
+                    timestamp: f.int("timestamp")?,
+                    to: f.optional_string("to")?,
+                }
+            }
+            "collab_signal" => {
+                let mut f = Fields::new("collab_signal", &params);
+                Self::Signal {
+                    from: f.string("from")?,
+                    kind: f.string("kind")?,
+                    payload: f.string("payload")?,
+                }
+            }
+            "comments" => Self::Comments(
+                Fields::new("comments", &params)
+                    .array("comments")?
+                    .iter()
+                    .map(Comment::from_value)
+                    .collect::<Result<_, _>>()?,
+            ),
+            "comment" => Self::Comment(Comment::from_value(
+                Fields::new("comment", &params).value("comment")?,
+            )?),
+            "comment_deleted" => Self::CommentDeleted {
+                id: Fields::new("comment_deleted", &params).string("id")?,
+            },
+            "auth_required" => Self::AuthRequired {
+                login_url: Fields::new("auth_required", &params).string("login_url")?,
+            },
+            "auth_restored" => Self::AuthRestored,
+            _ => Self::Notification { method, params },
+        })
+    }
+
+    /// Encode to MessagePack bytes
+    pub fn encode(&self) -> Vec<u8> {
+        encode_value(&self.to_value())
+    }
+
+    /// Decode from MessagePack bytes
+    pub fn decode(data: &[u8]) -> Result<Self, ProtocolError> {
+        Self::from_value(decode_value(data)?)
+    }
+}
+
+fn tagged(tag: &str, fields: Vec<Value>) -> Value {
+    let mut items = Vec::with_capacity(fields.len() + 1);
+    items.push(tag.into());
+    items.extend(fields);
+    Value::Array(items)
+}
+
+fn notification(method: &str, params: Vec<Value>) -> Value {
+    Value::Array(vec![2.into(), method.into(), Value::Array(params)])
+}
+
+fn into_items(value: Value) -> Result<Vec<Value>, ProtocolError> {
+    match value {
+        Value::Array(items) if !items.is_empty() => Ok(items),
+        _ => Err(ProtocolError::NotAnArray),
+    }
+}
+
+fn encode_value(value: &Value) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    // Writing to a Vec cannot fail
+    let _ = rmpv::encode::write_value(&mut bytes, value);
+    bytes
+}
+
+fn decode_value(data: &[u8]) -> Result<Value, ProtocolError> {
+    rmpv::decode::read_value(&mut &data[..]).map_err(|e| ProtocolError::Malformed(e.to_string()))
+}
+
+fn crdt_sync(buffer_id: u64, msg: &SyncMessage) -> Value {
+    notification("crdt_sync", vec![sync_to_value(msg), buffer_id.into()])
+}
+
+/// `[message, buffer_id]`
+fn crdt_sync_from_params(params: &[Value]) -> Result<(u64, SyncMessage), ProtocolError> {
+    let mut f = Fields::new("crdt_sync", params);
+    let message = sync_from_value(f.value("message")?)?;
+    Ok((f.int("buffer_id")?, message))
+}
+
+fn sync_to_value(msg: &SyncMessage) -> Value {
+    let (kind, field, data) = match msg {
+        SyncMessage::SyncStep1 { state_vector } => ("sync1", "state_vector", state_vector),
+        SyncMessage::SyncStep2 { update } => ("sync2", "update", update),
+        SyncMessage::Update { update } => ("update", "update", update),
+        SyncMessage::Awareness { data } => ("awareness", "data", data),
+    };
+    Value::Map(vec![
+        ("type".into(), kind.into()),
+        (field.into(), Value::Binary(data.clone())),
+    ])
+}
+
+fn sync_from_value(value: &Value) -> Result<SyncMessage, ProtocolError> {
+    const MSG: &str = "crdt_sync";
+    let map = value.as_map().ok_or(ProtocolError::InvalidField {
+        message: MSG,
+        field: "message",
+    })?;
+    let get = |key: &'static str| {
+        map.iter()
+            .find(|(k, _)| k.as_str() == Some(key))
+            .map(|(_, v)| v)
+            .ok_or(ProtocolError::MissingField {
+                message: MSG,
+                field: key,
+            })
+    };
+    let bytes = |key: &'static str| -> Result<Vec<u8>, ProtocolError> {
+        match get(key)? {
+            Value::Binary(data) => Ok(data.clone()),
+            // serde-encoded byte vectors arrive as integer arrays
+            Value::Array(ints) => ints
+                .iter()
+                .map(|v| v.as_u64().and_then(|b| u8::try_from(b).ok()))
+                .collect::<Option<Vec<u8>>>()
+                .ok_or(ProtocolError::InvalidField {
+                    message: MSG,
+                    field: key,
+                }),
+            _ => Err(ProtocolError::InvalidField {
+                message: MSG,
+                field: key,
+            }),
+        }
+    };
+
+    match get("type")?.as_str() {
+        Some("sync1") => Ok(SyncMessage::SyncStep1 {
+            state_vector: bytes("state_vector")?,
+        }),
+        Some("sync2") => Ok(SyncMessage::SyncStep2 {
+            update: bytes("update")?,
+        }),
+        Some("update") => Ok(SyncMessage::Update {
+            update: bytes("update")?,
+        }),
+        Some("awareness") => Ok(SyncMessage::Awareness {
+            data: bytes("data")?,
+        }),
+        _ => Err(ProtocolError::InvalidField {
+            message: MSG,
+            field: "type",
+        }),
+    }
+}
+
+fn image_to_values(cmd: &ImageCommand) -> Vec<Value> {
+    match cmd {
+        ImageCommand::Show {
+            id,
+            url,
+            x,
+            y,
+            width,
+            height,
+        } => vec![
+            "show".into(),
+            id.as_str().into(),
+            url.as_str().into(),
+            (*x).into(),
+            (*y).into(),
+            (*width).into(),
+            (*height).into(),
+        ],
+        ImageCommand::Hide { id } => vec!["hide".into(), id.as_str().into()],
+        ImageCommand::Clear => vec!["clear".into()],
+    }
+}
+
+fn image_from_values(params: &[Value]) -> Result<ImageCommand, ProtocolError> {
+    let mut f = Fields::new("nvim_web_image", params);
+    match f.string("command")?.as_str() {
+        "show" => Ok(ImageCommand::Show {
+            id: f.string("id")?,
+            url: f.string("url")?,
+            x: f.float("x")?,
+            y: f.float("y")?,
+            width: f.float("width")?,
+            height: f.float("height")?,
+        }),
+        "hide" => Ok(ImageCommand::Hide {
+            id: f.string("id")?,
+        }),
+        "clear" => Ok(ImageCommand::Clear),
+        _ => Err(ProtocolError::InvalidField {
+            message: "nvim_web_image",
+            field: "command",
+        }),
+    }
+}
+
+/// Sequential reader over a message's positional fields
+struct Fields<'a> {
+    message: &'static str,
+    items: std::slice::Iter<'a, Value>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(message: &'static str, items: &'a [Value]) -> Self {
+        Self {
+            message,
+            items: items.iter(),
+        }
+    }
+
+    /// Rename the message reported in errors
+    const fn named(self, message: &'static str) -> Self {
+        Self {
+            message,
+            items: self.items,
+        }
+    }
+
+    const fn invalid(&self, field: &'static str) -> ProtocolError {
+        ProtocolError::InvalidField {
+            message: self.message,
+            field,
+        }
+    }
+
+    fn value(&mut self, field: &'static str) -> Result<&'a Value, ProtocolError> {
+        self.items.next().ok_or(ProtocolError::MissingField {
+            message: self.message,
+            field,
+        })
+    }
+
+    /// Next field, `None` if absent or nil
+    fn optional(&mut self) -> Option<&'a Value> {
+        self.items.next().filter(|v| !v.is_nil())
+    }
+
+    fn string(&mut self, field: &'static str) -> Result<String, ProtocolError> {
+        let value = self.value(field)?;
+        value
+            .as_str()
+            .map(ToString::to_string)
+            .ok_or_else(|| self.invalid(field))
+    }
+
+    fn optional_string(&mut self, field: &'static str) -> Result<Option<String>, ProtocolError> {
+        self.optional()
+            .map(|v| {
+                v.as_str()
+                    .map(ToString::to_string)
+                    .ok_or_else(|| self.invalid(field))
+            })
+            .transpose()
+    }
+
+    fn strings(&mut self, field: &'static str) -> Result<Vec<String>, ProtocolError> {
+        let value = self.value(field)?;
+        value
+            .as_array()
+            .and_then(|items| {
+                items
+                    .iter()
+                    .map(|v| v.as_str().map(ToString::to_string))
+                    .collect()
+            })
+            .ok_or_else(|| self.invalid(field))
+    }
+
+    fn int<T: TryFrom<i64> + TryFrom<u64>>(
+        &mut self,
+        field: &'static str,
+    ) -> Result<T, ProtocolError> {
+        let value = self.value(field)?;
+        int_from(value).ok_or_else(|| self.invalid(field))
+    }
+
+    fn optional_int<T: TryFrom<i64> + TryFrom<u64>>(
+        &mut self,
+        field: &'static str,
+    ) -> Result<Option<T>, ProtocolError> {
+        self.optional()
+            .map(|v| int_from(v).ok_or_else(|| self.invalid(field)))
+            .transpose()
+    }
+
+    fn float(&mut self, field: &'static str) -> Result<f64, ProtocolError> {
+        let value = self.value(field)?;
+        value.as_f64().ok_or_else(|| self.invalid(field))
+    }
+
+    fn bool(&mut self, field: &'static str) -> Result<bool, ProtocolError> {
+        let value = self.value(field)?;
+        value.as_bool().ok_or_else(|| self.invalid(field))
+    }
+
+    fn array(&mut self, field: &'static str) -> Result<Vec<Value>, ProtocolError> {
+        let value = self.value(field)?;
+        value.as_array().cloned().ok_or_else(|| self.invalid(field))
+    }
+
+    fn bytes(&mut self, field: &'static str) -> Result<Vec<u8>, ProtocolError> {
+        match self.value(field)? {
+            Value::Binary(data) => Ok(data.clone()),
+            _ => Err(self.invalid(field)),
+        }
+    }
+
+    fn optional_bytes(&mut self, field: &'static str) -> Result<Option<Vec<u8>>, ProtocolError> {
+        match self.optional() {
+            None => Ok(None),
+            Some(Value::Binary(data)) => Ok(Some(data.clone())),
+            Some(_) => Err(self.invalid(field)),
+        }
+    }
+
+    fn text_or_bytes(&mut self, field: &'static str) -> Result<Vec<u8>, ProtocolError> {
+        match self.value(field)? {
+            Value::Binary(data) => Ok(data.clone()),
+            Value::String(s) => s
+                .as_str()
+                .map(|t| t.as_bytes().to_vec())
+                .ok_or_else(|| self.invalid(field)),
+            _ => Err(self.invalid(field)),
+        }
+    }
+}
+
+fn int_from<T: TryFrom<i64> + TryFrom<u64>>(value: &Value) -> Option<T> {
+    match value {
+        Value::Integer(n) => n
+            .as_u64()
+            .and_then(|u| T::try_from(u).ok())
+            .or_else(|| n.as_i64().and_then(|i| T::try_from(i).ok())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip_browser(msg: BrowserMessage) {
+        assert_eq!(BrowserMessage::decode(&msg.encode()), Ok(msg));
+    }
+
+    fn roundtrip_host(msg: HostMessage) {
+        assert_eq!(HostMessage::decode(&msg.encode()), Ok(msg));
+    }
+
+    #[test]
+    fn browser_messages_roundtrip() {
+        for msg in [
+            BrowserMessage::Rpc {
+                id: 7,
+                method: "vfs_open".into(),
+                params: vec!["vfs://local/tmp/a".into()],
+            },
+            BrowserMessage::FsResponse {
+                id: 3,
+                ok: false,
+                result: "not found".into(),
+            },
+            BrowserMessage::ClipboardReadResponse {
+                request_id: 42,
+                content: Value::Array(vec!["line".into()]),
+                session_id: "s1".into(),
+            },
+            BrowserMessage::CrdtSync {
+                buffer_id: 2,
+                message: SyncMessage::SyncStep1 {
+                    state_vector: vec![0],
+                },
+            },
+            BrowserMessage::Chat {
+                message: "hi".into(),
+                to: None,
+            },
+            BrowserMessage::Chat {
+                message: "psst".into(),
+                to: Some("p2".into()),
+            },
+            BrowserMessage::Signal {
+                to: "p2".into(),
+                kind: "offer".into(),
+                payload: "v=0".into(),
+            },
+            BrowserMessage::SetName("Ada".into()),
+            BrowserMessage::CommentAdd {
+                body: "why?".into(),
+                parent: None,
+                target: CommentTarget::Lines {
+                    path: "/src/main.rs".into(),
+                    start_line: 3,
+                    end_line: 5,
+                },
+            },
+            BrowserMessage::CommentAdd {
+                body: "here".into(),
+                parent: None,
+                target: CommentTarget::Cursor,
+            },
+            BrowserMessage::CommentAdd {
+                body: "because".into(),
+                parent: Some("c1".into()),
+                target: CommentTarget::General,
+            },
+            BrowserMessage::CommentEdit {
+                id: "c1".into(),
+                body: "why not?".into(),
+            },
+            BrowserMessage::CommentResolve {
+                id: "c1".into(),
+                resolved: true,
+            },
+            BrowserMessage::CommentDelete("c1".into()),
+            BrowserMessage::Input("<C-w>v".into()),
+            BrowserMessage::Paste("hello\nworld".into()),
+            BrowserMessage::Resize {
+                cols: 120,
+                rows: 40,
+            },
+            BrowserMessage::Mouse {
+                button: "left".into(),
+                action: "press".into(),
+                modifier: "C".into(),
+                row: 3,
+                col: 9,
+            },
+            BrowserMessage::Scroll {
+                direction: "down".into(),
+                modifier: String::new(),
+                row: 1,
+                col: 2,
+            },
+            BrowserMessage::InputMouse {
+                button: "left".into(),
+                action: "drag".into(),
+                modifier: String::new(),
+                grid: 4,
+                row: 5,
+                col: 6,
+            },
+            BrowserMessage::FileDrop {
+                name: "a.txt".into(),
+                data: b"abc".to_vec(),
+            },
+            BrowserMessage::TerminalSpawn { cols: 80, rows: 24 },
+            BrowserMessage::TerminalInput(b"ls\r".to_vec()),
+            BrowserMessage::TerminalClose,
+            BrowserMessage::Datagram(vec![0x03, 0, 0, 0, 0, 0, 0, 0, 1]),
+        ] {
+            roundtrip_browser(msg);
+        }
+    }
+
+    #[test]
+    fn host_messages_roundtrip() {
+        for msg in [
+            HostMessage::Session {
+                session_id: "s1".into(),
+                is_viewer: true,
+                protocol_version: PROTOCOL_VERSION,
+            },
+            HostMessage::ProtocolMismatch {
+                host_version: 2,
+                min_version: 2,
+            },
+            HostMessage::RpcResponse {
+                id: 9,
+                error: Value::Nil,
+                result: 1.into(),
+            },
+            HostMessage::Redraw(vec![Value::Array(vec!["flush".into()])]),
+            HostMessage::OptionSet {
+                name: "guifont".into(),
+                value: "Fira Code:h12".into(),
+            },
+            HostMessage::ClipboardRead {
+                request_id: 5,
+                session_id: "s1".into(),
+            },
+            HostMessage::ClipboardWrite {
+                lines: vec!["a".into(), "b".into()],
+                regtype: "V".into(),
+            },
+            HostMessage::CwdInfo(CwdInfo {
+                cwd: "/src".into(),
+                file: "main.rs".into(),
+                backend: "local".into(),
+                git_branch: Some("main".into()),
+            }),
+            HostMessage::CwdInfo(CwdInfo::default()),
+            HostMessage::RecordingStart {
+                register: "q".into(),
+            },
+            HostMessage::RecordingStop,
+            HostMessage::VfxChange {
+                mode: "railgun".into(),
+            },
+            HostMessage::CrdtSync {
+                buffer_id: 3,
+                message: SyncMessage::Update {
+                    update: vec![1, 2, 3],
+                },
+            },
+            HostMessage::Image(ImageCommand::Show {
+                id: "img".into(),
+                url: "/a.png".into(),
+                x: 1.0,
+                y: 2.0,
+                width: 10.0,
+                height: 5.0,
+            }),
+            HostMessage::Image(ImageCommand::Hide { id: "img".into() }),
+            HostMessage::Image(ImageCommand::Clear),
+            HostMessage::Action {
+                name: "browse_files".into(),
+                args: vec![],
+            },
+            HostMessage::FsRequest {
+                id: 11,
+                operation: "write".into(),
+                namespace: "default".into(),
+                path: "/a".into(),
+                data: Some(b"x".to_vec()),
+            },
+            HostMessage::FsRequest {
+                id: 12,
+                operation: "stat".into(),
+                namespace: "default".into(),
+                path: "/a".into(),
+                data: None,
+            },
+            HostMessage::TerminalSpawned { error: None },
+            HostMessage::TerminalSpawned {
+                error: Some("no pty".into()),
+            },
+            HostMessage::TerminalOutput(b"$ ".to_vec()),
+            HostMessage::Presence {
+                self_id: "p1".into(),
+                driver: None,
+                participants: vec![Participant {
+                    id: "p1".into(),
+                    name: Some("Ada".into()),
+                    role: "owner".into(),
+                    color: "#ff6b6b".into(),
+                }],
+            },
+            HostMessage::PeerInfo(Participant {
+                id: "p2".into(),
+                role: "viewer".into(),
+                ..Participant::default()
+            }),
+            HostMessage::PeerLeft {
+                peer_id: "p2".into(),
+            },
+            HostMessage::RoleChanged {
+                peer_id: "p2".into(),
+                role: "editor".into(),
+            },
+            HostMessage::ControlChanged {
+                driver: Some("p2".into()),
+            },
+            HostMessage::ControlChanged { driver: None },
+            HostMessage::ControlRequested {
+                peer_id: "p2".into(),
+            },
+            HostMessage::Chat {
+                from: "p1".into(),
+                message: "hi".into(),
+                timestamp: 1_700_000_000_000,
+                to: None,
+            },
+            HostMessage::Signal {
+                from: "p1".into(),
+                kind: "ice_candidate".into(),
+                payload: "{}".into(),
+            },
+            HostMessage::Comments(vec![Comment {
+                id: "c1".into(),
+                author_id: "p1".into(),
+                author: "Ada".into(),
+                body: "why?".into(),
+                path: Some("/src/main.rs".into()),
+                start_line: 3,
+                end_line: 5,
+                created_at: 1_700_000_000_000,
+                ..Comment::default()
+            }]),
+            HostMessage::Comment(Comment {
+                id: "c2".into(),
+                parent: Some("c1".into()),
+                body: "because".into(),
+                resolved: true,
+                ..Comment::default()
+            }),
+            HostMessage::CommentDeleted { id: "c1".into() },
+            HostMessage::AuthRequired {
+                login_url: "/auth/login".into(),
+            },
+            HostMessage::AuthRestored,
+            HostMessage::Notification {
+                method: "custom".into(),
+                params: vec![true.into()],
+            },
+        ] {
+            roundtrip_host(msg);
+        }
+    }
+
+    #[test]
+    fn wire_format_is_unchanged() {
+        let legacy = Value::Array(vec![
+            "input_mouse".into(),
+            "left".into(),
+            "press".into(),
+            "".into(),
+            1.into(),
+            2.into(),
+            3.into(),
+        ]);
+        let msg = BrowserMessage::from_value(legacy.clone()).unwrap();
+        assert_eq!(msg.to_value(), legacy);
+
+        // Pre-versioning hosts send no protocol_version
+        let old_session = Value::Array(vec!["session".into(), "s1".into(), false.into()]);
+        assert_eq!(
+            HostMessage::from_value(old_session),
+            Ok(HostMessage::Session {
+                session_id: "s1".into(),
+                is_viewer: false,
+                protocol_version: 0,
+            })
+        );
+
+        // Legacy string terminal input and integer image geometry
+        let input = Value::Array(vec!["terminal_input".into(), "ls".into()]);
+        assert_eq!(
+            BrowserMessage::from_value(input),
+            Ok(BrowserMessage::TerminalInput(b"ls".to_vec()))
+        );
+        let image = notification(
+            "nvim_web_image",
+            vec![
+                "show".into(),
+                "i".into(),
+                "u".into(),
+                1.into(),
+                2.into(),
+                3.into(),
+                4.into(),
+            ],
+        );
+        assert!(matches!(
+            HostMessage::from_value(image),
+            Ok(HostMessage::Image(ImageCommand::Show { x, .. })) if x == 1.0
+        ));
+    }
+
+    #[test]
+    fn malformed_messages_are_rejected() {
+        let resize = Value::Array(vec!["resize".into(), 80.into()]);
+        assert_eq!(
+            BrowserMessage::from_value(resize),
+            Err(ProtocolError::MissingField {
+                message: "resize",
+                field: "rows",
+            })
+        );
+
+        let mouse = Value::Array(vec![
+            "mouse".into(),
+            "left".into(),
+            "press".into(),
+            "".into(),
+            "row".into(),
+            1.into(),
+        ]);
+        assert_eq!(
+            BrowserMessage::from_value(mouse),
+            Err(ProtocolError::InvalidField {
+                message: "mouse",
+                field: "row",
+            })
+        );
+
+        assert_eq!(
+            BrowserMessage::from_value(Value::Array(vec!["bogus".into()])),
+            Err(ProtocolError::UnknownMessage("bogus".into()))
+        );
+        assert_eq!(
+            HostMessage::from_value(Value::Array(vec![])),
+            Err(ProtocolError::NotAnArray)
+        );
+        assert!(matches!(
+            BrowserMessage::decode(&[0x92, 0xa5]),
+            Err(ProtocolError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn version_check() {
+        assert!(check_version(PROTOCOL_VERSION).is_ok());
+        assert_eq!(
+            check_version(0),
+            Err(ProtocolError::VersionMismatch {
+                local: PROTOCOL_VERSION,
+                remote: 0,
+            })
+        );
+        assert!(check_version(PROTOCOL_VERSION + 1).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+//! Review comments of a shared session
+//! Mirrors the session's comments from the host's comment notifications and
+//! posts them to the main thread as threads for the side panel.
+//! Runs in the Worker.
+
+use std::cell::RefCell;
+
+use nvim_web_protocol::schema::{BrowserMessage, Comment, CommentTarget, HostMessage};
+use wasm_bindgen::prelude::*;
+
+use crate::input_queue::InputQueue;
+use crate::presence::{post_to_main, self_id};
+
+thread_local! {
+    /// Every comment in the session, oldest first
+    static COMMENTS: RefCell<Vec<Comment>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Handle a comment message from the host
+pub fn handle(msg: HostMessage) {
+    COMMENTS.with(|all| {
+        let mut all = all.borrow_mut();
+        match msg {
+            HostMessage::Comments(comments) => *all = comments,
+            HostMessage::Comment(comment) => match all.iter_mut().find(|c| c.id == comment.id) {
+                Some(existing) => *existing = comment,
+                None => all.push(comment),
+            },
+            HostMessage::CommentDeleted { id } => {
+                all.retain(|c| c.id != id && c.parent.as_deref() != Some(id.as_str()));
+            }
+            _ => {}
+        }
+    });
+    post_threads();
+}
+
+/// Start a thread on the cursor line, or a general one, or reply to `parent`
+pub fn add(input_queue: &InputQueue, body: &str, parent: Option<String>, on_cursor: bool) {
+    let body = body.trim();
+    if body.is_empty() {
+        return;
+    }
+    let target = if parent.is_none() && on_cursor {
+        CommentTarget::Cursor
+    } else {
+        CommentTarget::General
+    };
+    input_queue.send(&BrowserMessage::CommentAdd {
+        body: body.to_string(),
+        parent,
+        target,
+    });
+}
+
+pub fn resolve(input_queue: &InputQueue, id: &str, resolved: bool) {
+    input_queue.send(&BrowserMessage::CommentResolve {
+        id: id.to_string(),
+        resolved,
+    });
+}
+
+pub fn delete(input_queue: &InputQueue, id: &str) {
+    input_queue.send(&BrowserMessage::CommentDelete(id.to_string()));
+}
+
+/// Post threads for the side panel, open ones first
+fn post_threads() {
+    let me = self_id();
+    let threads = js_sys::Array::new();
+    COMMENTS.with(|all| {
+        let all = all.borrow();
+        let mut roots: Vec<&Comment> = all.iter().filter(|c| c.parent.is_none()).collect();
+        roots.sort_by_key(|c| c.resolved);
+        for root in roots {
+            let replies = js_sys::Array::new();
+            for reply in all.iter().filter(|c| c.parent.as_deref() == Some(&root.id)) {
+                replies.push(&entry(reply, &me));
+            }
+            let thread = entry(root, &me);
+            let location = match &root.path {
+                Some(path) if root.end_line > root.start_line => {
+                    format!("{path}:{}-{}", root.start_line, root.end_line)
+                }
+                Some(path) => format!("{path}:{}", root.start_line),
+                None => String::new(),
+            };
+            let _ = js_sys::Reflect::set(&thread, &"location".into(), &location.into());
+            let _ = js_sys::Reflect::set(&thread, &"resolved".into(), &root.resolved.into());
+            let _ = js_sys::Reflect::set(&thread, &"replies".into(), &replies);
+            threads.push(&thread);
+        }
+    });
+    post_to_main("comments", &[("threads", threads.into())]);
+}
+
+/// `{id, author, body, mine}`
+fn entry(comment: &Comment, me: &str) -> js_sys::Object {
+    let entry = js_sys::Object::new();
+    let set = |key: &str, value: JsValue| {
+        let _ = js_sys::Reflect::set(&entry, &key.into(), &value);
+    };
+    set("id", comment.id.as_str().into());
+    set("author", comment.author.as_str().into());
+    set("body", comment.body.as_str().into());
+    set("mine", (comment.author_id == me).into());
+    entry
+}
@@ -245,6 +245,7 @@ pub fn update_connection_status(status: &str) {
                 "connected" => "Connected",
                 "connecting" => "Connecting...",
                 "disconnected" => "Disconnected",
+                "protocol_mismatch" => "Incompatible host version",
                 _ => status,
             };
             let _ = el.set_attribute("title", title);
@@ -252,6 +253,249 @@ pub fn update_connection_status(status: &str) {
     }
 }
 
+/// Show or hide the login expired banner
+///
+/// Shown when the login expires or a connection is refused for lack of one.
+/// The host ignores input until the user logs in again; `login_url` opens in
+/// a new tab so the editor does not have to reload.
+pub fn show_auth_banner(login_url: Option<&str>) {
+    if let Some(doc) = get_document() {
+        if let Some(link) = doc.get_element_by_id("auth-banner-link") {
+            if let Some(url) = login_url {
+                let _ = link.set_attribute("href", url);
+            }
+        }
+        if let Some(el) = doc.get_element_by_id("auth-banner") {
+            let display = if login_url.is_some() {
+                "display:block;"
+            } else {
+                "display:none;"
+            };
+            let _ = el.set_attribute("style", display);
+        }
+    }
+}
+
+/// Show the round-trip latency on the connection dot tooltip
+pub fn update_latency(rtt_ms: f64) {
+    if let Some(doc) = get_document() {
+        if let Some(el) = doc.get_element_by_id("connection-dot") {
+            let _ = el.set_attribute("title", &format!("Connected ({rtt_ms:.0} ms)"));
+        }
+    }
+}
+
+/// Move (or create) a collaborator's cursor marker, labelled with their name
+pub fn update_peer_cursor(
+    peer: &str,
+    name: &str,
+    color: &str,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+) {
+    const COLORS: &[&str] = &[
+        "#ff6b6b", "#4ecdc4", "#ffe66d", "#95e1d3", "#f38181", "#aa96da", "#fcbad3", "#a8d8ea",
+    ];
+
+    if let Some(doc) = get_document() {
+        if let Some(container) = doc.get_element_by_id("nvim-peers") {
+            let el_id = format!("peer-{peer}");
+            let el = doc.get_element_by_id(&el_id).or_else(|| {
+                let el = doc.create_element("div").ok()?;
+                el.set_id(&el_id);
+                el.set_class_name("peer-cursor");
+                container.append_child(&el).ok()?;
+                Some(el)
+            });
+
+            if let Some(el) = el.and_then(|e| e.dyn_into::<web_sys::HtmlElement>().ok()) {
+                let _ = el.set_attribute("title", name);
+                let _ = el.set_attribute("data-name", name);
+                // Host-assigned colour, or a stable one per peer id before
+                // the participant list arrives
+                let color = if color.is_empty() {
+                    let hash = peer
+                        .bytes()
+                        .fold(0usize, |h, b| h.wrapping_mul(31) + b as usize);
+                    COLORS[hash % COLORS.len()]
+                } else {
+                    color
+                };
+                let style = el.style();
+                let _ = style.set_property("--peer-color", color);
+                let _ = style.set_property("left", &format!("{x}px"));
+                let _ = style.set_property("top", &format!("{y}px"));
+                let _ = style.set_property("width", &format!("{width}px"));
+                let _ = style.set_property("height", &format!("{height}px"));
+            }
+        }
+    }
+}
+
+/// Remove a collaborator's cursor marker once they leave
+pub fn remove_peer_cursor(peer: &str) {
+    if let Some(doc) = get_document() {
+        if let Some(el) = doc.get_element_by_id(&format!("peer-{peer}")) {
+            el.remove();
+        }
+    }
+}
+
+/// A participant as shown in the status bar
+pub struct ParticipantBadge {
+    pub name: String,
+    pub role: String,
+    pub color: String,
+    pub is_self: bool,
+    /// Whether their keystrokes currently reach Neovim
+    pub driving: bool,
+}
+
+/// Show who is in the session as coloured initials in the status bar
+pub fn update_participants(participants: &[ParticipantBadge]) {
+    let Some(doc) = get_document() else {
+        return;
+    };
+    let Some(container) = doc.get_element_by_id("participants") else {
+        return;
+    };
+    container.set_inner_html("");
+    // Alone in the session: nothing worth showing
+    if participants.len() < 2 {
+        return;
+    }
+    for p in participants {
+        let Ok(el) = doc.create_element("span") else {
+            continue;
+        };
+        let mut class = String::from("participant");
+        if p.is_self {
+            class.push_str(" self");
+        }
+        if p.driving {
+            class.push_str(" driving");
+        }
+        el.set_class_name(&class);
+        let initial: String = p
+            .name
+            .chars()
+            .take(1)
+            .flat_map(char::to_uppercase)
+            .collect();
+        el.set_text_content(Some(&initial));
+        let suffix = if p.is_self { " (you)" } else { "" };
+        let _ = el.set_attribute("title", &format!("{}{suffix} - {}", p.name, p.role));
+        if let Some(el) = el.dyn_ref::<web_sys::HtmlElement>() {
+            let _ = el.style().set_property("--peer-color", &p.color);
+        }
+        let _ = container.append_child(&el);
+    }
+}
+
+/// One comment in the side panel
+pub struct CommentEntry {
+    pub id: String,
+    pub author: String,
+    pub body: String,
+    /// Written by us, so we may delete it
+    pub mine: bool,
+}
+
+/// A comment thread in the side panel
+pub struct CommentThread {
+    pub root: CommentEntry,
+    /// `path:line` or `path:start-end`, empty for general comments
+    pub location: String,
+    pub resolved: bool,
+    pub replies: Vec<CommentEntry>,
+}
+
+/// Show the session's comment threads in the side panel
+///
+/// Buttons carry `data-action` and `data-id` and reply boxes `data-parent`;
+/// their events are handled on the panel.
+pub fn update_comments(threads: &[CommentThread]) {
+    let Some(doc) = get_document() else {
+        return;
+    };
+    if let Some(count) = doc.get_element_by_id("comments-count") {
+        let open = threads.iter().filter(|t| !t.resolved).count();
+        let text = if open > 0 { open.to_string() } else { String::new() };
+        count.set_text_content(Some(&text));
+    }
+    let Some(container) = doc.get_element_by_id("comment-threads") else {
+        return;
+    };
+    container.set_inner_html("");
+    let element = |tag: &str, class: &str, text: Option<&str>| -> Option<web_sys::Element> {
+        let el = doc.create_element(tag).ok()?;
+        el.set_class_name(class);
+        if text.is_some() {
+            el.set_text_content(text);
+        }
+        Some(el)
+    };
+    let comment = |entry: &CommentEntry, class: &str| -> Option<web_sys::Element> {
+        let el = element("div", class, None)?;
+        let author = element("span", "comment-author", Some(&entry.author))?;
+        let body = element("span", "comment-body", Some(&entry.body))?;
+        let _ = el.append_child(&author);
+        let _ = el.append_child(&body);
+        if entry.mine {
+            let delete = element("button", "comment-action", Some("×"))?;
+            let _ = delete.set_attribute("data-action", "delete");
+            let _ = delete.set_attribute("data-id", &entry.id);
+            let _ = delete.set_attribute("title", "Delete");
+            let _ = el.append_child(&delete);
+        }
+        Some(el)
+    };
+
+    for thread in threads {
+        let class = if thread.resolved {
+            "comment-thread resolved"
+        } else {
+            "comment-thread"
+        };
+        let Some(el) = element("div", class, None) else {
+            continue;
+        };
+        let location = if thread.location.is_empty() {
+            "General"
+        } else {
+            &thread.location
+        };
+        if let Some(header) = element("div", "comment-location", Some(location)) {
+            if let Some(resolve) = element(
+                "button",
+                "comment-action",
+                Some(if thread.resolved { "Reopen" } else { "Resolve" }),
+            ) {
+                let _ = resolve.set_attribute("data-action", "resolve");
+                let _ = resolve.set_attribute("data-id", &thread.root.id);
+                let _ = resolve.set_attribute("data-resolved", &(!thread.resolved).to_string());
+                let _ = header.append_child(&resolve);
+            }
+            let _ = el.append_child(&header);
+        }
+        for (entry, class) in std::iter::once((&thread.root, "comment"))
+            .chain(thread.replies.iter().map(|reply| (reply, "comment reply")))
+        {
+            if let Some(c) = comment(entry, class) {
+                let _ = el.append_child(&c);
+            }
+        }
+        if let Some(reply) = element("input", "comment-reply", None) {
+            let _ = reply.set_attribute("placeholder", "Reply");
+            let _ = reply.set_attribute("data-parent", &thread.root.id);
+            let _ = el.append_child(&reply);
+        }
+        let _ = container.append_child(&el);
+    }
+}
+
 /// Update git branch display
 pub fn update_git_branch(branch: Option<&str>) {
     if let Some(doc) = get_document() {
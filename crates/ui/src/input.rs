@@ -14,6 +14,7 @@ use crate::render::RenderState;
 use crate::renderer::Renderer;
 // Import the shared InputQueue that is worker-safe
 use crate::input_queue::InputQueue;
+use nvim_web_protocol::schema::BrowserMessage;
 
 /// Setup all input event listeners (keyboard, mouse, touch, paste, focus)
 pub fn setup_input_listeners(
@@ -434,21 +435,14 @@ fn send_mouse_event(
         modifier.push('S');
     }
 
-    // Message: ["input_mouse", button, action, modifier, grid, row, col]
-    let msg = rmpv::Value::Array(vec![
-        rmpv::Value::String("input_mouse".into()),
-        rmpv::Value::String("left".into()), // Assuming left button for main interactions
-        rmpv::Value::String(action.into()),
-        rmpv::Value::String(modifier.into()),
-        rmpv::Value::Integer(grid_id.into()),
-        rmpv::Value::Integer(local_row.into()),
-        rmpv::Value::Integer(local_col.into()),
-    ]);
-
-    let mut bytes = Vec::new();
-    if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-        input_queue.enqueue(bytes);
-    }
+    input_queue.send(&BrowserMessage::InputMouse {
+        button: "left".to_string(), // Assuming left button for main interactions
+        action: action.to_string(),
+        modifier,
+        grid: i64::from(grid_id),
+        row: i64::from(local_row),
+        col: i64::from(local_col),
+    });
 }
 
 fn setup_scroll_listener(
@@ -818,17 +812,12 @@ fn send_mouse_rpc(
     row: i32,
     col: i32,
 ) {
-    let msg = rmpv::Value::Array(vec![
-        rmpv::Value::String("input_mouse".into()),
-        rmpv::Value::String(button.into()),
-        rmpv::Value::String(action.into()),
-        rmpv::Value::String(modifier.into()),
-        rmpv::Value::Integer(grid_id.into()),
-        rmpv::Value::Integer(row.into()),
-        rmpv::Value::Integer(col.into()),
-    ]);
-    let mut bytes = Vec::new();
-    if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-        input_queue.enqueue(bytes);
-    }
+    input_queue.send(&BrowserMessage::InputMouse {
+        button: button.to_string(),
+        action: action.to_string(),
+        modifier: modifier.to_string(),
+        grid: grid_id as i64,
+        row: i64::from(row),
+        col: i64::from(col),
+    });
 }
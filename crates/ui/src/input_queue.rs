@@ -5,6 +5,8 @@
 use std::cell::{Cell, RefCell};
 use std::collections::VecDeque;
 use std::rc::Rc;
+use nvim_web_protocol::datagram::Datagram;
+use nvim_web_protocol::schema::BrowserMessage;
 use web_sys::WebSocket;
 
 const MAX_RETRIES: u8 = 5;
@@ -150,91 +152,68 @@ impl InputQueue {
         }
     }
 
+    /// Encode and queue a message for the host
+    pub fn send(&self, msg: &BrowserMessage) {
+        self.enqueue(msg.encode());
+    }
+
     /// Send a key input (convenience method)
     pub fn send_key(&self, nvim_key: &str) {
-        let msg = rmpv::Value::Array(vec![
-            rmpv::Value::String("input".into()),
-            rmpv::Value::String(nvim_key.into()),
-        ]);
-
-        let mut bytes = Vec::new();
-        if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-            self.enqueue(bytes);
-        }
+        self.send(&BrowserMessage::Input(nvim_key.to_string()));
     }
 
     /// Send mouse input
     pub fn send_mouse(&self, button: &str, action: &str, row: usize, col: usize, modifiers: &str) {
-        let msg = rmpv::Value::Array(vec![
-            rmpv::Value::String("mouse".into()),
-            rmpv::Value::String(button.into()),
-            rmpv::Value::String(action.into()),
-            rmpv::Value::String(modifiers.into()),
-            rmpv::Value::Integer((row as i64).into()),
-            rmpv::Value::Integer((col as i64).into()),
-        ]);
-
-        let mut bytes = Vec::new();
-        if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-            self.enqueue(bytes);
-        }
+        self.send(&BrowserMessage::Mouse {
+            button: button.to_string(),
+            action: action.to_string(),
+            modifier: modifiers.to_string(),
+            row: row as i64,
+            col: col as i64,
+        });
     }
 
     /// Send scroll input
     pub fn send_scroll(&self, direction: &str, row: usize, col: usize, modifiers: &str) {
-        let msg = rmpv::Value::Array(vec![
-            rmpv::Value::String("scroll".into()),
-            rmpv::Value::String(direction.into()),
-            rmpv::Value::String(modifiers.into()),
-            rmpv::Value::Integer((row as i64).into()),
-            rmpv::Value::Integer((col as i64).into()),
-        ]);
-
-        let mut bytes = Vec::new();
-        if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-            self.enqueue(bytes);
-        }
+        self.send(&BrowserMessage::Scroll {
+            direction: direction.to_string(),
+            modifier: modifiers.to_string(),
+            row: row as i64,
+            col: col as i64,
+        });
     }
 
     /// Send resize notification
     pub fn send_resize(&self, cols: usize, rows: usize) {
-        let msg = rmpv::Value::Array(vec![
-            rmpv::Value::String("resize".into()),
-            rmpv::Value::Integer((cols as i64).into()),
-            rmpv::Value::Integer((rows as i64).into()),
-        ]);
-
-        let mut bytes = Vec::new();
-        if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-            self.enqueue(bytes);
-        }
+        self.send(&BrowserMessage::Resize {
+            cols: cols as u32,
+            rows: rows as u32,
+        });
     }
 
     /// Send paste text
     pub fn send_paste(&self, text: &str) {
         // Neovim handles paste via `nvim_paste` API
-        let msg = rmpv::Value::Array(vec![
-            rmpv::Value::String("paste".into()),
-            rmpv::Value::String(text.into()),
-        ]);
-
-        let mut bytes = Vec::new();
-        if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-            self.enqueue(bytes);
-        }
+        self.send(&BrowserMessage::Paste(text.to_string()));
     }
 
     /// Send file drop event
     pub fn send_file_drop(&self, name: &str, data: &[u8]) {
-        let msg = rmpv::Value::Array(vec![
-            rmpv::Value::String("file_drop".into()),
-            rmpv::Value::String(name.into()),
-            rmpv::Value::Binary(data.to_vec()),
-        ]);
-
-        let mut bytes = Vec::new();
-        if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-            self.enqueue(bytes);
+        self.send(&BrowserMessage::FileDrop {
+            name: name.to_string(),
+            data: data.to_vec(),
+        });
+    }
+
+    /// Send a datagram (cursor, heartbeat) inside the reliable envelope
+    ///
+    /// Datagrams are not queued: if the socket is not open the update is
+    /// dropped, the next one supersedes it anyway.
+    pub fn send_datagram(&self, datagram: &Datagram) {
+        if let Some(ws) = self.ws.borrow().as_ref() {
+            if ws.ready_state() == WebSocket::OPEN {
+                let _ = ws.send_with_u8_array(&datagram.to_envelope());
+            }
         }
     }
 
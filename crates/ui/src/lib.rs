@@ -1,3 +1,4 @@
+mod comments;
 mod dom;
 mod fs;
 mod grid;
@@ -5,8 +6,10 @@ mod highlight;
 mod input;
 mod input_queue;
 mod opfs;
+mod presence;
 mod render;
 mod renderer;
+mod webrtc;
 mod worker;
 pub mod crdt;
 
@@ -181,6 +184,23 @@ fn main_thread_entry() -> Result<(), JsValue> {
                             }
                         }
                     }
+                    Some("protocol_error") => {
+                        // Host and UI builds are incompatible; stay disconnected
+                        if let Some(message) = js_sys::Reflect::get(obj, &"message".into())
+                            .ok()
+                            .and_then(|v| v.as_string())
+                        {
+                            crate::dom::show_toast(&message);
+                        }
+                        crate::dom::update_connection_status("protocol_mismatch");
+                    }
+                    Some("auth_status") => {
+                        // Login expired or restored; the connection stays open
+                        let login_url = js_sys::Reflect::get(obj, &"login_url".into())
+                            .ok()
+                            .and_then(|v| v.as_string());
+                        crate::dom::show_auth_banner(login_url.as_deref());
+                    }
                     Some("connection_status") => {
                         // Update connection status indicator
                         if let Ok(status_val) = js_sys::Reflect::get(obj, &"status".into()) {
@@ -256,6 +276,120 @@ fn main_thread_entry() -> Result<(), JsValue> {
                         }
                     }
 
+                    Some("peer_cursor") => {
+                        let get = |key: &str| {
+                            js_sys::Reflect::get(obj, &key.into()).unwrap_or(JsValue::UNDEFINED)
+                        };
+                        if let Some(peer) = get("peer").as_string() {
+                            crate::dom::update_peer_cursor(
+                                &peer,
+                                &get("name").as_string().unwrap_or_default(),
+                                &get("color").as_string().unwrap_or_default(),
+                                get("x").as_f64().unwrap_or(0.0),
+                                get("y").as_f64().unwrap_or(0.0),
+                                get("width").as_f64().unwrap_or(0.0),
+                                get("height").as_f64().unwrap_or(0.0),
+                            );
+                        }
+                    }
+                    Some("peer_left") => {
+                        if let Ok(peer) = js_sys::Reflect::get(obj, &"peer".into()) {
+                            if let Some(peer) = peer.as_string() {
+                                crate::dom::remove_peer_cursor(&peer);
+                            }
+                        }
+                    }
+                    Some("participants") => {
+                        let list = js_sys::Reflect::get(obj, &"list".into())
+                            .map(|list| js_sys::Array::from(&list))
+                            .unwrap_or_default();
+                        let mut self_id = String::new();
+                        let mut peer_ids = Vec::new();
+                        let badges: Vec<_> = list
+                            .iter()
+                            .map(|entry| {
+                                let get = |key: &str| {
+                                    js_sys::Reflect::get(&entry, &key.into())
+                                        .unwrap_or(JsValue::UNDEFINED)
+                                };
+                                let id = get("id").as_string().unwrap_or_default();
+                                if get("is_self").as_bool().unwrap_or(false) {
+                                    self_id.clone_from(&id);
+                                }
+                                peer_ids.push(id);
+                                crate::dom::ParticipantBadge {
+                                    name: get("name").as_string().unwrap_or_default(),
+                                    role: get("role").as_string().unwrap_or_default(),
+                                    color: get("color").as_string().unwrap_or_default(),
+                                    is_self: get("is_self").as_bool().unwrap_or(false),
+                                    driving: get("driving").as_bool().unwrap_or(false),
+                                }
+                            })
+                            .collect();
+                        crate::dom::update_participants(&badges);
+                        crate::webrtc::sync_participants(&self_id, &peer_ids);
+                    }
+                    Some("comments") => {
+                        let threads = js_sys::Reflect::get(obj, &"threads".into())
+                            .map(|list| js_sys::Array::from(&list))
+                            .unwrap_or_default();
+                        let threads: Vec<_> = threads
+                            .iter()
+                            .map(|thread| {
+                                let get = |key: &str| {
+                                    js_sys::Reflect::get(&thread, &key.into())
+                                        .unwrap_or(JsValue::UNDEFINED)
+                                };
+                                crate::dom::CommentThread {
+                                    root: comment_entry(&thread),
+                                    location: get("location").as_string().unwrap_or_default(),
+                                    resolved: get("resolved").as_bool().unwrap_or(false),
+                                    replies: js_sys::Array::from(&get("replies"))
+                                        .iter()
+                                        .map(|reply| comment_entry(&reply))
+                                        .collect(),
+                                }
+                            })
+                            .collect();
+                        crate::dom::update_comments(&threads);
+                    }
+                    Some("peer_signal") => {
+                        let get = |key: &str| {
+                            js_sys::Reflect::get(obj, &key.into())
+                                .ok()
+                                .and_then(|v| v.as_string())
+                                .unwrap_or_default()
+                        };
+                        crate::webrtc::handle_signal(&get("from"), &get("kind"), &get("payload"));
+                    }
+                    Some("chat") => {
+                        let get = |key: &str| {
+                            js_sys::Reflect::get(obj, &key.into()).unwrap_or(JsValue::UNDEFINED)
+                        };
+                        let from = get("from").as_string().unwrap_or_default();
+                        let message = get("message").as_string().unwrap_or_default();
+                        let private = if get("private").as_bool().unwrap_or(false) {
+                            " (private)"
+                        } else {
+                            ""
+                        };
+                        crate::dom::show_toast(&format!("{from}{private}: {message}"));
+                    }
+                    Some("notice") => {
+                        if let Ok(text) = js_sys::Reflect::get(obj, &"text".into()) {
+                            if let Some(text) = text.as_string() {
+                                crate::dom::show_toast(&text);
+                            }
+                        }
+                    }
+                    Some("latency") => {
+                        if let Ok(rtt) = js_sys::Reflect::get(obj, &"rtt_ms".into()) {
+                            if let Some(rtt) = rtt.as_f64() {
+                                crate::dom::update_latency(rtt);
+                            }
+                        }
+                    }
+
                     Some("action") => {
                         // Handle generic actions
                         if let Ok(val) = js_sys::Reflect::get(obj, &"name".into()) {
@@ -276,6 +410,7 @@ fn main_thread_entry() -> Result<(), JsValue> {
 
     // 6. Setup Event Forwarding
     let worker_rc = Rc::new(worker);
+    crate::webrtc::init(worker_rc.clone());
     setup_keyboard_forwarding(&window, &worker_rc)?;
     setup_mouse_forwarding(&canvas, &worker_rc)?;
     setup_wheel_forwarding(&canvas, &worker_rc)?;
@@ -283,6 +418,8 @@ fn main_thread_entry() -> Result<(), JsValue> {
     setup_paste_forwarding(&window, &worker_rc)?;
     setup_dragdrop_forwarding(&canvas, &worker_rc)?;
     setup_file_picker(&worker_rc)?;
+    setup_chat_input(&worker_rc)?;
+    setup_comments_panel(&worker_rc)?;
     setup_start_screen(&worker_rc)?;
 
     web_sys::console::log_1(&"[Main] Worker spawned, waiting for ready signal".into());
@@ -319,11 +456,19 @@ fn get_ws_url() -> Result<String, JsValue> {
     // Check sessionStorage for existing session ID (for reconnection)
     let session_id = get_stored_session_id();
 
-    let base_url = format!("{}//{}:{}", ws_protocol, hostname, ws_port);
+    // Announce our protocol version so an incompatible host refuses us loudly
+    let base_url = format!(
+        "{}//{}:{}?{}={}",
+        ws_protocol,
+        hostname,
+        ws_port,
+        nvim_web_protocol::schema::PROTOCOL_QUERY_PARAM,
+        nvim_web_protocol::schema::PROTOCOL_VERSION
+    );
 
     if let Some(id) = session_id {
         web_sys::console::log_1(&format!("[Main] Reconnecting to session: {}", id).into());
-        Ok(format!("{}?session={}", base_url, id))
+        Ok(format!("{}&session={}", base_url, id))
     } else {
         Ok(base_url)
     }
@@ -648,6 +793,190 @@ fn setup_dragdrop_forwarding(
     Ok(())
 }
 
+/// Send the status bar chat box to the session on Enter
+///
+/// `/name <name>` sets the name other participants see instead, and
+/// `/p2p <text>` goes straight to the other browsers over WebRTC.
+fn setup_chat_input(worker: &Rc<Worker>) -> Result<(), JsValue> {
+    let Some(input) = window()
+        .and_then(|w| w.document())
+        .and_then(|doc| doc.get_element_by_id("chat-input"))
+        .and_then(|el| el.dyn_into::<web_sys::HtmlInputElement>().ok())
+    else {
+        return Ok(());
+    };
+
+    let worker = worker.clone();
+    let input_clone = input.clone();
+    let on_keydown = Closure::wrap(Box::new(move |e: KeyboardEvent| {
+        // Keep typing out of Neovim
+        e.stop_propagation();
+        match e.key().as_str() {
+            "Enter" => {
+                let text = input_clone.value();
+                let text = text.trim();
+                let msg = js_sys::Object::new();
+                if let Some(direct) = text.strip_prefix("/p2p ") {
+                    if crate::webrtc::broadcast(direct) == 0 {
+                        crate::dom::show_toast("No direct connection to other participants");
+                    }
+                    input_clone.set_value("");
+                    return;
+                }
+                if let Some(name) = text.strip_prefix("/name ") {
+                    let _ = js_sys::Reflect::set(&msg, &"type".into(), &"set_name".into());
+                    let _ = js_sys::Reflect::set(&msg, &"name".into(), &name.into());
+                } else {
+                    let _ = js_sys::Reflect::set(&msg, &"type".into(), &"chat_send".into());
+                    let _ = js_sys::Reflect::set(&msg, &"text".into(), &text.into());
+                }
+                let _ = worker.post_message(&msg);
+                input_clone.set_value("");
+            }
+            "Escape" => {
+                input_clone.set_value("");
+                let _ = input_clone.blur();
+                focus_input();
+            }
+            _ => {}
+        }
+    }) as Box<dyn FnMut(KeyboardEvent)>);
+    input.add_event_listener_with_callback("keydown", on_keydown.as_ref().unchecked_ref())?;
+    on_keydown.forget();
+
+    let on_paste = Closure::wrap(Box::new(move |e: web_sys::Event| {
+        e.stop_propagation();
+    }) as Box<dyn FnMut(web_sys::Event)>);
+    input.add_event_listener_with_callback("paste", on_paste.as_ref().unchecked_ref())?;
+    on_paste.forget();
+    Ok(())
+}
+
+/// `{id, author, body, mine}` posted by the worker's comments module
+fn comment_entry(entry: &JsValue) -> crate::dom::CommentEntry {
+    let get = |key: &str| js_sys::Reflect::get(entry, &key.into()).unwrap_or(JsValue::UNDEFINED);
+    crate::dom::CommentEntry {
+        id: get("id").as_string().unwrap_or_default(),
+        author: get("author").as_string().unwrap_or_default(),
+        body: get("body").as_string().unwrap_or_default(),
+        mine: get("mine").as_bool().unwrap_or(false),
+    }
+}
+
+/// Wire up the comments side panel
+///
+/// The status bar button toggles it. Enter in the new comment box comments
+/// on Neovim's cursor line (or selection), or starts a general thread when
+/// "General" is ticked; Enter in a thread's reply box replies to it.
+fn setup_comments_panel(worker: &Rc<Worker>) -> Result<(), JsValue> {
+    let Some(doc) = window().and_then(|w| w.document()) else {
+        return Ok(());
+    };
+    let (Some(panel), Some(toggle)) = (
+        doc.get_element_by_id("comments-panel"),
+        doc.get_element_by_id("comments-toggle"),
+    ) else {
+        return Ok(());
+    };
+    let general = doc
+        .get_element_by_id("comment-general")
+        .and_then(|el| el.dyn_into::<web_sys::HtmlInputElement>().ok());
+
+    let panel_clone = panel.clone();
+    let on_toggle = Closure::wrap(Box::new(move || {
+        let _ = panel_clone.class_list().toggle("open");
+    }) as Box<dyn FnMut()>);
+    toggle.add_event_listener_with_callback("click", on_toggle.as_ref().unchecked_ref())?;
+    on_toggle.forget();
+
+    let post = {
+        let worker = worker.clone();
+        move |fields: &[(&str, JsValue)]| {
+            let msg = js_sys::Object::new();
+            for (key, value) in fields {
+                let _ = js_sys::Reflect::set(&msg, &(*key).into(), value);
+            }
+            let _ = worker.post_message(&msg);
+        }
+    };
+
+    let post_click = post.clone();
+    let on_click = Closure::wrap(Box::new(move |e: MouseEvent| {
+        let Some(button) = e.target().and_then(|t| t.dyn_into::<web_sys::Element>().ok()) else {
+            return;
+        };
+        let Some(id) = button.get_attribute("data-id") else {
+            return;
+        };
+        match button.get_attribute("data-action").as_deref() {
+            Some("resolve") => {
+                let resolved = button.get_attribute("data-resolved").as_deref() == Some("true");
+                post_click(&[
+                    ("type", "comment_resolve".into()),
+                    ("id", id.into()),
+                    ("resolved", resolved.into()),
+                ]);
+            }
+            Some("delete") => {
+                post_click(&[("type", "comment_delete".into()), ("id", id.into())]);
+            }
+            _ => {}
+        }
+    }) as Box<dyn FnMut(MouseEvent)>);
+    panel.add_event_listener_with_callback("click", on_click.as_ref().unchecked_ref())?;
+    on_click.forget();
+
+    let on_keydown = Closure::wrap(Box::new(move |e: KeyboardEvent| {
+        // Keep typing out of Neovim
+        e.stop_propagation();
+        let Some(input) = e
+            .target()
+            .and_then(|t| t.dyn_into::<web_sys::HtmlInputElement>().ok())
+        else {
+            return;
+        };
+        match e.key().as_str() {
+            "Enter" => {
+                let body = input.value();
+                if body.trim().is_empty() {
+                    return;
+                }
+                match input.get_attribute("data-parent") {
+                    Some(parent) => post(&[
+                        ("type", "comment_add".into()),
+                        ("body", body.into()),
+                        ("parent", parent.into()),
+                    ]),
+                    None => {
+                        let on_cursor = !general.as_ref().is_some_and(|g| g.checked());
+                        post(&[
+                            ("type", "comment_add".into()),
+                            ("body", body.into()),
+                            ("cursor", on_cursor.into()),
+                        ]);
+                    }
+                }
+                input.set_value("");
+            }
+            "Escape" => {
+                input.set_value("");
+                let _ = input.blur();
+                focus_input();
+            }
+            _ => {}
+        }
+    }) as Box<dyn FnMut(KeyboardEvent)>);
+    panel.add_event_listener_with_callback("keydown", on_keydown.as_ref().unchecked_ref())?;
+    on_keydown.forget();
+
+    let on_paste = Closure::wrap(Box::new(move |e: web_sys::Event| {
+        e.stop_propagation();
+    }) as Box<dyn FnMut(web_sys::Event)>);
+    panel.add_event_listener_with_callback("paste", on_paste.as_ref().unchecked_ref())?;
+    on_paste.forget();
+    Ok(())
+}
+
 fn setup_file_picker(worker: &Rc<Worker>) -> Result<(), JsValue> {
     if let Some(doc) = window().and_then(|w| w.document()) {
         if let Some(el) = doc.get_element_by_id("file-picker") {
@@ -0,0 +1,285 @@
+//! Collaborator presence over the datagram channel
+//! Sends our pointer cell and heartbeats, renders remote peers' cursors.
+//! Tracks the session's participants (names, colours, roles, edit control)
+//! from the host's collab_* notifications and relays chat.
+//! Runs in the Worker; DOM updates are posted to the main thread.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use nvim_web_protocol::datagram::{CursorPosition, CursorUpdate, Datagram, SeqFilter};
+use nvim_web_protocol::schema::{BrowserMessage, HostMessage, Participant};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::DedicatedWorkerGlobalScope;
+
+use crate::input_queue::InputQueue;
+
+const HEARTBEAT_INTERVAL_MS: i32 = 10_000;
+
+thread_local! {
+    /// Sequence number for our own cursor datagrams
+    static CURSOR_SEQ: Cell<u32> = const { Cell::new(0) };
+    /// Last pointer cell sent (grid, row, col), to skip duplicates
+    static LAST_CELL: Cell<Option<(u32, u32, u32)>> = const { Cell::new(None) };
+    /// Per-peer filters dropping reordered cursor updates
+    static PEER_SEQ: RefCell<HashMap<String, SeqFilter>> = RefCell::new(HashMap::new());
+    /// Session participants by id, including ourselves
+    static PARTICIPANTS: RefCell<HashMap<String, Participant>> = RefCell::new(HashMap::new());
+    /// Our own participant id
+    static SELF_ID: RefCell<String> = const { RefCell::new(String::new()) };
+    /// Participant holding edit control, `None` while the owners drive
+    static DRIVER: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Share our pointer position when it enters a new cell
+pub fn pointer_moved(input_queue: &InputQueue, grid: u32, row: u32, col: u32) {
+    if LAST_CELL.with(|c| c.replace(Some((grid, row, col)))) == Some((grid, row, col)) {
+        return;
+    }
+    let seq = CURSOR_SEQ.with(|s| {
+        let seq = s.get();
+        s.set(seq.wrapping_add(1));
+        seq
+    });
+    input_queue.send_datagram(&Datagram::Cursor(CursorUpdate {
+        seq,
+        position: CursorPosition { row, col, grid },
+        peer_id: String::new(),
+    }));
+}
+
+/// Send a heartbeat every 10s; the host echoes it back for RTT
+pub fn start_heartbeat(input_queue: Rc<InputQueue>) {
+    let tick = Closure::wrap(Box::new(move || {
+        input_queue.send_datagram(&Datagram::Heartbeat {
+            timestamp_ms: js_sys::Date::now() as u64,
+        });
+    }) as Box<dyn FnMut()>);
+
+    let global: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let _ = global.set_interval_with_callback_and_timeout_and_arguments_0(
+        tick.as_ref().unchecked_ref(),
+        HEARTBEAT_INTERVAL_MS,
+    );
+    tick.forget();
+}
+
+/// Handle a datagram relayed by the host
+/// `cell_w`/`cell_h` are CSS pixels per grid cell.
+pub fn handle_datagram(raw: &[u8], cell_w: f64, cell_h: f64) {
+    match Datagram::decode(raw) {
+        Ok(Datagram::Cursor(update)) => {
+            let fresh = PEER_SEQ.with(|peers| {
+                peers
+                    .borrow_mut()
+                    .entry(update.peer_id.clone())
+                    .or_default()
+                    .accept(update.seq)
+            });
+            if fresh {
+                forward_peer_cursor(&update, cell_w, cell_h);
+            }
+        }
+        Ok(Datagram::Heartbeat { timestamp_ms }) => {
+            let rtt = js_sys::Date::now() - timestamp_ms as f64;
+            post_to_main("latency", &[("rtt_ms", rtt.max(0.0).into())]);
+        }
+        Ok(Datagram::Input(_)) => {}
+        Err(e) => {
+            web_sys::console::warn_1(&format!("[Presence] Bad datagram: {e}").into());
+        }
+    }
+}
+
+/// Handle a participant, control, chat or signaling message from the host
+pub fn handle_collab(msg: HostMessage) {
+    match msg {
+        HostMessage::Presence {
+            self_id,
+            driver,
+            participants,
+        } => {
+            // Drop cursors of peers that left while we were disconnected
+            let current: HashMap<String, Participant> = participants
+                .into_iter()
+                .map(|p| (p.id.clone(), p))
+                .collect();
+            let gone: Vec<String> = PARTICIPANTS.with(|all| {
+                all.borrow()
+                    .keys()
+                    .filter(|id| !current.contains_key(*id))
+                    .cloned()
+                    .collect()
+            });
+            for id in gone {
+                forget_peer(&id);
+            }
+            PARTICIPANTS.with(|all| *all.borrow_mut() = current);
+            SELF_ID.with(|id| *id.borrow_mut() = self_id);
+            DRIVER.with(|d| *d.borrow_mut() = driver);
+        }
+        HostMessage::PeerInfo(participant) => {
+            PARTICIPANTS.with(|all| all.borrow_mut().insert(participant.id.clone(), participant));
+        }
+        HostMessage::PeerLeft { peer_id } => forget_peer(&peer_id),
+        HostMessage::RoleChanged { peer_id, role } => {
+            PARTICIPANTS.with(|all| {
+                if let Some(p) = all.borrow_mut().get_mut(&peer_id) {
+                    p.role = role;
+                }
+            });
+        }
+        HostMessage::ControlChanged { driver } => {
+            if driver
+                .as_deref()
+                .is_some_and(|d| SELF_ID.with(|id| *id.borrow() == d))
+            {
+                post_to_main("notice", &[("text", "You have edit control".into())]);
+            }
+            DRIVER.with(|d| *d.borrow_mut() = driver);
+        }
+        HostMessage::ControlRequested { peer_id } => {
+            let text = format!("{} asks for edit control", display_name(&peer_id));
+            post_to_main("notice", &[("text", text.into())]);
+            return;
+        }
+        HostMessage::Chat {
+            from, message, to, ..
+        } => {
+            post_to_main(
+                "chat",
+                &[
+                    ("from", display_name(&from).into()),
+                    ("message", message.into()),
+                    ("private", to.is_some().into()),
+                ],
+            );
+            return;
+        }
+        HostMessage::Signal {
+            from,
+            kind,
+            payload,
+        } => {
+            post_to_main(
+                "peer_signal",
+                &[
+                    ("from", from.into()),
+                    ("kind", kind.into()),
+                    ("payload", payload.into()),
+                ],
+            );
+            return;
+        }
+        _ => return,
+    }
+    post_participants();
+}
+
+/// Send our display name to the other participants
+pub fn set_name(input_queue: &InputQueue, name: &str) {
+    input_queue.send(&BrowserMessage::SetName(name.to_string()));
+}
+
+/// Send a chat message to everyone in the session
+pub fn send_chat(input_queue: &InputQueue, message: &str) {
+    if !message.is_empty() {
+        input_queue.send(&BrowserMessage::Chat {
+            message: message.to_string(),
+            to: None,
+        });
+    }
+}
+
+/// Relay a WebRTC signal to another participant through the host
+pub fn send_signal(input_queue: &InputQueue, to: &str, kind: &str, payload: &str) {
+    input_queue.send(&BrowserMessage::Signal {
+        to: to.to_string(),
+        kind: kind.to_string(),
+        payload: payload.to_string(),
+    });
+}
+
+fn forget_peer(peer_id: &str) {
+    PARTICIPANTS.with(|all| all.borrow_mut().remove(peer_id));
+    PEER_SEQ.with(|peers| peers.borrow_mut().remove(peer_id));
+    post_to_main("peer_left", &[("peer", peer_id.into())]);
+}
+
+/// Our own participant id, empty until the host sent presence
+pub fn self_id() -> String {
+    SELF_ID.with(|id| id.borrow().clone())
+}
+
+/// Name to show for a participant: their chosen name or a short id
+fn display_name(peer_id: &str) -> String {
+    PARTICIPANTS
+        .with(|all| all.borrow().get(peer_id).and_then(|p| p.name.clone()))
+        .unwrap_or_else(|| peer_id.chars().take(8).collect())
+}
+
+fn peer_color(peer_id: &str) -> String {
+    PARTICIPANTS
+        .with(|all| all.borrow().get(peer_id).map(|p| p.color.clone()))
+        .unwrap_or_default()
+}
+
+/// Post the participant list for the status bar, ourselves first
+fn post_participants() {
+    let self_id = SELF_ID.with(|id| id.borrow().clone());
+    let driver = DRIVER.with(|d| d.borrow().clone());
+    let mut participants: Vec<Participant> =
+        PARTICIPANTS.with(|all| all.borrow().values().cloned().collect());
+    participants.sort_by_key(|p| (p.id != self_id, p.id.clone()));
+
+    let list = js_sys::Array::new();
+    for p in participants {
+        let entry = js_sys::Object::new();
+        let set = |key: &str, value: JsValue| {
+            let _ = js_sys::Reflect::set(&entry, &key.into(), &value);
+        };
+        set("id", p.id.as_str().into());
+        set("name", display_name(&p.id).into());
+        set("role", p.role.as_str().into());
+        set("color", p.color.as_str().into());
+        set("is_self", (p.id == self_id).into());
+        // Owners drive while nobody holds control
+        let driving = match &driver {
+            Some(driver) => *driver == p.id,
+            None => p.role == "owner",
+        };
+        set("driving", driving.into());
+        list.push(&entry);
+    }
+    post_to_main("participants", &[("list", list.into())]);
+}
+
+fn forward_peer_cursor(update: &CursorUpdate, cell_w: f64, cell_h: f64) {
+    let pos = update.position;
+    post_to_main(
+        "peer_cursor",
+        &[
+            ("peer", update.peer_id.as_str().into()),
+            ("name", display_name(&update.peer_id).into()),
+            ("color", peer_color(&update.peer_id).into()),
+            ("x", (f64::from(pos.col) * cell_w).into()),
+            ("y", (f64::from(pos.row) * cell_h).into()),
+            ("width", cell_w.into()),
+            ("height", cell_h.into()),
+        ],
+    );
+}
+
+pub(crate) fn post_to_main(msg_type: &str, fields: &[(&str, JsValue)]) {
+    let global = js_sys::global();
+    if let Some(scope) = global.dyn_ref::<DedicatedWorkerGlobalScope>() {
+        let msg = js_sys::Object::new();
+        let _ = js_sys::Reflect::set(&msg, &"type".into(), &msg_type.into());
+        for (key, value) in fields {
+            let _ = js_sys::Reflect::set(&msg, &(*key).into(), value);
+        }
+        let _ = scope.post_message(&msg);
+    }
+}
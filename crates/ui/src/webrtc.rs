@@ -0,0 +1,239 @@
+//! Peer-to-peer data channels between session participants
+//! The host only relays signaling (offers, answers, ICE candidates) over the
+//! session socket; the channels themselves connect the browsers directly.
+//! Runs on the main thread: `RTCPeerConnection` is not available in workers.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{
+    MessageEvent, RtcDataChannel, RtcDataChannelEvent, RtcDataChannelState, RtcIceCandidateInit,
+    RtcPeerConnection, RtcPeerConnectionIceEvent, RtcSdpType, RtcSessionDescriptionInit, Worker,
+};
+
+/// Label of the data channel opened between every pair of participants
+const CHANNEL_LABEL: &str = "nvim-web";
+
+/// Connection to one other participant
+struct Link {
+    pc: RtcPeerConnection,
+    /// Set once the data channel exists (ours or the one the peer opened)
+    channel: Option<RtcDataChannel>,
+}
+
+thread_local! {
+    /// Worker that owns the session socket, for sending signals
+    static WORKER: RefCell<Option<Rc<Worker>>> = const { RefCell::new(None) };
+    /// Links by participant id
+    static LINKS: RefCell<HashMap<String, Link>> = RefCell::new(HashMap::new());
+}
+
+/// Route outgoing signals through `worker`
+pub fn init(worker: Rc<Worker>) {
+    WORKER.with(|w| *w.borrow_mut() = Some(worker));
+}
+
+/// Match links to the current participants
+///
+/// Of each pair, the participant with the smaller id makes the offer, so
+/// both sides never offer at once. Links to participants who left are closed.
+pub fn sync_participants(self_id: &str, peers: &[String]) {
+    let gone: Vec<String> = LINKS.with(|links| {
+        links
+            .borrow()
+            .keys()
+            .filter(|id| !peers.contains(id))
+            .cloned()
+            .collect()
+    });
+    for id in gone {
+        close(&id);
+    }
+
+    for peer in peers {
+        let linked = LINKS.with(|links| links.borrow().contains_key(peer));
+        if peer.as_str() != self_id && !linked && self_id < peer.as_str() {
+            if let Some(pc) = connect(peer) {
+                let channel = pc.create_data_channel(CHANNEL_LABEL);
+                attach_channel(peer, channel);
+                make_offer(peer.clone(), pc);
+            }
+        }
+    }
+}
+
+/// Handle a signal relayed by the host
+pub fn handle_signal(from: &str, kind: &str, payload: &str) {
+    match kind {
+        "offer" => {
+            // A fresh offer replaces any previous link (the peer reloaded)
+            close(from);
+            if let Some(pc) = connect(from) {
+                answer_offer(from.to_string(), pc, payload.to_string());
+            }
+        }
+        "answer" => {
+            if let Some(pc) = link_pc(from) {
+                let desc = RtcSessionDescriptionInit::new(RtcSdpType::Answer);
+                desc.set_sdp(payload);
+                let promise = pc.set_remote_description(&desc);
+                spawn_local(async move {
+                    if let Err(e) = JsFuture::from(promise).await {
+                        web_sys::console::warn_2(&"[WebRTC] Bad answer".into(), &e);
+                    }
+                });
+            }
+        }
+        "ice_candidate" => {
+            let (Some(pc), Ok(init)) = (link_pc(from), js_sys::JSON::parse(payload)) else {
+                return;
+            };
+            let init: RtcIceCandidateInit = init.unchecked_into();
+            let promise = pc.add_ice_candidate_with_opt_rtc_ice_candidate_init(Some(&init));
+            spawn_local(async move {
+                let _ = JsFuture::from(promise).await;
+            });
+        }
+        _ => {}
+    }
+}
+
+/// Send text to every participant with an open channel, returns how many
+pub fn broadcast(text: &str) -> usize {
+    LINKS.with(|links| {
+        links
+            .borrow()
+            .values()
+            .filter_map(|link| link.channel.as_ref())
+            .filter(|channel| channel.ready_state() == RtcDataChannelState::Open)
+            .filter(|channel| channel.send_with_str(text).is_ok())
+            .count()
+    })
+}
+
+fn link_pc(peer: &str) -> Option<RtcPeerConnection> {
+    LINKS.with(|links| links.borrow().get(peer).map(|link| link.pc.clone()))
+}
+
+fn close(peer: &str) {
+    if let Some(link) = LINKS.with(|links| links.borrow_mut().remove(peer)) {
+        if let Some(channel) = link.channel {
+            channel.close();
+        }
+        link.pc.close();
+    }
+}
+
+/// Create a connection to `peer` that trickles ICE candidates to it
+fn connect(peer: &str) -> Option<RtcPeerConnection> {
+    let pc = match RtcPeerConnection::new() {
+        Ok(pc) => pc,
+        Err(e) => {
+            web_sys::console::warn_2(&"[WebRTC] Unavailable".into(), &e);
+            return None;
+        }
+    };
+
+    let to = peer.to_string();
+    let on_candidate = Closure::wrap(Box::new(move |e: RtcPeerConnectionIceEvent| {
+        // A null candidate marks the end of gathering
+        if let Some(candidate) = e.candidate() {
+            if let Ok(json) = js_sys::JSON::stringify(&candidate.to_json()) {
+                send_signal(&to, "ice_candidate", &String::from(json));
+            }
+        }
+    }) as Box<dyn FnMut(RtcPeerConnectionIceEvent)>);
+    pc.set_onicecandidate(Some(on_candidate.as_ref().unchecked_ref()));
+    on_candidate.forget();
+
+    let from = peer.to_string();
+    let on_channel = Closure::wrap(Box::new(move |e: RtcDataChannelEvent| {
+        attach_channel(&from, e.channel());
+    }) as Box<dyn FnMut(RtcDataChannelEvent)>);
+    pc.set_ondatachannel(Some(on_channel.as_ref().unchecked_ref()));
+    on_channel.forget();
+
+    LINKS.with(|links| {
+        links.borrow_mut().insert(
+            peer.to_string(),
+            Link {
+                pc: pc.clone(),
+                channel: None,
+            },
+        )
+    });
+    Some(pc)
+}
+
+/// Remember a channel to `peer` and show what arrives on it
+fn attach_channel(peer: &str, channel: RtcDataChannel) {
+    let from: String = peer.chars().take(8).collect();
+    let on_message = Closure::wrap(Box::new(move |e: MessageEvent| {
+        if let Some(text) = e.data().as_string() {
+            crate::dom::show_toast(&format!("{from} (direct): {text}"));
+        }
+    }) as Box<dyn FnMut(MessageEvent)>);
+    channel.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    on_message.forget();
+
+    LINKS.with(|links| {
+        if let Some(link) = links.borrow_mut().get_mut(peer) {
+            link.channel = Some(channel);
+        }
+    });
+}
+
+fn make_offer(peer: String, pc: RtcPeerConnection) {
+    spawn_local(async move {
+        let result: Result<(), JsValue> = async {
+            let offer = JsFuture::from(pc.create_offer()).await?;
+            let offer: RtcSessionDescriptionInit = offer.unchecked_into();
+            JsFuture::from(pc.set_local_description(&offer)).await?;
+            send_signal(&peer, "offer", &offer.get_sdp().unwrap_or_default());
+            Ok(())
+        }
+        .await;
+        if let Err(e) = result {
+            web_sys::console::warn_2(&"[WebRTC] Offer failed".into(), &e);
+        }
+    });
+}
+
+fn answer_offer(peer: String, pc: RtcPeerConnection, sdp: String) {
+    let offer = RtcSessionDescriptionInit::new(RtcSdpType::Offer);
+    offer.set_sdp(&sdp);
+    // Started before any await so later ICE candidates queue behind it
+    let remote = pc.set_remote_description(&offer);
+    spawn_local(async move {
+        let result: Result<(), JsValue> = async {
+            JsFuture::from(remote).await?;
+            let answer = JsFuture::from(pc.create_answer()).await?;
+            let answer: RtcSessionDescriptionInit = answer.unchecked_into();
+            JsFuture::from(pc.set_local_description(&answer)).await?;
+            send_signal(&peer, "answer", &answer.get_sdp().unwrap_or_default());
+            Ok(())
+        }
+        .await;
+        if let Err(e) = result {
+            web_sys::console::warn_2(&"[WebRTC] Answer failed".into(), &e);
+        }
+    });
+}
+
+/// Ask the worker to relay a signal to `to` through the host
+fn send_signal(to: &str, kind: &str, payload: &str) {
+    WORKER.with(|worker| {
+        if let Some(worker) = worker.borrow().as_ref() {
+            let msg = js_sys::Object::new();
+            let _ = js_sys::Reflect::set(&msg, &"type".into(), &"signal_send".into());
+            let _ = js_sys::Reflect::set(&msg, &"to".into(), &to.into());
+            let _ = js_sys::Reflect::set(&msg, &"kind".into(), &kind.into());
+            let _ = js_sys::Reflect::set(&msg, &"payload".into(), &payload.into());
+            let _ = worker.post_message(&msg);
+        }
+    });
+}
@@ -5,6 +5,10 @@ use crate::grid::GridManager;
 use crate::highlight::HighlightMap;
 use crate::input_queue::InputQueue;
 use crate::crdt::CrdtClient; // Import CRDT client
+use nvim_web_protocol::crdt::SyncMessage;
+use nvim_web_protocol::schema::{
+    check_version, BrowserMessage, HostMessage, ImageCommand, PROTOCOL_VERSION,
+};
 use std::cell::RefCell;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
@@ -119,6 +123,7 @@ fn start_worker(
 
         // 6. Setup Main Thread Message Handler
         setup_main_thread_handler(input_queue.clone(), renderer_rc.clone(), grids.clone());
+        crate::presence::start_heartbeat(input_queue.clone());
 
         // 7. Send initial resize to Neovim
         send_resize(&ws, cols, rows);
@@ -158,20 +163,22 @@ fn setup_websocket_handlers(
         if let Ok(abuf) = e.data().dyn_into::<js_sys::ArrayBuffer>() {
             let array = js_sys::Uint8Array::new(&abuf);
             let bytes = array.to_vec();
-            let mut cursor = std::io::Cursor::new(bytes);
 
-            if let Ok(msg) = rmpv::decode::read_value(&mut cursor) {
-                let grids = grids_msg.clone();
-                let highlights = highlights_msg.clone();
+            match HostMessage::decode(&bytes) {
+                Ok(msg) => {
+                    let grids = grids_msg.clone();
+                    let highlights = highlights_msg.clone();
                     let renderer = renderer_msg.clone();
                     let ws = ws_msg.clone();
                     let crdt = crdt_msg.clone(); // Clone for async
 
                     spawn_local(async move {
-                        // Use legacy handler for now - we can refactor later
-                        // handle_message expects RenderState, so we adapt
                         process_neovim_message(msg, grids, highlights, renderer, crdt, ws);
                     });
+                }
+                Err(e) => {
+                    web_sys::console::warn_1(&format!("[Worker] Bad host message: {e}").into());
+                }
             }
         }
     }) as Box<dyn FnMut(MessageEvent)>);
@@ -189,6 +196,11 @@ fn setup_websocket_handlers(
     // On Close - with reconnection
     let onclose = Closure::wrap(Box::new(move |e: web_sys::CloseEvent| {
         web_sys::console::warn_1(&format!("[Worker] WebSocket closed: {}", e.code()).into());
+
+        // Reconnecting to an incompatible host would fail the same way
+        if PROTOCOL_FAILED.with(std::cell::Cell::get) {
+            return;
+        }
         forward_connection_status("disconnected");
 
         // Schedule reconnection with exponential backoff
@@ -205,303 +217,179 @@ fn setup_websocket_handlers(
 }
 
 fn process_neovim_message(
-    msg: rmpv::Value,
+    msg: HostMessage,
     grids: Rc<RefCell<GridManager>>,
     highlights: Rc<RefCell<HighlightMap>>,
-    _renderer: Rc<RefCell<crate::renderer::Renderer>>,
+    renderer: Rc<RefCell<crate::renderer::Renderer>>,
     crdt: Rc<RefCell<CrdtClient>>,
-    _ws: WebSocket,
+    ws: WebSocket,
 ) {
-    // Parse Neovim RPC message
-    if let rmpv::Value::Array(arr) = &msg {
-        // Check for session message: ["session", session_id, is_viewer]
-        if arr.len() >= 2 {
-            if let Some(method) = arr.first().and_then(|v| v.as_str()) {
-                if method == "session" {
-                    if let Some(session_id) = arr.get(1).and_then(|v| v.as_str()) {
-                        // Forward session ID to main thread for sessionStorage
-                        forward_session_id_to_main(session_id);
-                    }
-                    return;
-                }
+    match msg {
+        HostMessage::Session {
+            session_id,
+            protocol_version,
+            ..
+        } => {
+            // Hosts that predate versioning report version 0 and fail here
+            if let Err(e) = check_version(protocol_version) {
+                fail_protocol(&ws, &format!("Host is incompatible with this UI ({e})"));
+                return;
             }
+            // Forward session ID to main thread for sessionStorage
+            forward_session_id_to_main(&session_id);
+            // Only logged-in connections get this far
+            forward_auth_status(None);
         }
-
-        if arr.len() >= 2 {
-            // Notification: [2, method, params]
-            if let Some(2) = arr.first().and_then(|v| v.as_i64()) {
-                let method = arr.get(1).and_then(|v| v.as_str()).unwrap_or("");
-                let params = arr.get(2).cloned().unwrap_or(rmpv::Value::Nil);
-
-                match method {
-                    "redraw" => {
-                        if let rmpv::Value::Array(events) = params {
-                            for event in events {
-                                process_redraw_event(&event, &grids, &highlights);
-                            }
-                        }
+        HostMessage::ProtocolMismatch {
+            host_version,
+            min_version,
+        } => {
+            fail_protocol(
+                &ws,
+                &format!(
+                    "Host speaks protocol {host_version} (min {min_version}), this UI speaks {PROTOCOL_VERSION}"
+                ),
+            );
+        }
+        // Datagram fallback envelope (cursor, heartbeat)
+        HostMessage::Datagram(raw) => {
+            let renderer = renderer.borrow();
+            crate::presence::handle_datagram(
+                &raw,
+                renderer.cell_w / renderer.dpr,
+                renderer.cell_h / renderer.dpr,
+            );
+        }
+        HostMessage::Redraw(events) => {
+            for event in events {
+                process_redraw_event(&event, &grids, &highlights);
+            }
+        }
+        HostMessage::OptionSet { name, value } if name == "guifont" => {
+            if let Some(font_str) = value.as_str() {
+                forward_guifont_to_main(font_str);
+
+                // Parse guifont: "Font Name:h12" -> "Font Name", 12
+                let mut family = font_str.to_string();
+                let mut size = None;
+
+                if let Some(idx) = font_str.rfind(":h") {
+                    if let Ok(s) = font_str[idx + 2..].parse::<f64>() {
+                        family = font_str[..idx].to_string().replace("_", " ");
+                        // Get DPR from renderer to scale points to pixels
+                        let dpr = renderer.borrow().dpr;
+                        size = Some(s * dpr);
                     }
-                    "option_set" => {
-                        // params is [name, value]
-                        if let rmpv::Value::Array(args) = params {
-                            if let (Some(rmpv::Value::String(name)), Some(val)) =
-                                (args.get(0), args.get(1))
-                            {
-                                if let Some(name_str) = name.as_str() {
-                                    if name_str == "guifont" {
-                                        if let rmpv::Value::String(font_val) = val {
-                                            if let Some(font_str) = font_val.as_str() {
-                                                forward_guifont_to_main(font_str);
-
-                                                // Parse guifont: "Font Name:h12" -> "Font Name", 12
-                                                let mut family = font_str.to_string();
-                                                let mut size = None;
-
-                                                if let Some(idx) = font_str.rfind(":h") {
-                                                    if let Ok(s) =
-                                                        font_str[idx + 2..].parse::<f64>()
-                                                    {
-                                                        family = font_str[..idx]
-                                                            .to_string()
-                                                            .replace("_", " ");
-                                                        // Get DPR from renderer to scale points to pixels
-                                                        let dpr = _renderer.borrow().dpr;
-                                                        size = Some(s * dpr);
-                                                    }
-                                                }
+                }
 
-                                                // If no :h parsed, assume it's just the family name (Neovim default)
+                // If no :h parsed, assume it's just the family name (Neovim default)
 
-                                                _renderer.borrow_mut().set_font(&family, size);
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    "nvim_web_image" => {
-                        // Custom image protocol: [subcommand, args...]
-                        if let rmpv::Value::Array(args) = params {
-                            if let Some(rmpv::Value::String(subcmd)) = args.get(0) {
-                                let cmd = subcmd.as_str().unwrap_or("");
-                                match cmd {
-                                    "show" => {
-                                        // ["show", id, url, x, y, w, h]
-                                        if args.len() >= 7 {
-                                            let id = args[1].as_str().unwrap_or("?");
-                                            let url = args[2].as_str().unwrap_or("");
-                                            let x = args[3].as_f64().unwrap_or(0.0);
-                                            let y = args[4].as_f64().unwrap_or(0.0);
-                                            let w = args[5].as_f64().unwrap_or(1.0);
-                                            let h = args[6].as_f64().unwrap_or(1.0);
-
-                                            // Convert Cell -> CSS Pixels
-                                            let renderer = _renderer.borrow();
-                                            let cell_w = renderer.cell_w / renderer.dpr;
-                                            let cell_h = renderer.cell_h / renderer.dpr;
-
-                                            let px_x = x * cell_w;
-                                            let px_y = y * cell_h;
-                                            let px_w = w * cell_w;
-                                            let px_h = h * cell_h;
-
-                                            let global = js_sys::global();
-                                            if let Some(scope) =
-                                                global.dyn_ref::<DedicatedWorkerGlobalScope>()
-                                            {
-                                                let msg = js_sys::Object::new();
-                                                let _ = js_sys::Reflect::set(
-                                                    &msg,
-                                                    &"type".into(),
-                                                    &"image_update".into(),
-                                                );
-                                                let _ = js_sys::Reflect::set(
-                                                    &msg,
-                                                    &"action".into(),
-                                                    &"show".into(),
-                                                );
-                                                let _ = js_sys::Reflect::set(
-                                                    &msg,
-                                                    &"id".into(),
-                                                    &id.into(),
-                                                );
-                                                let _ = js_sys::Reflect::set(
-                                                    &msg,
-                                                    &"url".into(),
-                                                    &url.into(),
-                                                );
-                                                let _ = js_sys::Reflect::set(
-                                                    &msg,
-                                                    &"x".into(),
-                                                    &px_x.into(),
-                                                );
-                                                let _ = js_sys::Reflect::set(
-                                                    &msg,
-                                                    &"y".into(),
-                                                    &px_y.into(),
-                                                );
-                                                let _ = js_sys::Reflect::set(
-                                                    &msg,
-                                                    &"width".into(),
-                                                    &px_w.into(),
-                                                );
-                                                let _ = js_sys::Reflect::set(
-                                                    &msg,
-                                                    &"height".into(),
-                                                    &px_h.into(),
-                                                );
-                                                let _ = scope.post_message(&msg);
-                                            }
-                                        }
-                                    }
-                                    "hide" => {
-                                        // ["hide", id]
-                                        let id =
-                                            args.get(1).and_then(|v| v.as_str()).unwrap_or("?");
-                                        let global = js_sys::global();
-                                        if let Some(scope) =
-                                            global.dyn_ref::<DedicatedWorkerGlobalScope>()
-                                        {
-                                            let msg = js_sys::Object::new();
-                                            let _ = js_sys::Reflect::set(
-                                                &msg,
-                                                &"type".into(),
-                                                &"image_update".into(),
-                                            );
-                                            let _ = js_sys::Reflect::set(
-                                                &msg,
-                                                &"action".into(),
-                                                &"hide".into(),
-                                            );
-                                            let _ = js_sys::Reflect::set(
-                                                &msg,
-                                                &"id".into(),
-                                                &id.into(),
-                                            );
-                                            let _ = scope.post_message(&msg);
-                                        }
-                                    }
-                                    "clear" => {
-                                        // ["clear"]
-                                        let global = js_sys::global();
-                                        if let Some(scope) =
-                                            global.dyn_ref::<DedicatedWorkerGlobalScope>()
-                                        {
-                                            let msg = js_sys::Object::new();
-                                            let _ = js_sys::Reflect::set(
-                                                &msg,
-                                                &"type".into(),
-                                                &"image_update".into(),
-                                            );
-                                            let _ = js_sys::Reflect::set(
-                                                &msg,
-                                                &"action".into(),
-                                                &"clear".into(),
-                                            );
-                                            let _ = scope.post_message(&msg);
-                                        }
-                                    }
-                                    _ => {}
-                                }
-                            }
-                        }
-                    }
-                    "crdt_sync" => {
-                        // Handle CRDT sync messages
-                        // Payload: [{"type": "sync1", ...}]
-                        if let rmpv::Value::Array(args) = params {
-                             if let Some(msg_map) = args.first() {
-                                 // Simple manual parsing of the map to avoid complex deserialization logic here
-                                 if let rmpv::Value::Map(kvs) = msg_map {
-                                     let mut type_str = "";
-                                     let mut binary_data: Option<Vec<u8>> = None;
-
-                                     for (k, v) in kvs {
-                                         if let rmpv::Value::String(s) = k {
-                                             if s.as_str() == Some("type") {
-                                                 if let rmpv::Value::String(ts) = v {
-                                                     type_str = ts.as_str().unwrap_or("");
-                                                 }
-                                             } else if s.as_str() == Some("update") || s.as_str() == Some("state_vector") {
-                                                  // Both fields are byte arrays (Vec<u8>)
-                                                  if let rmpv::Value::Binary(bin) = v {
-                                                      binary_data = Some(bin.clone());
-                                                  } else if let rmpv::Value::Array(ints) = v {
-                                                      // Sometimes RMP decodes bin as array of ints
-                                                      let vec: Vec<u8> = ints.iter().filter_map(|x| x.as_u64().map(|b| b as u8)).collect();
-                                                      binary_data = Some(vec);
-                                                  }
-                                             }
-                                         }
-                                     }
-
-                                     if let Some(data) = binary_data {
-                                         let mut client = crdt.borrow_mut();
-                                         match type_str {
-                                             "sync1" => {
-                                                  // Host asking for our state vector? Usually client sends sync1 first.
-                                                  // But if host sends sync1, we reply with sync2?
-                                                  // Typically: Client connects -> Client sends Sync1.
-                                                  // Host replies Sync2.
-                                                  // If Host sends Sync1, it wants to pull our state.
-                                             }
-                                             "sync2" => {
-                                                  // Host sent updates
-                                                  let _ = client.apply_update(&data);
-                                                  web_sys::console::log_1(&"[CRDT] Applied SyncStep2 update".into());
-                                             }
-                                             "update" => {
-                                                  // Incremental update
-                                                  let _ = client.apply_update(&data);
-                                                  // web_sys::console::log_1(&"[CRDT] Applied incremental update".into());
-                                             }
-                                             _ => {}
-                                         }
-                                     }
-                                 }
-                             }
-                        }
-                    }
-                    "nvim_web_action" => {
-                        // Custom action protocol: [action_name, args...]
-                        if let rmpv::Value::Array(args) = params {
-                            if let Some(rmpv::Value::String(action_val)) = args.get(0) {
-                                let action = action_val.as_str().unwrap_or("");
-                                match action {
-                                    "browse_files" => {
-                                        let global = js_sys::global();
-                                        if let Some(scope) =
-                                            global.dyn_ref::<DedicatedWorkerGlobalScope>()
-                                        {
-                                            let msg = js_sys::Object::new();
-                                            let _ = js_sys::Reflect::set(
-                                                &msg,
-                                                &"type".into(),
-                                                &"action".into(),
-                                            );
-                                            let _ = js_sys::Reflect::set(
-                                                &msg,
-                                                &"name".into(),
-                                                &"open_file_picker".into(),
-                                            );
-                                            let _ = scope.post_message(&msg);
-                                        }
-                                    }
-                                    _ => {}
-                                }
-                            }
-                        }
-                    }
-                    _ => {
-                        // Other notifications
-                    }
+                renderer.borrow_mut().set_font(&family, size);
+            }
+        }
+        HostMessage::Image(cmd) => {
+            let msg = js_sys::Object::new();
+            let _ = js_sys::Reflect::set(&msg, &"type".into(), &"image_update".into());
+            match cmd {
+                ImageCommand::Show {
+                    id,
+                    url,
+                    x,
+                    y,
+                    width,
+                    height,
+                } => {
+                    // Convert Cell -> CSS Pixels
+                    let renderer = renderer.borrow();
+                    let cell_w = renderer.cell_w / renderer.dpr;
+                    let cell_h = renderer.cell_h / renderer.dpr;
+
+                    let _ = js_sys::Reflect::set(&msg, &"action".into(), &"show".into());
+                    let _ = js_sys::Reflect::set(&msg, &"id".into(), &id.into());
+                    let _ = js_sys::Reflect::set(&msg, &"url".into(), &url.into());
+                    let _ = js_sys::Reflect::set(&msg, &"x".into(), &(x * cell_w).into());
+                    let _ = js_sys::Reflect::set(&msg, &"y".into(), &(y * cell_h).into());
+                    let _ =
+                        js_sys::Reflect::set(&msg, &"width".into(), &(width * cell_w).into());
+                    let _ =
+                        js_sys::Reflect::set(&msg, &"height".into(), &(height * cell_h).into());
+                }
+                ImageCommand::Hide { id } => {
+                    let _ = js_sys::Reflect::set(&msg, &"action".into(), &"hide".into());
+                    let _ = js_sys::Reflect::set(&msg, &"id".into(), &id.into());
+                }
+                ImageCommand::Clear => {
+                    let _ = js_sys::Reflect::set(&msg, &"action".into(), &"clear".into());
+                }
+            }
+            let global = js_sys::global();
+            if let Some(scope) = global.dyn_ref::<DedicatedWorkerGlobalScope>() {
+                let _ = scope.post_message(&msg);
+            }
+        }
+        HostMessage::CrdtSync { message: sync, .. } => {
+            let mut client = crdt.borrow_mut();
+            match sync {
+                // Host asking for our state vector. Typically the client
+                // connects and sends Sync1 first, and the host replies Sync2.
+                SyncMessage::SyncStep1 { .. } | SyncMessage::Awareness { .. } => {}
+                SyncMessage::SyncStep2 { update } => {
+                    // Host sent updates
+                    let _ = client.apply_update(&update);
+                    web_sys::console::log_1(&"[CRDT] Applied SyncStep2 update".into());
+                }
+                SyncMessage::Update { update } => {
+                    // Incremental update
+                    let _ = client.apply_update(&update);
                 }
             }
         }
+        msg @ (HostMessage::Presence { .. }
+        | HostMessage::PeerInfo(_)
+        | HostMessage::PeerLeft { .. }
+        | HostMessage::RoleChanged { .. }
+        | HostMessage::ControlChanged { .. }
+        | HostMessage::ControlRequested { .. }
+        | HostMessage::Chat { .. }
+        | HostMessage::Signal { .. }) => crate::presence::handle_collab(msg),
+        msg @ (HostMessage::Comments(_)
+        | HostMessage::Comment(_)
+        | HostMessage::CommentDeleted { .. }) => crate::comments::handle(msg),
+        HostMessage::AuthRequired { login_url } => forward_auth_status(Some(&login_url)),
+        HostMessage::AuthRestored => forward_auth_status(None),
+        HostMessage::Action { name, .. } if name == "browse_files" => {
+            let global = js_sys::global();
+            if let Some(scope) = global.dyn_ref::<DedicatedWorkerGlobalScope>() {
+                let msg = js_sys::Object::new();
+                let _ = js_sys::Reflect::set(&msg, &"type".into(), &"action".into());
+                let _ = js_sys::Reflect::set(&msg, &"name".into(), &"open_file_picker".into());
+                let _ = scope.post_message(&msg);
+            }
+        }
+        _ => {
+            // Other messages are handled by the main thread or ignored
+        }
     }
 }
 
+/// Stop talking to a host that speaks an incompatible protocol
+///
+/// Closes the socket without scheduling a reconnect and tells the main
+/// thread so the user sees why the editor is not loading.
+fn fail_protocol(ws: &WebSocket, reason: &str) {
+    PROTOCOL_FAILED.with(|f| f.set(true));
+    web_sys::console::error_1(&format!("[Worker] Protocol mismatch: {reason}").into());
+
+    let global = js_sys::global();
+    if let Some(scope) = global.dyn_ref::<DedicatedWorkerGlobalScope>() {
+        let msg = js_sys::Object::new();
+        let _ = js_sys::Reflect::set(&msg, &"type".into(), &"protocol_error".into());
+        let _ = js_sys::Reflect::set(&msg, &"message".into(), &reason.into());
+        let _ = scope.post_message(&msg);
+    }
+    let _ = ws.close_with_code(1000);
+}
+
 /// Forward session ID to main thread for storage in sessionStorage
 fn forward_session_id_to_main(session_id: &str) {
     let global = js_sys::global();
@@ -525,6 +413,19 @@ fn forward_mode_to_main(mode: &str) {
     }
 }
 
+/// Tell the main thread the login expired (`Some(login_url)`) or was restored
+fn forward_auth_status(login_url: Option<&str>) {
+    let global = js_sys::global();
+    if let Some(scope) = global.dyn_ref::<DedicatedWorkerGlobalScope>() {
+        let msg = js_sys::Object::new();
+        let _ = js_sys::Reflect::set(&msg, &"type".into(), &"auth_status".into());
+        if let Some(url) = login_url {
+            let _ = js_sys::Reflect::set(&msg, &"login_url".into(), &url.into());
+        }
+        let _ = scope.post_message(&msg);
+    }
+}
+
 /// Forward connection status to main thread for UI indicator
 fn forward_connection_status(status: &str) {
     let global = js_sys::global();
@@ -551,6 +452,8 @@ fn forward_guifont_to_main(font_str: &str) {
 /// Reconnection state (global for worker)
 thread_local! {
     static RECONNECT_ATTEMPT: std::cell::Cell<u32> = const { std::cell::Cell::new(0) };
+    /// Set once the host turned out to speak an incompatible protocol
+    static PROTOCOL_FAILED: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
     /// Last garbage collection timestamp for memory recycling
     static LAST_GC: std::cell::Cell<f64> = const { std::cell::Cell::new(0.0) };
 }
@@ -1140,6 +1043,10 @@ fn setup_main_thread_handler(
                         _ => "press",
                     };
 
+                    if action == "move" {
+                        crate::presence::pointer_moved(&input_queue, 1, row as u32, col as u32);
+                    }
+
                     let mods = helper_build_modifiers(ctrl, shift, alt, meta);
                     // Remove trailing dash if present
                     let mods = mods.trim_end_matches('-');
@@ -1195,6 +1102,58 @@ fn setup_main_thread_handler(
                         .unwrap_or_default();
                     input_queue.send_paste(&text);
                 }
+                Some("chat_send") => {
+                    let text = js_sys::Reflect::get(obj, &"text".into())
+                        .ok()
+                        .and_then(|v| v.as_string())
+                        .unwrap_or_default();
+                    crate::presence::send_chat(&input_queue, &text);
+                }
+                Some("signal_send") => {
+                    let get = |key: &str| {
+                        js_sys::Reflect::get(obj, &key.into())
+                            .ok()
+                            .and_then(|v| v.as_string())
+                            .unwrap_or_default()
+                    };
+                    crate::presence::send_signal(
+                        &input_queue,
+                        &get("to"),
+                        &get("kind"),
+                        &get("payload"),
+                    );
+                }
+                Some("comment_add") => {
+                    let get = |key: &str| js_sys::Reflect::get(obj, &key.into()).ok();
+                    crate::comments::add(
+                        &input_queue,
+                        &get("body").and_then(|v| v.as_string()).unwrap_or_default(),
+                        get("parent").and_then(|v| v.as_string()),
+                        get("cursor").and_then(|v| v.as_bool()).unwrap_or(false),
+                    );
+                }
+                Some("comment_resolve") => {
+                    let get = |key: &str| js_sys::Reflect::get(obj, &key.into()).ok();
+                    if let Some(id) = get("id").and_then(|v| v.as_string()) {
+                        let resolved = get("resolved").and_then(|v| v.as_bool()).unwrap_or(true);
+                        crate::comments::resolve(&input_queue, &id, resolved);
+                    }
+                }
+                Some("comment_delete") => {
+                    if let Some(id) = js_sys::Reflect::get(obj, &"id".into())
+                        .ok()
+                        .and_then(|v| v.as_string())
+                    {
+                        crate::comments::delete(&input_queue, &id);
+                    }
+                }
+                Some("set_name") => {
+                    let name = js_sys::Reflect::get(obj, &"name".into())
+                        .ok()
+                        .and_then(|v| v.as_string())
+                        .unwrap_or_default();
+                    crate::presence::set_name(&input_queue, &name);
+                }
                 Some("file_drop") => {
                     let name = js_sys::Reflect::get(obj, &"name".into())
                         .ok()
@@ -1315,15 +1274,11 @@ fn translate_key(key: &str, ctrl: bool, shift: bool, alt: bool, meta: bool) -> S
 }
 
 fn send_resize(ws: &WebSocket, cols: usize, rows: usize) {
-    let msg = rmpv::Value::Array(vec![
-        rmpv::Value::String("resize".into()),
-        rmpv::Value::Integer((cols as i64).into()),
-        rmpv::Value::Integer((rows as i64).into()),
-    ]);
-    let mut bytes = Vec::new();
-    if rmpv::encode::write_value(&mut bytes, &msg).is_ok() {
-        let _ = ws.send_with_u8_array(&bytes);
-    }
+    let msg = BrowserMessage::Resize {
+        cols: cols as u32,
+        rows: rows as u32,
+    };
+    let _ = ws.send_with_u8_array(&msg.encode());
 }
 
 #[allow(clippy::type_complexity)]
@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use std::time::SystemTime;
 
 use anyhow::{bail, Result};
@@ -162,4 +163,11 @@ pub trait VfsBackend: Send + Sync {
     fn supports_streaming(&self) -> bool {
         false
     }
+
+    /// Path on the host filesystem, for backends that map onto it
+    ///
+    /// Lets Neovim open the file directly (e.g. from a quickfix list).
+    fn host_path(&self, _path: &str) -> Option<PathBuf> {
+        None
+    }
 }
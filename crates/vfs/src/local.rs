@@ -240,6 +240,10 @@ impl VfsBackend for LocalFs {
     fn supports_streaming(&self) -> bool {
         true
     }
+
+    fn host_path(&self, path: &str) -> Option<PathBuf> {
+        self.resolve_existing(path).ok()
+    }
 }
 
 /// Streaming read handle for local files
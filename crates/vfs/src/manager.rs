@@ -339,6 +339,22 @@ impl VfsManager {
         Ok((parts[0].to_string(), parts[1].to_string()))
     }
 
+    /// Resolve a VFS path to its backend and the path within that backend
+    pub async fn resolve_backend(&self, vfs_path: &str) -> Result<(Arc<dyn VfsBackend>, String)> {
+        let resolved = self.resolve_aliases(vfs_path).await;
+        let (backend_name, path) = self.parse_vfs_path(&resolved).await?;
+
+        if backend_name == "ssh" {
+            use super::SshFsBackend;
+            // vfs://ssh/user@host:port/path - strip the connection part
+            let backend: Arc<dyn VfsBackend> = SshFsBackend::get_or_connect(&resolved)?;
+            let remote = path.split_once('/').map_or("", |(_, p)| p);
+            return Ok((backend, format!("/{remote}")));
+        }
+
+        Ok((self.get_backend(&backend_name).await?, path))
+    }
+
     /// Read file with caching
     pub async fn read_file(&self, vfs_path: &str) -> Result<Vec<u8>> {
         let resolved = self.resolve_aliases(vfs_path).await;
@@ -461,6 +477,22 @@ mod tests {
         assert_eq!(aliases.len(), 2);
     }
 
+    #[tokio::test]
+    async fn test_resolve_backend() {
+        let mgr = VfsManager::new();
+        mgr.register_backend(
+            "mem",
+            Box::new(crate::MemoryFs::with_files(vec![("/src/lib.rs", b"x")])),
+        )
+        .await;
+        mgr.add_alias("@src", "vfs://mem/src").await;
+
+        let (backend, path) = mgr.resolve_backend("@src/lib.rs").await.unwrap();
+        assert_eq!(path, "src/lib.rs");
+        assert_eq!(backend.read(&path).await.unwrap(), b"x");
+        assert!(mgr.resolve_backend("vfs://nope/a").await.is_err());
+    }
+
     #[tokio::test]
     async fn test_backend_list() {
         let mgr = VfsManager::new();
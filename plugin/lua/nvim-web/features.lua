@@ -0,0 +1,109 @@
+-- nvim-web host integration features
+--
+-- Each feature is switched on by the host through `require("nvim-web").handshake()`
+-- and can be turned off in the `[plugin]` section of the host's config.toml.
+-- `channel` is the host's RPC channel.
+
+local M = {}
+
+-- Order features are enabled in: auto_cd changes directory before cwd_sync reports it
+M.order = { "vfs_status", "auto_cd", "cwd_sync", "recording", "clipboard" }
+
+-- Backend of a buffer name: local, browser or ssh
+local function backend_of(name)
+  if name:match("^vfs://browser/") then
+    return "browser"
+  elseif name:match("^vfs://ssh/") then
+    return "ssh"
+  end
+  return "local"
+end
+
+-- :VfsStatus - Show the current buffer's backend, path and working directory
+function M.vfs_status()
+  vim.api.nvim_create_user_command("VfsStatus", function()
+    local name = vim.api.nvim_buf_get_name(0)
+    vim.notify(table.concat({
+      "Backend: " .. backend_of(name),
+      "Path: " .. (name ~= "" and name or "[No Name]"),
+      "CWD: " .. vim.fn.getcwd(),
+    }, "\n"), vim.log.levels.INFO)
+  end, { desc = "Show current VFS backend and path" })
+end
+
+-- Change the window's directory to the git root of the file entered
+function M.auto_cd()
+  vim.api.nvim_create_autocmd("BufEnter", {
+    group = vim.api.nvim_create_augroup("NvimWebGitCD", { clear = true }),
+    callback = function()
+      local file = vim.fn.expand("%:p")
+      if file == "" or file:match("^term://") or file:match("^fugitive://") then
+        return
+      end
+      local root = vim.fn.system({ "git", "-C", vim.fn.expand("%:p:h"), "rev-parse", "--show-toplevel" })
+      if vim.v.shell_error == 0 and root ~= "" then
+        vim.cmd.lcd(vim.fn.fnameescape(vim.trim(root)))
+      end
+    end,
+    desc = "Change to the git root of the current file",
+  })
+end
+
+-- Tell the host the working directory, file, backend and git branch
+function M.cwd_sync(channel)
+  local function notify()
+    local cwd = vim.fn.getcwd()
+    local file = vim.fn.expand("%:p")
+    local branch = vim.fn.system({ "git", "-C", cwd, "branch", "--show-current" })
+    branch = vim.v.shell_error == 0 and vim.trim(branch) or ""
+    vim.rpcnotify(channel, "cwd_changed", cwd, file, backend_of(file), branch)
+  end
+
+  vim.api.nvim_create_autocmd({ "DirChanged", "BufEnter" }, {
+    group = vim.api.nvim_create_augroup("NvimWebCwdSync", { clear = true }),
+    callback = notify,
+    desc = "Report the working directory to the host",
+  })
+  notify()
+end
+
+-- Tell the host when a macro recording starts and stops
+function M.recording(channel)
+  local group = vim.api.nvim_create_augroup("NvimWebRecording", { clear = true })
+  vim.api.nvim_create_autocmd("RecordingEnter", {
+    group = group,
+    callback = function()
+      vim.rpcnotify(channel, "recording_start", vim.fn.reg_recording())
+    end,
+  })
+  vim.api.nvim_create_autocmd("RecordingLeave", {
+    group = group,
+    callback = function()
+      vim.rpcnotify(channel, "recording_stop")
+    end,
+  })
+end
+
+-- Use the browser's clipboard for the + and * registers
+function M.clipboard(channel)
+  local function copy(lines, regtype)
+    vim.rpcnotify(channel, "clipboard_write", lines, regtype)
+  end
+  local function paste()
+    -- The host asks the browser and returns its answer
+    local ok, result = pcall(vim.rpcrequest, channel, "clipboard_read")
+    if ok then
+      return result
+    end
+    return { { "" }, "v" }
+  end
+
+  vim.g.clipboard = {
+    name = "nvim-web",
+    copy = { ["+"] = copy, ["*"] = copy },
+    paste = { ["+"] = paste, ["*"] = paste },
+    cache_enabled = 1,
+  }
+end
+
+return M
@@ -5,6 +5,14 @@
 
 local M = {}
 
+-- Version of the plugin, checked by the host on handshake
+M.version = "1.0.0"
+
+-- RPC channel of the nvim-web host, learned on handshake
+function M.channel()
+  return vim.g.nvim_web_channel or 1
+end
+
 -- Git subcommands for completion
 local git_subcommands = {
   "add", "blame", "branch", "checkout", "cherry-pick", "clone", "commit",
@@ -96,7 +104,7 @@ function M.setup()
     
     -- If no path given, trigger browser file picker
     if path == "" then
-      vim.rpcnotify(1, 'open_file_picker')
+      vim.rpcnotify(M.channel(), 'open_file_picker')
       vim.notify("Opening file picker...", vim.log.levels.INFO)
       return
     end
@@ -117,30 +125,10 @@ function M.setup()
 
   -- :Edit - Explicit file picker trigger (alias)
   vim.api.nvim_create_user_command("Edit", function()
-    vim.rpcnotify(1, 'open_file_picker')
+    vim.rpcnotify(M.channel(), 'open_file_picker')
     vim.notify("Opening file picker...", vim.log.levels.INFO)
   end, { desc = "Open browser file picker" })
 
-  -- Show current buffer's VFS backend and path
-  vim.api.nvim_create_user_command("VfsStatus", function()
-    local name = vim.api.nvim_buf_get_name(0)
-    local backend = "local"
-    local path = name
-    
-    if name:match("^vfs://browser/") then
-      backend = "browser"
-      path = name:sub(15)
-    elseif name:match("^vfs://ssh/") then
-      backend = "ssh"
-      path = name:sub(11)
-    elseif name:match("^vfs://local/") then
-      backend = "local"
-      path = name:sub(13)
-    end
-    
-    vim.notify(string.format("[%s] %s", backend:upper(), path), vim.log.levels.INFO)
-  end, { desc = "Show current VFS backend and path" })
-
   ---------------------------------------------------------------------------
   -- Git Commands
   ---------------------------------------------------------------------------
@@ -228,7 +216,7 @@ function M.setup()
       vim.api.nvim_buf_set_option(args.buf, 'buftype', 'acwrite')
       
       -- Request file content from Host via RPC
-      local ok, result = pcall(vim.rpcrequest, 1, 'vfs_read', uri)
+      local ok, result = pcall(vim.rpcrequest, M.channel(), 'vfs_read', uri)
       if ok and type(result) == 'table' then
         -- result is array of lines
         vim.api.nvim_buf_set_lines(args.buf, 0, -1, false, result)
@@ -250,7 +238,7 @@ function M.setup()
       local lines = vim.api.nvim_buf_get_lines(args.buf, 0, -1, false)
       
       -- Send file content to Host via RPC
-      local ok, result = pcall(vim.rpcrequest, 1, 'vfs_write', uri, lines)
+      local ok, result = pcall(vim.rpcrequest, M.channel(), 'vfs_write', uri, lines)
       if ok and result then
         vim.api.nvim_buf_set_option(args.buf, 'modified', false)
         vim.notify("VFS: Saved " .. uri, vim.log.levels.INFO)
@@ -306,7 +294,7 @@ function M.setup()
   })
 
   ---------------------------------------------------------------------------
-  -- Netrw and Clipboard Configuration
+  -- Netrw Configuration
   ---------------------------------------------------------------------------
   
   -- Configure netrw for minimal file browsing
@@ -314,35 +302,6 @@ function M.setup()
   vim.g.netrw_liststyle = 3   -- Tree style
   vim.g.netrw_winsize = 25    -- 25% width for splits
 
-  -- Configure clipboard provider
-  -- This delegates clipboard operations to the host via RPC
-  _G.NvimWebClipboardCopy = function(lines, regtype)
-    vim.rpcnotify(1, 'clipboard_write', lines, regtype)
-  end
-
-  _G.NvimWebClipboardPaste = function()
-    -- This sends a request to host, which asks browser, and returns result
-    local ok, result = pcall(vim.rpcrequest, 1, 'clipboard_read', '')
-    if ok then
-      return result
-    else
-      return { '', 'v' }
-    end
-  end
-
-  vim.g.clipboard = {
-    name = 'nvim-web',
-    copy = {
-      ['+'] = 'v:lua.NvimWebClipboardCopy',
-      ['*'] = 'v:lua.NvimWebClipboardCopy',
-    },
-    paste = {
-      ['+'] = 'v:lua.NvimWebClipboardPaste',
-      ['*'] = 'v:lua.NvimWebClipboardPaste',
-    },
-    cache_enabled = 1,
-  }
-
   ---------------------------------------------------------------------------
   -- Browser Integration Commands (Phase 12.3)
   ---------------------------------------------------------------------------
@@ -351,7 +310,7 @@ function M.setup()
   vim.api.nvim_create_user_command("WebShare", function(args)
     local viewer_mode = args.bang and "?viewer=1" or ""
     -- Request session URL from host
-    local ok, session_id = pcall(vim.rpcrequest, 1, 'get_session_id')
+    local ok, session_id = pcall(vim.rpcrequest, M.channel(), 'get_session_id')
     if ok and session_id then
       local url = "https://nvim-web.app/?session=" .. session_id .. viewer_mode
       -- Copy to clipboard via browser
@@ -370,23 +329,28 @@ function M.setup()
   vim.api.nvim_create_user_command("WebNotify", function(args)
     local message = args.args
     local level = args.bang and "warn" or "info"
-    vim.rpcnotify(1, 'browser_notify', { message = message, level = level })
+    vim.rpcnotify(M.channel(), 'browser_notify', { message = message, level = level })
   end, { nargs = 1, bang = true, desc = "Show browser notification (! for warning)" })
 
   -- :WebPrint - Trigger browser print dialog
   vim.api.nvim_create_user_command("WebPrint", function()
-    vim.rpcnotify(1, 'browser_print')
+    vim.rpcnotify(M.channel(), 'browser_print')
     vim.notify("Print dialog triggered", vim.log.levels.INFO)
   end, { desc = "Open browser print dialog" })
 
   -- :WebFullscreen - Toggle browser fullscreen
   vim.api.nvim_create_user_command("WebFullscreen", function()
-    vim.rpcnotify(1, 'browser_fullscreen')
+    vim.rpcnotify(M.channel(), 'browser_fullscreen')
   end, { desc = "Toggle browser fullscreen" })
 
+  -- :WebBrowse - Open the browser's file browser
+  vim.api.nvim_create_user_command("WebBrowse", function()
+    vim.rpcnotify(M.channel(), 'nvim_web_action', 'browse_files')
+  end, { desc = "Open browser file browser" })
+
   -- :WebViewers - Show connected viewers (for collaboration)
   vim.api.nvim_create_user_command("WebViewers", function()
-    local ok, viewers = pcall(vim.rpcrequest, 1, 'get_viewers')
+    local ok, viewers = pcall(vim.rpcrequest, M.channel(), 'get_viewers')
     if ok and type(viewers) == 'table' then
       if #viewers == 0 then
         vim.notify("No viewers connected", vim.log.levels.INFO)
@@ -403,6 +367,36 @@ function M.setup()
 
 end
 
+-- Called by the host once it has attached
+--
+-- opts.channel is the host's RPC channel and opts.features turns the host
+-- integration features (see nvim-web.features) on or off; features not
+-- mentioned are on. Returns the plugin version and the features enabled.
+function M.handshake(opts)
+  opts = opts or {}
+  vim.g.nvim_web_channel = opts.channel
+  -- Started with --clean or without plugin/ on the runtimepath
+  if not vim.g.loaded_nvim_web then
+    vim.g.loaded_nvim_web = true
+    M.setup({})
+  end
+
+  local features = require("nvim-web.features")
+  local toggles = opts.features or {}
+  local enabled = {}
+  for _, name in ipairs(features.order) do
+    if toggles[name] ~= false then
+      local ok, err = pcall(features[name], M.channel())
+      if ok then
+        table.insert(enabled, name)
+      else
+        vim.notify("nvim-web: " .. name .. ": " .. tostring(err), vim.log.levels.WARN)
+      end
+    end
+  end
+  return { version = M.version, features = enabled }
+end
+
 -- Status function for statusline integration
 -- Usage: set statusline+=%{nvim_web#status()}
 function M.status()
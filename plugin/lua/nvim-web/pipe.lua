@@ -1,11 +1,12 @@
 -- nvim-web pipe.lua
 -- Universal tool pipe wrapper for CLI tools (LLMs, formatters, etc.)
+-- Tools are registered by name in the [tools] section of the host's config.toml
 
 local M = {}
 
---- Execute a CLI tool with input
---- @param command string The command to execute (e.g., "claude", "gemini-cli")
---- @param args table Array of arguments
+--- Execute a registered tool with input
+--- @param command string Name of the tool in [tools] (e.g., "prettier")
+--- @param args table Array of arguments filling the tool's {0}, {1}, ... placeholders
 --- @param input? string Input to pipe to stdin
 --- @param callback? function Callback(err, result) for async execution
 --- @return table|nil Result {stdout, stderr, exit_code} for sync, nil for async
@@ -37,8 +38,8 @@ function M.exec(command, args, input, callback)
 end
 
 --- Execute tool with visual selection as input
---- @param command string The command to execute
---- @param args? table Array of arguments
+--- @param command string Name of the tool in [tools]
+--- @param args? table Array of arguments filling the tool's placeholders
 function M.exec_visual(command, args)
   args = args or {}
 
@@ -118,17 +119,18 @@ function M.setup()
         end
       end)
     end
-  end, { nargs = "+", range = true, desc = "Execute CLI tool" })
+  end, { nargs = "+", range = true, desc = "Execute registered tool" })
 
-  -- Example keymaps (users can override)
-  -- <leader>tc - Claude on selection
+  -- Example keymaps (users can override); they need tools named "explain"
+  -- and "prettier" in [tools]
+  -- <leader>tc - Explain selection
   vim.keymap.set("v", "<leader>tc", function()
-    M.exec_visual("claude", { "-p", "explain this code" })
-  end, { desc = "Claude explain selection" })
+    M.exec_visual("explain", {})
+  end, { desc = "Explain selection" })
 
   -- <leader>tf - Format with prettier
   vim.keymap.set("v", "<leader>tf", function()
-    M.exec_visual("prettier", { "--stdin-filepath", vim.fn.expand("%") })
+    M.exec_visual("prettier", { vim.fn.expand("%") })
   end, { desc = "Format selection with prettier" })
 end
 